@@ -1,15 +1,15 @@
 use anyhow::Result;
 
-use relly::btree::{BTree, SearchMode};
+use relly::btree::{BTree, SearchMode, FIRST_META_PAGE_ID};
 use relly::buffer::{BufferPool, BufferPoolManager};
-use relly::disk::{DiskManager, PageId};
+use relly::disk::DiskManager;
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("test.btr")?;
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
 
-    let btree = BTree::new(PageId(0));
+    let btree = BTree::new(FIRST_META_PAGE_ID);
     let mut iter = btree.search(&mut bufmgr, SearchMode::Start)?;
 
     while let Some((key, value)) = iter.next(&mut bufmgr)? {
@@ -1,6 +1,6 @@
 use anyhow::Result;
 
-use relly::btree::BTree;
+use relly::btree::{self, BTree};
 use relly::buffer::{BufferPool, BufferPoolManager};
 use relly::disk::DiskManager;
 
@@ -9,7 +9,13 @@ fn main() -> Result<()> {
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
 
-    let btree = BTree::create(&mut bufmgr)?;
+    let btree = match BTree::create_first(&mut bufmgr, false) {
+        Err(btree::Error::FileNotEmpty) => {
+            println!("test.btr already has its tree");
+            return Ok(());
+        }
+        result => result?,
+    };
 
     btree.insert(&mut bufmgr, b"Kanagawa", b"Yokohama")?;
     btree.insert(&mut bufmgr, b"Osaka", b"Osaka")?;
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use md5::{Digest, Md5};
 
-use relly::btree::BTree;
+use relly::btree::{self, BTree};
 use relly::buffer::{BufferPool, BufferPoolManager};
 use relly::disk::DiskManager;
 
@@ -12,7 +12,13 @@ fn main() -> Result<()> {
     let pool = BufferPool::new(100);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
 
-    let btree = BTree::create(&mut bufmgr)?;
+    let btree = match BTree::create_first(&mut bufmgr, false) {
+        Err(btree::Error::FileNotEmpty) => {
+            println!("large.btr already has its tree");
+            return Ok(());
+        }
+        result => result?,
+    };
     for i in 1u32..=NUM_PAIRS {
         let pkey = i.to_be_bytes();
         let md5 = Md5::digest(&pkey);
@@ -0,0 +1,31 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+use anyhow::Result;
+
+/// Sends the lines of stdin to a `relly serve` server and prints the
+/// responses, one JSON line each.
+fn main() -> Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:5433".to_string());
+    let stream = TcpStream::connect(&addr)?;
+
+    let reader = BufReader::new(stream.try_clone()?);
+    let responses = thread::spawn(move || -> io::Result<()> {
+        for line in reader.lines() {
+            println!("{}", line?);
+        }
+        Ok(())
+    });
+
+    let mut writer = &stream;
+    for line in io::stdin().lock().lines() {
+        writeln!(writer, "{}", line?)?;
+    }
+    stream.shutdown(Shutdown::Write)?;
+    responses.join().unwrap()?;
+    Ok(())
+}
@@ -1,19 +1,23 @@
 use anyhow::Result;
 
 use relly::buffer::{BufferPool, BufferPoolManager};
-use relly::disk::{DiskManager, PageId};
-use relly::table::SimpleTable;
+use relly::catalog;
+use relly::disk::DiskManager;
+use relly::table::Table;
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.rly")?;
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
 
-    let mut table = SimpleTable {
-        meta_page_id: PageId(0),
-        num_key_elems: 1,
+    let mut table = Table::new("simple", 1, 3);
+    match table.create(&mut bufmgr) {
+        Err(relly::Error::Catalog(catalog::Error::TableExists(_))) => {
+            println!("simple.rly already has the table {:?}", table.name);
+            return Ok(());
+        }
+        result => result?,
     };
-    table.create(&mut bufmgr)?;
     dbg!(&table);
     table.insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])?;
     table.insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])?;
@@ -1,22 +1,33 @@
+use std::cmp::Ordering;
+
 use anyhow::Result;
 
 use relly::buffer::{BufferPool, BufferPoolManager};
-use relly::disk::{DiskManager, PageId};
-use relly::query::{Filter, PlanNode, SeqScan, TupleSearchMode};
+use relly::catalog;
+use relly::disk::DiskManager;
+use relly::query::{Filter, KeyBound, PlanNode, ScanDirection, SeqScan, TupleSearchMode};
 use relly::tuple;
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.rly")?;
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let table = catalog::open_table(&mut bufmgr, "simple")?;
 
     let plan = Filter {
-        cond: &|record| record[1].as_slice() < b"Dave",
-        inner_plan: &SeqScan {
-            table_meta_page_id: PageId(0),
-            search_mode: TupleSearchMode::Key(&[b"w"]),
-            while_cond: &|pkey| pkey[0].as_slice() < b"z",
-        },
+        cond: Box::new(|record| Ok(record[1].as_deref() < Some(b"Dave"))),
+        inner_plan: Box::new(SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Key(vec![Some(b"w".to_vec())]),
+            direction: ScanDirection::Forward,
+            while_bound: Some(KeyBound {
+                key: vec![Some(b"z".to_vec())],
+                accept: Ordering::is_lt,
+            }),
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+            filter: None,
+        }),
     };
     let mut exec = plan.start(&mut bufmgr)?;
 
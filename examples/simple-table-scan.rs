@@ -2,21 +2,23 @@ use anyhow::Result;
 
 use relly::btree::{BTree, SearchMode};
 use relly::buffer::{BufferPool, BufferPoolManager};
-use relly::disk::{DiskManager, PageId};
+use relly::catalog;
+use relly::disk::DiskManager;
 use relly::tuple;
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.rly")?;
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let table = catalog::open_table(&mut bufmgr, "simple")?;
 
-    let btree = BTree::new(PageId(0));
+    let btree = BTree::new(table.meta_page_id);
     let mut iter = btree.search(&mut bufmgr, SearchMode::Start)?;
 
     while let Some((key, value)) = iter.next(&mut bufmgr)? {
         let mut record = vec![];
-        tuple::decode(&key, &mut record);
-        tuple::decode(&value, &mut record);
+        tuple::decode(&key, &mut record)?;
+        tuple::decode(&value, &mut record)?;
         if record[2] == b"Smith" {
             println!("{:?}", tuple::Pretty(&record));
         }
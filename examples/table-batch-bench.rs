@@ -0,0 +1,60 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use md5::Md5;
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::disk::{DiskManager, PageId};
+use relly::table::{Table, UniqueIndex};
+use sha1::{Digest, Sha1};
+
+const NUM_ROWS: u32 = 1_000_000;
+
+fn create_table(bufmgr: &mut BufferPoolManager) -> Result<Table> {
+    let mut table = Table {
+        unique_indices: vec![UniqueIndex {
+            name: None,
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            skey: vec![2],
+            directions: vec![],
+            include: vec![],
+        }],
+        ..Table::new("people", 1, 3)
+    };
+    table.create(bufmgr)?;
+    Ok(table)
+}
+
+fn records() -> impl Iterator<Item = Vec<Vec<u8>>> {
+    (0u32..NUM_ROWS).map(|i| {
+        let pkey = Md5::digest(&i.to_be_bytes());
+        let sha1 = Sha1::digest(&i.to_be_bytes());
+        vec![pkey.to_vec(), i.to_be_bytes().to_vec(), sha1.to_vec()]
+    })
+}
+
+fn new_bufmgr() -> Result<BufferPoolManager> {
+    let disk = DiskManager::new(tempfile::tempfile()?)?;
+    let pool = BufferPool::new(1_000);
+    Ok(BufferPoolManager::new(disk, pool))
+}
+
+// Inserts the same rows, keyed by md5(id) so they arrive in random key
+// order, once with Table::insert and once with Table::insert_batch.
+fn main() -> Result<()> {
+    let mut bufmgr = new_bufmgr()?;
+    let table = create_table(&mut bufmgr)?;
+    let start = Instant::now();
+    for record in records() {
+        let record: Vec<_> = record.iter().map(Vec::as_slice).collect();
+        table.insert(&mut bufmgr, &record)?;
+    }
+    println!("insert:       {:?}", start.elapsed());
+
+    let mut bufmgr = new_bufmgr()?;
+    let table = create_table(&mut bufmgr)?;
+    let start = Instant::now();
+    let inserted = table.insert_batch(&mut bufmgr, records())?;
+    println!("insert_batch: {:?}", start.elapsed());
+    assert_eq!(NUM_ROWS as usize, inserted);
+    Ok(())
+}
@@ -1,8 +1,9 @@
 use anyhow::Result;
 
 use relly::buffer::{BufferPool, BufferPoolManager};
-use relly::disk::{DiskManager, PageId};
-use relly::query::{IndexScan, PlanNode, TupleSearchMode};
+use relly::catalog;
+use relly::disk::DiskManager;
+use relly::query::{IndexScan, PlanNode, ScanDirection, TupleSearchMode};
 use relly::tuple;
 
 // SELECT * WHERE last_name = 'Smith'
@@ -12,11 +13,17 @@ fn main() -> Result<()> {
     let pool = BufferPool::new(10);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
 
+    let table = catalog::open_table(&mut bufmgr, "people")?;
     let plan = IndexScan {
-        table_meta_page_id: PageId(0),
-        index_meta_page_id: PageId(2),
-        search_mode: TupleSearchMode::Key(&[b"Smith"]),
-        while_cond: &|skey| skey[0].as_slice() == b"Smith",
+        table_meta_page_id: table.meta_page_id,
+        index_meta_page_id: table.unique_indices[0].meta_page_id,
+        directions: table.unique_indices[0].directions.clone(),
+        num_included: table.unique_indices[0].include.len(),
+        search_mode: TupleSearchMode::Key(vec![Some(b"Smith".to_vec())]),
+        direction: ScanDirection::Forward,
+        end: None,
+        while_cond: Box::new(|skey| Ok(skey[0].as_deref() == Some(b"Smith"))),
+        expiry: None,
     };
     let mut exec = plan.start(&mut bufmgr)?;
 
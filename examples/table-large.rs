@@ -1,6 +1,7 @@
 use anyhow::Result;
 use md5::Md5;
 use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog;
 use relly::disk::{DiskManager, PageId};
 use relly::table::{Table, UniqueIndex};
 use sha1::{Digest, Sha1};
@@ -22,14 +23,22 @@ fn main() -> Result<()> {
     let pool = BufferPool::new(1_000_000);
     let mut bufmgr = BufferPoolManager::new(disk, pool);
     let mut table = Table {
-        meta_page_id: PageId(0),
-        num_key_elems: 1,
         unique_indices: vec![UniqueIndex {
+            name: None,
             meta_page_id: PageId::INVALID_PAGE_ID,
             skey: vec![2],
+            directions: vec![],
+            include: vec![1],
         }],
+        ..Table::new("people", 1, 3)
+    };
+    match table.create(&mut bufmgr) {
+        Err(relly::Error::Catalog(catalog::Error::TableExists(_))) => {
+            println!("table.rly already has the table {:?}", table.name);
+            return Ok(());
+        }
+        result => result?,
     };
-    table.create(&mut bufmgr)?;
     dbg!(&table);
     table.insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])?;
     table.insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])?;
@@ -0,0 +1,123 @@
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::time::Instant;
+
+use anyhow::Result;
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog;
+use relly::disk::DiskManager;
+use relly::query::{
+    CountScan, Filter, KeyBound, PlanNode, ScanDirection, SeqScan, TupleSearchMode,
+};
+use relly::tuple;
+
+fn count(bufmgr: &mut BufferPoolManager, plan: &dyn PlanNode) -> Result<usize> {
+    let mut exec = plan.start(bufmgr)?;
+    let mut rows = 0;
+    while exec.next(bufmgr)?.is_some() {
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+fn count_batches(bufmgr: &mut BufferPoolManager, plan: &dyn PlanNode) -> Result<usize> {
+    let mut exec = plan.start(bufmgr)?;
+    let mut rows = 0;
+    loop {
+        let batch = exec.next_batch(bufmgr, 1024)?;
+        if batch.is_empty() {
+            return Ok(rows);
+        }
+        rows += batch.len();
+    }
+}
+
+// SELECT * WHERE md5 < x'01', over the table made by table-large, once as
+// a Filter on top of a SeqScan and once with the predicate in the SeqScan.
+// Then SELECT COUNT(*) WHERE id < 5000000, once by counting the rows of a
+// SeqScan and once with a CountScan. Last, a full scan read one row at a
+// time and in batches.
+fn main() -> Result<()> {
+    let disk = DiskManager::open("table.rly")?;
+    let pool = BufferPool::new(1_000_000);
+    let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let table = catalog::open_table(&mut bufmgr, "people")?;
+
+    let filter = Filter {
+        inner_plan: Box::new(SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            while_bound: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+            filter: None,
+        }),
+        cond: Box::new(|record| Ok(record[1].as_deref() < Some(&[0x01][..]))),
+    };
+    let start = Instant::now();
+    let rows = count(&mut bufmgr, &filter)?;
+    println!("Filter:          {} rows in {:?}", rows, start.elapsed());
+
+    let scan = SeqScan {
+        table_meta_page_id: table.meta_page_id,
+        search_mode: TupleSearchMode::Start,
+        direction: ScanDirection::Forward,
+        while_bound: None,
+        while_cond: Box::new(|_| Ok(true)),
+        expiry: None,
+        filter: Some(Box::new(|record| {
+            Ok(record[1].as_deref() < Some(&[0x01][..]))
+        })),
+    };
+    let start = Instant::now();
+    let rows = count(&mut bufmgr, &scan)?;
+    println!("SeqScan filter: {} rows in {:?}", rows, start.elapsed());
+
+    let bound = || KeyBound {
+        key: vec![Some(5_000_000u32.to_be_bytes().to_vec())],
+        accept: Ordering::is_lt,
+    };
+    let scan = SeqScan {
+        table_meta_page_id: table.meta_page_id,
+        search_mode: TupleSearchMode::Start,
+        direction: ScanDirection::Forward,
+        while_bound: Some(bound()),
+        while_cond: Box::new(|_| Ok(true)),
+        expiry: None,
+        filter: None,
+    };
+    let start = Instant::now();
+    let rows = count(&mut bufmgr, &scan)?;
+    println!("SeqScan count:  {} rows in {:?}", rows, start.elapsed());
+
+    let count_scan = CountScan {
+        table_meta_page_id: table.meta_page_id,
+        search_mode: TupleSearchMode::Start,
+        while_bound: Some(bound()),
+        while_cond: None,
+        expiry: None,
+    };
+    let start = Instant::now();
+    let row = count_scan.start(&mut bufmgr)?.next(&mut bufmgr)?.unwrap();
+    let rows = tuple::decode_u64(row[0].as_deref().unwrap().try_into()?);
+    println!("CountScan:      {} rows in {:?}", rows, start.elapsed());
+
+    let full_scan = SeqScan {
+        table_meta_page_id: table.meta_page_id,
+        search_mode: TupleSearchMode::Start,
+        direction: ScanDirection::Forward,
+        while_bound: None,
+        while_cond: Box::new(|_| Ok(true)),
+        expiry: None,
+        filter: None,
+    };
+    let start = Instant::now();
+    let rows = count(&mut bufmgr, &full_scan)?;
+    println!("SeqScan rows:   {} rows in {:?}", rows, start.elapsed());
+    let start = Instant::now();
+    let rows = count_batches(&mut bufmgr, &full_scan)?;
+    println!("SeqScan batch:  {} rows in {:?}", rows, start.elapsed());
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::iter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use relly::tuple;
+
+struct CountingAlloc;
+
+static NUM_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        NUM_ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        NUM_ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+const NUM_ROWS: usize = 100_000;
+
+fn bench(name: &str, row: &[Vec<u8>], encode: impl Fn(&[Vec<u8>], &mut Vec<u8>)) {
+    let allocs_before = NUM_ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..NUM_ROWS {
+        let mut bytes = vec![];
+        encode(row, &mut bytes);
+        assert_eq!(tuple::encoded_len(row.iter().map(Vec::len)), bytes.len());
+    }
+    let allocs = NUM_ALLOCS.load(Ordering::Relaxed) - allocs_before;
+    println!(
+        "{}: {:?}, {:.1} allocations per row",
+        name,
+        start.elapsed(),
+        allocs as f64 / NUM_ROWS as f64
+    );
+}
+
+// Encodes a 10-column row at once, which sizes the buffer up front, and
+// one element at a time, which grows it as it goes.
+fn main() {
+    let row: Vec<Vec<u8>> = (0..10).map(|i| vec![b'a' + i as u8; 4 + 6 * i]).collect();
+    bench("encode", &row, |row, bytes| {
+        tuple::encode(row.iter(), bytes)
+    });
+    bench("per element", &row, |row, bytes| {
+        for elem in row {
+            tuple::encode(iter::once(elem), bytes);
+        }
+    });
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::disk::DiskManager;
+use relly::lang::{self, Request};
+
+fuzz_target!(|requests: Vec<Request>| {
+    let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(32));
+    for request in &requests {
+        // errors are fine; panics and broken btrees are not
+        let _ = lang::run_script(&mut bufmgr, std::slice::from_ref(request));
+        if request.is_mutating() {
+            // reading the btrees fails once a transaction has dirtied the
+            // whole pool
+            if let Ok(problems) = lang::verify_tables(&mut bufmgr) {
+                assert!(problems.is_empty(), "{:?}: {:?}", request, problems);
+            }
+        }
+    }
+});
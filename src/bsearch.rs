@@ -1,4 +1,4 @@
-use std::cmp::Ordering::{self, Greater, Less};
+use std::cmp::Ordering::{self, Equal, Greater, Less};
 
 #[rustfmt::skip]
 /*
@@ -50,13 +50,31 @@ where
     Err(left)
 }
 
+/// Like `binary_search_by`, but stops at the first error `f` returns.
+pub fn try_binary_search_by<F, E>(size: usize, mut f: F) -> Result<Result<usize, usize>, E>
+where
+    F: FnMut(usize) -> Result<Ordering, E>,
+{
+    let mut error = None;
+    let found = binary_search_by(size, |idx| {
+        f(idx).unwrap_or_else(|err| {
+            error = Some(err);
+            Equal
+        })
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(found),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::binary_search_by;
+    use super::{binary_search_by, try_binary_search_by};
 
     #[test]
     fn test() {
-        let a = vec![1, 2, 3, 5, 8, 13, 21];
+        let a = [1, 2, 3, 5, 8, 13, 21];
         assert_eq!(Ok(0), binary_search_by(a.len(), |idx| a[idx].cmp(&1)));
         assert_eq!(Err(0), binary_search_by(a.len(), |idx| a[idx].cmp(&0)));
         assert_eq!(Ok(1), binary_search_by(a.len(), |idx| a[idx].cmp(&2)));
@@ -65,4 +83,14 @@ mod tests {
         assert_eq!(Ok(6), binary_search_by(a.len(), |idx| a[idx].cmp(&21)));
         assert_eq!(Err(7), binary_search_by(a.len(), |idx| a[idx].cmp(&22)));
     }
+
+    #[test]
+    fn test_try() {
+        let a = [Ok(1), Ok(2), Ok(3), Err("five"), Ok(8)];
+        let search = |x| try_binary_search_by(a.len(), |idx| a[idx].map(|n: i32| n.cmp(&x)));
+        assert_eq!(Ok(Ok(0)), search(1));
+        assert_eq!(Ok(Ok(4)), search(8));
+        assert_eq!(Ok(Err(5)), search(13));
+        assert_eq!(Err("five"), search(5));
+    }
 }
@@ -1,5 +1,5 @@
-use std::cell::{Ref, RefMut};
 use std::convert::identity;
+use std::mem::size_of;
 use std::rc::Rc;
 
 use bincode::Options;
@@ -7,8 +7,9 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use zerocopy::{AsBytes, ByteSlice};
 
-use crate::buffer::{self, Buffer, BufferPoolManager};
-use crate::disk::PageId;
+use crate::buffer::{self, Buffer, BufferPoolManager, LatchMode, PageGuard};
+use crate::disk::{InvalidPageId, PageId, PAGE_SIZE};
+use crate::slotted;
 
 mod branch;
 mod leaf;
@@ -26,99 +27,497 @@ impl<'a> Pair<'a> {
         bincode::options().serialize(self).unwrap()
     }
 
-    fn from_bytes(bytes: &'a [u8]) -> Self {
-        bincode::options().deserialize(bytes).unwrap()
+    fn from_bytes(bytes: &'a [u8]) -> Result<Self, bincode::Error> {
+        bincode::options().deserialize(bytes)
     }
 }
 
+/// Checks that every slot of a node read from disk holds a pair no larger
+/// than `max_pair_size` and that the keys ascend, and returns the pairs.
+fn check_pairs<B: ByteSlice>(
+    body: &slotted::Slotted<B>,
+    max_pair_size: usize,
+) -> Result<Vec<Pair<'_>>, &'static str> {
+    body.check()?;
+    let mut pairs: Vec<Pair> = Vec::with_capacity(body.num_slots());
+    for slot_id in 0..body.num_slots() {
+        let bytes = &body[slot_id];
+        if bytes.len() > max_pair_size {
+            return Err("a pair is too large");
+        }
+        let pair = Pair::from_bytes(bytes).map_err(|_| "a pair does not decode")?;
+        if matches!(pairs.last(), Some(prev) if prev.key >= pair.key) {
+            return Err("keys are out of order");
+        }
+        pairs.push(pair);
+    }
+    Ok(pairs)
+}
+
+/// The size of a serialized `Pair`, whose lengths are varints.
+fn pair_size(key_len: usize, value_len: usize) -> usize {
+    fn varint_len(n: usize) -> usize {
+        match n {
+            0..=250 => 1,
+            251..=0xffff => 3,
+            0x1_0000..=0xffff_ffff => 5,
+            _ => 9,
+        }
+    }
+    varint_len(key_len) + key_len + varint_len(value_len) + value_len
+}
+
+/// The `max_pair_size` of a node whose own header takes `header_size` bytes.
+fn max_pair_size(header_size: usize) -> usize {
+    let capacity =
+        PAGE_SIZE - size_of::<node::Header>() - header_size - size_of::<slotted::Header>();
+    capacity / 2 - size_of::<slotted::Pointer>()
+}
+
+/// No key or value stored in a btree is longer than this, so neither is
+/// anything decoded from one.
+pub fn max_entry_size() -> usize {
+    max_pair_size(size_of::<leaf::Header>())
+}
+
+/// Whether `insert` accepts a key and value of these lengths. The key must
+/// also fit in a branch node next to a child page id.
+pub fn fits(key_len: usize, value_len: usize) -> bool {
+    pair_size(key_len, value_len) <= max_pair_size(size_of::<leaf::Header>())
+        && pair_size(key_len, size_of::<PageId>()) <= max_pair_size(size_of::<branch::Header>())
+}
+
+fn check_fits(key: &[u8], value: &[u8]) -> Result<(), Error> {
+    if !fits(key.len(), value.len()) {
+        return Err(Error::PairTooLarge {
+            key_len: key.len(),
+            value_len: value.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Deeper than a tree of 2-way branches over every page id could be; a
+/// walk that gets here has met a cycle in the child pointers.
+const MAX_DEPTH: usize = 64;
+
+/// The smallest buffer pool a tree is written through. An insert pins the
+/// path from the root to its leaf and, to split the leaf, `SPLIT_PAGES`
+/// more; an `Iter` pins the meta page and a leaf, and a third page as it
+/// steps. That is enough to insert into a tree four levels deep while an
+/// iterator over another tree is open, as building an index does.
+pub const MIN_POOL_SIZE: usize = 8;
+
+/// The pages a leaf split brings in next to the pinned path: the leaf
+/// before it and the new leaf. The splits further up bring in one page
+/// each, with fewer pinned.
+const SPLIT_PAGES: usize = 2;
+
+/// Fails unless the pool has `MIN_POOL_SIZE` frames.
+fn check_pool_size(bufmgr: &BufferPoolManager) -> Result<(), Error> {
+    if bufmgr.pool_size() < MIN_POOL_SIZE {
+        return Err(Error::PoolTooSmall {
+            pool_size: bufmgr.pool_size(),
+            required: MIN_POOL_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Fails unless `num_pages` more pages can be brought into the pool next to
+/// the pinned ones. Called before a split changes anything, since running
+/// out of frames halfway through would leave the tree inconsistent.
+fn reserve_frames(bufmgr: &BufferPoolManager, num_pages: usize) -> Result<(), Error> {
+    let num_evictable = bufmgr.num_evictable_frames(num_pages);
+    if num_evictable >= num_pages {
+        return Ok(());
+    }
+    if bufmgr.in_transaction() {
+        return Err(buffer::Error::TransactionTooLarge.into());
+    }
+    Err(Error::PoolTooSmall {
+        pool_size: bufmgr.pool_size(),
+        required: bufmgr.pool_size() - num_evictable + num_pages,
+    })
+}
+
+/// Fetches a node, checking its layout the first time it is read from disk
+/// so that a corrupted page is an error rather than a panic further on.
+fn fetch_node(bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+    let buffer = bufmgr.fetch_page(page_id)?;
+    check_node(&buffer)?;
+    Ok(buffer)
+}
+
+fn check_node(buffer: &Buffer) -> Result<(), Error> {
+    if !buffer.is_checked.get() {
+        node::Node::new(buffer.bytes())
+            .check()
+            .map_err(|reason| Error::Corrupted {
+                page_id: buffer.page_id,
+                reason,
+            })?;
+        buffer.is_checked.set(true);
+    }
+    Ok(())
+}
+
+/// Like `fetch_node`, latching the node in `mode`.
+fn latch_node(
+    bufmgr: &mut BufferPoolManager,
+    page_id: PageId,
+    mode: LatchMode,
+) -> Result<PageGuard, Error> {
+    let guard = bufmgr.fetch_page_latched(page_id, mode)?;
+    check_node(&guard)?;
+    Ok(guard)
+}
+
+/// Like `fetch_child`, latching the child in `mode` while `parent` is still
+/// latched. The parent must be the page latched last, so that the latches
+/// of a descent are taken from the root down one path.
+fn latch_child(
+    bufmgr: &mut BufferPoolManager,
+    parent: &PageGuard,
+    page_id: PageId,
+    depth: usize,
+    mode: LatchMode,
+) -> Result<PageGuard, Error> {
+    debug_assert_eq!(
+        Some(&parent.page_id),
+        bufmgr.held_latches().last(),
+        "a child must be latched right after its parent"
+    );
+    if depth > MAX_DEPTH {
+        return Err(Error::Corrupted {
+            page_id,
+            reason: "the tree is too deep",
+        });
+    }
+    latch_node(bufmgr, page_id, mode)
+}
+
+fn is_leaf(buffer: &Buffer) -> bool {
+    node::Node::new(buffer.bytes()).header.node_type == node::NODE_TYPE_LEAF
+}
+
+/// Like `fetch_node`, for a node at `depth` below the root.
+fn fetch_child(
+    bufmgr: &mut BufferPoolManager,
+    page_id: PageId,
+    depth: usize,
+) -> Result<Rc<Buffer>, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Corrupted {
+            page_id,
+            reason: "the tree is too deep",
+        });
+    }
+    fetch_node(bufmgr, page_id)
+}
+
+/// Like `fetch_node`, for a page linked to as a leaf.
+fn fetch_leaf(bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+    let buffer = fetch_node(bufmgr, page_id)?;
+    if !is_leaf(&buffer) {
+        return Err(Error::Corrupted {
+            page_id,
+            reason: "a leaf links to a branch",
+        });
+    }
+    Ok(buffer)
+}
+
+/// Like `fetch_leaf`, latching the leaf exclusively.
+fn latch_leaf(bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<PageGuard, Error> {
+    let guard = latch_node(bufmgr, page_id, LatchMode::Exclusive)?;
+    if !is_leaf(&guard) {
+        return Err(Error::Corrupted {
+            page_id,
+            reason: "a leaf links to a branch",
+        });
+    }
+    Ok(guard)
+}
+
+/// The header of a meta page, from whichever copy of it is intact.
+fn meta_header(meta_buffer: &Buffer) -> Result<meta::Header, Error> {
+    meta::Meta::new(meta_buffer.bytes())
+        .header()
+        .map_err(|reason| Error::Corrupted {
+            page_id: meta_buffer.page_id,
+            reason,
+        })
+}
+
+/// The root is fetched like any node; this only keeps a root page id of a
+/// corrupted meta page from sending the reader off the end of the file.
+fn check_root(
+    bufmgr: &BufferPoolManager,
+    meta_page_id: PageId,
+    root_page_id: PageId,
+) -> Result<(), Error> {
+    if root_page_id == meta_page_id
+        || root_page_id.valid().is_none()
+        || root_page_id.to_u64() >= bufmgr.num_pages()
+    {
+        return Err(Error::Corrupted {
+            page_id: meta_page_id,
+            reason: "the root page id is out of range",
+        });
+    }
+    Ok(())
+}
+
+/// The slot of `key` in a leaf, looked up without marking the leaf dirty.
+fn find_slot(leaf_buffer: &Buffer, key: &[u8]) -> Result<usize, Error> {
+    let node = node::Node::new(leaf_buffer.bytes());
+    let slot_id = leaf::Leaf::new(node.body)
+        .search_slot_id(key)
+        .map_err(Error::invalid_slot(leaf_buffer.page_id))?
+        .or(Err(Error::KeyNotFound))?;
+    Ok(slot_id)
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("duplicate key")]
     DuplicateKey,
+    #[error("key of {key_len} bytes and value of {value_len} bytes do not fit in a page")]
+    PairTooLarge { key_len: usize, value_len: usize },
+    #[error("key not found")]
+    KeyNotFound,
+    #[error("the heap file already has pages")]
+    FileNotEmpty,
+    #[error("a buffer pool of {pool_size} frames is too small, this needs at least {required}")]
+    PoolTooSmall { pool_size: usize, required: usize },
+    #[error("page {} is corrupted: {reason}", .page_id.to_u64())]
+    Corrupted {
+        page_id: PageId,
+        reason: &'static str,
+    },
+    #[error("page {}: {source}", .page_id.to_u64())]
+    InvalidSlot { page_id: PageId, source: SlotError },
+    #[error("compact cannot run in a transaction, which holds back the flushes it relies on")]
+    InTransaction,
     #[error(transparent)]
     Buffer(#[from] buffer::Error),
 }
 
+impl Error {
+    fn invalid_slot(page_id: PageId) -> impl FnOnce(SlotError) -> Self {
+        move |source| Error::InvalidSlot { page_id, source }
+    }
+}
+
+/// A slot that does not hold what the leaf and branch accessors expect.
+/// `Error::InvalidSlot` adds the page it is in.
+#[derive(Debug, Error)]
+pub enum SlotError {
+    #[error("slot {slot_id} does not hold a pair")]
+    InvalidPair {
+        slot_id: usize,
+        source: bincode::Error,
+    },
+    #[error("slot {slot_id} does not hold a child: {source}")]
+    InvalidChild {
+        slot_id: usize,
+        source: InvalidPageId,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub enum SearchMode {
     Start,
     Key(Vec<u8>),
 }
 
+/// Which way an `Iter` walks the leaves. `Backward` starts from the last
+/// key not greater than the search key, or from the last key for
+/// `SearchMode::Start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScanDirection {
+    #[default]
+    Forward,
+    Backward,
+}
+
 impl SearchMode {
-    fn child_page_id(&self, branch: &branch::Branch<impl ByteSlice>) -> PageId {
-        match self {
-            SearchMode::Start => branch.child_at(0),
-            SearchMode::Key(key) => branch.search_child(key),
+    fn child_page_id(
+        &self,
+        branch: &branch::Branch<impl ByteSlice>,
+        direction: ScanDirection,
+    ) -> Result<PageId, SlotError> {
+        match (self, direction) {
+            (SearchMode::Start, ScanDirection::Forward) => branch.child_at(0),
+            (SearchMode::Start, ScanDirection::Backward) => branch.child_at(branch.num_pairs()),
+            (SearchMode::Key(key), _) => branch.search_child(key),
         }
     }
 
-    fn tuple_slot_id(&self, leaf: &leaf::Leaf<impl ByteSlice>) -> Result<usize, usize> {
+    fn tuple_slot_id(
+        &self,
+        leaf: &leaf::Leaf<impl ByteSlice>,
+    ) -> Result<Result<usize, usize>, SlotError> {
         match self {
-            SearchMode::Start => Err(0),
+            SearchMode::Start => Ok(Err(0)),
             SearchMode::Key(key) => leaf.search_slot_id(key),
         }
     }
+
+    /// The slot after the first one a backward scan visits in `leaf`.
+    fn end_slot_id(&self, leaf: &leaf::Leaf<impl ByteSlice>) -> Result<usize, SlotError> {
+        match self {
+            SearchMode::Start => Ok(leaf.num_pairs()),
+            SearchMode::Key(key) => match leaf.search_slot_id(key)? {
+                Ok(slot_id) => Ok(slot_id + 1),
+                Err(slot_id) => Ok(slot_id),
+            },
+        }
+    }
 }
 
 pub struct BTree {
     pub meta_page_id: PageId,
 }
 
+/// The meta page of a tree made by `BTree::create_first`.
+pub const FIRST_META_PAGE_ID: PageId = PageId(0);
+
+/// What `BTree::verify` found; `problems` is empty for a sound tree.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// Including the meta page.
+    pub num_pages: u64,
+    /// As counted in the leaves, which the meta page should agree with.
+    pub num_entries: u64,
+    /// The number of levels of nodes.
+    pub depth: usize,
+    pub problems: Vec<String>,
+}
+
+/// The leaves in key order and the depth they are at, as `verify_node`
+/// walks down to them.
+#[derive(Default)]
+struct Walk {
+    leaves: Vec<(PageId, Option<PageId>, Option<PageId>)>,
+    leaf_depth: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    pub num_pages_before: u64,
+    pub num_pages_after: u64,
+    pub num_entries: u64,
+}
+
 impl BTree {
     pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        Self::create_with_key_format(bufmgr, 0)
+    }
+
+    /// Like `create`, but records `key_format` in the meta page for
+    /// readers that only know the tree by its meta page id.
+    pub fn create_with_key_format(
+        bufmgr: &mut BufferPoolManager,
+        key_format: u64,
+    ) -> Result<Self, Error> {
+        check_pool_size(bufmgr)?;
         let meta_buffer = bufmgr.create_page()?;
-        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
         let root_buffer = bufmgr.create_page()?;
-        let mut root = node::Node::new(root_buffer.page.borrow_mut() as RefMut<[_]>);
+        let mut root = node::Node::new(root_buffer.bytes_mut());
         root.initialize_as_leaf();
         let mut leaf = leaf::Leaf::new(root.body);
         leaf.initialize();
-        meta.header.root_page_id = root_buffer.page_id;
+        meta::Meta::new(meta_buffer.bytes_mut()).initialize(&meta::Header {
+            root_page_id: root_buffer.page_id,
+            num_entries: 0,
+            key_format,
+            version: 0,
+            key_arity: 0,
+        });
         Ok(Self::new(meta_buffer.page_id))
     }
 
+    /// Creates the only tree of a heap file, at `FIRST_META_PAGE_ID`. A file
+    /// with pages already has its tree, so it is refused with
+    /// `Error::FileNotEmpty`; `force` creates another tree after those
+    /// pages anyway.
+    pub fn create_first(bufmgr: &mut BufferPoolManager, force: bool) -> Result<Self, Error> {
+        if !force && bufmgr.num_pages() != 0 {
+            return Err(Error::FileNotEmpty);
+        }
+        Self::create(bufmgr)
+    }
+
     pub fn new(meta_page_id: PageId) -> Self {
         Self { meta_page_id }
     }
 
+    /// The header of the meta page, with its root page id checked against
+    /// the heap file.
+    fn read_meta(&self, bufmgr: &mut BufferPoolManager) -> Result<meta::Header, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let header = meta_header(&meta_buffer)?;
+        check_root(bufmgr, self.meta_page_id, header.root_page_id)?;
+        Ok(header)
+    }
+
+    fn write_meta(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        f: impl FnOnce(&mut meta::Header),
+    ) -> Result<(), Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut header = meta_header(&meta_buffer)?;
+        f(&mut header);
+        meta::Meta::new(meta_buffer.bytes_mut()).set_header(&header);
+        Ok(())
+    }
+
+    #[cfg(test)]
     fn fetch_root_page(&self, bufmgr: &mut BufferPoolManager) -> Result<Rc<Buffer>, Error> {
-        let root_page_id = {
-            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
-            let meta = meta::Meta::new(meta_buffer.page.borrow() as Ref<[_]>);
-            meta.header.root_page_id
-        };
-        Ok(bufmgr.fetch_page(root_page_id)?)
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        fetch_node(bufmgr, root_page_id)
     }
 
+    /// The leaf and slot an `Iter` for `search_mode` starts from. Descends
+    /// with shared latches, each released once the child is latched.
     fn search_internal(
         &self,
         bufmgr: &mut BufferPoolManager,
-        node_buffer: Rc<Buffer>,
-        search_mode: SearchMode,
-    ) -> Result<Iter, Error> {
-        let node = node::Node::new(node_buffer.page.borrow() as Ref<[_]>);
+        node_buffer: PageGuard,
+        search_mode: &SearchMode,
+        direction: ScanDirection,
+        depth: usize,
+    ) -> Result<(Rc<Buffer>, usize), Error> {
+        let node = node::Node::new(node_buffer.bytes());
         match node::Body::new(node.header.node_type, node.body.as_bytes()) {
             node::Body::Leaf(leaf) => {
-                let slot_id = search_mode.tuple_slot_id(&leaf).unwrap_or_else(identity);
-                let is_right_most = leaf.num_pairs() == slot_id;
-                drop(node);
-
-                let mut iter = Iter {
-                    buffer: node_buffer,
-                    slot_id,
-                };
-                if is_right_most {
-                    iter.advance(bufmgr)?;
+                let slot_id = match direction {
+                    ScanDirection::Forward => search_mode
+                        .tuple_slot_id(&leaf)
+                        .map(|slot_id| slot_id.unwrap_or_else(identity)),
+                    ScanDirection::Backward => search_mode.end_slot_id(&leaf),
                 }
-                Ok(iter)
+                .map_err(Error::invalid_slot(node_buffer.page_id))?;
+                drop(node);
+                Ok((Rc::clone(node_buffer.buffer()), slot_id))
             }
             node::Body::Branch(branch) => {
-                let child_page_id = search_mode.child_page_id(&branch);
+                let child_page_id = search_mode
+                    .child_page_id(&branch, direction)
+                    .map_err(Error::invalid_slot(node_buffer.page_id))?;
                 drop(node);
+                let child_node_page = latch_child(
+                    bufmgr,
+                    &node_buffer,
+                    child_page_id,
+                    depth + 1,
+                    LatchMode::Shared,
+                )?;
                 drop(node_buffer);
-                let child_node_page = bufmgr.fetch_page(child_page_id)?;
-                self.search_internal(bufmgr, child_node_page, search_mode)
+                self.search_internal(bufmgr, child_node_page, search_mode, direction, depth + 1)
             }
         }
     }
@@ -128,86 +527,199 @@ impl BTree {
         bufmgr: &mut BufferPoolManager,
         search_mode: SearchMode,
     ) -> Result<Iter, Error> {
-        let root_page = self.fetch_root_page(bufmgr)?;
-        self.search_internal(bufmgr, root_page, search_mode)
+        self.search_directed(bufmgr, search_mode, ScanDirection::Forward)
+    }
+
+    /// Like `search`, but the iterator walks the keys in `direction`.
+    pub fn search_directed(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        search_mode: SearchMode,
+        direction: ScanDirection,
+    ) -> Result<Iter, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut iter = Iter {
+            buffer: meta_buffer.clone(),
+            slot_id: 0,
+            direction,
+            meta_buffer,
+            version: 0,
+            search_mode,
+            last_key: None,
+        };
+        iter.seek(bufmgr)?;
+        Ok(iter)
+    }
+
+    fn find_leaf_internal(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        node_buffer: PageGuard,
+        key: &[u8],
+        depth: usize,
+        leaf_mode: LatchMode,
+    ) -> Result<(PageGuard, usize), Error> {
+        let node = node::Node::new(node_buffer.bytes());
+        let child_page_id = match node::Body::new(node.header.node_type, node.body.as_bytes()) {
+            node::Body::Leaf(_) => {
+                drop(node);
+                return Ok((node_buffer, depth));
+            }
+            node::Body::Branch(branch) => branch
+                .search_child(key)
+                .map_err(Error::invalid_slot(node_buffer.page_id))?,
+        };
+        drop(node);
+        let mut child_node_page = latch_child(
+            bufmgr,
+            &node_buffer,
+            child_page_id,
+            depth + 1,
+            LatchMode::Shared,
+        )?;
+        if leaf_mode == LatchMode::Exclusive && is_leaf(&child_node_page) {
+            // the parent is still latched, so the leaf cannot change
+            // between the two latches
+            drop(child_node_page);
+            child_node_page =
+                latch_child(bufmgr, &node_buffer, child_page_id, depth + 1, leaf_mode)?;
+        }
+        drop(node_buffer);
+        self.find_leaf_internal(bufmgr, child_node_page, key, depth + 1, leaf_mode)
+    }
+
+    /// The leaf `key` belongs in, latched in `leaf_mode`, and its depth
+    /// below the root. The branches above it are latched shared on the way
+    /// down and released.
+    fn find_leaf(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        leaf_mode: LatchMode,
+    ) -> Result<(PageGuard, usize), Error> {
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        let mut root_page = latch_node(bufmgr, root_page_id, LatchMode::Shared)?;
+        if leaf_mode == LatchMode::Exclusive && is_leaf(&root_page) {
+            drop(root_page);
+            root_page = latch_node(bufmgr, root_page_id, leaf_mode)?;
+        }
+        self.find_leaf_internal(bufmgr, root_page, key, 0, leaf_mode)
+    }
+
+    pub fn get(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let (leaf_buffer, _) = self.find_leaf(bufmgr, key, LatchMode::Shared)?;
+        let node = node::Node::new(leaf_buffer.bytes());
+        let leaf = leaf::Leaf::new(node.body);
+        let value = match leaf.search_slot_id(key) {
+            Ok(Ok(slot_id)) => leaf.pair_at(slot_id).map(|pair| Some(pair.value.to_vec())),
+            Ok(Err(_)) => Ok(None),
+            Err(err) => Err(err),
+        };
+        value.map_err(Error::invalid_slot(leaf_buffer.page_id))
     }
 
+    /// Inserts below the node `buffer` latches exclusively, latching each
+    /// node on the way down exclusively too and holding it until the child
+    /// below it has taken the entry or split.
     fn insert_internal(
         &self,
         bufmgr: &mut BufferPoolManager,
-        buffer: Rc<Buffer>,
+        buffer: PageGuard,
         key: &[u8],
         value: &[u8],
+        depth: usize,
     ) -> Result<Option<(Vec<u8>, PageId)>, Error> {
-        let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
-        match node::Body::new(node.header.node_type, node.body) {
-            node::Body::Leaf(mut leaf) => {
-                let slot_id = match leaf.search_slot_id(key) {
+        let node = node::Node::new(buffer.bytes());
+        match node::Body::new(node.header.node_type, node.body.as_bytes()) {
+            node::Body::Leaf(leaf) => {
+                let slot_id = match leaf
+                    .search_slot_id(key)
+                    .map_err(Error::invalid_slot(buffer.page_id))?
+                {
                     Ok(_) => return Err(Error::DuplicateKey),
                     Err(slot_id) => slot_id,
                 };
+                drop(node);
+                let node = node::Node::new(buffer.bytes_mut());
+                let mut leaf = leaf::Leaf::new(node.body);
                 if leaf.insert(slot_id, key, value).is_some() {
-                    buffer.is_dirty.set(true);
                     Ok(None)
                 } else {
+                    reserve_frames(bufmgr, SPLIT_PAGES)?;
                     let prev_leaf_page_id = leaf.prev_page_id();
+                    // the one latch not taken top-down; readers hold no
+                    // latch while moving between leaves, so none waits
+                    // for this leaf while holding the one before it
                     let prev_leaf_buffer = prev_leaf_page_id
-                        .map(|next_leaf_page_id| bufmgr.fetch_page(next_leaf_page_id))
+                        .map(|prev_leaf_page_id| latch_leaf(bufmgr, prev_leaf_page_id))
                         .transpose()?;
 
                     let new_leaf_buffer = bufmgr.create_page()?;
 
                     if let Some(prev_leaf_buffer) = prev_leaf_buffer {
-                        let node =
-                            node::Node::new(prev_leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+                        let node = node::Node::new(prev_leaf_buffer.bytes_mut());
                         let mut prev_leaf = leaf::Leaf::new(node.body);
                         prev_leaf.set_next_page_id(Some(new_leaf_buffer.page_id));
-                        prev_leaf_buffer.is_dirty.set(true);
                     }
                     leaf.set_prev_page_id(Some(new_leaf_buffer.page_id));
 
-                    let mut new_leaf_node =
-                        node::Node::new(new_leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+                    let mut new_leaf_node = node::Node::new(new_leaf_buffer.bytes_mut());
                     new_leaf_node.initialize_as_leaf();
                     let mut new_leaf = leaf::Leaf::new(new_leaf_node.body);
                     new_leaf.initialize();
-                    let overflow_key = leaf.split_insert(&mut new_leaf, key, value);
+                    let overflow_key = leaf
+                        .split_insert(&mut new_leaf, key, value)
+                        .map_err(Error::invalid_slot(buffer.page_id))?;
                     new_leaf.set_next_page_id(Some(buffer.page_id));
                     new_leaf.set_prev_page_id(prev_leaf_page_id);
-                    buffer.is_dirty.set(true);
                     Ok(Some((overflow_key, new_leaf_buffer.page_id)))
                 }
             }
-            node::Body::Branch(mut branch) => {
-                let child_idx = branch.search_child_idx(key);
-                let child_page_id = branch.child_at(child_idx);
-                let child_node_buffer = bufmgr.fetch_page(child_page_id)?;
-                if let Some((overflow_key_from_child, overflow_child_page_id)) =
-                    self.insert_internal(bufmgr, child_node_buffer, key, value)?
+            node::Body::Branch(branch) => {
+                let child_idx = branch
+                    .search_child_idx(key)
+                    .map_err(Error::invalid_slot(buffer.page_id))?;
+                let child_page_id = branch
+                    .child_at(child_idx)
+                    .map_err(Error::invalid_slot(buffer.page_id))?;
+                drop(node);
+                let child_node_buffer = latch_child(
+                    bufmgr,
+                    &buffer,
+                    child_page_id,
+                    depth + 1,
+                    LatchMode::Exclusive,
+                )?;
+                // the branch is only written to if the child splits
+                let (overflow_key_from_child, overflow_child_page_id) =
+                    match self.insert_internal(bufmgr, child_node_buffer, key, value, depth + 1)? {
+                        Some(overflow) => overflow,
+                        None => return Ok(None),
+                    };
+                let node = node::Node::new(buffer.bytes_mut());
+                let mut branch = branch::Branch::new(node.body);
+                if branch
+                    .insert(child_idx, &overflow_key_from_child, overflow_child_page_id)
+                    .is_some()
                 {
-                    if branch
-                        .insert(child_idx, &overflow_key_from_child, overflow_child_page_id)
-                        .is_some()
-                    {
-                        buffer.is_dirty.set(true);
-                        Ok(None)
-                    } else {
-                        let new_branch_buffer = bufmgr.create_page()?;
-                        let mut new_branch_node =
-                            node::Node::new(new_branch_buffer.page.borrow_mut() as RefMut<[_]>);
-                        new_branch_node.initialize_as_branch();
-                        let mut new_branch = branch::Branch::new(new_branch_node.body);
-                        let overflow_key = branch.split_insert(
+                    Ok(None)
+                } else {
+                    let new_branch_buffer = bufmgr.create_page()?;
+                    let mut new_branch_node = node::Node::new(new_branch_buffer.bytes_mut());
+                    new_branch_node.initialize_as_branch();
+                    let mut new_branch = branch::Branch::new(new_branch_node.body);
+                    let overflow_key = branch
+                        .split_insert(
                             &mut new_branch,
                             &overflow_key_from_child,
                             overflow_child_page_id,
-                        );
-                        buffer.is_dirty.set(true);
-                        new_branch_buffer.is_dirty.set(true);
-                        Ok(Some((overflow_key, new_branch_buffer.page_id)))
-                    }
-                } else {
-                    Ok(None)
+                        )
+                        .map_err(Error::invalid_slot(buffer.page_id))?;
+                    Ok(Some((overflow_key, new_branch_buffer.page_id)))
                 }
             }
         }
@@ -219,141 +731,970 @@ impl BTree {
         key: &[u8],
         value: &[u8],
     ) -> Result<(), Error> {
-        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
-        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
-        let root_page_id = meta.header.root_page_id;
-        let root_buffer = bufmgr.fetch_page(root_page_id)?;
-        if let Some((key, child_page_id)) = self.insert_internal(bufmgr, root_buffer, key, value)? {
-            let new_root_buffer = bufmgr.create_page()?;
-            let mut node = node::Node::new(new_root_buffer.page.borrow_mut() as RefMut<[_]>);
-            node.initialize_as_branch();
-            let mut branch = branch::Branch::new(node.body);
-            branch.initialize(&key, child_page_id, root_page_id);
-            meta.header.root_page_id = new_root_buffer.page_id;
-            meta_buffer.is_dirty.set(true);
-        }
-        Ok(())
+        self.insert_entry(bufmgr, key, value, 1)
     }
-}
-
-pub struct Iter {
-    buffer: Rc<Buffer>,
-    slot_id: usize,
-}
 
-impl Iter {
-    fn get(&self) -> Option<(Vec<u8>, Vec<u8>)> {
-        let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
-        let leaf = leaf::Leaf::new(leaf_node.body);
-        if self.slot_id < leaf.num_pairs() {
-            let pair = leaf.pair_at(self.slot_id);
-            Some((pair.key.to_vec(), pair.value.to_vec()))
-        } else {
-            None
-        }
+    fn insert_entry(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+        num_added: u64,
+    ) -> Result<(), Error> {
+        check_fits(key, value)?;
+        check_pool_size(bufmgr)?;
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        let root_buffer = latch_node(bufmgr, root_page_id, LatchMode::Exclusive)?;
+        let new_root_page_id = match self.insert_internal(bufmgr, root_buffer, key, value, 0)? {
+            Some((key, child_page_id)) => {
+                let new_root_buffer = bufmgr.create_page()?;
+                let mut node = node::Node::new(new_root_buffer.bytes_mut());
+                node.initialize_as_branch();
+                let mut branch = branch::Branch::new(node.body);
+                branch.initialize(&key, child_page_id, root_page_id);
+                new_root_buffer.page_id
+            }
+            None => root_page_id,
+        };
+        self.write_meta(bufmgr, |header| {
+            header.root_page_id = new_root_page_id;
+            header.num_entries = header.num_entries.saturating_add(num_added);
+            header.version = header.version.wrapping_add(1);
+        })
     }
 
-    fn advance(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
-        self.slot_id += 1;
-        let next_page_id = {
-            let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
-            let leaf = leaf::Leaf::new(leaf_node.body);
-            if self.slot_id < leaf.num_pairs() {
+    pub fn update(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        check_fits(key, value)?;
+        check_pool_size(bufmgr)?;
+        let (leaf_buffer, depth) = self.find_leaf(bufmgr, key, LatchMode::Exclusive)?;
+        {
+            let slot_id = find_slot(&leaf_buffer, key)?;
+            let node = node::Node::new(leaf_buffer.bytes_mut());
+            let mut leaf = leaf::Leaf::new(node.body);
+            if leaf
+                .update(slot_id, value)
+                .map_err(Error::invalid_slot(leaf_buffer.page_id))?
+                .is_some()
+            {
                 return Ok(());
             }
-            leaf.next_page_id()
-        };
-        if let Some(next_page_id) = next_page_id {
-            self.buffer = bufmgr.fetch_page(next_page_id)?;
-            self.slot_id = 0;
+            // the insert below pins the path back down to this leaf and
+            // may split it; the key must not be removed if it cannot
+            reserve_frames(bufmgr, depth + SPLIT_PAGES)?;
+            leaf.remove(slot_id);
         }
-        Ok(())
+        drop(leaf_buffer);
+        self.insert_entry(bufmgr, key, value, 0)
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn next(
-        &mut self,
+    fn child_page_ids(
+        &self,
         bufmgr: &mut BufferPoolManager,
-    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
-        let value = self.get();
-        self.advance(bufmgr)?;
-        Ok(value)
+        page_id: PageId,
+        depth: usize,
+    ) -> Result<Vec<PageId>, Error> {
+        let buffer = fetch_child(bufmgr, page_id, depth)?;
+        let node = node::Node::new(buffer.bytes());
+        let child_page_ids = match node::Body::new(node.header.node_type, node.body.as_bytes()) {
+            node::Body::Leaf(_) => vec![],
+            node::Body::Branch(branch) => (0..=branch.num_pairs())
+                .map(|child_idx| branch.child_at(child_idx))
+                .collect::<Result<_, _>>()
+                .map_err(Error::invalid_slot(page_id))?,
+        };
+        Ok(child_page_ids)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use tempfile::tempfile;
 
-    use crate::{buffer::BufferPool, disk::DiskManager};
-
-    use super::*;
-    #[test]
-    fn test() {
-        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
-        let pool = BufferPool::new(10);
-        let mut bufmgr = BufferPoolManager::new(disk, pool);
-        let btree = BTree::create(&mut bufmgr).unwrap();
-        btree
-            .insert(&mut bufmgr, &6u64.to_be_bytes(), b"world")
-            .unwrap();
-        btree
-            .insert(&mut bufmgr, &3u64.to_be_bytes(), b"hello")
-            .unwrap();
-        btree
-            .insert(&mut bufmgr, &8u64.to_be_bytes(), b"!")
-            .unwrap();
-        btree
-            .insert(&mut bufmgr, &4u64.to_be_bytes(), b",")
-            .unwrap();
+    fn destroy_internal(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        page_id: PageId,
+        depth: usize,
+    ) -> Result<(), Error> {
+        for child_page_id in self.child_page_ids(bufmgr, page_id, depth)? {
+            self.destroy_internal(bufmgr, child_page_id, depth + 1)?;
+        }
+        bufmgr.deallocate_page(page_id)?;
+        Ok(())
+    }
 
-        let (_, value) = btree
-            .search(&mut bufmgr, SearchMode::Key(3u64.to_be_bytes().to_vec()))
-            .unwrap()
-            .get()
-            .unwrap();
-        assert_eq!(b"hello", &value[..]);
-        let (_, value) = btree
-            .search(&mut bufmgr, SearchMode::Key(8u64.to_be_bytes().to_vec()))
-            .unwrap()
-            .get()
-            .unwrap();
-        assert_eq!(b"!", &value[..]);
+    /// Deallocates every page of the tree, including its meta page.
+    pub fn destroy(self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        self.destroy_internal(bufmgr, root_page_id, 0)?;
+        bufmgr.deallocate_page(self.meta_page_id)?;
+        Ok(())
     }
 
-    #[test]
-    fn test_search_iter() {
-        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
-        let pool = BufferPool::new(10);
-        let mut bufmgr = BufferPoolManager::new(disk, pool);
-        let btree = BTree::create(&mut bufmgr).unwrap();
+    /// Deallocates every node of the tree and replaces them with a single
+    /// empty leaf, keeping the meta page id.
+    pub fn recreate(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        check_pool_size(bufmgr)?;
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        self.destroy_internal(bufmgr, root_page_id, 0)?;
+        let root_buffer = bufmgr.create_page()?;
+        let mut root = node::Node::new(root_buffer.bytes_mut());
+        root.initialize_as_leaf();
+        let mut leaf = leaf::Leaf::new(root.body);
+        leaf.initialize();
+        self.write_meta(bufmgr, |header| {
+            header.root_page_id = root_buffer.page_id;
+            header.num_entries = 0;
+            header.version = header.version.wrapping_add(1);
+        })
+    }
 
-        for i in 0u64..16 {
-            btree
-                .insert(&mut bufmgr, &(i * 2).to_be_bytes(), &[0; 1024])
-                .unwrap();
+    fn count_pages_internal(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        page_id: PageId,
+        depth: usize,
+    ) -> Result<u64, Error> {
+        let mut num_pages = 1;
+        for child_page_id in self.child_page_ids(bufmgr, page_id, depth)? {
+            num_pages += self.count_pages_internal(bufmgr, child_page_id, depth + 1)?;
         }
+        Ok(num_pages)
+    }
 
-        for i in 0u64..15 {
-            let (key, _) = btree
-                .search(
-                    &mut bufmgr,
-                    SearchMode::Key((i * 2 + 1).to_be_bytes().to_vec()),
-                )
-                .unwrap()
-                .get()
-                .unwrap();
-            assert_eq!(key.as_slice(), &((i + 1) * 2).to_be_bytes());
+    /// Checks that the keys are in order within each node and within the
+    /// bounds its parent sets, that every leaf is at the same depth, that
+    /// the leaves link to their neighbours in both directions, and that
+    /// the meta page counts the entries there are.
+    pub fn verify(&self, bufmgr: &mut BufferPoolManager) -> Result<Verification, Error> {
+        let meta::Header {
+            root_page_id,
+            num_entries: meta_num_entries,
+            ..
+        } = self.read_meta(bufmgr)?;
+        let mut verification = Verification {
+            num_pages: 1,
+            ..Verification::default()
+        };
+        let mut walk = Walk::default();
+        self.verify_node(
+            bufmgr,
+            root_page_id,
+            (None, None),
+            1,
+            &mut walk,
+            &mut verification,
+        )?;
+        verification.depth = walk.leaf_depth.unwrap_or(0);
+        for (i, &(page_id, prev, next)) in walk.leaves.iter().enumerate() {
+            let expected_prev = i.checked_sub(1).map(|i| walk.leaves[i].0);
+            let expected_next = walk.leaves.get(i + 1).map(|leaf| leaf.0);
+            if prev != expected_prev || next != expected_next {
+                verification.problems.push(format!(
+                    "leaf {} links to {:?} and {:?} instead of {:?} and {:?}",
+                    page_id.to_u64(),
+                    prev.map(PageId::to_u64),
+                    next.map(PageId::to_u64),
+                    expected_prev.map(PageId::to_u64),
+                    expected_next.map(PageId::to_u64),
+                ));
+            }
+        }
+        if meta_num_entries != verification.num_entries {
+            verification.problems.push(format!(
+                "the meta page counts {} entries but the leaves hold {}",
+                meta_num_entries, verification.num_entries
+            ));
         }
+        Ok(verification)
     }
 
-    #[test]
-    fn test_split() {
+    /// Checks the node at `page_id`, whose keys must be at least `bounds.0`
+    /// and less than `bounds.1`, and the nodes below it.
+    fn verify_node(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        page_id: PageId,
+        bounds: (Option<&[u8]>, Option<&[u8]>),
+        depth: usize,
+        walk: &mut Walk,
+        verification: &mut Verification,
+    ) -> Result<(), Error> {
+        verification.num_pages += 1;
+        let buffer = match fetch_child(bufmgr, page_id, depth) {
+            Err(err @ Error::Corrupted { .. }) => {
+                verification.problems.push(err.to_string());
+                return Ok(());
+            }
+            result => result?,
+        };
+        let node = node::Node::new(buffer.bytes());
+        let (keys, children) = match node::Body::new(node.header.node_type, node.body.as_bytes()) {
+            node::Body::Leaf(leaf) => {
+                let keys: Vec<_> = (0..leaf.num_pairs())
+                    .map(|slot_id| Ok(leaf.pair_at(slot_id)?.key.to_vec()))
+                    .collect::<Result<_, _>>()
+                    .map_err(Error::invalid_slot(page_id))?;
+                verification.num_entries += keys.len() as u64;
+                walk.leaves
+                    .push((page_id, leaf.prev_page_id(), leaf.next_page_id()));
+                match walk.leaf_depth {
+                    None => walk.leaf_depth = Some(depth),
+                    Some(leaf_depth) if leaf_depth != depth => verification.problems.push(format!(
+                        "leaf {} is at depth {} but others are at {}",
+                        page_id.to_u64(),
+                        depth,
+                        leaf_depth
+                    )),
+                    Some(_) => {}
+                }
+                (keys, vec![])
+            }
+            node::Body::Branch(branch) => {
+                let keys: Vec<_> = (0..branch.num_pairs())
+                    .map(|slot_id| Ok(branch.pair_at(slot_id)?.key.to_vec()))
+                    .collect::<Result<_, _>>()
+                    .map_err(Error::invalid_slot(page_id))?;
+                let children: Vec<_> = (0..=branch.num_pairs())
+                    .map(|child_idx| branch.child_at(child_idx))
+                    .collect::<Result<_, _>>()
+                    .map_err(Error::invalid_slot(page_id))?;
+                (keys, children)
+            }
+        };
+        drop(node);
+        drop(buffer);
+        let (lower, upper) = bounds;
+        for (i, key) in keys.iter().enumerate() {
+            let in_order = i == 0 || keys[i - 1] < *key;
+            let below = matches!(lower, Some(lower) if key.as_slice() < lower);
+            let above = matches!(upper, Some(upper) if key.as_slice() >= upper);
+            if !in_order || below || above {
+                verification.problems.push(format!(
+                    "key {} of page {} is out of order",
+                    i,
+                    page_id.to_u64()
+                ));
+            }
+        }
+        for (i, &child_page_id) in children.iter().enumerate() {
+            let lower = match i {
+                0 => lower,
+                _ => Some(keys[i - 1].as_slice()),
+            };
+            let upper = keys.get(i).map(Vec::as_slice).or(upper);
+            self.verify_node(
+                bufmgr,
+                child_page_id,
+                (lower, upper),
+                depth + 1,
+                walk,
+                verification,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The number of pages the tree occupies, including its meta page.
+    pub fn num_pages(&self, bufmgr: &mut BufferPoolManager) -> Result<u64, Error> {
+        let root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        Ok(1 + self.count_pages_internal(bufmgr, root_page_id, 0)?)
+    }
+
+    /// Packs the pairs of `iter` into as few leaves as possible and returns
+    /// the first key and page id of each leaf along with the number of pairs.
+    #[allow(clippy::type_complexity)]
+    fn load_leaves(
+        bufmgr: &mut BufferPoolManager,
+        iter: &mut Iter,
+    ) -> Result<(Vec<(Vec<u8>, PageId)>, u64), Error> {
+        let mut leaves = vec![];
+        let mut num_pairs = 0;
+        let mut leaf_buffer: Option<Rc<Buffer>> = None;
+        while let Some((key, value)) = iter.next(bufmgr)? {
+            num_pairs += 1;
+            if let Some(buffer) = &leaf_buffer {
+                let node = node::Node::new(buffer.bytes_mut());
+                let mut leaf = leaf::Leaf::new(node.body);
+                if leaf.insert(leaf.num_pairs(), &key, &value).is_some() {
+                    continue;
+                }
+            }
+            let new_leaf_buffer = bufmgr.create_page()?;
+            {
+                let mut new_leaf_node = node::Node::new(new_leaf_buffer.bytes_mut());
+                new_leaf_node.initialize_as_leaf();
+                let mut new_leaf = leaf::Leaf::new(new_leaf_node.body);
+                new_leaf.initialize();
+                new_leaf
+                    .insert(0, &key, &value)
+                    .expect("new leaf must have space");
+                if let Some(buffer) = &leaf_buffer {
+                    let node = node::Node::new(buffer.bytes_mut());
+                    let mut leaf = leaf::Leaf::new(node.body);
+                    leaf.set_next_page_id(Some(new_leaf_buffer.page_id));
+                    new_leaf.set_prev_page_id(Some(buffer.page_id));
+                }
+            }
+            leaves.push((key, new_leaf_buffer.page_id));
+            leaf_buffer = Some(new_leaf_buffer);
+        }
+        if leaves.is_empty() {
+            let leaf_buffer = bufmgr.create_page()?;
+            let mut node = node::Node::new(leaf_buffer.bytes_mut());
+            node.initialize_as_leaf();
+            leaf::Leaf::new(node.body).initialize();
+            leaves.push((vec![], leaf_buffer.page_id));
+        }
+        Ok((leaves, num_pairs))
+    }
+
+    /// Packs one level of children into as few branches as possible and
+    /// returns the first key and page id of each branch.
+    fn load_branches(
+        bufmgr: &mut BufferPoolManager,
+        children: Vec<(Vec<u8>, PageId)>,
+    ) -> Result<Vec<(Vec<u8>, PageId)>, Error> {
+        let mut branches: Vec<(Vec<u8>, PageId)> = vec![];
+        let mut children = children.into_iter().peekable();
+        while let Some((first_key, first_child)) = children.next() {
+            let (key, right_child) = match children.next() {
+                Some(child) => child,
+                None => {
+                    // a branch needs two children, so take one from the previous branch
+                    let (prev_first_key, prev_page_id) =
+                        branches.pop().expect("level must have two children");
+                    let prev_buffer = bufmgr.fetch_page(prev_page_id)?;
+                    let prev_node = node::Node::new(prev_buffer.bytes_mut());
+                    let mut prev_branch = branch::Branch::new(prev_node.body);
+                    let prev_right_child = prev_branch
+                        .child_at(prev_branch.num_pairs())
+                        .map_err(Error::invalid_slot(prev_page_id))?;
+                    let prev_key = prev_branch
+                        .fill_right_child()
+                        .map_err(Error::invalid_slot(prev_page_id))?;
+                    let buffer = bufmgr.create_page()?;
+                    let mut node = node::Node::new(buffer.bytes_mut());
+                    node.initialize_as_branch();
+                    let mut branch = branch::Branch::new(node.body);
+                    branch.initialize(&first_key, prev_right_child, first_child);
+                    branches.push((prev_first_key, prev_page_id));
+                    branches.push((prev_key, buffer.page_id));
+                    break;
+                }
+            };
+            let buffer = bufmgr.create_page()?;
+            let mut node = node::Node::new(buffer.bytes_mut());
+            node.initialize_as_branch();
+            let mut branch = branch::Branch::new(node.body);
+            branch.initialize(&key, first_child, right_child);
+            while let Some((next_key, _)) = children.peek() {
+                let right_child = branch
+                    .child_at(branch.num_pairs())
+                    .map_err(Error::invalid_slot(buffer.page_id))?;
+                if branch
+                    .insert(branch.num_pairs(), next_key, right_child)
+                    .is_none()
+                {
+                    break;
+                }
+                let (_, next_child) = children.next().unwrap();
+                branch.set_right_child(next_child);
+            }
+            branches.push((first_key, buffer.page_id));
+        }
+        Ok(branches)
+    }
+
+    /// Rebuilds the tree with fully packed nodes, keeping the meta page id.
+    ///
+    /// The new nodes are written and synced before the meta page is pointed
+    /// at the new root, so a crash leaves either the old or the new tree
+    /// intact. The old nodes are deallocated after the swap. A transaction
+    /// would defer those syncs to its commit, so it is refused in one.
+    pub fn compact(&self, bufmgr: &mut BufferPoolManager) -> Result<Compaction, Error> {
+        if bufmgr.in_transaction() {
+            return Err(Error::InTransaction);
+        }
+        check_pool_size(bufmgr)?;
+        let num_pages_before = self.num_pages(bufmgr)?;
+        let old_root_page_id = self.read_meta(bufmgr)?.root_page_id;
+        let mut iter = self.search(bufmgr, SearchMode::Start)?;
+        let (mut level, num_entries) = Self::load_leaves(bufmgr, &mut iter)?;
+        drop(iter);
+        while level.len() > 1 {
+            level = Self::load_branches(bufmgr, level)?;
+        }
+        let (_, new_root_page_id) = level[0];
+        bufmgr.flush()?;
+        self.write_meta(bufmgr, |header| {
+            header.root_page_id = new_root_page_id;
+            header.num_entries = num_entries;
+            header.version = header.version.wrapping_add(1);
+        })?;
+        bufmgr.flush()?;
+        self.destroy_internal(bufmgr, old_root_page_id, 0)?;
+        Ok(Compaction {
+            num_pages_before,
+            num_pages_after: self.num_pages(bufmgr)?,
+            num_entries,
+        })
+    }
+
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, key: &[u8]) -> Result<(), Error> {
+        {
+            let (leaf_buffer, _) = self.find_leaf(bufmgr, key, LatchMode::Exclusive)?;
+            let slot_id = find_slot(&leaf_buffer, key)?;
+            let node = node::Node::new(leaf_buffer.bytes_mut());
+            leaf::Leaf::new(node.body).remove(slot_id);
+        }
+        self.write_meta(bufmgr, |header| {
+            header.num_entries = header.num_entries.saturating_sub(1);
+            header.version = header.version.wrapping_add(1);
+        })
+    }
+
+    /// The number of entries as counted by insert and delete.
+    pub fn num_entries(&self, bufmgr: &mut BufferPoolManager) -> Result<u64, Error> {
+        Ok(self.read_meta(bufmgr)?.num_entries)
+    }
+
+    pub fn key_format(&self, bufmgr: &mut BufferPoolManager) -> Result<u64, Error> {
+        Ok(self.read_meta(bufmgr)?.key_format)
+    }
+
+    pub fn key_arity(&self, bufmgr: &mut BufferPoolManager) -> Result<u64, Error> {
+        Ok(self.read_meta(bufmgr)?.key_arity)
+    }
+
+    pub fn set_key_arity(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key_arity: u64,
+    ) -> Result<(), Error> {
+        self.write_meta(bufmgr, |header| header.key_arity = key_arity)
+    }
+
+    pub fn set_num_entries(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        num_entries: u64,
+    ) -> Result<(), Error> {
+        self.write_meta(bufmgr, |header| header.num_entries = num_entries)
+    }
+
+    /// As if the tree were written by a binary with other key formats.
+    #[cfg(test)]
+    pub fn set_key_format(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key_format: u64,
+    ) -> Result<(), Error> {
+        self.write_meta(bufmgr, |header| header.key_format = key_format)
+    }
+}
+
+/// Walks the keys from a search. A change to the tree while it is open,
+/// which may split its leaf or shift its slots, makes it search again
+/// from the last key it returned, so it neither skips nor repeats keys.
+pub struct Iter {
+    buffer: Rc<Buffer>,
+    /// Going backward, the slot after the current one, so that a scan
+    /// past the first key stops at 0.
+    slot_id: usize,
+    direction: ScanDirection,
+    meta_buffer: Rc<Buffer>,
+    /// `meta::Header::version` when `buffer` and `slot_id` were found.
+    version: u64,
+    search_mode: SearchMode,
+    last_key: Option<Vec<u8>>,
+}
+
+impl Iter {
+    fn current_version(&self) -> Result<u64, Error> {
+        Ok(meta_header(&self.meta_buffer)?.version)
+    }
+
+    /// Finds the place after `last_key`, or the start of `search_mode`
+    /// before any key was returned, from the root.
+    fn seek(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let header = meta_header(&self.meta_buffer)?;
+        check_root(bufmgr, self.meta_buffer.page_id, header.root_page_id)?;
+        self.version = header.version;
+        let root_page_id = header.root_page_id;
+        let root_page = latch_node(bufmgr, root_page_id, LatchMode::Shared)?;
+        let btree = BTree::new(self.meta_buffer.page_id);
+        let (buffer, slot_id) = match &self.last_key {
+            Some(last_key) => {
+                let search_mode = SearchMode::Key(last_key.clone());
+                let (buffer, slot_id) =
+                    btree.search_internal(bufmgr, root_page, &search_mode, self.direction, 0)?;
+                let skip = {
+                    let leaf_node = node::Node::new(buffer.bytes());
+                    let leaf = leaf::Leaf::new(leaf_node.body);
+                    leaf.search_slot_id(last_key)
+                        .map_err(Error::invalid_slot(buffer.page_id))?
+                        .is_ok()
+                };
+                match (self.direction, skip) {
+                    (ScanDirection::Forward, true) => (buffer, slot_id + 1),
+                    (ScanDirection::Backward, true) => (buffer, slot_id - 1),
+                    (_, false) => (buffer, slot_id),
+                }
+            }
+            None => {
+                btree.search_internal(bufmgr, root_page, &self.search_mode, self.direction, 0)?
+            }
+        };
+        self.buffer = buffer;
+        self.slot_id = slot_id;
+        self.move_to_next_leaf(bufmgr)
+    }
+
+    fn revalidate(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        if self.current_version()? != self.version || !self.is_in_place()? {
+            self.seek(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the pairs around `slot_id` still fall on either side of
+    /// `last_key`. The version catches changes made through the tree; this
+    /// catches a leaf changed behind its back, whose slots shifted under
+    /// the iterator.
+    fn is_in_place(&self) -> Result<bool, Error> {
+        let last_key = match &self.last_key {
+            Some(last_key) => last_key.as_slice(),
+            None => return Ok(true),
+        };
+        let leaf_node = node::Node::new(self.buffer.bytes());
+        let leaf = leaf::Leaf::new(leaf_node.body);
+        if self.slot_id > leaf.num_pairs() {
+            return Ok(false);
+        }
+        let key_at = |slot_id| {
+            leaf.pair_at(slot_id)
+                .map(|pair| pair.key)
+                .map_err(Error::invalid_slot(self.buffer.page_id))
+        };
+        let before = match self.slot_id.checked_sub(1) {
+            Some(slot_id) => Some(key_at(slot_id)?),
+            None => None,
+        };
+        let after = if self.slot_id < leaf.num_pairs() {
+            Some(key_at(self.slot_id)?)
+        } else {
+            None
+        };
+        // going forward the last key is before the slot, going backward at it
+        Ok(match self.direction {
+            ScanDirection::Forward => {
+                before.is_none_or(|key| key <= last_key) && after.is_none_or(|key| key > last_key)
+            }
+            ScanDirection::Backward => {
+                before.is_none_or(|key| key < last_key) && after.is_none_or(|key| key >= last_key)
+            }
+        })
+    }
+
+    fn remember(last_key: &mut Option<Vec<u8>>, key: &[u8]) {
+        let last_key = last_key.get_or_insert_with(Vec::new);
+        last_key.clear();
+        last_key.extend_from_slice(key);
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        self.get_with(|key, value| (key.to_vec(), value.to_vec()))
+    }
+
+    fn get_with<T>(&mut self, f: impl FnOnce(&[u8], &[u8]) -> T) -> Result<Option<T>, Error> {
+        let leaf_node = node::Node::new(self.buffer.bytes());
+        let leaf = leaf::Leaf::new(leaf_node.body);
+        let slot_id = match self.direction {
+            ScanDirection::Forward => self.slot_id,
+            ScanDirection::Backward => match self.slot_id.checked_sub(1) {
+                Some(slot_id) => slot_id,
+                None => return Ok(None),
+            },
+        };
+        if slot_id < leaf.num_pairs() {
+            let pair = leaf
+                .pair_at(slot_id)
+                .map_err(Error::invalid_slot(self.buffer.page_id))?;
+            Self::remember(&mut self.last_key, pair.key);
+            Ok(Some(f(pair.key, pair.value)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn advance(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        match self.direction {
+            ScanDirection::Forward => self.slot_id += 1,
+            ScanDirection::Backward => self.slot_id = self.slot_id.saturating_sub(1),
+        }
+        self.move_to_next_leaf(bufmgr)
+    }
+
+    /// Moves on to the next leaf with keys left in `direction`, if the
+    /// current one has none left.
+    fn move_to_next_leaf(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        // leaves emptied by deletion are skipped over
+        loop {
+            let next_page_id = {
+                let leaf_node = node::Node::new(self.buffer.bytes());
+                let leaf = leaf::Leaf::new(leaf_node.body);
+                match self.direction {
+                    ScanDirection::Forward if self.slot_id < leaf.num_pairs() => return Ok(()),
+                    ScanDirection::Forward => leaf.next_page_id(),
+                    ScanDirection::Backward if self.slot_id > 0 => return Ok(()),
+                    ScanDirection::Backward => leaf.prev_page_id(),
+                }
+            };
+            match next_page_id {
+                Some(next_page_id) => {
+                    self.buffer = fetch_leaf(bufmgr, next_page_id)?;
+                    self.slot_id = match self.direction {
+                        ScanDirection::Forward => 0,
+                        ScanDirection::Backward => {
+                            let leaf_node = node::Node::new(self.buffer.bytes());
+                            leaf::Leaf::new(leaf_node.body).num_pairs()
+                        }
+                    };
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn next(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        self.revalidate(bufmgr)?;
+        let value = self.get()?;
+        self.advance(bufmgr)?;
+        Ok(value)
+    }
+
+    /// Like `next`, but hands the key and value to `f` in the page instead
+    /// of copying them out.
+    pub fn next_with<T>(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        f: impl FnOnce(&[u8], &[u8]) -> T,
+    ) -> Result<Option<T>, Error> {
+        self.revalidate(bufmgr)?;
+        let value = self.get_with(f)?;
+        self.advance(bufmgr)?;
+        Ok(value)
+    }
+
+    /// Hands up to `max_pairs` of the pairs left in the current leaf to
+    /// `f`, in the page, until it returns false, then moves past them. The
+    /// page is borrowed once for all of them. Returns how many `f` saw,
+    /// which is 0 only at the end of the tree.
+    pub fn next_in_leaf_with(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_pairs: usize,
+        mut f: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<usize, Error> {
+        self.revalidate(bufmgr)?;
+        let seen = {
+            let leaf_node = node::Node::new(self.buffer.bytes());
+            let leaf = leaf::Leaf::new(leaf_node.body);
+            let slot_ids: Box<dyn Iterator<Item = usize>> = match self.direction {
+                ScanDirection::Forward => Box::new(self.slot_id..leaf.num_pairs()),
+                ScanDirection::Backward => Box::new((0..self.slot_id.min(leaf.num_pairs())).rev()),
+            };
+            let mut seen = 0;
+            let mut last_slot_id = None;
+            for slot_id in slot_ids.take(max_pairs) {
+                let pair = leaf
+                    .pair_at(slot_id)
+                    .map_err(Error::invalid_slot(self.buffer.page_id))?;
+                seen += 1;
+                last_slot_id = Some(slot_id);
+                if !f(pair.key, pair.value) {
+                    break;
+                }
+            }
+            if let Some(slot_id) = last_slot_id {
+                let pair = leaf
+                    .pair_at(slot_id)
+                    .map_err(Error::invalid_slot(self.buffer.page_id))?;
+                Self::remember(&mut self.last_key, pair.key);
+            }
+            seen
+        };
+        match self.direction {
+            ScanDirection::Forward => self.slot_id += seen,
+            ScanDirection::Backward => self.slot_id -= seen,
+        }
+        self.move_to_next_leaf(bufmgr)?;
+        Ok(seen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use tempfile::tempfile;
+
+    use crate::{
+        buffer::BufferPool,
+        disk::{DiskManager, PAGE_SIZE},
+        tuple::testing::Rng,
+    };
+
+    use super::*;
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        btree
+            .insert(&mut bufmgr, &6u64.to_be_bytes(), b"world")
+            .unwrap();
+        btree
+            .insert(&mut bufmgr, &3u64.to_be_bytes(), b"hello")
+            .unwrap();
+        btree
+            .insert(&mut bufmgr, &8u64.to_be_bytes(), b"!")
+            .unwrap();
+        btree
+            .insert(&mut bufmgr, &4u64.to_be_bytes(), b",")
+            .unwrap();
+
+        let (_, value) = btree
+            .search(&mut bufmgr, SearchMode::Key(3u64.to_be_bytes().to_vec()))
+            .unwrap()
+            .get()
+            .unwrap()
+            .unwrap();
+        assert_eq!(b"hello", &value[..]);
+        let (_, value) = btree
+            .search(&mut bufmgr, SearchMode::Key(8u64.to_be_bytes().to_vec()))
+            .unwrap()
+            .get()
+            .unwrap()
+            .unwrap();
+        assert_eq!(b"!", &value[..]);
+    }
+
+    #[test]
+    fn test_search_iter() {
         let disk = DiskManager::new(tempfile().unwrap()).unwrap();
-        let pool = BufferPool::new(10);
+        let pool = BufferPool::new(MIN_POOL_SIZE);
         let mut bufmgr = BufferPoolManager::new(disk, pool);
         let btree = BTree::create(&mut bufmgr).unwrap();
-        let long_data_list = vec![
+
+        for i in 0u64..16 {
+            btree
+                .insert(&mut bufmgr, &(i * 2).to_be_bytes(), &[0; 1024])
+                .unwrap();
+        }
+
+        for i in 0u64..15 {
+            let (key, _) = btree
+                .search(
+                    &mut bufmgr,
+                    SearchMode::Key((i * 2 + 1).to_be_bytes().to_vec()),
+                )
+                .unwrap()
+                .get()
+                .unwrap()
+                .unwrap();
+            assert_eq!(key.as_slice(), &((i + 1) * 2).to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_search_backward() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+
+        for i in 1u64..=16 {
+            btree
+                .insert(&mut bufmgr, &(i * 2).to_be_bytes(), &[0; 1024])
+                .unwrap();
+        }
+        // an emptied leaf in the middle must be skipped
+        for i in 5u64..=7 {
+            btree.delete(&mut bufmgr, &(i * 2).to_be_bytes()).unwrap();
+        }
+
+        let mut scan = |search_mode| {
+            let mut iter = btree
+                .search_directed(&mut bufmgr, search_mode, ScanDirection::Backward)
+                .unwrap();
+            let mut keys = vec![];
+            while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+                keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+            }
+            keys
+        };
+        let all: Vec<u64> = (1..=16)
+            .rev()
+            .filter(|i| !(5..=7).contains(i))
+            .map(|i| i * 2)
+            .collect();
+        assert_eq!(all, scan(SearchMode::Start));
+        assert_eq!(
+            all[1..],
+            scan(SearchMode::Key(31u64.to_be_bytes().to_vec()))[..]
+        );
+        assert_eq!(
+            all[1..],
+            scan(SearchMode::Key(30u64.to_be_bytes().to_vec()))[..]
+        );
+        assert_eq!(
+            vec![8, 6, 4, 2],
+            scan(SearchMode::Key(14u64.to_be_bytes().to_vec()))
+        );
+        assert_eq!(vec![2], scan(SearchMode::Key(2u64.to_be_bytes().to_vec())));
+        assert!(scan(SearchMode::Key(1u64.to_be_bytes().to_vec())).is_empty());
+    }
+
+    #[test]
+    fn test_modify_while_iterating() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        // a dozen or so pairs per leaf
+        for i in 0u64..100 {
+            btree
+                .insert(&mut bufmgr, &(i * 4).to_be_bytes(), &[0; 300])
+                .unwrap();
+        }
+
+        for &direction in &[ScanDirection::Forward, ScanDirection::Backward] {
+            let mut iter = btree
+                .search_directed(&mut bufmgr, SearchMode::Start, direction)
+                .unwrap();
+            let mut keys = vec![];
+            let mut next = |iter: &mut Iter, bufmgr: &mut BufferPoolManager| {
+                let key = iter.next(bufmgr).unwrap().map(|(key, _)| key);
+                keys.extend(key.map(|key| u64::from_be_bytes(key.try_into().unwrap())));
+            };
+            for _ in 0..50 {
+                next(&mut iter, &mut bufmgr);
+            }
+            // splits the leaf the iterator is in, with keys on both sides
+            // of it, and deletes the key it would return next
+            for i in 90u64..110 {
+                btree
+                    .insert(&mut bufmgr, &(i * 2 + 1).to_be_bytes(), &[0; 300])
+                    .unwrap();
+            }
+            let after = match direction {
+                ScanDirection::Forward => 200u64,
+                ScanDirection::Backward => 196,
+            };
+            btree.delete(&mut bufmgr, &after.to_be_bytes()).unwrap();
+            for _ in 0..100 {
+                next(&mut iter, &mut bufmgr);
+            }
+
+            let originals = keys.iter().filter(|key| *key % 2 == 0).count();
+            assert_eq!(99, originals, "{:?}", direction);
+            assert!(!keys.contains(&after));
+            let sorted = keys.windows(2).all(|pair| match direction {
+                ScanDirection::Forward => pair[0] < pair[1],
+                ScanDirection::Backward => pair[0] > pair[1],
+            });
+            assert!(sorted, "{:?} {:?}", direction, keys);
+            // the keys inserted behind the iterator are not seen, those ahead of it are
+            let inserted: Vec<_> = keys.iter().filter(|key| *key % 2 == 1).collect();
+            match direction {
+                ScanDirection::Forward => assert_eq!(vec![&197, &199], inserted[..2].to_vec()),
+                ScanDirection::Backward => assert_eq!(Some(&&199), inserted.first()),
+            }
+
+            btree
+                .insert(&mut bufmgr, &after.to_be_bytes(), &[0; 300])
+                .unwrap();
+            for i in 90u64..110 {
+                btree
+                    .delete(&mut bufmgr, &(i * 2 + 1).to_be_bytes())
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_interleaved_inserts() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..10 {
+            btree
+                .insert(&mut bufmgr, &(i * 10).to_be_bytes(), b"")
+                .unwrap();
+        }
+        let key = |key: Vec<u8>| u64::from_be_bytes(key.try_into().unwrap());
+
+        // a key ahead of the iterator and one behind it after each one it returns
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((found, _)) = iter.next(&mut bufmgr).unwrap() {
+            let found = key(found);
+            keys.push(found);
+            if found % 10 == 0 {
+                let behind = found.saturating_sub(3);
+                for &new in &[found + 5, behind] {
+                    if new != found {
+                        btree.insert(&mut bufmgr, &new.to_be_bytes(), b"").unwrap();
+                    }
+                }
+            }
+        }
+        let expected: Vec<_> = (0u64..20).map(|i| i * 5).collect();
+        assert_eq!(expected, keys);
+
+        // the same, but in the leaf behind the tree's back, which leaves its
+        // version as it was
+        let mut expected = vec![];
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        while let Some((found, _)) = iter.next(&mut bufmgr).unwrap() {
+            expected.push(key(found));
+        }
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        for i in 0.. {
+            match iter.next(&mut bufmgr).unwrap() {
+                Some((found, _)) => keys.push(key(found)),
+                None => break,
+            }
+            let root_buffer = btree.fetch_root_page(&mut bufmgr).unwrap();
+            let node = node::Node::new(root_buffer.bytes_mut());
+            let mut leaf = leaf::Leaf::new(node.body);
+            if i % 2 == 0 {
+                leaf.insert(0, &[], b"").unwrap();
+            } else {
+                leaf.remove(0);
+            }
+        }
+        assert_eq!(expected, keys);
+    }
+
+    #[test]
+    fn test_split() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let long_data_list = [
             vec![0xC0u8; 1000],
             vec![0x01u8; 1000],
             vec![0xCAu8; 1000],
@@ -371,9 +1712,631 @@ mod tests {
                 .search(&mut bufmgr, SearchMode::Key(data.clone()))
                 .unwrap()
                 .get()
+                .unwrap()
                 .unwrap();
             assert_eq!(data, &k);
             assert_eq!(data, &v);
         }
     }
+
+    #[test]
+    fn test_update_delete() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..16 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 512])
+                .unwrap();
+        }
+
+        btree
+            .update(&mut bufmgr, &3u64.to_be_bytes(), b"small")
+            .unwrap();
+        btree
+            .update(&mut bufmgr, &4u64.to_be_bytes(), &[4; 1500])
+            .unwrap();
+        assert_eq!(
+            Some(b"small".to_vec()),
+            btree.get(&mut bufmgr, &3u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            Some(vec![4; 1500]),
+            btree.get(&mut bufmgr, &4u64.to_be_bytes()).unwrap()
+        );
+        assert!(matches!(
+            btree.update(&mut bufmgr, &100u64.to_be_bytes(), b""),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(16, btree.num_entries(&mut bufmgr).unwrap());
+
+        for i in 2u64..10 {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        assert!(matches!(
+            btree.delete(&mut bufmgr, &5u64.to_be_bytes()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(None, btree.get(&mut bufmgr, &5u64.to_be_bytes()).unwrap());
+        assert_eq!(8, btree.num_entries(&mut bufmgr).unwrap());
+
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+            keys.push(u64::from_be_bytes(key.as_slice().try_into().unwrap()));
+        }
+        assert_eq!(vec![0, 1, 10, 11, 12, 13, 14, 15], keys);
+    }
+
+    #[test]
+    fn test_pair_too_large() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        assert!(fits(8, 2016));
+        assert!(!fits(8, 2017));
+        assert!(!fits(2025, 0));
+        for i in 0u64..8 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 2016])
+                .unwrap();
+        }
+        assert!(matches!(
+            btree.insert(&mut bufmgr, &8u64.to_be_bytes(), &[0; 2017]),
+            Err(Error::PairTooLarge {
+                key_len: 8,
+                value_len: 2017
+            })
+        ));
+        assert!(matches!(
+            btree.update(&mut bufmgr, &3u64.to_be_bytes(), &[0; 2017]),
+            Err(Error::PairTooLarge { .. })
+        ));
+        assert_eq!(
+            Some(vec![0; 2016]),
+            btree.get(&mut bufmgr, &3u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(8, btree.num_entries(&mut bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_destroy() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..16 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 1024])
+                .unwrap();
+        }
+        bufmgr.flush().unwrap();
+        let num_pages = bufmgr.num_pages();
+        btree.destroy(&mut bufmgr).unwrap();
+        assert_eq!(num_pages * PAGE_SIZE as u64, bufmgr.shrink().unwrap());
+    }
+
+    #[test]
+    fn test_recreate() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let pool = BufferPool::new(MIN_POOL_SIZE);
+        let mut bufmgr = BufferPoolManager::new(disk, pool);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..16 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 1024])
+                .unwrap();
+        }
+        let num_pages = bufmgr.num_pages();
+        btree.recreate(&mut bufmgr).unwrap();
+        assert_eq!(0, btree.num_entries(&mut bufmgr).unwrap());
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        for i in 0u64..16 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 1024])
+                .unwrap();
+        }
+        assert_eq!(num_pages, bufmgr.num_pages());
+    }
+
+    #[test]
+    fn test_verify() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..512 {
+            btree
+                .insert(&mut bufmgr, &(i * 7 % 512).to_be_bytes(), &[0; 256])
+                .unwrap();
+        }
+        let verification = btree.verify(&mut bufmgr).unwrap();
+        assert!(verification.problems.is_empty(), "{:?}", verification);
+        assert_eq!(512, verification.num_entries);
+        assert_eq!(
+            btree.num_pages(&mut bufmgr).unwrap(),
+            verification.num_pages
+        );
+        assert!(verification.depth > 1);
+
+        btree.set_num_entries(&mut bufmgr, 500).unwrap();
+        assert_eq!(
+            vec!["the meta page counts 500 entries but the leaves hold 512".to_string()],
+            btree.verify(&mut bufmgr).unwrap().problems
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        let (data_file, data_file_path) = tempfile::NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..512 {
+            btree
+                .insert(&mut bufmgr, &(i * 7 % 512).to_be_bytes(), &[0; 256])
+                .unwrap();
+        }
+        for i in (0u64..512).filter(|i| i % 4 != 0) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        let num_pages = btree.num_pages(&mut bufmgr).unwrap();
+
+        bufmgr.begin().unwrap();
+        assert!(matches!(
+            btree.compact(&mut bufmgr),
+            Err(Error::InTransaction)
+        ));
+        bufmgr.rollback().unwrap();
+        let compaction = btree.compact(&mut bufmgr).unwrap();
+        assert_eq!(num_pages, compaction.num_pages_before);
+        assert!(compaction.num_pages_after * 2 < compaction.num_pages_before);
+        assert_eq!(128, compaction.num_entries);
+        assert_eq!(
+            compaction.num_pages_after,
+            btree.num_pages(&mut bufmgr).unwrap()
+        );
+
+        // the swap is durable without a further flush
+        bufmgr.leak_on_drop();
+        drop(bufmgr);
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+            keys.push(u64::from_be_bytes(key.as_slice().try_into().unwrap()));
+        }
+        assert_eq!((0u64..512).step_by(4).collect::<Vec<_>>(), keys);
+        assert_eq!(128, btree.num_entries(&mut bufmgr).unwrap());
+        for i in (0u64..512).filter(|i| i % 4 == 1) {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[1; 256])
+                .unwrap();
+        }
+        assert_eq!(
+            Some(vec![0; 256]),
+            btree.get(&mut bufmgr, &8u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            Some(vec![1; 256]),
+            btree.get(&mut bufmgr, &9u64.to_be_bytes()).unwrap()
+        );
+
+        let empty = BTree::create(&mut bufmgr).unwrap();
+        let compaction = empty.compact(&mut bufmgr).unwrap();
+        assert_eq!(2, compaction.num_pages_after);
+        assert!(empty
+            .search(&mut bufmgr, SearchMode::Start)
+            .unwrap()
+            .next(&mut bufmgr)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_create_first() {
+        let (data_file, data_file_path) = tempfile::NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        assert!(bufmgr.was_empty());
+        let btree = BTree::create_first(&mut bufmgr, false).unwrap();
+        assert_eq!(FIRST_META_PAGE_ID, btree.meta_page_id);
+        btree.insert(&mut bufmgr, b"key", b"value").unwrap();
+        assert!(matches!(
+            BTree::create_first(&mut bufmgr, false),
+            Err(Error::FileNotEmpty)
+        ));
+        bufmgr.flush().unwrap();
+        drop(bufmgr);
+
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        assert!(!bufmgr.was_empty());
+        assert!(matches!(
+            BTree::create_first(&mut bufmgr, false),
+            Err(Error::FileNotEmpty)
+        ));
+        let other = BTree::create_first(&mut bufmgr, true).unwrap();
+        assert_ne!(FIRST_META_PAGE_ID, other.meta_page_id);
+        let btree = BTree::new(FIRST_META_PAGE_ID);
+        assert_eq!(
+            Some(b"value".to_vec()),
+            btree.get(&mut bufmgr, b"key").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_torn_meta() {
+        let (data_file, data_file_path) = tempfile::NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create_first(&mut bufmgr, false).unwrap();
+        for i in 0u64..1000 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 64])
+                .unwrap();
+        }
+        // both copies say the same, whichever one survives
+        btree.set_num_entries(&mut bufmgr, 1000).unwrap();
+        btree.set_num_entries(&mut bufmgr, 1000).unwrap();
+        drop(bufmgr);
+
+        let mut meta_page = vec![0; PAGE_SIZE];
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&data_file_path)
+            .unwrap();
+        file.read_exact(&mut meta_page).unwrap();
+        let mut write_meta_page = |corrupted: &[usize]| {
+            let mut page = meta_page.clone();
+            for &offset in corrupted {
+                page[offset..offset + 48].fill(0xaa);
+            }
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.write_all(&page).unwrap();
+        };
+        let open = || {
+            let disk = DiskManager::open(&data_file_path).unwrap();
+            BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE))
+        };
+
+        for &offset in &[0, PAGE_SIZE / 2] {
+            write_meta_page(&[offset]);
+            let mut bufmgr = open();
+            assert_eq!(
+                Some(vec![0; 64]),
+                btree.get(&mut bufmgr, &999u64.to_be_bytes()).unwrap()
+            );
+            let verification = btree.verify(&mut bufmgr).unwrap();
+            assert_eq!(1000, verification.num_entries);
+            assert!(verification.problems.is_empty());
+            bufmgr.leak_on_drop();
+        }
+
+        write_meta_page(&[0, PAGE_SIZE / 2]);
+        let mut bufmgr = open();
+        let err = btree.get(&mut bufmgr, &0u64.to_be_bytes()).unwrap_err();
+        assert_eq!(
+            "page 0 is corrupted: both copies of the meta header are corrupted",
+            err.to_string()
+        );
+        bufmgr.leak_on_drop();
+
+        write_meta_page(&[]);
+        let mut bufmgr = open();
+        btree
+            .write_meta(&mut bufmgr, |header| header.root_page_id = PageId(10_000))
+            .unwrap();
+        let err = btree.get(&mut bufmgr, &0u64.to_be_bytes()).unwrap_err();
+        assert_eq!(
+            "page 0 is corrupted: the root page id is out of range",
+            err.to_string()
+        );
+        bufmgr.leak_on_drop();
+    }
+
+    #[test]
+    fn test_latches() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..200 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 64])
+                .unwrap();
+        }
+        let root_page_id = btree.read_meta(&mut bufmgr).unwrap().root_page_id;
+        let (leaf_buffer, depth) = btree
+            .find_leaf(&mut bufmgr, &100u64.to_be_bytes(), LatchMode::Shared)
+            .unwrap();
+        assert!(depth > 0);
+        // the branches were released on the way down
+        assert_eq!(vec![leaf_buffer.page_id], bufmgr.held_latches());
+
+        // readers share the leaf; writers need it to themselves
+        assert!(btree
+            .get(&mut bufmgr, &100u64.to_be_bytes())
+            .unwrap()
+            .is_some());
+        let err = btree
+            .delete(&mut bufmgr, &100u64.to_be_bytes())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Buffer(buffer::Error::LatchConflict(page_id)) if page_id == leaf_buffer.page_id
+        ));
+        drop(leaf_buffer);
+        btree.delete(&mut bufmgr, &100u64.to_be_bytes()).unwrap();
+
+        // an insert latches the root exclusively
+        let root_buffer = bufmgr.fetch_page_shared(root_page_id).unwrap();
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_some());
+        let err = btree
+            .insert(&mut bufmgr, &1000u64.to_be_bytes(), b"")
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Buffer(buffer::Error::LatchConflict(_))
+        ));
+        drop(root_buffer);
+        btree
+            .insert(&mut bufmgr, &1000u64.to_be_bytes(), b"")
+            .unwrap();
+        assert!(bufmgr.held_latches().is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "a child must be latched right after its parent")]
+    fn test_latch_order() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let other = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..200 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &[0; 64])
+                .unwrap();
+        }
+        let root_page_id = btree.read_meta(&mut bufmgr).unwrap().root_page_id;
+        let root_buffer = latch_node(&mut bufmgr, root_page_id, LatchMode::Shared).unwrap();
+        let child_page_id = {
+            let node = node::Node::new(root_buffer.bytes());
+            branch::Branch::new(node.body).child_at(0).unwrap()
+        };
+        // a latch taken in between, on a page not on the path
+        let other_root_page_id = other.read_meta(&mut bufmgr).unwrap().root_page_id;
+        let _other_root = bufmgr.fetch_page_shared(other_root_page_id).unwrap();
+        let _ = latch_child(
+            &mut bufmgr,
+            &root_buffer,
+            child_page_id,
+            1,
+            LatchMode::Shared,
+        );
+    }
+
+    #[test]
+    fn test_pool_too_small() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE - 1));
+        let err = BTree::create(&mut bufmgr).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::PoolTooSmall {
+                required: MIN_POOL_SIZE,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains(&MIN_POOL_SIZE.to_string()));
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        // three pairs fill the root leaf
+        for key in [b"a", b"b", b"c"] {
+            btree.insert(&mut bufmgr, key, &[0; 1200]).unwrap();
+        }
+        let pin_others = |bufmgr: &mut BufferPoolManager| -> Vec<_> {
+            (0..MIN_POOL_SIZE - 2)
+                .map(|_| bufmgr.create_page().unwrap())
+                .collect()
+        };
+        // with the leaf pinned as well, one frame is left for a split that needs two
+        let pinned = pin_others(&mut bufmgr);
+        let err = btree.update(&mut bufmgr, b"b", &[1; 1900]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::PoolTooSmall {
+                required,
+                ..
+            } if required == MIN_POOL_SIZE + 1
+        ));
+        let err = btree.insert(&mut bufmgr, b"d", &[0; 1200]).unwrap_err();
+        assert!(matches!(err, Error::PoolTooSmall { .. }));
+        drop(pinned);
+
+        // neither changed anything
+        assert_eq!(Some(vec![0; 1200]), btree.get(&mut bufmgr, b"b").unwrap());
+        assert_eq!(None, btree.get(&mut bufmgr, b"d").unwrap());
+        let verification = btree.verify(&mut bufmgr).unwrap();
+        assert_eq!(3, verification.num_entries);
+        assert!(verification.problems.is_empty());
+
+        btree.update(&mut bufmgr, b"b", &[1; 1900]).unwrap();
+        btree.insert(&mut bufmgr, b"d", &[0; 1200]).unwrap();
+        let verification = btree.verify(&mut bufmgr).unwrap();
+        assert_eq!(4, verification.num_entries);
+        assert!(verification.problems.is_empty());
+    }
+
+    #[test]
+    fn test_evicted_writes() {
+        let (data_file, data_file_path) = tempfile::NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        // a page of zeros reads as a free one
+        let other_page_ids: Vec<_> = (0..MIN_POOL_SIZE)
+            .map(|_| {
+                let buffer = bufmgr.create_page().unwrap();
+                buffer.bytes_mut()[0] = 1;
+                buffer.page_id
+            })
+            .collect();
+        // pinned all at once, the other pages take every frame
+        let evict = |bufmgr: &mut BufferPoolManager| {
+            let _pinned: Vec<_> = other_page_ids
+                .iter()
+                .map(|&page_id| bufmgr.fetch_page(page_id).unwrap())
+                .collect();
+        };
+        evict(&mut bufmgr);
+        btree.insert(&mut bufmgr, b"a", b"1").unwrap();
+        btree.insert(&mut bufmgr, b"b", b"2").unwrap();
+        evict(&mut bufmgr);
+        btree.update(&mut bufmgr, b"a", b"3").unwrap();
+        evict(&mut bufmgr);
+        btree.delete(&mut bufmgr, b"b").unwrap();
+        evict(&mut bufmgr);
+        // only what the evictions wrote back survives
+        bufmgr.leak_on_drop();
+        drop(bufmgr);
+
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        assert_eq!(Some(b"3".to_vec()), btree.get(&mut bufmgr, b"a").unwrap());
+        assert_eq!(None, btree.get(&mut bufmgr, b"b").unwrap());
+        let verification = btree.verify(&mut bufmgr).unwrap();
+        assert_eq!(1, verification.num_entries);
+        assert!(verification.problems.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_pair() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for key in [b"a", b"b", b"c"] {
+            btree.insert(&mut bufmgr, key, b"value").unwrap();
+        }
+        let root_buffer = btree.fetch_root_page(&mut bufmgr).unwrap();
+        {
+            let mut node = node::Node::new(root_buffer.bytes_mut());
+            let mut slotted = slotted::Slotted::new(&mut node.body[size_of::<leaf::Header>()..]);
+            slotted[1].fill(0xff);
+        }
+
+        let err = btree.get(&mut bufmgr, b"b").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidSlot {
+                page_id,
+                source: SlotError::InvalidPair { slot_id: 1, .. },
+            } if page_id == root_buffer.page_id
+        ));
+        assert_eq!(
+            format!(
+                "page {}: slot 1 does not hold a pair",
+                root_buffer.page_id.to_u64()
+            ),
+            err.to_string()
+        );
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        assert_eq!(b"a", &iter.next(&mut bufmgr).unwrap().unwrap().0[..]);
+        assert!(iter.next(&mut bufmgr).is_err());
+    }
+
+    /// Walks up to a bounded number of keys, as a corrupted leaf chain
+    /// may loop.
+    fn scan(
+        btree: &BTree,
+        bufmgr: &mut BufferPoolManager,
+        direction: ScanDirection,
+    ) -> Result<(), Error> {
+        let mut iter = btree.search_directed(bufmgr, SearchMode::Start, direction)?;
+        for _ in 0..10_000 {
+            if iter.next(bufmgr)?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_pages() {
+        let mut heap_file = tempfile().unwrap();
+        {
+            let disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+            let btree = BTree::create(&mut bufmgr).unwrap();
+            for i in 0u64..512 {
+                btree
+                    .insert(&mut bufmgr, &(i * 7 % 512).to_be_bytes(), &[i as u8; 256])
+                    .unwrap();
+            }
+            bufmgr.flush().unwrap();
+        }
+        let mut heap = vec![];
+        heap_file.seek(SeekFrom::Start(0)).unwrap();
+        heap_file.read_to_end(&mut heap).unwrap();
+        let num_pages = heap.len() / PAGE_SIZE;
+
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut num_errors = 0;
+        for round in 0..300 {
+            let mut heap = heap.clone();
+            let page_id = rng.next_u64() as usize % num_pages;
+            let page = &mut heap[page_id * PAGE_SIZE..][..PAGE_SIZE];
+            match round % 3 {
+                // noise
+                0 => page
+                    .iter_mut()
+                    .for_each(|byte| *byte = rng.next_u64() as u8),
+                // noise under a node header
+                1 => {
+                    page.iter_mut()
+                        .for_each(|byte| *byte = rng.next_u64() as u8);
+                    let node_type = [node::NODE_TYPE_LEAF, node::NODE_TYPE_BRANCH][round % 2];
+                    page[..8].copy_from_slice(&node_type);
+                    let num_slots =
+                        8 + [size_of::<leaf::Header>(), size_of::<branch::Header>()][round % 2];
+                    page[num_slots..][..2]
+                        .copy_from_slice(&(rng.next_u64() as u16 % 64).to_le_bytes());
+                }
+                // a few bytes of a sound page
+                _ => {
+                    for _ in 0..8 {
+                        page[rng.next_u64() as usize % 64] = rng.next_u64() as u8;
+                    }
+                }
+            }
+            let mut file = tempfile().unwrap();
+            file.write_all(&heap).unwrap();
+            let disk = DiskManager::new(file).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+            let btree = BTree::new(PageId(0));
+
+            let results = [
+                scan(&btree, &mut bufmgr, ScanDirection::Forward),
+                scan(&btree, &mut bufmgr, ScanDirection::Backward),
+                btree.get(&mut bufmgr, &300u64.to_be_bytes()).map(drop),
+                btree.insert(&mut bufmgr, &1000u64.to_be_bytes(), b"new"),
+                btree.delete(&mut bufmgr, &5u64.to_be_bytes()),
+            ];
+            num_errors += results.iter().filter(|result| result.is_err()).count();
+            if round % 3 == 0 {
+                let page_is_reported = match btree.verify(&mut bufmgr) {
+                    Ok(verification) => !verification.problems.is_empty(),
+                    Err(_) => true,
+                };
+                assert!(page_is_reported, "page {} of round {}", page_id, round);
+            }
+        }
+        assert!(num_errors > 0);
+    }
 }
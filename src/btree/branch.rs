@@ -1,9 +1,10 @@
+use std::convert::{identity, TryFrom};
 use std::mem::size_of;
 
 use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, LayoutVerified};
 
-use super::Pair;
-use crate::bsearch::binary_search_by;
+use super::{Pair, SlotError};
+use crate::bsearch::try_binary_search_by;
 use crate::disk::PageId;
 use crate::slotted::{self, Slotted};
 
@@ -30,39 +31,60 @@ impl<B: ByteSlice> Branch<B> {
         self.body.num_slots()
     }
 
-    pub fn search_slot_id(&self, key: &[u8]) -> Result<usize, usize> {
-        binary_search_by(self.num_pairs(), |slot_id| {
-            self.pair_at(slot_id).key.cmp(key)
+    pub fn search_slot_id(&self, key: &[u8]) -> Result<Result<usize, usize>, SlotError> {
+        try_binary_search_by(self.num_pairs(), |slot_id| {
+            Ok(self.pair_at(slot_id)?.key.cmp(key))
         })
     }
 
-    pub fn search_child(&self, key: &[u8]) -> PageId {
-        let child_idx = self.search_child_idx(key);
+    pub fn search_child(&self, key: &[u8]) -> Result<PageId, SlotError> {
+        let child_idx = self.search_child_idx(key)?;
         self.child_at(child_idx)
     }
 
-    pub fn search_child_idx(&self, key: &[u8]) -> usize {
-        match self.search_slot_id(key) {
-            Ok(slot_id) => slot_id + 1,
-            Err(slot_id) => slot_id,
+    pub fn search_child_idx(&self, key: &[u8]) -> Result<usize, SlotError> {
+        match self.search_slot_id(key)? {
+            Ok(slot_id) => Ok(slot_id + 1),
+            Err(slot_id) => Ok(slot_id),
         }
     }
 
-    pub fn child_at(&self, child_idx: usize) -> PageId {
+    pub fn child_at(&self, child_idx: usize) -> Result<PageId, SlotError> {
         if child_idx == self.num_pairs() {
-            self.header.right_child
+            Ok(self.header.right_child)
         } else {
-            self.pair_at(child_idx).value.into()
+            self.child_in(child_idx, self.pair_at(child_idx)?)
         }
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
+    fn child_in(&self, slot_id: usize, pair: Pair) -> Result<PageId, SlotError> {
+        PageId::try_from(pair.value).map_err(|source| SlotError::InvalidChild { slot_id, source })
+    }
+
+    pub fn pair_at(&self, slot_id: usize) -> Result<Pair<'_>, SlotError> {
         Pair::from_bytes(&self.body[slot_id])
+            .map_err(|source| SlotError::InvalidPair { slot_id, source })
     }
 
     pub fn max_pair_size(&self) -> usize {
         self.body.capacity() / 2 - size_of::<slotted::Pointer>()
     }
+
+    /// Besides the pairs, a branch must have a key and its values must be
+    /// page ids.
+    pub fn check(&self) -> Result<(), &'static str> {
+        let pairs = super::check_pairs(&self.body, self.max_pair_size())?;
+        if pairs.is_empty() {
+            return Err("a branch has no keys");
+        }
+        if pairs
+            .iter()
+            .any(|pair| PageId::try_from(pair.value).is_err())
+        {
+            return Err("a child is not a page id");
+        }
+        Ok(())
+    }
 }
 
 impl<B: ByteSliceMut> Branch<B> {
@@ -73,14 +95,18 @@ impl<B: ByteSliceMut> Branch<B> {
         self.header.right_child = right_child;
     }
 
-    pub fn fill_right_child(&mut self) -> Vec<u8> {
+    pub fn fill_right_child(&mut self) -> Result<Vec<u8>, SlotError> {
         let last_id = self.num_pairs() - 1;
-        let Pair { key, value } = self.pair_at(last_id);
-        let right_child: PageId = value.into();
-        let key_vec = key.to_vec();
+        let pair = self.pair_at(last_id)?;
+        let key_vec = pair.key.to_vec();
+        let right_child = self.child_in(last_id, pair)?;
         self.body.remove(last_id);
         self.header.right_child = right_child;
-        key_vec
+        Ok(key_vec)
+    }
+
+    pub fn set_right_child(&mut self, right_child: PageId) {
+        self.header.right_child = right_child;
     }
 
     #[must_use = "insertion may fail"]
@@ -105,18 +131,18 @@ impl<B: ByteSliceMut> Branch<B> {
         new_branch: &mut Branch<impl ByteSliceMut>,
         new_key: &[u8],
         new_page_id: PageId,
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>, SlotError> {
         new_branch.body.initialize();
         loop {
             if new_branch.is_half_full() {
-                let index = self
-                    .search_slot_id(new_key)
-                    .expect_err("key must be unique");
+                // a child of a corrupted tree may split at a key its
+                // parent already has, which is as good a place as any
+                let index = self.search_slot_id(new_key)?.unwrap_or_else(identity);
                 self.insert(index, new_key, new_page_id)
                     .expect("old branch must have space");
                 break;
             }
-            if self.pair_at(0).key < new_key {
+            if self.pair_at(0)?.key < new_key {
                 self.transfer(new_branch);
             } else {
                 new_branch
@@ -144,6 +170,7 @@ impl<B: ByteSliceMut> Branch<B> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::disk::InvalidPageId;
 
     #[test]
     fn test_insert_search() {
@@ -152,13 +179,22 @@ mod tests {
         branch.initialize(&5u64.to_be_bytes(), PageId(1), PageId(2));
         branch.insert(1, &8u64.to_be_bytes(), PageId(3)).unwrap();
         branch.insert(2, &11u64.to_be_bytes(), PageId(4)).unwrap();
-        assert_eq!(PageId(1), branch.search_child(&1u64.to_be_bytes()));
-        assert_eq!(PageId(3), branch.search_child(&5u64.to_be_bytes()));
-        assert_eq!(PageId(3), branch.search_child(&6u64.to_be_bytes()));
-        assert_eq!(PageId(4), branch.search_child(&8u64.to_be_bytes()));
-        assert_eq!(PageId(4), branch.search_child(&10u64.to_be_bytes()));
-        assert_eq!(PageId(2), branch.search_child(&11u64.to_be_bytes()));
-        assert_eq!(PageId(2), branch.search_child(&12u64.to_be_bytes()));
+        assert_eq!(PageId(1), branch.search_child(&1u64.to_be_bytes()).unwrap());
+        assert_eq!(PageId(3), branch.search_child(&5u64.to_be_bytes()).unwrap());
+        assert_eq!(PageId(3), branch.search_child(&6u64.to_be_bytes()).unwrap());
+        assert_eq!(PageId(4), branch.search_child(&8u64.to_be_bytes()).unwrap());
+        assert_eq!(
+            PageId(4),
+            branch.search_child(&10u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(2),
+            branch.search_child(&11u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(2),
+            branch.search_child(&12u64.to_be_bytes()).unwrap()
+        );
     }
 
     #[test]
@@ -171,19 +207,76 @@ mod tests {
 
         let mut data2 = vec![0u8; 100];
         let mut branch2 = Branch::new(data2.as_mut_slice());
-        let mid_key = branch.split_insert(&mut branch2, &10u64.to_be_bytes(), PageId(5));
+        let mid_key = branch
+            .split_insert(&mut branch2, &10u64.to_be_bytes(), PageId(5))
+            .unwrap();
         assert_eq!(&8u64.to_be_bytes(), mid_key.as_slice());
 
         assert_eq!(2, branch.num_pairs());
         assert_eq!(1, branch2.num_pairs());
 
-        assert_eq!(PageId(1), branch2.search_child(&1u64.to_be_bytes()));
-        assert_eq!(PageId(3), branch2.search_child(&5u64.to_be_bytes()));
-        assert_eq!(PageId(3), branch2.search_child(&6u64.to_be_bytes()));
+        assert_eq!(
+            PageId(1),
+            branch2.search_child(&1u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(3),
+            branch2.search_child(&5u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(3),
+            branch2.search_child(&6u64.to_be_bytes()).unwrap()
+        );
+
+        assert_eq!(PageId(5), branch.search_child(&9u64.to_be_bytes()).unwrap());
+        assert_eq!(
+            PageId(4),
+            branch.search_child(&10u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(2),
+            branch.search_child(&11u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            PageId(2),
+            branch.search_child(&12u64.to_be_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_malformed_child() {
+        let mut data = vec![0u8; 100];
+        let mut branch = Branch::new(data.as_mut_slice());
+        branch.initialize(&5u64.to_be_bytes(), PageId(1), PageId(2));
+        let pair = Pair {
+            key: &8u64.to_be_bytes(),
+            value: b"abc",
+        };
+        let pair_bytes = pair.to_bytes();
+        branch.body.insert(1, pair_bytes.len()).unwrap();
+        branch.body[1].copy_from_slice(&pair_bytes);
+
+        assert_eq!(PageId(1), branch.search_child(&1u64.to_be_bytes()).unwrap());
+        assert!(matches!(
+            branch.search_child(&5u64.to_be_bytes()),
+            Err(SlotError::InvalidChild {
+                slot_id: 1,
+                source: InvalidPageId(3)
+            })
+        ));
+        assert_eq!(PageId(2), branch.search_child(&8u64.to_be_bytes()).unwrap());
+        assert_eq!(Err("a child is not a page id"), branch.check());
+        assert!(matches!(
+            branch.fill_right_child(),
+            Err(SlotError::InvalidChild { slot_id: 1, .. })
+        ));
+        assert_eq!("a page id is 8 bytes, not 3", InvalidPageId(3).to_string());
 
-        assert_eq!(PageId(5), branch.search_child(&9u64.to_be_bytes()));
-        assert_eq!(PageId(4), branch.search_child(&10u64.to_be_bytes()));
-        assert_eq!(PageId(2), branch.search_child(&11u64.to_be_bytes()));
-        assert_eq!(PageId(2), branch.search_child(&12u64.to_be_bytes()));
+        // a slot that is not a pair at all
+        branch.body[0].fill(0xff);
+        assert!(matches!(
+            branch.search_child(&1u64.to_be_bytes()),
+            Err(SlotError::InvalidPair { slot_id: 0, .. })
+        ));
     }
 }
@@ -2,8 +2,8 @@ use std::mem::size_of;
 
 use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, LayoutVerified};
 
-use super::Pair;
-use crate::bsearch::binary_search_by;
+use super::{Pair, SlotError};
+use crate::bsearch::try_binary_search_by;
 use crate::disk::PageId;
 use crate::slotted::{self, Slotted};
 
@@ -39,25 +39,31 @@ impl<B: ByteSlice> Leaf<B> {
         self.body.num_slots()
     }
 
-    pub fn search_slot_id(&self, key: &[u8]) -> Result<usize, usize> {
-        binary_search_by(self.num_pairs(), |slot_id| {
-            self.pair_at(slot_id).key.cmp(key)
+    pub fn search_slot_id(&self, key: &[u8]) -> Result<Result<usize, usize>, SlotError> {
+        try_binary_search_by(self.num_pairs(), |slot_id| {
+            Ok(self.pair_at(slot_id)?.key.cmp(key))
         })
     }
 
     #[cfg(test)]
-    pub fn search_pair(&self, key: &[u8]) -> Option<Pair> {
-        let slot_id = self.search_slot_id(key).ok()?;
-        Some(self.pair_at(slot_id))
+    pub fn search_pair(&self, key: &[u8]) -> Option<Pair<'_>> {
+        let slot_id = self.search_slot_id(key).unwrap().ok()?;
+        Some(self.pair_at(slot_id).unwrap())
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
+    pub fn pair_at(&self, slot_id: usize) -> Result<Pair<'_>, SlotError> {
         Pair::from_bytes(&self.body[slot_id])
+            .map_err(|source| SlotError::InvalidPair { slot_id, source })
     }
 
     pub fn max_pair_size(&self) -> usize {
         self.body.capacity() / 2 - size_of::<slotted::Pointer>()
     }
+
+    pub fn check(&self) -> Result<(), &'static str> {
+        super::check_pairs(&self.body, self.max_pair_size())?;
+        Ok(())
+    }
 }
 
 impl<B: ByteSliceMut> Leaf<B> {
@@ -85,6 +91,23 @@ impl<B: ByteSliceMut> Leaf<B> {
         Some(())
     }
 
+    /// `Ok(None)` if the new value does not fit.
+    pub fn update(&mut self, slot_id: usize, value: &[u8]) -> Result<Option<()>, SlotError> {
+        let key = self.pair_at(slot_id)?.key.to_vec();
+        let pair = Pair { key: &key, value };
+        let pair_bytes = pair.to_bytes();
+        assert!(pair_bytes.len() <= self.max_pair_size());
+        if self.body.resize(slot_id, pair_bytes.len()).is_none() {
+            return Ok(None);
+        }
+        self.body[slot_id].copy_from_slice(&pair_bytes);
+        Ok(Some(()))
+    }
+
+    pub fn remove(&mut self, slot_id: usize) {
+        self.body.remove(slot_id);
+    }
+
     fn is_half_full(&self) -> bool {
         2 * self.body.free_space() < self.body.capacity()
     }
@@ -94,18 +117,18 @@ impl<B: ByteSliceMut> Leaf<B> {
         new_leaf: &mut Leaf<impl ByteSliceMut>,
         new_key: &[u8],
         new_value: &[u8],
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>, SlotError> {
         new_leaf.initialize();
         loop {
             if new_leaf.is_half_full() {
                 let index = self
-                    .search_slot_id(new_key)
+                    .search_slot_id(new_key)?
                     .expect_err("key must be unique");
                 self.insert(index, new_key, new_value)
                     .expect("old leaf must have space");
                 break;
             }
-            if self.pair_at(0).key < new_key {
+            if self.pair_at(0)?.key < new_key {
                 self.transfer(new_leaf);
             } else {
                 new_leaf
@@ -117,7 +140,7 @@ impl<B: ByteSliceMut> Leaf<B> {
                 break;
             }
         }
-        self.pair_at(0).key.to_vec()
+        Ok(self.pair_at(0)?.key.to_vec())
     }
 
     pub fn transfer(&mut self, dest: &mut Leaf<impl ByteSliceMut>) {
@@ -138,45 +161,70 @@ mod tests {
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
         leaf_page.initialize();
 
-        let id = leaf_page.search_slot_id(b"deadbeef").unwrap_err();
+        let id = leaf_page.search_slot_id(b"deadbeef").unwrap().unwrap_err();
         assert_eq!(0, id);
         leaf_page.insert(id, b"deadbeef", b"world").unwrap();
-        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key);
+        assert_eq!(b"deadbeef", leaf_page.pair_at(0).unwrap().key);
 
-        let id = leaf_page.search_slot_id(b"facebook").unwrap_err();
+        let id = leaf_page.search_slot_id(b"facebook").unwrap().unwrap_err();
         assert_eq!(1, id);
         leaf_page.insert(id, b"facebook", b"!").unwrap();
-        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key);
-        assert_eq!(b"facebook", leaf_page.pair_at(1).key);
+        assert_eq!(b"deadbeef", leaf_page.pair_at(0).unwrap().key);
+        assert_eq!(b"facebook", leaf_page.pair_at(1).unwrap().key);
 
-        let id = leaf_page.search_slot_id(b"beefdead").unwrap_err();
+        let id = leaf_page.search_slot_id(b"beefdead").unwrap().unwrap_err();
         assert_eq!(0, id);
         leaf_page.insert(id, b"beefdead", b"hello").unwrap();
-        assert_eq!(b"beefdead", leaf_page.pair_at(0).key);
-        assert_eq!(b"deadbeef", leaf_page.pair_at(1).key);
-        assert_eq!(b"facebook", leaf_page.pair_at(2).key);
+        assert_eq!(b"beefdead", leaf_page.pair_at(0).unwrap().key);
+        assert_eq!(b"deadbeef", leaf_page.pair_at(1).unwrap().key);
+        assert_eq!(b"facebook", leaf_page.pair_at(2).unwrap().key);
         assert_eq!(
             &b"hello"[..],
             leaf_page.search_pair(b"beefdead").unwrap().value
         );
     }
 
+    #[test]
+    fn test_leaf_update_remove() {
+        let mut page_data = vec![0; 100];
+        let mut leaf_page = Leaf::new(page_data.as_mut_slice());
+        leaf_page.initialize();
+        leaf_page.insert(0, b"deadbeef", b"world").unwrap();
+        leaf_page.insert(1, b"facebook", b"!").unwrap();
+
+        leaf_page.update(0, b"hello, world").unwrap().unwrap();
+        assert_eq!(&b"hello, world"[..], leaf_page.pair_at(0).unwrap().value);
+        assert_eq!(&b"!"[..], leaf_page.pair_at(1).unwrap().value);
+        leaf_page.update(0, b"w").unwrap().unwrap();
+        assert_eq!(&b"w"[..], leaf_page.pair_at(0).unwrap().value);
+        leaf_page.insert(2, b"zzzzzzzz", b"hello").unwrap();
+        leaf_page.insert(3, b"zzzzzzzzz", b"hello").unwrap();
+        assert!(leaf_page.update(1, &[0; 20]).unwrap().is_none());
+        assert_eq!(&b"!"[..], leaf_page.pair_at(1).unwrap().value);
+
+        leaf_page.remove(0);
+        assert_eq!(3, leaf_page.num_pairs());
+        assert_eq!(b"facebook", leaf_page.pair_at(0).unwrap().key);
+    }
+
     #[test]
     fn test_leaf_split_insert() {
         let mut page_data = vec![0; 62];
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
         leaf_page.initialize();
-        let id = leaf_page.search_slot_id(b"deadbeef").unwrap_err();
+        let id = leaf_page.search_slot_id(b"deadbeef").unwrap().unwrap_err();
         leaf_page.insert(id, b"deadbeef", b"world").unwrap();
-        let id = leaf_page.search_slot_id(b"facebook").unwrap_err();
+        let id = leaf_page.search_slot_id(b"facebook").unwrap().unwrap_err();
         leaf_page.insert(id, b"facebook", b"!").unwrap();
-        let id = leaf_page.search_slot_id(b"beefdead").unwrap_err();
+        let id = leaf_page.search_slot_id(b"beefdead").unwrap().unwrap_err();
         assert!(leaf_page.insert(id, b"beefdead", b"hello").is_none());
 
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
         let mut new_page_data = vec![0; 62];
         let mut new_leaf_page = Leaf::new(new_page_data.as_mut_slice());
-        leaf_page.split_insert(&mut new_leaf_page, b"beefdead", b"hello");
+        leaf_page
+            .split_insert(&mut new_leaf_page, b"beefdead", b"hello")
+            .unwrap();
         assert_eq!(
             &b"world"[..],
             new_leaf_page.search_pair(b"deadbeef").unwrap().value
@@ -1,22 +1,145 @@
-use zerocopy::{AsBytes, ByteSlice, FromBytes, LayoutVerified};
+use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, LayoutVerified};
 
 use crate::disk::PageId;
 
-#[derive(Debug, FromBytes, AsBytes)]
+#[derive(Debug, Clone, FromBytes, AsBytes)]
 #[repr(C)]
 pub struct Header {
     pub root_page_id: PageId,
+    /// Maintained by insert and delete. Trees written before it existed
+    /// read as 0 until it is set again.
+    pub num_entries: u64,
+    /// An opaque tag describing how keys are encoded; see
+    /// `BTree::create_with_key_format`.
+    pub key_format: u64,
+    /// Bumped by every change that may move entries within or between
+    /// leaves, so that an `Iter` knows to find its place again.
+    pub version: u64,
+    /// The number of elements in each key, for trees of tables; 0 when
+    /// not recorded, as in trees written before it was.
+    pub key_arity: u64,
+}
+
+/// One of the two copies of the header. A copy with sequence 0 was never
+/// written.
+#[derive(Debug, FromBytes, AsBytes)]
+#[repr(C)]
+struct Record {
+    header: Header,
+    sequence: u64,
+    checksum: u64,
+}
+
+impl Record {
+    fn is_intact(&self) -> bool {
+        self.sequence != 0 && self.checksum == checksum(&self.header, self.sequence)
+    }
+}
+
+/// FNV-1a over the header and its sequence number.
+fn checksum(header: &Header, sequence: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in header.as_bytes().iter().chain(&sequence.to_ne_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+    hash
 }
 
+/// The meta page keeps two copies of the header, one in each half of the
+/// page so that a torn write is unlikely to reach both, and each update
+/// overwrites the older one. Reads take the newest copy whose checksum
+/// holds.
 pub struct Meta<B> {
-    pub header: LayoutVerified<B, Header>,
-    _unused: B,
+    records: [LayoutVerified<B, Record>; 2],
 }
 
 impl<B: ByteSlice> Meta<B> {
     pub fn new(bytes: B) -> Self {
-        let (header, _unused) =
-            LayoutVerified::new_from_prefix(bytes).expect("meta page must be aligned");
-        Self { header, _unused }
+        let half = bytes.len() / 2;
+        let (first, second) = bytes.split_at(half);
+        let record = |bytes| {
+            let (record, _) =
+                LayoutVerified::new_from_prefix(bytes).expect("meta page must be aligned");
+            record
+        };
+        Self {
+            records: [record(first), record(second)],
+        }
+    }
+
+    /// The index of the newest intact copy.
+    fn newest(&self) -> Option<usize> {
+        (0..2)
+            .filter(|&i| self.records[i].is_intact())
+            .max_by_key(|&i| (self.records[i].sequence, 1 - i))
+    }
+
+    /// A page written before there were two copies has the header at the
+    /// start and zeros after it.
+    fn is_single_copy(&self) -> bool {
+        self.records
+            .iter()
+            .all(|record| record.sequence == 0 && record.checksum == 0)
+    }
+
+    pub fn header(&self) -> Result<Header, &'static str> {
+        match self.newest() {
+            Some(i) => Ok(self.records[i].header.clone()),
+            None if self.is_single_copy() => Ok(self.records[0].header.clone()),
+            None => Err("both copies of the meta header are corrupted"),
+        }
+    }
+}
+
+impl<B: ByteSliceMut> Meta<B> {
+    /// Writes both copies, for a new page.
+    pub fn initialize(&mut self, header: &Header) {
+        self.set_header(header);
+        self.set_header(header);
+    }
+
+    /// Overwrites the older copy, or the second one of a single copy page,
+    /// so that the newest one survives a torn write of this one.
+    pub fn set_header(&mut self, header: &Header) {
+        let (i, sequence) = match self.newest() {
+            Some(newest) => (1 - newest, self.records[newest].sequence.saturating_add(1)),
+            None => (1, 1),
+        };
+        let record = &mut self.records[i];
+        record.header = header.clone();
+        record.sequence = sequence;
+        record.checksum = checksum(header, sequence);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_torn_copy() {
+        let mut data = vec![0u64; 64];
+        let mut meta = Meta::new(data.as_bytes_mut());
+        let header = |version| Header {
+            root_page_id: PageId(1),
+            num_entries: 0,
+            key_format: 0,
+            version,
+            key_arity: 0,
+        };
+        meta.initialize(&header(0));
+        meta.set_header(&header(1));
+        assert_eq!(1, meta.header().unwrap().version);
+
+        // the copy just written is torn, so the one before it is read
+        meta.records[1].header.version = 2;
+        assert_eq!(0, meta.header().unwrap().version);
+        meta.set_header(&header(3));
+        assert_eq!(3, meta.header().unwrap().version);
+
+        meta.records[0].checksum ^= 1;
+        meta.records[1].checksum ^= 1;
+        assert!(meta.header().is_err());
     }
 }
@@ -22,6 +22,16 @@ impl<B: ByteSlice> Node<B> {
         let (header, body) = LayoutVerified::new_from_prefix(bytes).expect("node must be aligned");
         Self { header, body }
     }
+
+    /// Checks a node read from disk, after which `Body::new` and the leaf
+    /// and branch accessors may take its layout for granted.
+    pub fn check(&self) -> Result<(), &'static str> {
+        match self.header.node_type {
+            NODE_TYPE_LEAF => Leaf::new(self.body.as_bytes()).check(),
+            NODE_TYPE_BRANCH => Branch::new(self.body.as_bytes()).check(),
+            _ => Err("unknown node type"),
+        }
+    }
 }
 
 impl<B: ByteSliceMut> Node<B> {
@@ -44,7 +54,7 @@ impl<B: ByteSlice> Body<B> {
         match node_type {
             NODE_TYPE_LEAF => Body::Leaf(Leaf::new(bytes)),
             NODE_TYPE_BRANCH => Body::Branch(Branch::new(bytes)),
-            _ => unreachable!(),
+            _ => unreachable!("node type must have been checked"),
         }
     }
 }
@@ -1,10 +1,24 @@
-use std::cell::{Cell, RefCell};
+//! The buffer pool, caching pages of the heap file in frames.
+//!
+//! The pool is single-threaded: frames are shared as `Rc<Buffer>` and their
+//! pages borrowed through `RefCell`. The page latches taken by
+//! `fetch_page_latched` are a stand-in for the per-frame `RwLock` a
+//! multi-threaded engine needs, not that lock: a latch is a pair of `Cell`
+//! counters, and one that cannot be taken fails with `LatchConflict` rather
+//! than blocking, as there is no other thread to wait for. They keep the
+//! btree's latch discipline honest until the pool moves to `Arc` and real
+//! locks, which is still to be done.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::HashMap;
+use std::fmt;
 use std::io;
-use std::ops::{Index, IndexMut};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+use crate::disk::{Allocation, DiskManager, IoStats, PageId, ReadError, PAGE_SIZE};
+use crate::lock::{LockError, LockGuard, LockManager, LockMode, TxnId};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -12,26 +26,196 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error("no free buffer available in buffer pool")]
     NoFreeBuffer,
+    #[error("the transaction has dirtied every page of the buffer pool")]
+    TransactionTooLarge,
+    #[error("a transaction is already open")]
+    TransactionOpen,
+    #[error("no transaction is open")]
+    NoTransaction,
+    #[error("page {} is latched in a conflicting mode", .0.to_u64())]
+    LatchConflict(PageId),
+    #[error("page {} is free or was never written", .0.to_u64())]
+    FreePage(PageId),
+    #[error("page {} is still pinned", .0.to_u64())]
+    PagePinned(PageId),
+}
+
+impl From<ReadError> for Error {
+    fn from(err: ReadError) -> Self {
+        match err {
+            ReadError::Io(err) => Error::Io(err),
+            ReadError::FreePage(page_id) => Error::FreePage(page_id),
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct BufferId(usize);
 
-pub type Page = [u8; PAGE_SIZE];
+/// The bytes of a page, aligned for the zerocopy headers laid over them,
+/// and to the page size for direct I/O.
+#[derive(Clone)]
+#[repr(C, align(4096))]
+pub struct PageData(pub [u8; PAGE_SIZE]);
+
+// The headers read with `LayoutVerified` need at least 8.
+const _: () = assert!(std::mem::align_of::<PageData>() >= 8);
+const _: () = assert!(std::mem::size_of::<PageData>() == PAGE_SIZE);
+
+impl Default for PageData {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl fmt::Debug for PageData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PageData({} bytes)", PAGE_SIZE)
+    }
+}
+
+impl Deref for PageData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for PageData {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+pub type Page = PageData;
+
+/// The pages an open transaction has modified, as they were before.
+type UndoMap = RefCell<HashMap<PageId, Box<Page>>>;
 
 #[derive(Debug)]
 pub struct Buffer {
     pub page_id: PageId,
-    pub page: RefCell<Page>,
+    /// Private so that every write goes through `bytes_mut`.
+    page: RefCell<Page>,
     pub is_dirty: Cell<bool>,
+    /// Set by readers that check the layout of a page once after it is
+    /// read from disk; cleared whenever the frame takes another page.
+    pub is_checked: Cell<bool>,
+    /// Where `bytes_mut` saves the page before the open transaction first
+    /// modifies it.
+    undo: RefCell<Option<Rc<UndoMap>>>,
+    latch: Latch,
+}
+
+impl Buffer {
+    /// The page as bytes, for the node and meta layouts.
+    pub fn bytes(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.page.borrow(), |page| &page[..])
+    }
+
+    /// Marks the page dirty, as taking it mutably is as good as writing it.
+    /// Within a transaction, a clean page is first copied to its undo map:
+    /// `begin` leaves every page clean and the transaction keeps the ones
+    /// it dirties in the pool, so that is the first modification.
+    pub fn bytes_mut(&self) -> RefMut<'_, [u8]> {
+        if !self.is_dirty.get() {
+            if let Some(undo) = &*self.undo.borrow() {
+                undo.borrow_mut()
+                    .entry(self.page_id)
+                    .or_insert_with(|| Box::new(self.page.borrow().clone()));
+            }
+        }
+        self.is_dirty.set(true);
+        RefMut::map(self.page.borrow_mut(), |page| &mut page[..])
+    }
 }
 
 impl Default for Buffer {
     fn default() -> Self {
         Self {
             page_id: Default::default(),
-            page: RefCell::new([0u8; PAGE_SIZE]),
+            page: RefCell::new(PageData::default()),
             is_dirty: Cell::new(false),
+            is_checked: Cell::new(false),
+            undo: RefCell::new(None),
+            latch: Latch::default(),
+        }
+    }
+}
+
+/// How a `PageGuard` holds its page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatchMode {
+    Shared,
+    Exclusive,
+}
+
+/// A reader-writer try-latch, standing in for a per-frame `RwLock` (see the
+/// module docs). A latch that cannot be taken is never waited for:
+/// `fetch_page_latched` fails instead.
+#[derive(Debug, Default)]
+struct Latch {
+    readers: Cell<usize>,
+    writer: Cell<bool>,
+}
+
+impl Latch {
+    fn try_acquire(&self, mode: LatchMode) -> bool {
+        if self.writer.get() {
+            return false;
+        }
+        match mode {
+            LatchMode::Shared => self.readers.set(self.readers.get() + 1),
+            LatchMode::Exclusive if self.readers.get() > 0 => return false,
+            LatchMode::Exclusive => self.writer.set(true),
+        }
+        true
+    }
+
+    fn release(&self, mode: LatchMode) {
+        match mode {
+            LatchMode::Shared => self.readers.set(self.readers.get() - 1),
+            LatchMode::Exclusive => self.writer.set(false),
+        }
+    }
+}
+
+/// A pinned page and a latch on it, released on drop.
+#[derive(Debug)]
+pub struct PageGuard {
+    buffer: Rc<Buffer>,
+    mode: LatchMode,
+    held: Rc<RefCell<Vec<PageId>>>,
+}
+
+impl PageGuard {
+    pub fn mode(&self) -> LatchMode {
+        self.mode
+    }
+
+    pub fn buffer(&self) -> &Rc<Buffer> {
+        &self.buffer
+    }
+}
+
+impl Deref for PageGuard {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        self.buffer.latch.release(self.mode);
+        let mut held = self.held.borrow_mut();
+        if let Some(pos) = held
+            .iter()
+            .rposition(|&page_id| page_id == self.buffer.page_id)
+        {
+            held.remove(pos);
         }
     }
 }
@@ -62,16 +246,34 @@ impl BufferPool {
         self.buffers.len()
     }
 
-    fn evict(&mut self) -> Option<BufferId> {
+    /// The number of frames `evict` could pick from, counting no further
+    /// than `at_most` so that a pool with room to spare is not walked
+    /// through.
+    fn num_evictable(&self, keep_dirty: bool, at_most: usize) -> usize {
+        self.buffers
+            .iter()
+            .filter(|frame| Rc::strong_count(&frame.buffer) == 1)
+            .filter(|frame| !keep_dirty || !frame.buffer.is_dirty.get())
+            .take(at_most)
+            .count()
+    }
+
+    /// Picks a frame to reuse; with `keep_dirty`, never one holding a
+    /// dirty page.
+    fn evict(&mut self, keep_dirty: bool) -> Option<BufferId> {
         let pool_size = self.size();
         let mut consecutive_pinned = 0;
         let victim_id = loop {
             let next_victim_id = self.next_victim_id;
             let frame = &mut self[next_victim_id];
-            if frame.usage_count == 0 {
+            if keep_dirty && frame.buffer.is_dirty.get() {
+                consecutive_pinned += 1;
+                if consecutive_pinned >= pool_size {
+                    return None;
+                }
+            } else if frame.usage_count == 0 {
                 break self.next_victim_id;
-            }
-            if Rc::get_mut(&mut frame.buffer).is_some() {
+            } else if Rc::get_mut(&mut frame.buffer).is_some() {
                 frame.usage_count -= 1;
                 consecutive_pinned = 0;
             } else {
@@ -104,10 +306,88 @@ impl IndexMut<BufferId> for BufferPool {
     }
 }
 
+/// Counts of `BufferPoolManager::fetch_page` calls since the manager was
+/// created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Fetches of pages already in the pool.
+    pub hits: u64,
+    /// Fetches that read the page from disk.
+    pub misses: u64,
+}
+
+impl Stats {
+    pub fn fetches(&self) -> u64 {
+        self.hits + self.misses
+    }
+}
+
+/// A page held in the buffer pool, as listed by
+/// `BufferPoolManager::resident_pages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidentPage {
+    pub page_id: PageId,
+    pub is_dirty: bool,
+    /// The number of `Rc<Buffer>`s handed out that are still alive.
+    pub pins: usize,
+    pub usage_count: u64,
+}
+
+/// Lets commits share a sync of the heap file; see
+/// `BufferPoolManager::set_group_commit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCommit {
+    /// Syncs once this many commits wait for it.
+    pub max_batch: usize,
+    /// Syncs at the first `commit` or `tick` this long after the oldest
+    /// commit still waiting.
+    pub max_delay: Duration,
+}
+
+/// What `BufferPoolManager::commit` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Commit {
+    /// The pages the transaction dirtied, now written to the heap file.
+    pub pages: usize,
+    /// Numbers the commits from 1, in the order they happened; what the
+    /// LSN of its commit record would be if there were a log.
+    pub seq: u64,
+    /// Whether the heap file has been synced since. A commit that is not
+    /// durable yet becomes so at the sync of a later `commit` or `tick`;
+    /// see `BufferPoolManager::is_durable`.
+    pub durable: bool,
+}
+
+/// What `BufferPoolManager::rollback` needs to undo a transaction.
+struct Transaction {
+    allocation: Allocation,
+    /// Pages freed by the transaction, which stay allocated until commit.
+    deallocated: Vec<PageId>,
+    /// Shared with the buffers of the pool while the transaction is open.
+    undo: Rc<UndoMap>,
+    /// Given by the lock manager, if there is one.
+    txn_id: Option<TxnId>,
+    /// The row locks the transaction holds until it ends, one for each
+    /// row, by table.
+    locks: HashMap<PageId, HashMap<Vec<u8>, LockGuard>>,
+}
+
 pub struct BufferPoolManager {
     disk: DiskManager,
     pool: BufferPool,
     page_table: HashMap<PageId, BufferId>,
+    flush_on_drop: bool,
+    stats: Stats,
+    transaction: Option<Transaction>,
+    /// The pages latched by live `PageGuard`s, in the order they were.
+    held_latches: Rc<RefCell<Vec<PageId>>>,
+    group_commit: Option<GroupCommit>,
+    /// The `Commit::seq` of the last commit, and of the last one synced.
+    last_commit_seq: u64,
+    synced_commit_seq: u64,
+    /// When the oldest commit not synced yet happened.
+    oldest_unsynced: Option<Instant>,
+    lock_manager: Option<LockManager>,
 }
 
 impl BufferPoolManager {
@@ -117,6 +397,60 @@ impl BufferPoolManager {
             disk,
             pool,
             page_table,
+            flush_on_drop: true,
+            stats: Stats::default(),
+            transaction: None,
+            held_latches: Default::default(),
+            group_commit: None,
+            last_commit_seq: 0,
+            synced_commit_seq: 0,
+            oldest_unsynced: None,
+            lock_manager: None,
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn io_stats(&self) -> IoStats {
+        self.disk.io_stats()
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool.size()
+    }
+
+    /// The pages in the pool, in page id order.
+    pub fn resident_pages(&self) -> Vec<ResidentPage> {
+        let mut pages: Vec<_> = self
+            .page_table
+            .iter()
+            .map(|(&page_id, &buffer_id)| {
+                let frame = &self.pool[buffer_id];
+                ResidentPage {
+                    page_id,
+                    is_dirty: frame.buffer.is_dirty.get(),
+                    pins: Rc::strong_count(&frame.buffer) - 1,
+                    usage_count: frame.usage_count,
+                }
+            })
+            .collect();
+        pages.sort_by_key(|page| page.page_id.to_u64());
+        pages
+    }
+
+    /// How many more pages, up to `at_most`, can be brought into the pool
+    /// while the pages held now stay pinned.
+    pub fn num_evictable_frames(&self, at_most: usize) -> usize {
+        self.pool.num_evictable(self.transaction.is_some(), at_most)
+    }
+
+    fn evict(&mut self) -> Result<BufferId, Error> {
+        match self.pool.evict(self.transaction.is_some()) {
+            Some(buffer_id) => Ok(buffer_id),
+            None if self.transaction.is_some() => Err(Error::TransactionTooLarge),
+            None => Err(Error::NoFreeBuffer),
         }
     }
 
@@ -124,9 +458,11 @@ impl BufferPoolManager {
         if let Some(&buffer_id) = self.page_table.get(&page_id) {
             let frame = &mut self.pool[buffer_id];
             frame.usage_count += 1;
+            self.stats.hits += 1;
             return Ok(Rc::clone(&frame.buffer));
         }
-        let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
+        self.stats.misses += 1;
+        let buffer_id = self.evict()?;
         let frame = &mut self.pool[buffer_id];
         let evict_page_id = frame.buffer.page_id;
         {
@@ -135,19 +471,61 @@ impl BufferPoolManager {
                 self.disk
                     .write_page_data(evict_page_id, buffer.page.get_mut())?;
             }
-            buffer.page_id = page_id;
+            // if the read fails, the frame is left holding no page
+            self.page_table.remove(&evict_page_id);
+            buffer.page_id = PageId::INVALID_PAGE_ID;
             buffer.is_dirty.set(false);
+            buffer.is_checked.set(false);
             self.disk.read_page_data(page_id, buffer.page.get_mut())?;
+            buffer.page_id = page_id;
+            *buffer.undo.get_mut() = self.transaction.as_ref().map(|tx| Rc::clone(&tx.undo));
             frame.usage_count = 1;
         }
         let page = Rc::clone(&frame.buffer);
-        self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, buffer_id);
         Ok(page)
     }
 
+    /// Fetches the page and latches it in `mode`. Any number of shared
+    /// latches can be held on a page at once, or one exclusive latch.
+    ///
+    /// Latches are taken from the top of a btree down, a child while its
+    /// parent is still held. Once latches can be waited for, no one waiting
+    /// for a parent while holding its child leaves no cycle to deadlock on;
+    /// for now a conflict is an error. `held_latches` lets callers assert
+    /// the order.
+    pub fn fetch_page_latched(
+        &mut self,
+        page_id: PageId,
+        mode: LatchMode,
+    ) -> Result<PageGuard, Error> {
+        let buffer = self.fetch_page(page_id)?;
+        if !buffer.latch.try_acquire(mode) {
+            return Err(Error::LatchConflict(page_id));
+        }
+        self.held_latches.borrow_mut().push(page_id);
+        Ok(PageGuard {
+            buffer,
+            mode,
+            held: Rc::clone(&self.held_latches),
+        })
+    }
+
+    pub fn fetch_page_shared(&mut self, page_id: PageId) -> Result<PageGuard, Error> {
+        self.fetch_page_latched(page_id, LatchMode::Shared)
+    }
+
+    pub fn fetch_page_exclusive(&mut self, page_id: PageId) -> Result<PageGuard, Error> {
+        self.fetch_page_latched(page_id, LatchMode::Exclusive)
+    }
+
+    /// The pages latched now, oldest latch first.
+    pub fn held_latches(&self) -> Vec<PageId> {
+        self.held_latches.borrow().clone()
+    }
+
     pub fn create_page(&mut self) -> Result<Rc<Buffer>, Error> {
-        let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
+        let buffer_id = self.evict()?;
         let frame = &mut self.pool[buffer_id];
         let evict_page_id = frame.buffer.page_id;
         let page_id = {
@@ -169,22 +547,310 @@ impl BufferPoolManager {
         Ok(page)
     }
 
+    pub fn num_pages(&self) -> u64 {
+        self.disk.num_pages()
+    }
+
+    pub fn was_empty(&self) -> bool {
+        self.disk.was_empty()
+    }
+
+    /// Refuses a page someone still holds a buffer of, whose writes would
+    /// otherwise go to a frame no longer holding the page.
+    pub fn deallocate_page(&mut self, page_id: PageId) -> Result<u64, Error> {
+        if let Some(&buffer_id) = self.page_table.get(&page_id) {
+            if Rc::strong_count(&self.pool[buffer_id].buffer) > 1 {
+                return Err(Error::PagePinned(page_id));
+            }
+        }
+        if let Some(buffer_id) = self.page_table.remove(&page_id) {
+            let frame = &mut self.pool[buffer_id];
+            frame.buffer = Default::default();
+            frame.usage_count = 0;
+        }
+        if let Some(transaction) = &mut self.transaction {
+            transaction.deallocated.push(page_id);
+            return Ok(0);
+        }
+        Ok(self.disk.deallocate_page(page_id)?)
+    }
+
+    pub fn shrink(&mut self) -> Result<u64, Error> {
+        Ok(self.disk.shrink()?)
+    }
+
+    /// Writes every page back and syncs the heap file. Does nothing while
+    /// a transaction is open, as its pages wait for `commit`.
     pub fn flush(&mut self) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Ok(());
+        }
         for (&page_id, &buffer_id) in self.page_table.iter() {
             let frame = &self.pool[buffer_id];
             let mut page = frame.buffer.page.borrow_mut();
             self.disk.write_page_data(page_id, page.as_mut())?;
             frame.buffer.is_dirty.set(false);
         }
+        self.sync()
+    }
+
+    /// Syncs the heap file, which makes every commit so far durable.
+    fn sync(&mut self) -> Result<(), Error> {
         self.disk.sync()?;
+        self.synced_commit_seq = self.last_commit_seq;
+        self.oldest_unsynced = None;
         Ok(())
     }
+
+    fn write_back_dirty_pages(&mut self) -> Result<(), Error> {
+        for (&page_id, &buffer_id) in self.page_table.iter() {
+            let frame = &self.pool[buffer_id];
+            if !frame.buffer.is_dirty.get() {
+                continue;
+            }
+            let mut page = frame.buffer.page.borrow_mut();
+            self.disk.write_page_data(page_id, page.as_mut())?;
+            frame.buffer.is_dirty.set(false);
+        }
+        Ok(())
+    }
+
+    /// Starts holding back the pages dirtied from now on: they are neither
+    /// evicted nor flushed until `commit`, and `rollback` puts back what they
+    /// held before. Writes back the dirty pages first, so that the pages the
+    /// transaction dirties are its own; they are not synced, which is left
+    /// to a later `commit` or `flush` under group commit.
+    pub fn begin(&mut self) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::TransactionOpen);
+        }
+        self.write_back_dirty_pages()?;
+        let undo = Rc::new(UndoMap::default());
+        self.set_undo(Some(&undo));
+        self.transaction = Some(Transaction {
+            allocation: self.disk.allocation(),
+            deallocated: vec![],
+            undo,
+            txn_id: self
+                .lock_manager
+                .as_ref()
+                .map(|lock_manager| lock_manager.new_txn_id()),
+            locks: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    /// Points the buffers of the pool at the undo map of a transaction, or
+    /// at none.
+    fn set_undo(&self, undo: Option<&Rc<UndoMap>>) {
+        for &buffer_id in self.page_table.values() {
+            *self.pool[buffer_id].buffer.undo.borrow_mut() = undo.cloned();
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Has the transactions from now on lock the rows they touch in
+    /// `lock_manager`, which the managers of other connections may share.
+    pub fn set_lock_manager(&mut self, lock_manager: Option<LockManager>) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::TransactionOpen);
+        }
+        self.lock_manager = lock_manager;
+        Ok(())
+    }
+
+    /// The id the lock manager gave the open transaction.
+    pub fn txn_id(&self) -> Option<TxnId> {
+        self.transaction.as_ref()?.txn_id
+    }
+
+    /// Locks a row for the open transaction until it ends. Outside a
+    /// transaction or without a lock manager, nothing is locked, and
+    /// neither are rows read unless the lock manager locks point reads. A
+    /// row the transaction holds in a mode covering `mode` is not locked
+    /// again.
+    pub fn lock_row(
+        &mut self,
+        table_id: PageId,
+        pkey: &[u8],
+        mode: LockMode,
+    ) -> Result<(), LockError> {
+        let (lock_manager, transaction) = match (&self.lock_manager, &mut self.transaction) {
+            (Some(lock_manager), Some(transaction)) => (lock_manager, transaction),
+            _ => return Ok(()),
+        };
+        if mode == LockMode::Shared && !lock_manager.lock_point_reads() {
+            return Ok(());
+        }
+        let locks = transaction.locks.entry(table_id).or_default();
+        if let Some(guard) = locks.get(pkey) {
+            if guard.mode().covers(mode) {
+                return Ok(());
+            }
+        }
+        let txn_id = transaction.txn_id.unwrap();
+        let guard = lock_manager.acquire(txn_id, table_id, pkey, mode)?;
+        // an upgrade replaces the shared guard, which the lock outlives
+        locks.insert(pkey.to_vec(), guard);
+        Ok(())
+    }
+
+    /// The number of rows the open transaction holds locks on.
+    pub fn num_row_locks(&self) -> usize {
+        self.transaction.as_ref().map_or(0, |transaction| {
+            transaction.locks.values().map(HashMap::len).sum()
+        })
+    }
+
+    /// Closes the transaction, whose row locks are released as it is
+    /// dropped.
+    fn end_transaction(&mut self) -> Result<Transaction, Error> {
+        let transaction = self.transaction.take().ok_or(Error::NoTransaction)?;
+        self.set_undo(None);
+        if let (Some(lock_manager), Some(txn_id)) = (&self.lock_manager, transaction.txn_id) {
+            lock_manager.cancel_waits(txn_id);
+        }
+        Ok(transaction)
+    }
+
+    fn num_dirty_pages(&self) -> usize {
+        self.page_table
+            .values()
+            .filter(|&&buffer_id| self.pool[buffer_id].buffer.is_dirty.get())
+            .count()
+    }
+
+    /// Discards the undo images, frees the pages the transaction freed and
+    /// writes back the pages it dirtied. The heap file is synced then, or
+    /// under group commit, once enough commits wait for it.
+    pub fn commit(&mut self) -> Result<Commit, Error> {
+        let transaction = self.end_transaction()?;
+        let num_dirty_pages = self.num_dirty_pages();
+        for page_id in transaction.deallocated {
+            self.disk.deallocate_page(page_id)?;
+        }
+        self.write_back_dirty_pages()?;
+        self.last_commit_seq += 1;
+        self.oldest_unsynced.get_or_insert_with(Instant::now);
+        match self.group_commit {
+            Some(group_commit)
+                if self.last_commit_seq - self.synced_commit_seq
+                    < group_commit.max_batch as u64 =>
+            {
+                self.tick()?;
+            }
+            _ => self.sync()?,
+        }
+        Ok(Commit {
+            pages: num_dirty_pages,
+            seq: self.last_commit_seq,
+            durable: self.is_durable(self.last_commit_seq),
+        })
+    }
+
+    /// Lets up to `max_batch` commits share a sync, each waiting up to
+    /// `max_delay` for it, or with `None`, syncs at every commit. Syncs the
+    /// commits still waiting when group commit is turned off.
+    pub fn set_group_commit(&mut self, group_commit: Option<GroupCommit>) -> Result<(), Error> {
+        self.group_commit = group_commit;
+        match group_commit {
+            Some(_) => self.tick().map(|_| ()),
+            None if self.synced_commit_seq < self.last_commit_seq => self.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Syncs the heap file if a commit has waited `GroupCommit::max_delay`
+    /// for it. Returns whether it did. A single-threaded caller with
+    /// nothing else to do calls this every so often in place of a timer.
+    pub fn tick(&mut self) -> Result<bool, Error> {
+        let max_delay = self
+            .group_commit
+            .map_or(Duration::ZERO, |group_commit| group_commit.max_delay);
+        match self.oldest_unsynced {
+            Some(oldest) if oldest.elapsed() >= max_delay => {
+                self.sync()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether the commit numbered `seq` has been synced.
+    pub fn is_durable(&self, seq: u64) -> bool {
+        seq <= self.synced_commit_seq
+    }
+
+    /// Copies the undo images back into the pages the transaction
+    /// modified, which leaves them as clean as they were at `begin`, drops
+    /// the pages it created, and forgets the pages it allocated and freed.
+    /// Returns the number of pages undone.
+    pub fn rollback(&mut self) -> Result<usize, Error> {
+        let transaction = self.end_transaction()?;
+        let mut undo = transaction.undo.take();
+        let pool = &mut self.pool;
+        let mut num_undone = 0;
+        self.page_table.retain(|page_id, &mut buffer_id| {
+            let frame = &mut pool[buffer_id];
+            if !frame.buffer.is_dirty.get() {
+                return true;
+            }
+            num_undone += 1;
+            match undo.remove(page_id) {
+                Some(pre_image) => {
+                    *frame.buffer.page.borrow_mut() = *pre_image;
+                    frame.buffer.is_dirty.set(false);
+                    frame.buffer.is_checked.set(false);
+                    true
+                }
+                None => {
+                    frame.buffer = Default::default();
+                    frame.usage_count = 0;
+                    false
+                }
+            }
+        });
+        // what is left are the pages the transaction freed, which are
+        // still on disk as they were
+        self.disk.restore_allocation(transaction.allocation);
+        Ok(num_undone)
+    }
+
+    /// Flushes every page and syncs the heap file, reporting any failure
+    /// that `Drop` could only log.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.flush()?;
+        self.leak_on_drop();
+        Ok(())
+    }
+
+    /// Drops dirty pages on the floor instead of writing them back,
+    /// which is what a crash looks like to the heap file.
+    pub fn leak_on_drop(&mut self) {
+        self.flush_on_drop = false;
+        self.disk.leak_on_drop();
+    }
+}
+
+impl Drop for BufferPoolManager {
+    fn drop(&mut self) {
+        // an open transaction is rolled back, like in a crash
+        if !self.flush_on_drop || self.transaction.is_some() {
+            return;
+        }
+        if let Err(err) = self.write_back_dirty_pages() {
+            eprintln!("relly: failed to write back dirty pages on drop: {}", err);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempfile;
+    use tempfile::{tempfile, NamedTempFile};
 
     #[test]
     fn test() {
@@ -229,4 +895,228 @@ mod tests {
             assert_eq!(&world, page.as_ref());
         }
     }
+
+    #[test]
+    fn test_page_alignment() {
+        let buffers: Vec<_> = (0..64).map(|_| Rc::new(Buffer::default())).collect();
+        for buffer in &buffers {
+            assert_eq!(0, buffer.bytes().as_ptr() as usize % PAGE_SIZE);
+        }
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        for _ in 0..20 {
+            let buffer = bufmgr.create_page().unwrap();
+            assert_eq!(0, buffer.bytes_mut().as_ptr() as usize % PAGE_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_flush_on_drop() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let page_id = {
+            let buffer = bufmgr.create_page().unwrap();
+            buffer.page.borrow_mut()[..5].copy_from_slice(b"hello");
+            buffer.is_dirty.set(true);
+            buffer.page_id
+        };
+        drop(bufmgr);
+
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let buffer = bufmgr.fetch_page(page_id).unwrap();
+        assert_eq!(b"hello", &buffer.page.borrow()[..5]);
+    }
+
+    #[test]
+    fn test_leak_on_drop() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        {
+            let buffer = bufmgr.create_page().unwrap();
+            buffer.page.borrow_mut()[..5].copy_from_slice(b"hello");
+            buffer.is_dirty.set(true);
+        }
+        bufmgr.leak_on_drop();
+        drop(bufmgr);
+
+        assert_eq!(0, std::fs::metadata(&data_file_path).unwrap().len());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(2));
+        let write = |buffer: &Buffer, data: &[u8]| {
+            buffer.bytes_mut()[..data.len()].copy_from_slice(data);
+        };
+        let page1_id = {
+            let buffer = bufmgr.create_page().unwrap();
+            write(&buffer, b"hello");
+            buffer.page_id
+        };
+
+        bufmgr.begin().unwrap();
+        assert!(matches!(bufmgr.begin(), Err(Error::TransactionOpen)));
+        write(&bufmgr.fetch_page(page1_id).unwrap(), b"HELLO");
+        let page2_id = {
+            let buffer = bufmgr.create_page().unwrap();
+            write(&buffer, b"world");
+            buffer.page_id
+        };
+        // both frames hold pages the transaction dirtied
+        assert!(matches!(
+            bufmgr.create_page(),
+            Err(Error::TransactionTooLarge)
+        ));
+        bufmgr.flush().unwrap();
+        assert_eq!(2, bufmgr.rollback().unwrap());
+        // the modified page is restored in place, the created one dropped
+        let misses = bufmgr.stats().misses;
+        assert_eq!(
+            b"hello",
+            &bufmgr.fetch_page(page1_id).unwrap().page.borrow()[..5]
+        );
+        assert_eq!(misses, bufmgr.stats().misses);
+        assert_eq!(1, bufmgr.resident_pages().len());
+        assert_eq!(page2_id, bufmgr.create_page().unwrap().page_id);
+        assert!(matches!(bufmgr.rollback(), Err(Error::NoTransaction)));
+
+        bufmgr.begin().unwrap();
+        write(&bufmgr.fetch_page(page1_id).unwrap(), b"HELLO");
+        assert_eq!(1, bufmgr.commit().unwrap().pages);
+        bufmgr.begin().unwrap();
+        write(&bufmgr.fetch_page(page1_id).unwrap(), b"howdy");
+        // an open transaction is lost on drop
+        drop(bufmgr);
+
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let buffer = bufmgr.fetch_page(page1_id).unwrap();
+        assert_eq!(b"HELLO", &buffer.page.borrow()[..5]);
+    }
+
+    #[test]
+    fn test_latches() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(2));
+        let page1_id = bufmgr.create_page().unwrap().page_id;
+        let page2_id = bufmgr.create_page().unwrap().page_id;
+        assert_eq!(1, bufmgr.num_evictable_frames(1));
+        assert_eq!(2, bufmgr.num_evictable_frames(3));
+
+        let shared1 = bufmgr.fetch_page_shared(page1_id).unwrap();
+        let shared2 = bufmgr.fetch_page_shared(page1_id).unwrap();
+        assert!(matches!(
+            bufmgr.fetch_page_exclusive(page1_id),
+            Err(Error::LatchConflict(page_id)) if page_id == page1_id
+        ));
+        let exclusive = bufmgr.fetch_page_exclusive(page2_id).unwrap();
+        assert_eq!(LatchMode::Exclusive, exclusive.mode());
+        assert!(matches!(
+            bufmgr.fetch_page_shared(page2_id),
+            Err(Error::LatchConflict(_))
+        ));
+        assert_eq!(vec![page1_id, page1_id, page2_id], bufmgr.held_latches());
+        // latched pages are pinned
+        assert_eq!(0, bufmgr.num_evictable_frames(2));
+        assert!(matches!(bufmgr.create_page(), Err(Error::NoFreeBuffer)));
+
+        drop(shared1);
+        drop(exclusive);
+        assert_eq!(vec![page1_id], bufmgr.held_latches());
+        assert!(bufmgr.fetch_page_shared(page2_id).is_ok());
+        drop(shared2);
+        let exclusive = bufmgr.fetch_page_exclusive(page1_id).unwrap();
+        assert!(matches!(
+            bufmgr.fetch_page_exclusive(page1_id),
+            Err(Error::LatchConflict(_))
+        ));
+        drop(exclusive);
+        assert!(bufmgr.held_latches().is_empty());
+    }
+
+    #[test]
+    fn test_deallocate_pinned() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(2));
+        let buffer = bufmgr.create_page().unwrap();
+        buffer.bytes_mut()[0] = 1;
+        let page_id = buffer.page_id;
+        assert!(matches!(
+            bufmgr.deallocate_page(page_id),
+            Err(Error::PagePinned(id)) if id == page_id
+        ));
+        // still cached, so the pinned buffer's writes are not lost
+        buffer.bytes_mut()[1] = 2;
+        drop(buffer);
+        assert_eq!(2, bufmgr.fetch_page(page_id).unwrap().bytes()[1]);
+
+        bufmgr.deallocate_page(page_id).unwrap();
+        assert!(matches!(
+            bufmgr.fetch_page(page_id),
+            Err(Error::FreePage(id)) if id == page_id
+        ));
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(2));
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        bufmgr
+            .set_group_commit(Some(GroupCommit {
+                max_batch: 8,
+                max_delay: Duration::from_secs(3600),
+            }))
+            .unwrap();
+        let syncs = bufmgr.io_stats().syncs;
+        let mut commits = vec![];
+        for i in 0..20u8 {
+            bufmgr.begin().unwrap();
+            bufmgr.fetch_page(page_id).unwrap().bytes_mut()[0] = i;
+            let syncs_before = bufmgr.io_stats().syncs;
+            let commit = bufmgr.commit().unwrap();
+            // a commit is durable only if the heap file was synced after it
+            assert_eq!(commit.durable, bufmgr.io_stats().syncs > syncs_before);
+            commits.push(commit);
+        }
+        assert_eq!(2, bufmgr.io_stats().syncs - syncs);
+        let durable: Vec<_> = commits.iter().map(|commit| commit.durable).collect();
+        assert_eq!(vec![false; 7], durable[..7]);
+        assert!(durable[7] && durable[15]);
+        assert!(bufmgr.is_durable(commits[14].seq));
+        assert!(!bufmgr.is_durable(commits[16].seq));
+        assert!(!bufmgr.tick().unwrap());
+
+        bufmgr
+            .set_group_commit(Some(GroupCommit {
+                max_batch: 8,
+                max_delay: Duration::ZERO,
+            }))
+            .unwrap();
+        assert!(bufmgr.is_durable(commits[19].seq));
+        assert!(!bufmgr.tick().unwrap());
+        bufmgr.begin().unwrap();
+        let commit = bufmgr.commit().unwrap();
+        assert!(commit.durable);
+        assert_eq!(4, bufmgr.io_stats().syncs - syncs);
+
+        bufmgr
+            .set_group_commit(Some(GroupCommit {
+                max_batch: 8,
+                max_delay: Duration::from_secs(3600),
+            }))
+            .unwrap();
+        bufmgr.begin().unwrap();
+        let commit = bufmgr.commit().unwrap();
+        assert!(!commit.durable);
+        // turning group commit off syncs what waits for it
+        bufmgr.set_group_commit(None).unwrap();
+        assert!(bufmgr.is_durable(commit.seq));
+    }
 }
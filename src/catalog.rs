@@ -0,0 +1,443 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zerocopy::{AsBytes, ByteSlice, FromBytes, LayoutVerified};
+
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::disk::PageId;
+use crate::table::{
+    self, AddedColumn, Constraint, ForeignKey, SecondaryIndex, Table, TableStats, UniqueIndex,
+};
+use crate::tuple::{self, ColumnType, Direction, Format};
+use crate::Result;
+
+pub const CATALOG_PAGE_ID: PageId = PageId(0);
+const CATALOG_MAGIC: [u8; 8] = *b"RELLYCAT";
+/// The layout of `TableRecord`, kept in `Header::record_version`. Bump it
+/// whenever the layout changes; a catalog with another version is refused
+/// rather than misread.
+const RECORD_VERSION: u64 = 1;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("heap file has no catalog")]
+    NoCatalog,
+    #[error("catalog must be created on an empty heap file")]
+    NotEmpty,
+    #[error("table {0:?} already exists")]
+    TableExists(String),
+    #[error("table {0:?} not found")]
+    TableNotFound(String),
+    #[error("no table has meta page id {0}")]
+    TableIdNotFound(u64),
+    #[error("index {0:?} not found")]
+    IndexNotFound(String),
+    #[error("table {0:?} is recorded as {1:?} but its btree as {2:?}")]
+    FormatMismatch(String, Format, Format),
+    #[error("catalog records have version {0}, which this build does not know")]
+    UnknownRecordVersion(u64),
+    #[error("table {0:?} is recorded with {1} key elements but its btree with {2}")]
+    KeyArityMismatch(String, usize, u64),
+}
+
+#[derive(Debug, FromBytes, AsBytes)]
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    btree_meta_page_id: PageId,
+    /// Set to 1 once a table with a foreign key is recorded, and never
+    /// cleared, so that deletes need not look for referencing rows in a
+    /// database without any.
+    has_foreign_keys: u64,
+    record_version: u64,
+}
+
+struct CatalogPage<B> {
+    header: LayoutVerified<B, Header>,
+}
+
+impl<B: ByteSlice> CatalogPage<B> {
+    fn new(bytes: B) -> Self {
+        let (header, _) =
+            LayoutVerified::new_from_prefix(bytes).expect("catalog page must be aligned");
+        Self { header }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableRecord {
+    meta_page_id: u64,
+    num_key_elems: usize,
+    num_columns: usize,
+    schema: Option<Vec<ColumnType>>,
+    constraints: Vec<Constraint>,
+    foreign_keys: Vec<ForeignKey>,
+    column_names: Vec<String>,
+    added_columns: Vec<AddedColumn>,
+    stats: Option<TableStats>,
+    expiry_column: Option<usize>,
+    format: Format,
+    unique_indices: Vec<IndexRecord>,
+    secondary_indices: Vec<IndexRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexRecord {
+    name: Option<String>,
+    meta_page_id: u64,
+    skey: Vec<usize>,
+    directions: Vec<Direction>,
+    include: Vec<usize>,
+}
+
+impl TableRecord {
+    fn from_table(table: &Table) -> Self {
+        Self {
+            meta_page_id: table.meta_page_id.to_u64(),
+            num_key_elems: table.num_key_elems,
+            num_columns: table.num_columns,
+            schema: table.schema.clone(),
+            constraints: table.constraints.clone(),
+            foreign_keys: table.foreign_keys.clone(),
+            column_names: table.column_names.clone(),
+            added_columns: table.added_columns.clone(),
+            stats: table.stats.clone(),
+            expiry_column: table.expiry_column,
+            format: table.format,
+            unique_indices: table
+                .unique_indices
+                .iter()
+                .map(|index| IndexRecord {
+                    name: index.name.clone(),
+                    meta_page_id: index.meta_page_id.to_u64(),
+                    skey: index.skey.clone(),
+                    directions: index.directions.clone(),
+                    include: index.include.clone(),
+                })
+                .collect(),
+            secondary_indices: table
+                .secondary_indices
+                .iter()
+                .map(|index| IndexRecord {
+                    name: index.name.clone(),
+                    meta_page_id: index.meta_page_id.to_u64(),
+                    skey: index.skey.clone(),
+                    directions: index.directions.clone(),
+                    include: index.include.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn into_table(self, name: String) -> Table {
+        Table {
+            name,
+            meta_page_id: PageId(self.meta_page_id),
+            num_key_elems: self.num_key_elems,
+            num_columns: self.num_columns,
+            schema: self.schema,
+            constraints: self.constraints,
+            foreign_keys: self.foreign_keys,
+            column_names: self.column_names,
+            added_columns: self.added_columns,
+            stats: self.stats,
+            expiry_column: self.expiry_column,
+            format: self.format,
+            unique_indices: self
+                .unique_indices
+                .into_iter()
+                .map(|index| UniqueIndex {
+                    name: index.name,
+                    meta_page_id: PageId(index.meta_page_id),
+                    skey: index.skey,
+                    directions: index.directions,
+                    include: index.include,
+                })
+                .collect(),
+            secondary_indices: self
+                .secondary_indices
+                .into_iter()
+                .map(|index| SecondaryIndex {
+                    name: index.name,
+                    meta_page_id: PageId(index.meta_page_id),
+                    skey: index.skey,
+                    directions: index.directions,
+                    include: index.include,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut key = vec![];
+    tuple::encode([name].iter(), &mut key);
+    key
+}
+
+fn decode_name(key: &[u8]) -> Result<String> {
+    let mut elems = vec![];
+    tuple::decode(key, &mut elems)?;
+    let name = elems.first().ok_or(tuple::DecodeError::Truncated)?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+/// The system catalog: a btree keyed by table name whose meta page id is
+/// recorded in the page at `CATALOG_PAGE_ID`.
+pub struct Catalog {
+    btree: BTree,
+}
+
+impl Catalog {
+    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        if bufmgr.num_pages() != 0 {
+            return Err(Error::NotEmpty.into());
+        }
+        let header_buffer = bufmgr.create_page()?;
+        assert_eq!(CATALOG_PAGE_ID, header_buffer.page_id);
+        let btree = BTree::create(bufmgr)?;
+        let mut page = CatalogPage::new(header_buffer.bytes_mut());
+        page.header.magic = CATALOG_MAGIC;
+        page.header.btree_meta_page_id = btree.meta_page_id;
+        page.header.has_foreign_keys = 0;
+        page.header.record_version = RECORD_VERSION;
+        Ok(Self { btree })
+    }
+
+    pub fn open(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        if bufmgr.num_pages() == 0 {
+            return Err(Error::NoCatalog.into());
+        }
+        let header_buffer = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+        let page = CatalogPage::new(header_buffer.bytes());
+        if page.header.magic != CATALOG_MAGIC {
+            return Err(Error::NoCatalog.into());
+        }
+        if page.header.record_version != RECORD_VERSION {
+            return Err(Error::UnknownRecordVersion(page.header.record_version).into());
+        }
+        let btree = BTree::new(page.header.btree_meta_page_id);
+        Ok(Self { btree })
+    }
+
+    pub fn open_or_create(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        if bufmgr.num_pages() == 0 {
+            Self::create(bufmgr)
+        } else {
+            Self::open(bufmgr)
+        }
+    }
+
+    pub fn get(&self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<Option<Table>> {
+        let key = encode_name(name);
+        let mut iter = self.btree.search(bufmgr, SearchMode::Key(key.clone()))?;
+        let value = match iter.next(bufmgr)? {
+            Some((found_key, value)) if found_key == key => value,
+            _ => return Ok(None),
+        };
+        let record: TableRecord = bincode::options().deserialize(&value)?;
+        Ok(Some(record.into_table(name.to_string())))
+    }
+
+    /// Whether a table with a foreign key may have been recorded.
+    pub fn may_have_foreign_keys(&self, bufmgr: &mut BufferPoolManager) -> Result<bool> {
+        let header_buffer = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+        let page = CatalogPage::new(header_buffer.bytes());
+        Ok(page.header.has_foreign_keys != 0)
+    }
+
+    fn note_foreign_keys(&self, bufmgr: &mut BufferPoolManager, table: &Table) -> Result<()> {
+        if table.foreign_keys.is_empty() || self.may_have_foreign_keys(bufmgr)? {
+            return Ok(());
+        }
+        let header_buffer = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+        let mut page = CatalogPage::new(header_buffer.bytes_mut());
+        page.header.has_foreign_keys = 1;
+        Ok(())
+    }
+
+    pub fn insert(&self, bufmgr: &mut BufferPoolManager, table: &Table) -> Result<()> {
+        self.note_foreign_keys(bufmgr, table)?;
+        let key = encode_name(&table.name);
+        let value = bincode::options().serialize(&TableRecord::from_table(table))?;
+        match self.btree.insert(bufmgr, &key, &value) {
+            Err(btree::Error::DuplicateKey) => Err(Error::TableExists(table.name.clone()).into()),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn update(&self, bufmgr: &mut BufferPoolManager, table: &Table) -> Result<()> {
+        self.note_foreign_keys(bufmgr, table)?;
+        let key = encode_name(&table.name);
+        let value = bincode::options().serialize(&TableRecord::from_table(table))?;
+        match self.btree.update(bufmgr, &key, &value) {
+            Err(btree::Error::KeyNotFound) => Err(Error::TableNotFound(table.name.clone()).into()),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn remove(&self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<()> {
+        let key = encode_name(name);
+        match self.btree.delete(bufmgr, &key) {
+            Err(btree::Error::KeyNotFound) => Err(Error::TableNotFound(name.to_string()).into()),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn tables(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Table>> {
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        let mut tables = vec![];
+        while let Some((key, value)) = iter.next(bufmgr)? {
+            let record: TableRecord = bincode::options().deserialize(&value)?;
+            tables.push(record.into_table(decode_name(&key)?));
+        }
+        Ok(tables)
+    }
+}
+
+/// Also checks the format recorded in the table's btree, so that a table
+/// written by a binary with a format this one does not know is an error.
+pub fn open_table(bufmgr: &mut BufferPoolManager, name: &str) -> Result<Table> {
+    let catalog = Catalog::open(bufmgr)?;
+    let table = catalog
+        .get(bufmgr, name)?
+        .ok_or_else(|| Error::TableNotFound(name.to_string()))?;
+    let format = table::btree_format(bufmgr, table.meta_page_id)?;
+    if format != table.format {
+        return Err(Error::FormatMismatch(table.name, table.format, format).into());
+    }
+    check_key_arity(bufmgr, &table)?;
+    Ok(table)
+}
+
+/// Checks each table like `open_table` does its key arity.
+pub fn list_tables(bufmgr: &mut BufferPoolManager) -> Result<Vec<Table>> {
+    let catalog = Catalog::open(bufmgr)?;
+    let tables = catalog.tables(bufmgr)?;
+    for table in &tables {
+        check_key_arity(bufmgr, table)?;
+    }
+    Ok(tables)
+}
+
+/// A table whose keys were written with another number of elements than
+/// the catalog says would have its rows split at the wrong column. Trees
+/// that do not record it are taken at the catalog's word.
+pub(crate) fn check_key_arity(bufmgr: &mut BufferPoolManager, table: &Table) -> Result<()> {
+    let key_arity = BTree::new(table.meta_page_id).key_arity(bufmgr)?;
+    if key_arity != 0 && key_arity != table.num_key_elems as u64 {
+        return Err(
+            Error::KeyArityMismatch(table.name.clone(), table.num_key_elems, key_arity).into(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+
+    fn people() -> Table {
+        Table {
+            unique_indices: vec![UniqueIndex {
+                name: Some("people_by_last".to_string()),
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        }
+    }
+
+    #[test]
+    fn test_open_table() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let disk = DiskManager::new(data_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = people();
+        table.create(&mut bufmgr).unwrap();
+        let mut other = Table::new("animals", 2, 2);
+        other.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        assert!(people().create(&mut bufmgr).is_err());
+        bufmgr.flush().unwrap();
+        drop(bufmgr);
+
+        let disk = DiskManager::open(&data_file_path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let opened = open_table(&mut bufmgr, "people").unwrap();
+        assert_eq!(table.meta_page_id, opened.meta_page_id);
+        assert_eq!(1, opened.num_key_elems);
+        assert_eq!(3, opened.num_columns);
+        assert_eq!(1, opened.unique_indices.len());
+        assert_eq!(
+            table.unique_indices[0].meta_page_id,
+            opened.unique_indices[0].meta_page_id
+        );
+        assert_eq!(vec![2], opened.unique_indices[0].skey);
+        assert_eq!(
+            Some("people_by_last"),
+            opened.unique_indices[0].name.as_deref()
+        );
+        assert_eq!(None, opened.secondary_indices[0].name);
+        assert_eq!(
+            table.secondary_indices[0].meta_page_id,
+            opened.secondary_indices[0].meta_page_id
+        );
+        assert_eq!(vec![1], opened.secondary_indices[0].skey);
+        assert!(open_table(&mut bufmgr, "nobody").is_err());
+
+        let names: Vec<_> = list_tables(&mut bufmgr)
+            .unwrap()
+            .into_iter()
+            .map(|table| table.name)
+            .collect();
+        assert_eq!(vec!["animals", "people"], names);
+    }
+
+    #[test]
+    fn test_unknown_record_version() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        people().create(&mut bufmgr).unwrap();
+        for version in [0, RECORD_VERSION + 1] {
+            {
+                let header_buffer = bufmgr.fetch_page(CATALOG_PAGE_ID).unwrap();
+                let mut page = CatalogPage::new(header_buffer.bytes_mut());
+                page.header.record_version = version;
+            }
+            let err = list_tables(&mut bufmgr).unwrap_err();
+            assert!(
+                matches!(err, crate::Error::Catalog(Error::UnknownRecordVersion(v)) if v == version)
+            );
+            assert!(open_table(&mut bufmgr, "people").is_err());
+        }
+    }
+
+    #[test]
+    fn test_no_catalog() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        assert!(list_tables(&mut bufmgr).is_err());
+        BTree::create(&mut bufmgr).unwrap();
+        bufmgr.flush().unwrap();
+        let err = list_tables(&mut bufmgr).unwrap_err();
+        assert!(matches!(err, crate::Error::Catalog(Error::NoCatalog)));
+        assert!(people().create(&mut bufmgr).is_err());
+    }
+}
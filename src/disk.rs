@@ -1,13 +1,17 @@
-use std::convert::TryInto;
+use std::collections::BTreeSet;
+use std::convert::{TryFrom, TryInto};
 use std::fs::{File, OpenOptions};
 use std::io::{self, prelude::*, SeekFrom};
+use std::mem::size_of;
 use std::path::Path;
 
+use serde::Serialize;
+use thiserror::Error;
 use zerocopy::{AsBytes, FromBytes};
 
 pub const PAGE_SIZE: usize = 4096;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, FromBytes, AsBytes)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, FromBytes, AsBytes, Serialize)]
 #[repr(C)]
 pub struct PageId(pub u64);
 impl PageId {
@@ -38,25 +42,146 @@ impl From<Option<PageId>> for PageId {
     }
 }
 
-impl From<&[u8]> for PageId {
-    fn from(bytes: &[u8]) -> Self {
-        let arr = bytes.try_into().unwrap();
-        PageId(u64::from_ne_bytes(arr))
+/// Bytes that are not the size of a page id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("a page id is {} bytes, not {0}", size_of::<PageId>())]
+pub struct InvalidPageId(pub usize);
+
+impl TryFrom<&[u8]> for PageId {
+    type Error = InvalidPageId;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let arr = bytes.try_into().map_err(|_| InvalidPageId(bytes.len()))?;
+        Ok(PageId(u64::from_ne_bytes(arr)))
+    }
+}
+
+/// Why `DiskManager::read_page_data` read no page.
+#[derive(Debug, Error)]
+pub enum ReadError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The page is deallocated, or reads as zeros as a page freed before
+    /// the heap file was reopened or never written does; no page in use
+    /// is all zeros.
+    #[error("page {} is free or was never written", .0.to_u64())]
+    FreePage(PageId),
+}
+
+/// Which pages are allocated, as saved by `DiskManager::allocation`.
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    next_page_id: u64,
+    free_page_ids: BTreeSet<u64>,
+}
+
+/// Counts of the I/O a `DiskManager` has done since it was created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub syncs: u64,
+}
+
+/// Where a `DiskManager` keeps its pages: the heap file, or for tests, a
+/// `testing::FaultInjectingStore`. Page ids are checked by the
+/// `DiskManager` before they get here.
+pub trait PageStore {
+    /// The number of whole pages stored.
+    fn num_pages(&self) -> io::Result<u64>;
+    fn read_page(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()>;
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()>;
+    /// Gives the space of a free page back where that is supported, and
+    /// returns the number of bytes given back.
+    fn free_page(&mut self, page_id: PageId) -> io::Result<u64>;
+    /// The pages `free_page` gave back, and any never written below the
+    /// last page written, as far as the store can tell.
+    fn holes(&mut self) -> io::Result<Vec<u64>>;
+    /// Cuts the store down to its first `num_pages` pages, and returns the
+    /// number of bytes cut off.
+    fn truncate(&mut self, num_pages: u64) -> io::Result<u64>;
+    /// Makes every page written so far durable.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl PageStore for File {
+    fn num_pages(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len() / PAGE_SIZE as u64)
+    }
+
+    fn read_page(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(PAGE_SIZE as u64 * page_id.to_u64()))?;
+        self.read_exact(data)
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(PAGE_SIZE as u64 * page_id.to_u64()))?;
+        self.write_all(data)
+    }
+
+    fn free_page(&mut self, page_id: PageId) -> io::Result<u64> {
+        let offset = PAGE_SIZE as u64 * page_id.to_u64();
+        match punch_hole(self, offset, PAGE_SIZE as u64)? {
+            Some(len) => Ok(len),
+            None => {
+                self.seek(SeekFrom::Start(offset))?;
+                self.write_all(&[0; PAGE_SIZE])?;
+                Ok(0)
+            }
+        }
+    }
+
+    fn holes(&mut self) -> io::Result<Vec<u64>> {
+        find_holes(self)
+    }
+
+    fn truncate(&mut self, num_pages: u64) -> io::Result<u64> {
+        let file_size = self.metadata()?.len();
+        let new_file_size = PAGE_SIZE as u64 * num_pages;
+        self.set_len(new_file_size)?;
+        Ok(file_size.saturating_sub(new_file_size))
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.sync_all()
     }
 }
 
 pub struct DiskManager {
-    heap_file: File,
+    store: Box<dyn PageStore>,
     next_page_id: u64,
+    /// Rebuilt from the holes of the heap file when it is opened. Where
+    /// the filesystem cannot punch holes, freed pages are zeroed instead
+    /// and stay allocated once the heap file is reopened, though reading
+    /// one still fails.
+    free_page_ids: BTreeSet<u64>,
+    sync_on_drop: bool,
+    io_stats: IoStats,
+    read_only: bool,
+    was_empty: bool,
 }
 
 impl DiskManager {
     pub fn new(heap_file: File) -> io::Result<Self> {
-        let heap_file_size = heap_file.metadata()?.len();
-        let next_page_id = heap_file_size / PAGE_SIZE as u64;
+        Self::with_store(heap_file)
+    }
+
+    pub fn with_store(mut store: impl PageStore + 'static) -> io::Result<Self> {
+        let next_page_id = store.num_pages()?;
+        let free_page_ids = store
+            .holes()?
+            .into_iter()
+            .filter(|&page_id| page_id < next_page_id)
+            .collect();
         Ok(Self {
-            heap_file,
+            store: Box::new(store),
             next_page_id,
+            free_page_ids,
+            sync_on_drop: true,
+            io_stats: IoStats::default(),
+            read_only: false,
+            was_empty: next_page_id == 0,
         })
     }
 
@@ -65,34 +190,225 @@ impl DiskManager {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(heap_file_path)?;
         Self::new(heap_file)
     }
 
-    pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.read_exact(data)
+    /// Opens an existing heap file without write access. Writing a page
+    /// fails, and nothing is synced.
+    pub fn open_read_only(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file = OpenOptions::new().read(true).open(heap_file_path)?;
+        let mut disk = Self::new(heap_file)?;
+        disk.read_only = true;
+        disk.sync_on_drop = false;
+        Ok(disk)
+    }
+
+    pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> Result<(), ReadError> {
+        if self.free_page_ids.contains(&page_id.to_u64()) {
+            return Err(ReadError::FreePage(page_id));
+        }
+        if (PAGE_SIZE as u64).checked_mul(page_id.to_u64()).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} is out of range", page_id.to_u64()),
+            )
+            .into());
+        }
+        self.io_stats.reads += 1;
+        self.store.read_page(page_id, data)?;
+        if data.iter().all(|&b| b == 0) {
+            return Err(ReadError::FreePage(page_id));
+        }
+        Ok(())
     }
 
     pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.write_all(data)
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "cannot write page {}: the heap file is read-only",
+                    page_id.to_u64()
+                ),
+            ));
+        }
+        self.io_stats.writes += 1;
+        self.store.write_page(page_id, data)
     }
 
     pub fn allocate_page(&mut self) -> PageId {
+        if let Some(&page_id) = self.free_page_ids.iter().next() {
+            self.free_page_ids.remove(&page_id);
+            return PageId(page_id);
+        }
         let page_id = self.next_page_id;
         self.next_page_id += 1;
         PageId(page_id)
     }
 
+    pub fn num_pages(&self) -> u64 {
+        self.next_page_id
+    }
+
+    /// Whether the heap file had no pages when it was opened, however many
+    /// have been allocated since.
+    pub fn was_empty(&self) -> bool {
+        self.was_empty
+    }
+
+    /// Marks the page as free so that `allocate_page` can hand it out again,
+    /// and punches a hole in the heap file where the filesystem supports it,
+    /// or else zeroes the page. Returns the number of bytes given back to
+    /// the filesystem.
+    pub fn deallocate_page(&mut self, page_id: PageId) -> io::Result<u64> {
+        let page_id = page_id.to_u64();
+        if page_id >= self.next_page_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} is not allocated", page_id),
+            ));
+        }
+        if !self.free_page_ids.insert(page_id) {
+            return Ok(0);
+        }
+        self.store.free_page(PageId(page_id))
+    }
+
+    pub fn allocation(&self) -> Allocation {
+        Allocation {
+            next_page_id: self.next_page_id,
+            free_page_ids: self.free_page_ids.clone(),
+        }
+    }
+
+    /// Forgets the pages allocated and freed since `allocation` was saved.
+    /// Pages freed since then must not have been handed out again.
+    pub fn restore_allocation(&mut self, allocation: Allocation) {
+        self.next_page_id = allocation.next_page_id;
+        self.free_page_ids = allocation.free_page_ids;
+    }
+
+    /// Truncates the heap file while its tail consists of free pages.
+    /// Returns the number of bytes cut off the end of the file.
+    pub fn shrink(&mut self) -> io::Result<u64> {
+        let next_page_id_orig = self.next_page_id;
+        while self.next_page_id > 0 && self.free_page_ids.remove(&(self.next_page_id - 1)) {
+            self.next_page_id -= 1;
+        }
+        if self.next_page_id == next_page_id_orig {
+            return Ok(0);
+        }
+        self.store.truncate(self.next_page_id)
+    }
+
+    pub fn io_stats(&self) -> IoStats {
+        self.io_stats
+    }
+
     pub fn sync(&mut self) -> io::Result<()> {
-        self.heap_file.flush()?;
-        self.heap_file.sync_all()
+        if self.read_only {
+            return Ok(());
+        }
+        self.io_stats.syncs += 1;
+        self.store.sync()
+    }
+
+    /// Skips the sync that otherwise happens on drop.
+    pub fn leak_on_drop(&mut self) {
+        self.sync_on_drop = false;
+    }
+}
+
+impl Drop for DiskManager {
+    fn drop(&mut self) {
+        if !self.sync_on_drop {
+            return;
+        }
+        if let Err(err) = self.sync() {
+            eprintln!("relly: failed to sync heap file on drop: {}", err);
+        }
+    }
+}
+
+/// Returns the number of bytes punched out, or `None` if the filesystem
+/// cannot punch holes.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    let file_size = file.metadata()?.len();
+    if offset >= file_size {
+        return Ok(Some(0));
+    }
+    let len = len.min(file_size - offset);
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if ret == 0 {
+        return Ok(Some(len));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(None),
+        _ => Err(err),
     }
 }
 
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _offset: u64, _len: u64) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// The pages lying wholly in holes of the file, found with `SEEK_HOLE` and
+/// `SEEK_DATA`. A filesystem without them reports none.
+#[cfg(target_os = "linux")]
+fn find_holes(file: &File) -> io::Result<Vec<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    let seek = |offset: u64, whence| {
+        let ret = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret as u64)
+        }
+    };
+    let file_size = file.metadata()?.len();
+    let page_size = PAGE_SIZE as u64;
+    let mut holes = vec![];
+    let mut offset = 0;
+    while offset < file_size {
+        let hole = match seek(offset, libc::SEEK_HOLE) {
+            Ok(hole) => hole,
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(err) => return Err(err),
+        };
+        if hole >= file_size {
+            break;
+        }
+        let data = match seek(hole, libc::SEEK_DATA) {
+            Ok(data) => data.min(file_size),
+            Err(err) if err.raw_os_error() == Some(libc::ENXIO) => file_size,
+            Err(err) => return Err(err),
+        };
+        holes.extend(hole.div_ceil(page_size)..data / page_size);
+        offset = data;
+    }
+    Ok(holes)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn find_holes(_file: &File) -> io::Result<Vec<u64>> {
+    Ok(vec![])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,4 +436,81 @@ mod tests {
         disk2.read_page_data(world_page_id, &mut buf).unwrap();
         assert_eq!(world, buf);
     }
+
+    #[test]
+    fn test_deallocate() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let mut disk = DiskManager::new(data_file).unwrap();
+        let page = vec![0xAA; PAGE_SIZE];
+        let page_ids: Vec<_> = (0..4).map(|_| disk.allocate_page()).collect();
+        for &page_id in &page_ids {
+            disk.write_page_data(page_id, &page).unwrap();
+        }
+
+        let reclaimed = disk.deallocate_page(page_ids[1]).unwrap();
+        assert_eq!(0, disk.deallocate_page(page_ids[1]).unwrap());
+        let mut buf = vec![0; PAGE_SIZE];
+        assert!(matches!(
+            disk.read_page_data(page_ids[1], &mut buf),
+            Err(ReadError::FreePage(page_id)) if page_id == page_ids[1]
+        ));
+        assert_eq!(page_ids[1], disk.allocate_page());
+        // punched out or zeroed, it reads as a page never written
+        assert!(matches!(
+            disk.read_page_data(page_ids[1], &mut buf),
+            Err(ReadError::FreePage(_))
+        ));
+        disk.write_page_data(page_ids[1], &page).unwrap();
+        disk.read_page_data(page_ids[1], &mut buf).unwrap();
+        assert_eq!(page, buf);
+        assert!(reclaimed == 0 || reclaimed == PAGE_SIZE as u64);
+
+        assert_eq!(0, disk.shrink().unwrap());
+        disk.deallocate_page(page_ids[3]).unwrap();
+        disk.deallocate_page(page_ids[2]).unwrap();
+        assert_eq!(2 * PAGE_SIZE as u64, disk.shrink().unwrap());
+        assert_eq!(
+            2 * PAGE_SIZE as u64,
+            std::fs::metadata(&data_file_path).unwrap().len()
+        );
+        assert_eq!(page_ids[2], disk.allocate_page());
+    }
+
+    #[test]
+    fn test_deallocate_reopen() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let mut disk = DiskManager::new(data_file).unwrap();
+        let page = vec![0xAA; PAGE_SIZE];
+        let page_ids: Vec<_> = (0..4).map(|_| disk.allocate_page()).collect();
+        for &page_id in &page_ids {
+            disk.write_page_data(page_id, &page).unwrap();
+        }
+        let reclaimed = disk.deallocate_page(page_ids[1]).unwrap();
+        disk.deallocate_page(page_ids[3]).unwrap();
+        drop(disk);
+
+        let mut disk = DiskManager::open(&data_file_path).unwrap();
+        assert_eq!(4, disk.num_pages());
+        let mut buf = vec![0; PAGE_SIZE];
+        for &page_id in &[page_ids[1], page_ids[3]] {
+            assert!(matches!(
+                disk.read_page_data(page_id, &mut buf),
+                Err(ReadError::FreePage(_))
+            ));
+        }
+        disk.read_page_data(page_ids[2], &mut buf).unwrap();
+        if reclaimed == 0 {
+            // zeroed rather than punched out, the pages stay allocated
+            assert_eq!(0, disk.shrink().unwrap());
+            assert_eq!(PageId(4), disk.allocate_page());
+            return;
+        }
+        assert_eq!(PAGE_SIZE as u64, disk.shrink().unwrap());
+        assert_eq!(
+            3 * PAGE_SIZE as u64,
+            std::fs::metadata(&data_file_path).unwrap().len()
+        );
+        assert_eq!(page_ids[1], disk.allocate_page());
+        assert_eq!(PageId(3), disk.allocate_page());
+    }
 }
@@ -0,0 +1,146 @@
+//! The error of the library as a whole. Each module keeps its own error
+//! type for what can go wrong in it, and `Error` has a variant wrapping
+//! each of them, so a caller can match on what failed however many
+//! modules the error passed through:
+//!
+//! - `Io`, `Buffer`: reading and writing the heap file, and the buffer
+//!   pool and its transactions;
+//! - `BTree`: duplicate and missing keys, pairs too large for a page, and
+//!   corrupted pages, whether reached through a `Table`, a query executor
+//!   or a `lang::Request`;
+//! - `Catalog`: missing and existing tables and indices;
+//! - `Lock`: row locks a transaction has to wait for, or gave up on;
+//! - `Table`: rows that do not fit the table, its constraints or its
+//!   indices;
+//! - `Tuple`, `Decode`, `Expr`: values, encoded tuples and expressions;
+//! - `Query`, `Lang`, `Repl`, `Sql`: plans, requests, settings and SQL;
+//! - `Encoding`, `Json`: the bincode and JSON the catalog and the
+//!   requests are written in.
+//!
+//! An error the buffer pool hands the btree stays inside `BTree`, as
+//! `btree::Error::Buffer`.
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::lock::LockError;
+use crate::tuple::DecodeError;
+use crate::{btree, buffer, catalog, expr, lang, query, repl, table, tuple};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    BTree(#[from] btree::Error),
+    #[error(transparent)]
+    Catalog(#[from] catalog::Error),
+    #[error(transparent)]
+    Lock(#[from] LockError),
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error(transparent)]
+    Expr(#[from] expr::Error),
+    #[error(transparent)]
+    Query(#[from] query::Error),
+    #[error(transparent)]
+    Lang(#[from] lang::Error),
+    #[error(transparent)]
+    Repl(#[from] repl::Error),
+    #[cfg(feature = "sql")]
+    #[error(transparent)]
+    Sql(#[from] crate::sql::Error),
+    #[error(transparent)]
+    Encoding(#[from] bincode::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::{BufferPool, BufferPoolManager};
+    use crate::disk::DiskManager;
+    use crate::expr::BytesExpr;
+    use crate::lang::Request;
+    use crate::query::{PlanNode, ScanDirection, SeqScan, TupleSearchMode, UpdateWhere};
+    use crate::tuple::BytesValue;
+
+    fn execute(bufmgr: &mut BufferPoolManager, json: &str) -> Result<()> {
+        let req: Request = serde_json::from_str(json)?;
+        req.execute(bufmgr).map(drop)
+    }
+
+    #[test]
+    fn test_duplicate_key_variant() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {
+                "name": "people", "num_key_elems": 1, "num_columns": 3,
+                "indices": [{"skey": [1], "unique": true}]
+            }}"#,
+        )
+        .unwrap();
+        let insert = |record: &str| {
+            format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            )
+        };
+        execute(&mut bufmgr, &insert(r#"["z", "Alice", "Smith"]"#)).unwrap();
+        execute(&mut bufmgr, &insert(r#"["y", "Bob", "Jones"]"#)).unwrap();
+        let is_duplicate_pkey = |err| matches!(err, Error::BTree(btree::Error::DuplicateKey));
+        let is_duplicate_skey =
+            |err| matches!(err, Error::Table(table::Error::DuplicateIndexKey(0)));
+
+        // through lang
+        let err = execute(&mut bufmgr, &insert(r#"["z", "Carol", "Smith"]"#)).unwrap_err();
+        assert!(is_duplicate_pkey(err));
+        let err = execute(&mut bufmgr, &insert(r#"["x", "Alice", "Smith"]"#)).unwrap_err();
+        assert!(is_duplicate_skey(err));
+
+        // through Table
+        let table = catalog::open_table(&mut bufmgr, "people").unwrap();
+        let err = table
+            .insert(&mut bufmgr, &[b"z", b"Carol", b"Smith"])
+            .unwrap_err();
+        assert!(is_duplicate_pkey(err));
+        let err = table
+            .insert(&mut bufmgr, &[b"x", b"Alice", b"Smith"])
+            .unwrap_err();
+        assert!(is_duplicate_skey(err));
+
+        // through a query executor
+        let plan = UpdateWhere {
+            inner_plan: Box::new(SeqScan {
+                table_meta_page_id: table.meta_page_id,
+                search_mode: TupleSearchMode::Start,
+                direction: ScanDirection::Forward,
+                while_bound: None,
+                while_cond: Box::new(|_| Ok(true)),
+                expiry: None,
+                filter: None,
+            }),
+            table,
+            set: vec![(1, BytesExpr::Literal(BytesValue(b"Alice".to_vec())))],
+        };
+        let err = plan
+            .start(&mut bufmgr)
+            .and_then(|mut exec| exec.next(&mut bufmgr))
+            .unwrap_err();
+        assert!(is_duplicate_skey(err));
+    }
+}
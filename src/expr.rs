@@ -0,0 +1,274 @@
+//! Row-level expressions shared by query filters and table constraints.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tuple::{BytesValue, Element};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("column {column} is out of range for a {len}-column record")]
+    ColumnOutOfRange { column: usize, len: usize },
+    #[error("slice {start}..{end} is out of range for {len} bytes")]
+    SliceOutOfRange {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("{0:?} is not a number")]
+    NotANumber(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BoolExpr {
+    Eq(BytesExpr, BytesExpr),
+    Ne(BytesExpr, BytesExpr),
+    Lt(BytesExpr, BytesExpr),
+    Le(BytesExpr, BytesExpr),
+    Gt(BytesExpr, BytesExpr),
+    Ge(BytesExpr, BytesExpr),
+    /// Like `Eq` through `Ge`, but the operands are decimal numbers, such
+    /// as "-3" or "2.5", compared by value; fails if one does not parse.
+    NumEq(BytesExpr, BytesExpr),
+    NumNe(BytesExpr, BytesExpr),
+    NumLt(BytesExpr, BytesExpr),
+    NumLe(BytesExpr, BytesExpr),
+    NumGt(BytesExpr, BytesExpr),
+    NumGe(BytesExpr, BytesExpr),
+    And(Vec<BoolExpr>),
+    Or(Vec<BoolExpr>),
+    Not(Box<BoolExpr>),
+    IsNull(BytesExpr),
+}
+
+impl BoolExpr {
+    /// Evaluates the expression in three-valued logic: comparisons involving
+    /// NULL are unknown (`None`), and `Filter` only passes rows that are `Some(true)`.
+    /// Fails if the expression refers to a column the record does not have.
+    pub fn eval(&self, record: &[impl Element]) -> Result<Option<bool>, Error> {
+        let compare = |lhs: &BytesExpr, rhs: &BytesExpr, accept: fn(Ordering) -> bool| {
+            let (lhs, rhs) = (lhs.eval(record)?, rhs.eval(record)?);
+            Ok(lhs.zip(rhs).map(|(lhs, rhs)| accept(lhs.cmp(&rhs))))
+        };
+        let compare_nums = |lhs: &BytesExpr, rhs: &BytesExpr, accept: fn(Ordering) -> bool| {
+            let (lhs, rhs) = match (lhs.eval(record)?, rhs.eval(record)?) {
+                (Some(lhs), Some(rhs)) => (Number::parse(&lhs)?, Number::parse(&rhs)?),
+                _ => return Ok(None),
+            };
+            Ok(Some(accept(lhs.cmp(rhs))))
+        };
+        match self {
+            BoolExpr::Eq(lhs, rhs) => compare(lhs, rhs, Ordering::is_eq),
+            BoolExpr::Ne(lhs, rhs) => compare(lhs, rhs, Ordering::is_ne),
+            BoolExpr::Lt(lhs, rhs) => compare(lhs, rhs, Ordering::is_lt),
+            BoolExpr::Le(lhs, rhs) => compare(lhs, rhs, Ordering::is_le),
+            BoolExpr::Gt(lhs, rhs) => compare(lhs, rhs, Ordering::is_gt),
+            BoolExpr::Ge(lhs, rhs) => compare(lhs, rhs, Ordering::is_ge),
+            BoolExpr::NumEq(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_eq),
+            BoolExpr::NumNe(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_ne),
+            BoolExpr::NumLt(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_lt),
+            BoolExpr::NumLe(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_le),
+            BoolExpr::NumGt(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_gt),
+            BoolExpr::NumGe(lhs, rhs) => compare_nums(lhs, rhs, Ordering::is_ge),
+            BoolExpr::And(exprs) => {
+                let mut acc = Some(true);
+                for expr in exprs {
+                    match expr.eval(record)? {
+                        Some(false) => return Ok(Some(false)),
+                        Some(true) => {}
+                        None => acc = None,
+                    }
+                }
+                Ok(acc)
+            }
+            BoolExpr::Or(exprs) => {
+                let mut acc = Some(false);
+                for expr in exprs {
+                    match expr.eval(record)? {
+                        Some(true) => return Ok(Some(true)),
+                        Some(false) => {}
+                        None => acc = None,
+                    }
+                }
+                Ok(acc)
+            }
+            BoolExpr::Not(expr) => Ok(expr.eval(record)?.map(|b| !b)),
+            BoolExpr::IsNull(expr) => Ok(Some(expr.eval(record)?.is_none())),
+        }
+    }
+
+    /// The highest column the expression refers to, if any.
+    pub fn max_column(&self) -> Option<usize> {
+        match self {
+            BoolExpr::Eq(lhs, rhs)
+            | BoolExpr::Ne(lhs, rhs)
+            | BoolExpr::Lt(lhs, rhs)
+            | BoolExpr::Le(lhs, rhs)
+            | BoolExpr::Gt(lhs, rhs)
+            | BoolExpr::Ge(lhs, rhs)
+            | BoolExpr::NumEq(lhs, rhs)
+            | BoolExpr::NumNe(lhs, rhs)
+            | BoolExpr::NumLt(lhs, rhs)
+            | BoolExpr::NumLe(lhs, rhs)
+            | BoolExpr::NumGt(lhs, rhs)
+            | BoolExpr::NumGe(lhs, rhs) => lhs.max_column().max(rhs.max_column()),
+            BoolExpr::And(exprs) | BoolExpr::Or(exprs) => {
+                exprs.iter().filter_map(BoolExpr::max_column).max()
+            }
+            BoolExpr::Not(expr) => expr.max_column(),
+            BoolExpr::IsNull(expr) => expr.max_column(),
+        }
+    }
+
+    /// Adds each column the expression refers to to `columns`.
+    pub fn collect_columns(&self, columns: &mut Vec<usize>) {
+        match self {
+            BoolExpr::Eq(lhs, rhs)
+            | BoolExpr::Ne(lhs, rhs)
+            | BoolExpr::Lt(lhs, rhs)
+            | BoolExpr::Le(lhs, rhs)
+            | BoolExpr::Gt(lhs, rhs)
+            | BoolExpr::Ge(lhs, rhs)
+            | BoolExpr::NumEq(lhs, rhs)
+            | BoolExpr::NumNe(lhs, rhs)
+            | BoolExpr::NumLt(lhs, rhs)
+            | BoolExpr::NumLe(lhs, rhs)
+            | BoolExpr::NumGt(lhs, rhs)
+            | BoolExpr::NumGe(lhs, rhs) => {
+                lhs.collect_columns(columns);
+                rhs.collect_columns(columns);
+            }
+            BoolExpr::And(exprs) | BoolExpr::Or(exprs) => {
+                for expr in exprs {
+                    expr.collect_columns(columns);
+                }
+            }
+            BoolExpr::Not(expr) => expr.collect_columns(columns),
+            BoolExpr::IsNull(expr) => expr.collect_columns(columns),
+        }
+    }
+}
+
+/// An operand of a numeric comparison. Integers compare exactly; a
+/// comparison involving a fraction is done in `f64`.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let not_a_number = || Error::NotANumber(String::from_utf8_lossy(bytes).into_owned());
+        let s = std::str::from_utf8(bytes).map_err(|_| not_a_number())?;
+        if let Ok(n) = s.parse() {
+            return Ok(Number::Int(n));
+        }
+        match s.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(Number::Float(x)),
+            _ => Err(not_a_number()),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(x) => x,
+        }
+    }
+
+    fn cmp(self, other: Self) -> Ordering {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+            // both are finite
+            _ => self.as_f64().partial_cmp(&other.as_f64()).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BytesExpr {
+    Column(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+    Literal(BytesValue),
+    Null,
+    /// The parts one after another; NULL if any of them is.
+    Concat(Vec<BytesExpr>),
+    /// The bytes from `start` up to `end`, or to the end without one.
+    Slice(Box<BytesExpr>, usize, Option<usize>),
+    /// The number of bytes, as a decimal string.
+    Len(Box<BytesExpr>),
+}
+
+impl BytesExpr {
+    /// Fails if the expression refers to a column the record does not
+    /// have, or slices past the end of its bytes.
+    pub fn eval<'a>(&'a self, record: &'a [impl Element]) -> Result<Option<Cow<'a, [u8]>>, Error> {
+        match self {
+            BytesExpr::Column(idx) => match record.get(*idx) {
+                Some(elem) => Ok(elem.as_elem().map(Cow::Borrowed)),
+                None => Err(Error::ColumnOutOfRange {
+                    column: *idx,
+                    len: record.len(),
+                }),
+            },
+            BytesExpr::Literal(literal) => Ok(Some(Cow::Borrowed(literal.as_bytes()))),
+            BytesExpr::Null => Ok(None),
+            BytesExpr::Concat(exprs) => {
+                let mut bytes = vec![];
+                for expr in exprs {
+                    match expr.eval(record)? {
+                        Some(part) => bytes.extend_from_slice(&part),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(Cow::Owned(bytes)))
+            }
+            BytesExpr::Slice(expr, start, end) => {
+                let bytes = match expr.eval(record)? {
+                    Some(bytes) => bytes,
+                    None => return Ok(None),
+                };
+                let len = bytes.len();
+                let (start, end) = (*start, end.unwrap_or(len));
+                if start > end || end > len {
+                    return Err(Error::SliceOutOfRange { start, end, len });
+                }
+                Ok(Some(match bytes {
+                    Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[start..end]),
+                    Cow::Owned(bytes) => Cow::Owned(bytes[start..end].to_vec()),
+                }))
+            }
+            BytesExpr::Len(expr) => Ok(expr
+                .eval(record)?
+                .map(|bytes| Cow::Owned(bytes.len().to_string().into_bytes()))),
+        }
+    }
+
+    pub fn max_column(&self) -> Option<usize> {
+        match self {
+            BytesExpr::Column(idx) => Some(*idx),
+            BytesExpr::Literal(_) | BytesExpr::Null => None,
+            BytesExpr::Concat(exprs) => exprs.iter().filter_map(BytesExpr::max_column).max(),
+            BytesExpr::Slice(expr, _, _) | BytesExpr::Len(expr) => expr.max_column(),
+        }
+    }
+
+    /// Adds each column the expression refers to to `columns`.
+    pub fn collect_columns(&self, columns: &mut Vec<usize>) {
+        match self {
+            BytesExpr::Column(idx) => columns.push(*idx),
+            BytesExpr::Literal(_) | BytesExpr::Null => {}
+            BytesExpr::Concat(exprs) => {
+                for expr in exprs {
+                    expr.collect_columns(columns);
+                }
+            }
+            BytesExpr::Slice(expr, _, _) | BytesExpr::Len(expr) => expr.collect_columns(columns),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! What the `Arbitrary` impls of the requests draw names and column
+//! numbers from. Names come from a handful and numbers stay small, so that
+//! the requests of a fuzz input meet the tables the earlier ones created
+//! and the columns those tables have.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+const NAMES: [&str; 4] = ["a", "b", "c", "d"];
+const MAX_COLUMN: usize = 7;
+/// Past the pages a fuzz input creates, but not by much.
+const MAX_PAGE_ID: u64 = 63;
+
+pub fn name(u: &mut Unstructured) -> Result<String> {
+    Ok(u.choose(&NAMES)?.to_string())
+}
+
+pub fn opt_name(u: &mut Unstructured) -> Result<Option<String>> {
+    if u.arbitrary()? {
+        name(u).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn page_id(u: &mut Unstructured) -> Result<u64> {
+    u.int_in_range(0..=MAX_PAGE_ID)
+}
+
+pub fn column(u: &mut Unstructured) -> Result<usize> {
+    u.int_in_range(0..=MAX_COLUMN)
+}
+
+pub fn opt_column(u: &mut Unstructured) -> Result<Option<usize>> {
+    if u.arbitrary()? {
+        column(u).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn columns(u: &mut Unstructured) -> Result<Vec<usize>> {
+    let len = u.int_in_range(0..=MAX_COLUMN)?;
+    (0..len).map(|_| column(u)).collect()
+}
+
+/// A column paired with something, as in sort keys and `set`s.
+pub fn keyed<'a, T: Arbitrary<'a>>(u: &mut Unstructured<'a>) -> Result<Vec<(usize, T)>> {
+    let len = u.int_in_range(0..=MAX_COLUMN)?;
+    (0..len).map(|_| Ok((column(u)?, u.arbitrary()?))).collect()
+}
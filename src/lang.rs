@@ -0,0 +1,4063 @@
+//! The requests the REPL and the server take, and their responses. Each
+//! request is a JSON value, but they can as well be built in code and run
+//! with `Request::execute`:
+//!
+//! ```
+//! use relly::buffer::{BufferPool, BufferPoolManager};
+//! use relly::catalog::Catalog;
+//! use relly::disk::DiskManager;
+//! use relly::lang::*;
+//! use relly::tuple::{Format, Record};
+//!
+//! # fn main() -> relly::Result<()> {
+//! let dir = tempfile::tempdir()?;
+//! let disk = DiskManager::open(dir.path().join("example.rly"))?;
+//! let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+//! Catalog::open_or_create(&mut bufmgr)?;
+//!
+//! Request::CreateTable(CreateTableRequest {
+//!     name: "users".to_string(),
+//!     num_key_elems: 1,
+//!     num_columns: 2,
+//!     column_names: vec!["id".to_string(), "name".to_string()],
+//!     schema: None,
+//!     constraints: vec![],
+//!     foreign_keys: vec![],
+//!     expiry_column: None,
+//!     format: Format::default(),
+//!     indices: vec![],
+//! })
+//! .execute(&mut bufmgr)?;
+//! Request::Insert(InsertRequest {
+//!     table: ObjectRef::Name("users".to_string()),
+//!     record: Record(vec![Some(b"1".to_vec()), Some(b"Alice".to_vec())]),
+//! })
+//! .execute(&mut bufmgr)?;
+//!
+//! let resp = Request::Query(QueryRequest {
+//!     plan: PlanNode::SeqScan(SeqScanPlan {
+//!         table: ObjectRef::Name("users".to_string()),
+//!         key: None,
+//!         to: None,
+//!         while_expr: WhileExpr::default(),
+//!         direction: Default::default(),
+//!     }),
+//!     optimize: true,
+//!     analyze: false,
+//! })
+//! .execute(&mut bufmgr)?;
+//! match resp {
+//!     Response::Query(resp) => assert_eq!(b"Alice", &resp.rows[0].0[1].as_deref().unwrap()),
+//!     resp => panic!("unexpected response {:?}", resp),
+//! }
+//! bufmgr.close()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::btree::BTree;
+use crate::buffer::BufferPoolManager;
+use crate::catalog;
+use crate::disk::PageId;
+pub use crate::expr::{BoolExpr, BytesExpr};
+use crate::query::plan::LogicalPlan;
+use crate::query::{
+    self, BoxPlanNode, Metrics, PlanDescription, ScanDirection, TupleSearchMode, TupleSlice,
+};
+use crate::table::{self, Constraint, CsvEncoding, Expiry, ForeignKey, Table, TableStats};
+use crate::tuple::{self, BytesValue, ColumnType, Direction, Format, Record, Value};
+use crate::Result;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{request} is not allowed in read-only mode")]
+    ReadOnly { request: &'static str },
+    #[error("{request} changes a session, which only the REPL and the server have")]
+    NoSession { request: &'static str },
+    #[error("a {0:?} join needs left_columns")]
+    NoLeftColumns(JoinKind),
+    #[error("a {0:?} join needs `on` to have an Eq of a left and a right column")]
+    NoJoinEquality(JoinKind),
+    #[error("an IndexByPk join needs a SeqScan of a whole table on the right")]
+    IndexByPkRight,
+    #[error("`on` has no Eq for pkey column {0}")]
+    NoPkeyEquality(usize),
+    #[error("record has more columns than the schema")]
+    TooManyColumns,
+    #[error("{0:?} is not a regular file")]
+    NotAFile(String),
+    #[error("{path:?} has {len} bytes, more than max_bytes {max_bytes}")]
+    FileTooLarge {
+        path: String,
+        len: u64,
+        max_bytes: u64,
+    },
+    #[error("has_header is only for csv")]
+    HeaderNotCsv,
+    #[error("{request} is not allowed: this session may not access files")]
+    FileAccessRefused { request: &'static str },
+    #[error("{path:?} is not inside {dir:?}, the directory files may be in")]
+    PathOutsideDir { path: String, dir: PathBuf },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Request {
+    CreateTable(CreateTableRequest),
+    CreateIndex(CreateIndexRequest),
+    DropTable(DropTableRequest),
+    Insert(InsertRequest),
+    Query(QueryRequest),
+    /// Describes the plan `Query` would run; with `analyze`, also runs it
+    /// and responds with `ExplainAnalyze`.
+    Explain(QueryRequest),
+    Delete(DeleteRequest),
+    Update(UpdateRequest),
+    // the fuzz targets leave alone the files of the server
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    Export(ExportRequest),
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    Import(ImportRequest),
+    Analyze(AnalyzeRequest),
+    ListTables,
+    DescribeTable(DescribeTableRequest),
+    /// Holds back the changes of the following requests until `Commit`;
+    /// see `TRANSACTION_GUARANTEE`.
+    Begin,
+    Commit,
+    Rollback,
+    /// Changes a setting of the session; see `repl::Setting`.
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    Set(SetRequest),
+    Show(ShowRequest),
+}
+
+/// What `Begin` promises, which its response repeats.
+pub const TRANSACTION_GUARANTEE: &str = "buffered: changes reach the heap file at Commit, \
+and none of them do after Rollback or a crash before Commit; the transaction must fit \
+in the buffer pool, and with no write-ahead log a crash during Commit can leave it \
+partly written";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetRequest {
+    pub name: String,
+    /// A string, or a number or boolean as a shorthand for its text.
+    pub value: serde_json::Value,
+}
+
+impl SetRequest {
+    pub fn value_text(&self) -> String {
+        match &self.value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ShowRequest {
+    /// Every setting when `None`.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CreateTableRequest {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::name))]
+    pub name: String,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))]
+    pub num_key_elems: usize,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))]
+    pub num_columns: usize,
+    /// Lets SQL statements refer to the columns by name.
+    #[serde(default)]
+    pub column_names: Vec<String>,
+    #[serde(default)]
+    pub schema: Option<Vec<ColumnType>>,
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::opt_column))]
+    pub expiry_column: Option<usize>,
+    #[serde(default)]
+    pub format: Format,
+    /// Created along with the table; the response has their meta page ids
+    /// in this order.
+    #[serde(default)]
+    pub indices: Vec<IndexSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IndexSpec {
+    /// Lets plans refer to the index by name instead of meta page id.
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::opt_name))]
+    pub name: Option<String>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub skey: Vec<usize>,
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(default)]
+    pub directions: Vec<Direction>,
+    /// Value columns stored in the index, for `IndexOnlyScan`.
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub include: Vec<usize>,
+}
+
+impl IndexSpec {
+    fn create(self, bufmgr: &mut BufferPoolManager, table: &mut Table) -> Result<PageId> {
+        table.create_index(
+            bufmgr,
+            self.name,
+            self.skey,
+            self.directions,
+            self.include,
+            self.unique,
+        )
+    }
+}
+
+/// Adds an index to a table, over the rows already in it.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CreateIndexRequest {
+    pub table: ObjectRef,
+    #[serde(flatten)]
+    pub index: IndexSpec,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DropTableRequest {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::name))]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct InsertRequest {
+    pub table: ObjectRef,
+    /// Elements are strings, `{"hex": "..."}` or `{"b64": "..."}` for
+    /// binary values, or `null` for NULL; see `tuple::Record`.
+    pub record: Record,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct QueryRequest {
+    pub plan: PlanNode,
+    /// Runs `plan` as written instead of letting `PlanNode::plan` pick
+    /// scans for it when false.
+    #[serde(default = "optimize_by_default")]
+    pub optimize: bool,
+    /// Counts what each node of the plan does, for `QueryResponse::metrics`.
+    #[serde(default)]
+    pub analyze: bool,
+}
+
+/// How many rows a query reads from its plan at a time.
+const QUERY_BATCH_SIZE: usize = 256;
+
+fn optimize_by_default() -> bool {
+    true
+}
+
+impl QueryRequest {
+    fn build(&mut self, bufmgr: &mut BufferPoolManager) -> Result<BoxPlanNode<'_>> {
+        let now = table::unix_now();
+        let tables = catalog::list_tables(bufmgr)?;
+        self.plan.resolve(&tables)?;
+        Ok(if self.analyze {
+            let expiries = expiries(&tables, now);
+            Box::new(self.plan.analyzed(&tables, now, &expiries, self.optimize))
+        } else if self.optimize {
+            self.plan.plan(&tables, now)
+        } else {
+            self.plan.build(&expiries(&tables, now))
+        })
+    }
+}
+
+/// Deletes the rows of `table` that `plan` yields, which must be rows of
+/// `table`, such as those of a `Filter` over a `SeqScan` of it.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DeleteRequest {
+    pub table: ObjectRef,
+    pub plan: PlanNode,
+}
+
+/// Sets the `set` columns of the rows of `table` that `from` yields and
+/// `where` accepts; like `DeleteRequest::plan`, `from` must yield rows of
+/// `table`. Each expression sees the row as it was.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct UpdateRequest {
+    pub table: ObjectRef,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::keyed))]
+    pub set: Vec<(usize, BytesExpr)>,
+    #[serde(default, rename = "where")]
+    pub where_cond: Option<BoolExpr>,
+    pub from: Box<PlanNode>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportRequest {
+    pub table: ObjectRef,
+    pub path: String,
+    /// Hex-escapes every value instead of only those that are not UTF-8.
+    #[serde(default)]
+    pub hex: bool,
+}
+
+/// Loads the rows of a file on the server's side into a table, a batch
+/// at a time.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportRequest {
+    pub table: ObjectRef,
+    /// Must name a regular file.
+    pub path: String,
+    #[serde(default)]
+    pub format: ImportFormat,
+    /// Skips the first record; only for `Csv`.
+    #[serde(default)]
+    pub has_header: bool,
+    /// Fails at the row after this many, keeping the rows before it.
+    #[serde(default)]
+    pub max_rows: Option<usize>,
+    /// Refuses a larger file before reading any of it.
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+}
+
+/// Which files `Export` and `Import` may name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileAccess {
+    /// Any the process may open, as at the REPL.
+    Any,
+    /// None, as for the clients of the server, who are not its operator.
+    Refused,
+    /// Only those inside this canonical directory.
+    Within(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// See `table::import_csv`.
+    #[default]
+    Csv,
+    /// See `table::import_jsonl`.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AnalyzeRequest {
+    pub table: ObjectRef,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DescribeTableRequest {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::name))]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PlanNode {
+    SeqScan(SeqScanPlan),
+    Filter(FilterPlan),
+    IndexScan(IndexScanPlan),
+    IndexOnlyScan(IndexOnlyScanPlan),
+    Join(JoinPlan),
+    IndexJoin(IndexJoinPlan),
+    Exists(SemiJoinPlan),
+    NotExists(SemiJoinPlan),
+    Aggregate(AggregatePlan),
+    Count(CountPlan),
+    Materialize(MaterializePlan),
+    Project(ProjectPlan),
+    Map(MapPlan),
+    TopK(TopKPlan),
+    Sort(SortPlan),
+    Limit(LimitPlan),
+    RowNumber(RowNumberPlan),
+}
+
+/// A table or an index, by the meta page id of its btree or by its name
+/// in the catalog.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(untagged)]
+pub enum ObjectRef {
+    Id(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::page_id))] u64),
+    Name(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::name))] String),
+}
+
+impl ObjectRef {
+    /// The meta page id. Plans only hold ids once `PlanNode::resolve` has
+    /// looked up their names.
+    fn id(&self) -> u64 {
+        match self {
+            ObjectRef::Id(id) => *id,
+            ObjectRef::Name(name) => panic!("{:?} was not resolved", name),
+        }
+    }
+
+    fn open_table(&self, bufmgr: &mut BufferPoolManager) -> Result<Table> {
+        let name = match self {
+            ObjectRef::Id(id) => {
+                catalog::list_tables(bufmgr)?
+                    .into_iter()
+                    .find(|table| table.meta_page_id.to_u64() == *id)
+                    .ok_or(catalog::Error::TableIdNotFound(*id))?
+                    .name
+            }
+            ObjectRef::Name(name) => name.clone(),
+        };
+        catalog::open_table(bufmgr, &name)
+    }
+
+    fn resolve_table(&mut self, tables: &[Table]) -> Result<()> {
+        if let ObjectRef::Name(name) = self {
+            let table = tables
+                .iter()
+                .find(|table| table.name == *name)
+                .ok_or_else(|| catalog::Error::TableNotFound(name.clone()))?;
+            *self = ObjectRef::Id(table.meta_page_id.to_u64());
+        }
+        Ok(())
+    }
+
+    /// Also returns the directions and included columns of an index given
+    /// by name, which the catalog knows.
+    fn resolve_index(&mut self, tables: &[Table]) -> Result<Option<(Vec<Direction>, usize)>> {
+        let name = match self {
+            ObjectRef::Id(_) => return Ok(None),
+            ObjectRef::Name(name) => name,
+        };
+        let (meta_page_id, directions, include) = tables
+            .iter()
+            .find_map(|table| table.index_by_name(name))
+            .ok_or_else(|| catalog::Error::IndexNotFound(name.clone()))?;
+        let resolved = (directions.to_vec(), include.len());
+        *self = ObjectRef::Id(meta_page_id.to_u64());
+        Ok(Some(resolved))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SeqScanPlan {
+    pub table: ObjectRef,
+    #[serde(default, alias = "from")]
+    pub key: Option<ScanKey>,
+    #[serde(default)]
+    pub to: Option<ScanTo>,
+    #[serde(default, rename = "while")]
+    pub while_expr: WhileExpr,
+    /// `Desc` scans down from `key`, or from the last key without one.
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FilterPlan {
+    pub cond: BoolExpr,
+    pub from: Box<PlanNode>,
+}
+
+/// Joins the rows of `left` and `right` that `on` accepts, in the order of
+/// `left`. `on` sees a left row followed by a right row, so column `i` of
+/// the right row is `Column(left_columns + i)`, where `left_columns` is the
+/// number of columns of `left`. Each left row is checked to have
+/// `left_columns` columns, so a wrong count fails instead of shifting
+/// `on`; `Hash` and `IndexByPk` need it to tell the sides apart.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct JoinPlan {
+    #[serde(default)]
+    pub kind: JoinKind,
+    pub on: BoolExpr,
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::opt_column))]
+    pub left_columns: Option<usize>,
+    pub left: Box<PlanNode>,
+    pub right: Box<PlanNode>,
+    /// Set by `PlanNode::resolve` for `IndexByPk`.
+    #[serde(skip)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pkey_join: Option<PkeyJoin>,
+}
+
+/// How `Join` finds the right rows of a left row. `Hash` and `IndexByPk`
+/// use the `Eq`s of a left column and a right column among the `And`ed
+/// conditions of `on`, and still check all of `on`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum JoinKind {
+    /// Checks `on` for every pair of rows, reading `right` again for each
+    /// left row.
+    #[default]
+    NestedLoop,
+    /// Reads `right` once into a hash table on the right columns of the
+    /// `Eq`s.
+    Hash,
+    /// Looks up the right row by pkey. `right` must be a `SeqScan` of a
+    /// whole table, and `on` must have an `Eq` for each of its pkey columns.
+    IndexByPk,
+}
+
+/// The table and key columns of an `IndexByPk` join.
+#[derive(Debug, Clone)]
+struct PkeyJoin {
+    table: u64,
+    /// The left columns that make up the pkey of a right row.
+    key_columns: Vec<usize>,
+}
+
+impl JoinPlan {
+    /// The `Eq`s of `on` between a left column and a right column, as
+    /// pairs of a left column and the index of a right column in its row.
+    fn equalities(&self, left_columns: usize) -> Vec<(usize, usize)> {
+        fn collect(cond: &BoolExpr, left_columns: usize, pairs: &mut Vec<(usize, usize)>) {
+            match cond {
+                BoolExpr::And(conds) => {
+                    for cond in conds {
+                        collect(cond, left_columns, pairs);
+                    }
+                }
+                BoolExpr::Eq(BytesExpr::Column(a), BytesExpr::Column(b)) => {
+                    let (left, right) = if a < b { (*a, *b) } else { (*b, *a) };
+                    if left < left_columns && right >= left_columns {
+                        pairs.push((left, right - left_columns));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut pairs = vec![];
+        collect(&self.on, left_columns, &mut pairs);
+        pairs
+    }
+
+    /// Checks what `Hash` and `IndexByPk` need from the plan.
+    fn resolve(&mut self, tables: &[Table]) -> Result<()> {
+        if self.kind == JoinKind::NestedLoop {
+            return Ok(());
+        }
+        let left_columns = match self.left_columns {
+            Some(left_columns) => left_columns,
+            None => return Err(Error::NoLeftColumns(self.kind).into()),
+        };
+        let equalities = self.equalities(left_columns);
+        if equalities.is_empty() {
+            return Err(Error::NoJoinEquality(self.kind).into());
+        }
+        if self.kind == JoinKind::Hash {
+            return Ok(());
+        }
+        let meta_page_id = match &*self.right {
+            PlanNode::SeqScan(scan)
+                if scan.key.is_none()
+                    && scan.to.is_none()
+                    && matches!(scan.while_expr, WhileExpr::Always) =>
+            {
+                scan.table.id()
+            }
+            _ => return Err(Error::IndexByPkRight.into()),
+        };
+        let num_key_elems = tables
+            .iter()
+            .find(|table| table.meta_page_id.to_u64() == meta_page_id)
+            .ok_or(catalog::Error::TableIdNotFound(meta_page_id))?
+            .num_key_elems;
+        let key_columns = (0..num_key_elems)
+            .map(|i| {
+                equalities
+                    .iter()
+                    .find(|&&(_, right)| right == i)
+                    .map(|&(left, _)| left)
+                    .ok_or(Error::NoPkeyEquality(i))
+            })
+            .collect::<Result<_, _>>()?;
+        self.pkey_join = Some(PkeyJoin {
+            table: meta_page_id,
+            key_columns,
+        });
+        Ok(())
+    }
+
+    fn build<'a>(
+        &'a self,
+        expiries: &HashMap<u64, Expiry>,
+        child: &dyn Fn(&'a PlanNode) -> BoxPlanNode<'a>,
+    ) -> BoxPlanNode<'a> {
+        let left = match self.left_columns {
+            Some(expected) => Box::new(query::Annotated {
+                inner: Box::new(query::Filter {
+                    inner_plan: child(&self.left),
+                    cond: Box::new(move |row| match row.len() {
+                        actual if actual == expected => Ok(true),
+                        actual => Err(query::Error::JoinArity { expected, actual }.into()),
+                    }),
+                }),
+                properties: vec![("left_columns".to_string(), expected.to_string())],
+            }),
+            None => child(&self.left),
+        };
+        let cond = move |left: TupleSlice, right: TupleSlice| {
+            let record: Vec<_> = left.iter().chain(right).collect();
+            Ok(self.on.eval(&record)? == Some(true))
+        };
+        let join: BoxPlanNode<'a> = match self.kind {
+            JoinKind::NestedLoop => Box::new(query::NestedLoopJoin {
+                outer_plan: left,
+                inner_plan: child(&self.right),
+                cond: Box::new(cond),
+            }),
+            JoinKind::Hash => {
+                let (outer_key, inner_key) = self
+                    .equalities(self.left_columns.expect("resolved"))
+                    .into_iter()
+                    .unzip();
+                Box::new(query::HashJoin {
+                    outer_plan: left,
+                    inner_plan: child(&self.right),
+                    outer_key,
+                    inner_key,
+                    cond: Box::new(cond),
+                })
+            }
+            JoinKind::IndexByPk => {
+                let pkey_join = self.pkey_join.as_ref().expect("resolved");
+                Box::new(query::Filter {
+                    inner_plan: Box::new(query::IndexJoin {
+                        outer_plan: left,
+                        inner_table_meta_page_id: PageId(pkey_join.table),
+                        key_columns: pkey_join.key_columns.clone(),
+                        unmatched: query::Unmatched::Skip,
+                        inner_expiry: expiries.get(&pkey_join.table).copied(),
+                    }),
+                    cond: Box::new(move |record| Ok(self.on.eval(record)? == Some(true))),
+                })
+            }
+        };
+        annotated("on", &self.on, join)
+    }
+}
+
+/// Joins each row of `from` to the row of `table` whose pkey is made of
+/// the `key` columns of the row.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IndexJoinPlan {
+    pub from: Box<PlanNode>,
+    pub table: ObjectRef,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub key: Vec<usize>,
+    #[serde(default)]
+    pub unmatched: query::Unmatched,
+}
+
+/// The rows of `from` that have a row of `table` whose pkey is made of
+/// their `key` columns, for `Exists`, or that have none, for `NotExists`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SemiJoinPlan {
+    pub from: Box<PlanNode>,
+    pub table: ObjectRef,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub key: Vec<usize>,
+}
+
+impl SemiJoinPlan {
+    fn build<'a>(
+        &'a self,
+        negated: bool,
+        expiries: &HashMap<u64, Expiry>,
+        child: &dyn Fn(&'a PlanNode) -> BoxPlanNode<'a>,
+    ) -> query::SemiJoin<'a> {
+        query::SemiJoin {
+            outer_plan: child(&self.from),
+            inner_table_meta_page_id: PageId(self.table.id()),
+            key_columns: self.key.clone(),
+            negated,
+            inner_expiry: expiries.get(&self.table.id()).copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AggregatePlan {
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub group_by: Vec<usize>,
+    pub aggs: Vec<query::AggSpec>,
+    pub from: Box<PlanNode>,
+}
+
+/// The `columns` of each row of `from`, in that order.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ProjectPlan {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub columns: Vec<usize>,
+    pub from: Box<PlanNode>,
+}
+
+/// One column for each of `exprs`, evaluated on each row of `from`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MapPlan {
+    pub exprs: Vec<BytesExpr>,
+    pub from: Box<PlanNode>,
+}
+
+/// The first `k` rows of `from` in the order of the `sort_key` columns,
+/// each given as `[column, "Asc" or "Desc"]`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TopKPlan {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::keyed))]
+    pub sort_key: Vec<(usize, Direction)>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))]
+    pub k: usize,
+    pub from: Box<PlanNode>,
+}
+
+/// The rows of `from` in the order of the `by` columns, each given as
+/// `[column, "Asc" or "Desc"]`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SortPlan {
+    #[serde(deserialize_with = "non_empty_sort_key")]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::keyed))]
+    pub by: Vec<(usize, Direction)>,
+    pub from: Box<PlanNode>,
+}
+
+fn non_empty_sort_key<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<(usize, Direction)>, D::Error> {
+    let sort_key = Vec::deserialize(deserializer)?;
+    if sort_key.is_empty() {
+        return Err(D::Error::custom("sort key is empty"));
+    }
+    Ok(sort_key)
+}
+
+/// At most `limit` rows of `from`, after skipping the first `offset`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LimitPlan {
+    pub limit: usize,
+    #[serde(default)]
+    pub offset: usize,
+    pub from: Box<PlanNode>,
+}
+
+/// The rows of `from`, each followed by its number among the rows next to
+/// it with the same `partition_by` columns; `from` must be sorted by them.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RowNumberPlan {
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub partition_by: Vec<usize>,
+    pub from: Box<PlanNode>,
+}
+
+/// Keeps the rows of `from` in memory, so that a plan that reads them many
+/// times, like the right side of a `Join`, runs `from` once.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MaterializePlan {
+    pub from: Box<PlanNode>,
+}
+
+/// Counts the rows of a pkey range; the response has one row holding the
+/// count as a `U64`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CountPlan {
+    pub table: ObjectRef,
+    #[serde(default, alias = "from")]
+    pub key: Option<ScanKey>,
+    #[serde(default)]
+    pub to: Option<ScanTo>,
+    #[serde(default, rename = "while")]
+    pub while_expr: WhileExpr,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IndexScanPlan {
+    pub table: ObjectRef,
+    pub index: ObjectRef,
+    #[serde(default)]
+    pub directions: Vec<Direction>,
+    #[serde(default)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))]
+    pub num_included: usize,
+    #[serde(default, alias = "from")]
+    pub key: Option<ScanKey>,
+    #[serde(default)]
+    pub to: Option<ScanTo>,
+    #[serde(default, rename = "while")]
+    pub while_expr: WhileExpr,
+    /// `Desc` scans down from `key`, or from the last key without one.
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IndexOnlyScanPlan {
+    pub index: ObjectRef,
+    #[serde(default)]
+    pub directions: Vec<Direction>,
+    #[serde(default, alias = "from")]
+    pub key: Option<ScanKey>,
+    #[serde(default)]
+    pub to: Option<ScanTo>,
+    #[serde(default, rename = "while")]
+    pub while_expr: WhileExpr,
+    /// `Desc` scans down from `key`, or from the last key without one.
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+/// Where a scan ends: at the last key whose leading elements are `key`, or
+/// just before the first one if not `inclusive`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ScanTo {
+    pub key: Vec<BytesValue>,
+    #[serde(default)]
+    pub inclusive: bool,
+}
+
+impl ScanTo {
+    fn key(&self) -> query::Tuple {
+        self.key
+            .iter()
+            .map(|elem| Some(elem.as_bytes().to_vec()))
+            .collect()
+    }
+
+    fn range_end(&self) -> query::RangeEnd {
+        query::RangeEnd {
+            key: self.key(),
+            inclusive: self.inclusive,
+        }
+    }
+
+    fn bound(&self, direction: Direction) -> query::KeyBound {
+        query::KeyBound {
+            key: self.key(),
+            accept: match (direction, self.inclusive) {
+                (Direction::Asc, true) => Ordering::is_le,
+                (Direction::Asc, false) => Ordering::is_lt,
+                (Direction::Desc, true) => Ordering::is_ge,
+                (Direction::Desc, false) => Ordering::is_gt,
+            },
+        }
+    }
+}
+
+/// Bytes written in JSON as a hex string.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Hex(pub Vec<u8>);
+
+impl<'de> Deserialize<'de> for Hex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        tuple::from_hex(&hex)
+            .map(Hex)
+            .ok_or_else(|| D::Error::custom("invalid hex string"))
+    }
+}
+
+/// Where a scan starts.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(untagged)]
+pub enum ScanKey {
+    Elements(Vec<BytesValue>),
+    /// A key from `tuple::KeyBuilder`, used as is.
+    Encoded(Hex),
+    /// A key of `I64` columns, encoded like the bound of `WhileExpr::NumEq`.
+    Numbers(Vec<i64>),
+}
+
+/// A condition on the leading elements of the key being scanned.
+/// The scan stops at the first key for which it does not hold, so `Gt` and
+/// `Ge` are for `Desc` scans.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum WhileExpr {
+    #[default]
+    Always,
+    Eq(Vec<BytesValue>),
+    Lt(Vec<BytesValue>),
+    Le(Vec<BytesValue>),
+    Gt(Vec<BytesValue>),
+    Ge(Vec<BytesValue>),
+    /// Like `Eq` through `Ge`, for keys of `I64` columns: the bound is a
+    /// list of numbers, encoded with `tuple::encode_i64` so that it orders
+    /// like the keys.
+    #[serde(deserialize_with = "i64_bound")]
+    NumEq(Vec<BytesValue>),
+    #[serde(deserialize_with = "i64_bound")]
+    NumLt(Vec<BytesValue>),
+    #[serde(deserialize_with = "i64_bound")]
+    NumLe(Vec<BytesValue>),
+    #[serde(deserialize_with = "i64_bound")]
+    NumGt(Vec<BytesValue>),
+    #[serde(deserialize_with = "i64_bound")]
+    NumGe(Vec<BytesValue>),
+}
+
+fn i64_bound<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<BytesValue>, D::Error> {
+    let bound = Vec::<i64>::deserialize(deserializer)?;
+    Ok(bound
+        .into_iter()
+        .map(|n| BytesValue(tuple::encode_i64(n).to_vec()))
+        .collect())
+}
+
+impl WhileExpr {
+    fn elems(&self) -> &[BytesValue] {
+        match self {
+            WhileExpr::Always => &[],
+            WhileExpr::Eq(bound)
+            | WhileExpr::Lt(bound)
+            | WhileExpr::Le(bound)
+            | WhileExpr::Gt(bound)
+            | WhileExpr::Ge(bound)
+            | WhileExpr::NumEq(bound)
+            | WhileExpr::NumLt(bound)
+            | WhileExpr::NumLe(bound)
+            | WhileExpr::NumGt(bound)
+            | WhileExpr::NumGe(bound) => bound,
+        }
+    }
+
+    fn accept(&self) -> fn(Ordering) -> bool {
+        match self {
+            WhileExpr::Always => |_| true,
+            WhileExpr::Eq(_) | WhileExpr::NumEq(_) => Ordering::is_eq,
+            WhileExpr::Lt(_) | WhileExpr::NumLt(_) => Ordering::is_lt,
+            WhileExpr::Le(_) | WhileExpr::NumLe(_) => Ordering::is_le,
+            WhileExpr::Gt(_) | WhileExpr::NumGt(_) => Ordering::is_gt,
+            WhileExpr::Ge(_) | WhileExpr::NumGe(_) => Ordering::is_ge,
+        }
+    }
+
+    /// The condition as a bound on the encoded key, or `None` for `Always`.
+    pub fn bound(&self) -> Option<query::KeyBound> {
+        if let WhileExpr::Always = self {
+            return None;
+        }
+        Some(query::KeyBound {
+            key: self
+                .elems()
+                .iter()
+                .map(|elem| Some(elem.as_bytes().to_vec()))
+                .collect(),
+            accept: self.accept(),
+        })
+    }
+
+    pub fn eval(&self, key: TupleSlice) -> bool {
+        let bound = self.elems();
+        let ordering = key
+            .iter()
+            .zip(bound)
+            .map(|(elem, bound)| tuple::cmp_elem(elem.as_deref(), Some(bound.as_bytes())))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| key.len().min(bound.len()).cmp(&bound.len()));
+        self.accept()(ordering)
+    }
+}
+
+fn parse_value(ty: ColumnType, elem: Option<&[u8]>) -> Result<Value, tuple::Error> {
+    let elem = match elem {
+        Some(elem) if ty == ColumnType::Bytes => return Ok(Value::Bytes(elem.to_vec())),
+        Some(elem) => std::str::from_utf8(elem).ok(),
+        None => return Ok(Value::Null),
+    };
+    let value = elem.and_then(|elem| match ty {
+        ColumnType::Bytes => unreachable!(),
+        ColumnType::Utf8 => Some(Value::Utf8(elem.to_string())),
+        ColumnType::I64 => elem.parse().ok().map(Value::I64),
+        ColumnType::U64 => elem.parse().ok().map(Value::U64),
+        ColumnType::F64 => elem.parse().ok().map(Value::F64),
+        ColumnType::Bool => elem.parse().ok().map(Value::Bool),
+    });
+    value.ok_or(tuple::Error::InvalidValue(ty))
+}
+
+fn scan_direction(direction: Direction) -> ScanDirection {
+    match direction {
+        Direction::Asc => ScanDirection::Forward,
+        Direction::Desc => ScanDirection::Backward,
+    }
+}
+
+fn search_mode(key: &Option<ScanKey>) -> TupleSearchMode {
+    match key {
+        Some(ScanKey::Elements(key)) => TupleSearchMode::Key(
+            key.iter()
+                .map(|elem| Some(elem.as_bytes().to_vec()))
+                .collect(),
+        ),
+        Some(ScanKey::Encoded(Hex(key))) => TupleSearchMode::Encoded(key.clone()),
+        Some(ScanKey::Numbers(key)) => TupleSearchMode::Key(
+            key.iter()
+                .map(|&n| Some(tuple::encode_i64(n).to_vec()))
+                .collect(),
+        ),
+        None => TupleSearchMode::Start,
+    }
+}
+
+impl SeqScanPlan {
+    fn build<'a>(
+        &'a self,
+        cond: Option<&'a BoolExpr>,
+        expiries: &HashMap<u64, Expiry>,
+    ) -> query::SeqScan<'a> {
+        // the scan checks one bound on the encoded key, so a `while` next
+        // to `to` is checked on the decoded key instead
+        let (while_bound, while_cond): (_, query::Predicate<'a>) = match &self.to {
+            Some(to) => (
+                Some(to.bound(self.direction)),
+                Box::new(move |pkey| Ok(self.while_expr.eval(pkey))),
+            ),
+            None => (self.while_expr.bound(), Box::new(|_| Ok(true))),
+        };
+        query::SeqScan {
+            table_meta_page_id: PageId(self.table.id()),
+            search_mode: search_mode(&self.key),
+            direction: scan_direction(self.direction),
+            while_bound,
+            while_cond,
+            expiry: expiries.get(&self.table.id()).copied(),
+            filter: cond.map(|cond| -> query::BorrowedPredicate<'a> {
+                Box::new(move |record| Ok(cond.eval(record)? == Some(true)))
+            }),
+        }
+    }
+}
+
+impl CountPlan {
+    fn build<'a>(&'a self, expiries: &HashMap<u64, Expiry>) -> query::CountScan<'a> {
+        // as in `SeqScanPlan::build`, only one of `to` and `while` is
+        // checked on the encoded key
+        let (while_bound, while_cond): (_, Option<query::Predicate<'a>>) = match &self.to {
+            Some(to) if !matches!(self.while_expr, WhileExpr::Always) => (
+                Some(to.bound(Direction::Asc)),
+                Some(Box::new(move |pkey| Ok(self.while_expr.eval(pkey)))),
+            ),
+            Some(to) => (Some(to.bound(Direction::Asc)), None),
+            None => (self.while_expr.bound(), None),
+        };
+        query::CountScan {
+            table_meta_page_id: PageId(self.table.id()),
+            search_mode: search_mode(&self.key),
+            while_bound,
+            while_cond,
+            expiry: expiries.get(&self.table.id()).copied(),
+        }
+    }
+}
+
+impl PlanNode {
+    /// Replaces the names of tables and indices with their meta page ids,
+    /// which every other method expects. An index given by name also takes
+    /// its directions and number of included columns from the catalog.
+    pub fn resolve(&mut self, tables: &[Table]) -> Result<()> {
+        match self {
+            PlanNode::SeqScan(plan) => plan.table.resolve_table(tables),
+            PlanNode::Count(plan) => plan.table.resolve_table(tables),
+            PlanNode::IndexScan(plan) => {
+                plan.table.resolve_table(tables)?;
+                if let Some((directions, num_included)) = plan.index.resolve_index(tables)? {
+                    plan.directions = directions;
+                    plan.num_included = num_included;
+                }
+                Ok(())
+            }
+            PlanNode::IndexOnlyScan(plan) => {
+                if let Some((directions, _)) = plan.index.resolve_index(tables)? {
+                    plan.directions = directions;
+                }
+                Ok(())
+            }
+            PlanNode::IndexJoin(plan) => {
+                plan.table.resolve_table(tables)?;
+                plan.from.resolve(tables)
+            }
+            PlanNode::Exists(plan) | PlanNode::NotExists(plan) => {
+                plan.table.resolve_table(tables)?;
+                plan.from.resolve(tables)
+            }
+            PlanNode::Join(plan) => {
+                plan.left.resolve(tables)?;
+                plan.right.resolve(tables)?;
+                plan.resolve(tables)
+            }
+            PlanNode::Filter(FilterPlan { from, .. })
+            | PlanNode::Aggregate(AggregatePlan { from, .. })
+            | PlanNode::Materialize(MaterializePlan { from })
+            | PlanNode::Project(ProjectPlan { from, .. })
+            | PlanNode::Map(MapPlan { from, .. })
+            | PlanNode::TopK(TopKPlan { from, .. })
+            | PlanNode::Sort(SortPlan { from, .. })
+            | PlanNode::Limit(LimitPlan { from, .. })
+            | PlanNode::RowNumber(RowNumberPlan { from, .. }) => from.resolve(tables),
+        }
+    }
+
+    /// Builds the plan. `expiries` maps a table's meta page id to the filter
+    /// that hides its expired rows.
+    pub fn build(&self, expiries: &HashMap<u64, Expiry>) -> BoxPlanNode<'_> {
+        self.build_with(expiries, &|child| child.build(expiries))
+    }
+
+    /// Like `build`, but leaves it to `query::plan::optimize` to choose how
+    /// to read a table for filters and projections over a `SeqScan` of all
+    /// of it. `tables` are those of the catalog, and `now` decides which
+    /// rows have expired.
+    pub fn plan(&self, tables: &[Table], now: u64) -> BoxPlanNode<'_> {
+        self.plan_with(tables, now, &expiries(tables, now))
+    }
+
+    fn plan_with<'a>(
+        &'a self,
+        tables: &[Table],
+        now: u64,
+        expiries: &HashMap<u64, Expiry>,
+    ) -> BoxPlanNode<'a> {
+        match self.logical() {
+            Some(plan) => query::plan::optimize(plan, tables, now),
+            None => self.build_with(expiries, &|child| child.plan_with(tables, now, expiries)),
+        }
+    }
+
+    /// Like `plan_with`, or `build` unless `optimize`, with each node
+    /// wrapped in a `query::CollectMetrics`. A plan that
+    /// `query::plan::optimize` chooses counts as a single node.
+    fn analyzed<'a>(
+        &'a self,
+        tables: &[Table],
+        now: u64,
+        expiries: &HashMap<u64, Expiry>,
+        optimize: bool,
+    ) -> query::CollectMetrics<'a> {
+        let inputs = RefCell::new(vec![]);
+        let inner = match self.logical().filter(|_| optimize) {
+            Some(plan) => query::plan::optimize(plan, tables, now),
+            None => self.build_with(expiries, &|child| {
+                let child = child.analyzed(tables, now, expiries, optimize);
+                inputs.borrow_mut().push(child.meter());
+                Box::new(child)
+            }),
+        };
+        query::CollectMetrics::new(inner, inputs.into_inner())
+    }
+
+    /// The names of the columns of the rows of a resolved plan, if the
+    /// tables it reads have named columns.
+    fn column_names(&self, tables: &[Table]) -> Option<Vec<String>> {
+        let table_columns = |table: &ObjectRef| {
+            let table = tables
+                .iter()
+                .find(|table_| Some(table_.meta_page_id.to_u64()) == Some(table.id()))?;
+            if table.column_names.is_empty() && table.added_columns.is_empty() {
+                return None;
+            }
+            let first_added = table.num_columns - table.added_columns.len();
+            let names = (0..table.num_columns).map(|column| match table.column_names.get(column) {
+                Some(name) => name.clone(),
+                None => match column.checked_sub(first_added) {
+                    Some(i) => table.added_columns[i].name.clone(),
+                    None => column.to_string(),
+                },
+            });
+            Some(names.collect())
+        };
+        match self {
+            PlanNode::SeqScan(scan) => table_columns(&scan.table),
+            PlanNode::IndexScan(scan) => table_columns(&scan.table),
+            PlanNode::Filter(FilterPlan { from, .. })
+            | PlanNode::Sort(SortPlan { from, .. })
+            | PlanNode::Limit(LimitPlan { from, .. })
+            | PlanNode::TopK(TopKPlan { from, .. })
+            | PlanNode::Materialize(MaterializePlan { from }) => from.column_names(tables),
+            PlanNode::Project(project) => {
+                let names = project.from.column_names(tables)?;
+                project
+                    .columns
+                    .iter()
+                    .map(|&column| names.get(column).cloned())
+                    .collect()
+            }
+            PlanNode::Join(join) => {
+                let mut names = join.left.column_names(tables)?;
+                names.extend(join.right.column_names(tables)?);
+                Some(names)
+            }
+            _ => None,
+        }
+    }
+
+    /// The plan as a `LogicalPlan`, if it only reads whole tables, filters
+    /// and projects.
+    fn logical(&self) -> Option<LogicalPlan> {
+        match self {
+            PlanNode::SeqScan(scan)
+                if scan.key.is_none()
+                    && scan.to.is_none()
+                    && matches!(scan.while_expr, WhileExpr::Always)
+                    && scan.direction == Direction::Asc =>
+            {
+                Some(LogicalPlan::Scan {
+                    table: scan.table.id(),
+                })
+            }
+            PlanNode::Filter(plan) => Some(LogicalPlan::Filter {
+                cond: plan.cond.clone(),
+                from: Box::new(plan.from.logical()?),
+            }),
+            PlanNode::Project(plan) => Some(LogicalPlan::Project {
+                columns: plan.columns.clone(),
+                from: Box::new(plan.from.logical()?),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Builds this node, with `child` building the plans it reads from.
+    fn build_with<'a>(
+        &'a self,
+        expiries: &HashMap<u64, Expiry>,
+        child: &dyn Fn(&'a PlanNode) -> BoxPlanNode<'a>,
+    ) -> BoxPlanNode<'a> {
+        match self {
+            PlanNode::SeqScan(plan) => Box::new(plan.build(None, expiries)),
+            PlanNode::Filter(plan) => annotated(
+                "cond",
+                &plan.cond,
+                match &*plan.from {
+                    // evaluated inside the scan, before rows are copied out of the page
+                    PlanNode::SeqScan(scan) => Box::new(scan.build(Some(&plan.cond), expiries)),
+                    from => Box::new(query::Filter {
+                        inner_plan: child(from),
+                        cond: Box::new(move |record| Ok(plan.cond.eval(record)? == Some(true))),
+                    }),
+                },
+            ),
+            PlanNode::IndexScan(plan) => Box::new(query::IndexScan {
+                table_meta_page_id: PageId(plan.table.id()),
+                index_meta_page_id: PageId(plan.index.id()),
+                directions: plan.directions.clone(),
+                num_included: plan.num_included,
+                search_mode: search_mode(&plan.key),
+                direction: scan_direction(plan.direction),
+                end: plan.to.as_ref().map(ScanTo::range_end),
+                while_cond: Box::new(move |skey| Ok(plan.while_expr.eval(skey))),
+                expiry: expiries.get(&plan.table.id()).copied(),
+            }),
+            PlanNode::IndexOnlyScan(plan) => Box::new(query::IndexOnlyScan {
+                index_meta_page_id: PageId(plan.index.id()),
+                directions: plan.directions.clone(),
+                search_mode: search_mode(&plan.key),
+                direction: scan_direction(plan.direction),
+                end: plan.to.as_ref().map(ScanTo::range_end),
+                while_cond: Box::new(move |skey| Ok(plan.while_expr.eval(skey))),
+            }),
+            PlanNode::IndexJoin(plan) => Box::new(query::IndexJoin {
+                outer_plan: child(&plan.from),
+                inner_table_meta_page_id: PageId(plan.table.id()),
+                key_columns: plan.key.clone(),
+                unmatched: plan.unmatched,
+                inner_expiry: expiries.get(&plan.table.id()).copied(),
+            }),
+            PlanNode::Exists(plan) => Box::new(plan.build(false, expiries, child)),
+            PlanNode::NotExists(plan) => Box::new(plan.build(true, expiries, child)),
+            PlanNode::Count(plan) => Box::new(plan.build(expiries)),
+            PlanNode::Project(plan) => Box::new(query::Project {
+                inner_plan: child(&plan.from),
+                columns: plan.columns.clone(),
+            }),
+            PlanNode::Map(plan) => Box::new(query::Map {
+                inner_plan: child(&plan.from),
+                exprs: plan.exprs.clone(),
+            }),
+            PlanNode::TopK(plan) => Box::new(query::TopK {
+                inner_plan: child(&plan.from),
+                sort_key: plan.sort_key.clone(),
+                k: plan.k,
+            }),
+            PlanNode::Sort(plan) => Box::new(query::Sort {
+                inner_plan: child(&plan.from),
+                sort_key: plan.by.clone(),
+            }),
+            PlanNode::Limit(plan) => Box::new(query::Limit {
+                inner_plan: child(&plan.from),
+                limit: plan.limit,
+                offset: plan.offset,
+            }),
+            PlanNode::RowNumber(plan) => Box::new(query::RowNumber {
+                inner_plan: child(&plan.from),
+                partition_by: plan.partition_by.clone(),
+            }),
+            PlanNode::Materialize(plan) => Box::new(query::Materialize {
+                inner_plan: child(&plan.from),
+            }),
+            PlanNode::Aggregate(plan) => Box::new(query::Aggregate {
+                inner_plan: child(&plan.from),
+                group_by: plan.group_by.clone(),
+                aggs: plan.aggs.clone(),
+            }),
+            PlanNode::Join(plan) => plan.build(expiries, child),
+        }
+    }
+}
+
+/// Shows `expr` in what `plan` explains; the node only sees a closure.
+fn annotated<'a>(name: &str, expr: &BoolExpr, plan: BoxPlanNode<'a>) -> BoxPlanNode<'a> {
+    Box::new(query::Annotated {
+        inner: plan,
+        properties: vec![(name.to_string(), format!("{:?}", expr))],
+    })
+}
+
+/// Shown with `Display` in the REPL, or as a line of JSON with `--json`.
+#[derive(Debug, Serialize)]
+pub enum Response {
+    CreateTable {
+        meta_page_id: PageId,
+        /// Of `CreateTableRequest::indices`.
+        index_meta_page_ids: Vec<PageId>,
+    },
+    CreateIndex {
+        meta_page_id: PageId,
+    },
+    DropTable,
+    Insert,
+    Query(QueryResponse),
+    Explain(PlanDescription),
+    ExplainAnalyze {
+        plan: PlanDescription,
+        /// The number of rows the plan returned.
+        rows: usize,
+        metrics: Metrics,
+    },
+    Delete {
+        rows: u64,
+    },
+    Update {
+        rows: u64,
+    },
+    Export {
+        rows: usize,
+    },
+    Import {
+        rows: usize,
+    },
+    Analyze(TableStats),
+    ListTables(Vec<TableSummary>),
+    DescribeTable(TableDescription),
+    Begin {
+        guarantee: &'static str,
+    },
+    Commit {
+        /// The pages written to the heap file.
+        pages: usize,
+        /// False while group commit holds back the sync; see
+        /// `buffer::Commit::durable`.
+        durable: bool,
+    },
+    Rollback {
+        /// The pages the transaction modified or created, put back as they
+        /// were or dropped from the buffer pool.
+        pages: usize,
+    },
+    Set(SettingValue),
+    Show(Vec<SettingValue>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingValue {
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSummary {
+    pub name: String,
+    pub meta_page_id: u64,
+    pub num_key_elems: usize,
+    /// The number of entries in the table's btree; it counts expired rows
+    /// that are not yet purged.
+    pub rows: u64,
+    pub indices: Vec<IndexSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexSummary {
+    pub name: Option<String>,
+    pub meta_page_id: u64,
+    pub skey: Vec<usize>,
+    pub unique: bool,
+    pub directions: Vec<Direction>,
+    pub include: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableDescription {
+    #[serde(flatten)]
+    pub summary: TableSummary,
+    pub columns: Vec<ColumnDescription>,
+    pub constraints: Vec<Constraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub expiry_column: Option<usize>,
+    pub format: Format,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnDescription {
+    /// Only added columns have names.
+    pub name: Option<String>,
+    /// None for a table without a schema.
+    pub ty: Option<ColumnType>,
+    pub key: bool,
+}
+
+impl TableSummary {
+    fn new(bufmgr: &mut BufferPoolManager, table: &Table) -> Result<Self> {
+        let unique = table.unique_indices.iter().map(|index| IndexSummary {
+            name: index.name.clone(),
+            meta_page_id: index.meta_page_id.to_u64(),
+            skey: index.skey.clone(),
+            unique: true,
+            directions: index.directions.clone(),
+            include: index.include.clone(),
+        });
+        let secondary = table.secondary_indices.iter().map(|index| IndexSummary {
+            name: index.name.clone(),
+            meta_page_id: index.meta_page_id.to_u64(),
+            skey: index.skey.clone(),
+            unique: false,
+            directions: index.directions.clone(),
+            include: index.include.clone(),
+        });
+        Ok(Self {
+            name: table.name.clone(),
+            meta_page_id: table.meta_page_id.to_u64(),
+            num_key_elems: table.num_key_elems,
+            rows: BTree::new(table.meta_page_id).num_entries(bufmgr)?,
+            indices: unique.chain(secondary).collect(),
+        })
+    }
+}
+
+impl TableDescription {
+    fn new(bufmgr: &mut BufferPoolManager, table: Table) -> Result<Self> {
+        let first_added = table.num_columns - table.added_columns.len();
+        let columns = (0..table.num_columns)
+            .map(|column| ColumnDescription {
+                name: table.column_names.get(column).cloned().or_else(|| {
+                    column
+                        .checked_sub(first_added)
+                        .map(|i| table.added_columns[i].name.clone())
+                }),
+                ty: table.schema.as_ref().map(|schema| schema[column]),
+                key: column < table.num_key_elems,
+            })
+            .collect();
+        Ok(Self {
+            summary: TableSummary::new(bufmgr, &table)?,
+            columns,
+            constraints: table.constraints,
+            foreign_keys: table.foreign_keys,
+            expiry_column: table.expiry_column,
+            format: table.format,
+        })
+    }
+}
+
+impl IndexSummary {
+    fn describe(&self) -> String {
+        let skey: Vec<_> = self
+            .skey
+            .iter()
+            .enumerate()
+            .map(|(i, column)| match self.directions.get(i) {
+                Some(Direction::Desc) => format!("{} desc", column),
+                _ => column.to_string(),
+            })
+            .collect();
+        let mut description = self
+            .name
+            .as_ref()
+            .map_or_else(String::new, |name| format!("{}: ", name));
+        description.push_str(&format!(
+            "{}({})",
+            if self.unique { "unique" } else { "index" },
+            skey.join(", ")
+        ));
+        if !self.include.is_empty() {
+            description.push_str(&format!(" include {:?}", self.include));
+        }
+        description
+    }
+}
+
+/// Writes `rows` under `header` with each column padded to its widest cell.
+/// Rows of cells under a header, drawn like the tables of `Response`.
+pub struct TextTable<'a> {
+    pub header: &'a [&'a str],
+    pub rows: Vec<Vec<String>>,
+}
+
+impl fmt::Display for TextTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_aligned(f, self.header, &self.rows)
+    }
+}
+
+fn write_aligned(f: &mut fmt::Formatter<'_>, header: &[&str], rows: &[Vec<String>]) -> fmt::Result {
+    let mut widths: Vec<_> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let write_row = |f: &mut fmt::Formatter<'_>, row: &mut dyn Iterator<Item = &str>| {
+        let cells: Vec<_> = row
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(f, "{}", cells.join(" | ").trim_end())
+    };
+    write_row(f, &mut header.iter().copied())?;
+    let rule: Vec<_> = widths.iter().map(|&width| "-".repeat(width)).collect();
+    writeln!(f, "{}", rule.join("-+-"))?;
+    for row in rows {
+        write_row(f, &mut row.iter().map(String::as_str))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TableSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (meta_page_id = {}, rows = {})",
+            self.name, self.meta_page_id, self.rows
+        )?;
+        let rows: Vec<_> = self
+            .indices
+            .iter()
+            .map(|index| vec![index.meta_page_id.to_string(), index.describe()])
+            .collect();
+        write!(f, "indices:")?;
+        if rows.is_empty() {
+            return write!(f, " (none)");
+        }
+        writeln!(f)?;
+        write_aligned(f, &["meta_page_id", "index"], &rows)
+    }
+}
+
+impl fmt::Display for TableDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary)?;
+        let rows: Vec<_> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                vec![
+                    i.to_string(),
+                    column.name.clone().unwrap_or_default(),
+                    column.ty.map(|ty| format!("{:?}", ty)).unwrap_or_default(),
+                    if column.key { "key" } else { "" }.to_string(),
+                ]
+            })
+            .collect();
+        write_aligned(f, &["column", "name", "type", "key"], &rows)?;
+        for constraint in &self.constraints {
+            writeln!(f, "constraint: {:?}", constraint)?;
+        }
+        for foreign_key in &self.foreign_keys {
+            writeln!(
+                f,
+                "foreign key: {:?} -> {} on delete {:?}",
+                foreign_key.columns, foreign_key.parent, foreign_key.on_delete
+            )?;
+        }
+        if let Some(column) = self.expiry_column {
+            writeln!(f, "expiry column: {}", column)?;
+        }
+        write!(f, "format: {:?}", self.format)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryResponse {
+    pub rows: Vec<Record>,
+    /// What each node of the plan did, for a request with `analyze`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<Metrics>,
+    /// The names of the columns of the rows, where the plan and the catalog
+    /// tell them; only for display.
+    #[serde(skip)]
+    pub columns: Option<Vec<String>>,
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Response::CreateTable {
+                meta_page_id,
+                index_meta_page_ids,
+            } => {
+                write!(f, "CREATE TABLE (meta_page_id = {}", meta_page_id.to_u64())?;
+                if !index_meta_page_ids.is_empty() {
+                    let ids: Vec<_> = index_meta_page_ids.iter().map(|id| id.to_u64()).collect();
+                    write!(f, ", index_meta_page_ids = {:?}", ids)?;
+                }
+                write!(f, ")")
+            }
+            Response::CreateIndex { meta_page_id } => {
+                write!(f, "CREATE INDEX (meta_page_id = {})", meta_page_id.to_u64())
+            }
+            Response::DropTable => write!(f, "DROP TABLE"),
+            Response::Insert => write!(f, "INSERT"),
+            Response::Query(query) => write!(f, "{}", query),
+            Response::Explain(description) => write!(f, "{}", description),
+            Response::ExplainAnalyze {
+                plan,
+                rows,
+                metrics,
+            } => write!(f, "{}({} rows)\n{}", plan, rows, metrics),
+            Response::Delete { rows } => write!(f, "DELETE {}", rows),
+            Response::Update { rows } => write!(f, "UPDATE {}", rows),
+            Response::Export { rows } => write!(f, "EXPORT {}", rows),
+            Response::Import { rows } => write!(f, "IMPORT {}", rows),
+            Response::Analyze(stats) => write!(
+                f,
+                "ANALYZE rows={} avg_key_size={:.1} avg_value_size={:.1} distinct={:?}",
+                stats.row_count, stats.avg_key_size, stats.avg_value_size, stats.distinct_values
+            ),
+            Response::ListTables(tables) => {
+                let rows: Vec<_> = tables
+                    .iter()
+                    .map(|table| {
+                        let indices: Vec<_> =
+                            table.indices.iter().map(IndexSummary::describe).collect();
+                        vec![
+                            table.name.clone(),
+                            table.meta_page_id.to_string(),
+                            table.num_key_elems.to_string(),
+                            table.rows.to_string(),
+                            indices.join("; "),
+                        ]
+                    })
+                    .collect();
+                write_aligned(
+                    f,
+                    &["name", "meta_page_id", "key", "rows", "indices"],
+                    &rows,
+                )?;
+                write!(f, "({} tables)", tables.len())
+            }
+            Response::DescribeTable(description) => write!(f, "{}", description),
+            Response::Begin { guarantee } => write!(f, "BEGIN ({})", guarantee),
+            Response::Commit {
+                pages,
+                durable: true,
+            } => write!(f, "COMMIT ({} pages written)", pages),
+            Response::Commit {
+                pages,
+                durable: false,
+            } => write!(f, "COMMIT ({} pages written, not synced yet)", pages),
+            Response::Rollback { pages } => write!(f, "ROLLBACK ({} pages undone)", pages),
+            Response::Set(setting) => write!(f, "SET {} = {}", setting.name, setting.value),
+            Response::Show(settings) => {
+                let rows: Vec<_> = settings
+                    .iter()
+                    .map(|setting| vec![setting.name.to_string(), setting.value.clone()])
+                    .collect();
+                write_aligned(f, &["name", "value"], &rows)?;
+                write!(f, "({} settings)", settings.len())
+            }
+        }
+    }
+}
+
+impl QueryResponse {
+    /// Converts the rows to a JSON array of `tuple::Record` arrays.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.rows.iter().map(|row| tuple::to_json(&row.0)).collect()
+    }
+}
+
+impl QueryResponse {
+    /// Like `Display`, but with cells cut at `max_cell_width` characters.
+    pub fn display(&self, max_cell_width: usize) -> impl fmt::Display + '_ {
+        QueryDisplay {
+            response: self,
+            max_cell_width,
+        }
+    }
+}
+
+/// How many characters of a cell `QueryResponse` shows by default.
+pub const DEFAULT_MAX_CELL_WIDTH: usize = 40;
+
+struct QueryDisplay<'a> {
+    response: &'a QueryResponse,
+    max_cell_width: usize,
+}
+
+impl fmt::Display for QueryDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = &self.response.rows;
+        let num_columns = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+        let names = self.response.columns.as_deref().unwrap_or(&[]);
+        if num_columns > 0 {
+            let header: Vec<_> = (0..num_columns)
+                .map(|i| names.get(i).cloned().unwrap_or_else(|| i.to_string()))
+                .collect();
+            let header: Vec<_> = header.iter().map(String::as_str).collect();
+            let cells: Vec<Vec<_>> = rows
+                .iter()
+                .map(|row| {
+                    row.0
+                        .iter()
+                        .map(|elem| render_cell(elem.as_deref(), self.max_cell_width))
+                        .collect()
+                })
+                .collect();
+            write_aligned(f, &header, &cells)?;
+        }
+        write!(f, "({} rows)", rows.len())?;
+        if let Some(metrics) = &self.response.metrics {
+            write!(f, "\n{}", metrics)?;
+        }
+        Ok(())
+    }
+}
+
+/// A cell of a query result: UTF-8 as it is, with control characters
+/// escaped, binary as hex with its length, cut to `max_width` characters
+/// with an ellipsis.
+fn render_cell(elem: Option<&[u8]>, max_width: usize) -> String {
+    let max_width = max_width.max(1);
+    let bytes = match elem {
+        Some(bytes) => bytes,
+        None => return "NULL".to_string(),
+    };
+    let (text, suffix) = match std::str::from_utf8(bytes) {
+        Ok(s) => {
+            let mut text = String::new();
+            for c in s.chars() {
+                if c.is_control() {
+                    text.extend(c.escape_default());
+                } else {
+                    text.push(c);
+                }
+            }
+            (text, String::new())
+        }
+        Err(_) => (
+            format!("0x{}", tuple::to_hex(bytes)),
+            format!(" ({} bytes)", bytes.len()),
+        ),
+    };
+    let width = text.chars().count() + suffix.chars().count();
+    if width <= max_width {
+        return text + &suffix;
+    }
+    let keep = max_width.saturating_sub(suffix.chars().count() + 1);
+    let mut cell: String = text.chars().take(keep).collect();
+    cell.push('…');
+    cell + &suffix
+}
+
+impl fmt::Display for QueryResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(DEFAULT_MAX_CELL_WIDTH))
+    }
+}
+
+fn run_to_end(
+    bufmgr: &mut BufferPoolManager,
+    exec: &mut query::BoxExecutor,
+) -> Result<Vec<Record>> {
+    let mut rows = vec![];
+    loop {
+        let batch = exec.next_batch(bufmgr, QUERY_BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(rows);
+        }
+        rows.extend(batch.into_iter().map(Record));
+    }
+}
+
+/// The canonical form of `path`, or for a file yet to be created, that of
+/// its directory joined with its name. A dangling symlink has neither.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    if let Ok(resolved) = path.canonicalize() {
+        return Some(resolved);
+    }
+    if path.symlink_metadata().is_ok() {
+        return None;
+    }
+    let name = path.file_name()?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(dir.canonicalize().ok()?.join(name))
+}
+
+/// Filters hiding the rows of `tables` expired at `now`, keyed by table
+/// meta page id.
+fn expiries(tables: &[Table], now: u64) -> HashMap<u64, Expiry> {
+    tables
+        .iter()
+        .filter_map(|table| Some((table.meta_page_id.to_u64(), table.expiry(now)?)))
+        .collect()
+}
+
+impl Request {
+    /// Whether the request may write pages, as opposed to only reading.
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            Request::Query(_)
+                | Request::Explain(_)
+                | Request::Export(_)
+                | Request::ListTables
+                | Request::DescribeTable(_)
+                | Request::Begin
+                | Request::Commit
+                | Request::Rollback
+                | Request::Set(_)
+                | Request::Show(_)
+        )
+    }
+
+    /// Whether the request only reads, so that a read-only session runs
+    /// it. Transactions flush, so they are not, and neither is `Export`,
+    /// whose file could be the heap file itself.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Request::Query(_)
+                | Request::Explain(_)
+                | Request::ListTables
+                | Request::DescribeTable(_)
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Request::CreateTable(_) => "CreateTable",
+            Request::CreateIndex(_) => "CreateIndex",
+            Request::DropTable(_) => "DropTable",
+            Request::Insert(_) => "Insert",
+            Request::Query(_) => "Query",
+            Request::Explain(_) => "Explain",
+            Request::Delete(_) => "Delete",
+            Request::Update(_) => "Update",
+            Request::Export(_) => "Export",
+            Request::Import(_) => "Import",
+            Request::Analyze(_) => "Analyze",
+            Request::ListTables => "ListTables",
+            Request::DescribeTable(_) => "DescribeTable",
+            Request::Begin => "Begin",
+            Request::Commit => "Commit",
+            Request::Rollback => "Rollback",
+            Request::Set(_) => "Set",
+            Request::Show(_) => "Show",
+        }
+    }
+
+    /// Refuses an `Export` or `Import` of a file `access` does not allow.
+    /// The path is resolved through symlinks before it is compared.
+    pub fn check_file_access(&self, access: &FileAccess) -> Result<(), Error> {
+        let path = match self {
+            Request::Export(req) => &req.path,
+            Request::Import(req) => &req.path,
+            _ => return Ok(()),
+        };
+        let dir = match access {
+            FileAccess::Any => return Ok(()),
+            FileAccess::Refused => {
+                return Err(Error::FileAccessRefused {
+                    request: self.name(),
+                })
+            }
+            FileAccess::Within(dir) => dir,
+        };
+        match resolve_path(Path::new(path)) {
+            Some(resolved) if resolved.starts_with(dir) => Ok(()),
+            _ => Err(Error::PathOutsideDir {
+                path: path.clone(),
+                dir: dir.clone(),
+            }),
+        }
+    }
+
+    /// Like `execute`, but refuses a request that is not `is_read_only`
+    /// with `Error::ReadOnly`.
+    pub fn execute_read_only(self, bufmgr: &mut BufferPoolManager) -> Result<Response> {
+        if !self.is_read_only() {
+            return Err(Error::ReadOnly {
+                request: self.name(),
+            }
+            .into());
+        }
+        self.execute(bufmgr)
+    }
+
+    pub fn execute(self, bufmgr: &mut BufferPoolManager) -> Result<Response> {
+        match self {
+            Request::CreateTable(req) => {
+                let mut table = Table {
+                    schema: req.schema,
+                    constraints: req.constraints,
+                    foreign_keys: req.foreign_keys,
+                    column_names: req.column_names,
+                    expiry_column: req.expiry_column,
+                    format: req.format,
+                    ..Table::new(&req.name, req.num_key_elems, req.num_columns)
+                };
+                let meta_page_id = table.create(bufmgr)?;
+                let index_meta_page_ids = req
+                    .indices
+                    .into_iter()
+                    .map(|index| index.create(bufmgr, &mut table))
+                    .collect::<Result<_>>()?;
+                Ok(Response::CreateTable {
+                    meta_page_id,
+                    index_meta_page_ids,
+                })
+            }
+            Request::CreateIndex(req) => {
+                let mut table = req.table.open_table(bufmgr)?;
+                let meta_page_id = req.index.create(bufmgr, &mut table)?;
+                Ok(Response::CreateIndex { meta_page_id })
+            }
+            Request::DropTable(req) => {
+                let table = catalog::open_table(bufmgr, &req.name)?;
+                table.drop(bufmgr)?;
+                Ok(Response::DropTable)
+            }
+            Request::Insert(req) => {
+                let table = req.table.open_table(bufmgr)?;
+                let record = req.record.0;
+                match &table.schema {
+                    Some(schema) => {
+                        let values = record
+                            .iter()
+                            .zip(schema)
+                            .map(|(elem, &ty)| parse_value(ty, elem.as_deref()))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if values.len() != record.len() {
+                            return Err(Error::TooManyColumns.into());
+                        }
+                        table.insert_typed(bufmgr, &values)?;
+                    }
+                    None => {
+                        let record: Vec<_> = record.iter().map(Option::as_deref).collect();
+                        table.insert_nullable(bufmgr, &record)?;
+                    }
+                }
+                Ok(Response::Insert)
+            }
+            Request::Query(mut req) => {
+                let (rows, metrics) = {
+                    let plan = req.build(bufmgr)?;
+                    let mut exec = plan.start(bufmgr)?;
+                    (run_to_end(bufmgr, &mut exec)?, exec.metrics())
+                };
+                let columns = req.plan.column_names(&catalog::list_tables(bufmgr)?);
+                Ok(Response::Query(QueryResponse {
+                    rows,
+                    metrics,
+                    columns,
+                }))
+            }
+            Request::Explain(mut req) => {
+                let analyze = req.analyze;
+                let plan = req.build(bufmgr)?;
+                let description = plan.explain();
+                if !analyze {
+                    return Ok(Response::Explain(description));
+                }
+                let mut exec = plan.start(bufmgr)?;
+                let rows = run_to_end(bufmgr, &mut exec)?.len();
+                Ok(Response::ExplainAnalyze {
+                    plan: description,
+                    rows,
+                    metrics: exec.metrics().expect("an analyzed plan collects metrics"),
+                })
+            }
+            Request::Delete(mut req) => {
+                let table = req.table.open_table(bufmgr)?;
+                let now = table::unix_now();
+                let tables = catalog::list_tables(bufmgr)?;
+                req.plan.resolve(&tables)?;
+                let plan: BoxPlanNode = Box::new(query::DeleteWhere {
+                    table,
+                    inner_plan: req.plan.plan(&tables, now),
+                });
+                let row = plan.start(bufmgr)?.next(bufmgr)?.unwrap();
+                let rows = tuple::decode_u64(row[0].as_deref().unwrap().try_into().unwrap());
+                Ok(Response::Delete { rows })
+            }
+            Request::Update(req) => {
+                let table = req.table.open_table(bufmgr)?;
+                let now = table::unix_now();
+                let tables = catalog::list_tables(bufmgr)?;
+                let mut from = match req.where_cond {
+                    Some(cond) => PlanNode::Filter(FilterPlan {
+                        cond,
+                        from: req.from,
+                    }),
+                    None => *req.from,
+                };
+                from.resolve(&tables)?;
+                let plan: BoxPlanNode = Box::new(query::UpdateWhere {
+                    table,
+                    inner_plan: from.plan(&tables, now),
+                    set: req.set,
+                });
+                let row = plan.start(bufmgr)?.next(bufmgr)?.unwrap();
+                let rows = tuple::decode_u64(row[0].as_deref().unwrap().try_into().unwrap());
+                Ok(Response::Update { rows })
+            }
+            Request::Export(req) => {
+                let table = req.table.open_table(bufmgr)?;
+                let encoding = if req.hex {
+                    CsvEncoding::Hex
+                } else {
+                    CsvEncoding::Utf8
+                };
+                let out = BufWriter::new(File::create(&req.path)?);
+                let rows = table::export_csv(bufmgr, &table, out, encoding)?;
+                Ok(Response::Export { rows })
+            }
+            Request::Import(req) => {
+                let table = req.table.open_table(bufmgr)?;
+                let input = File::open(&req.path)?;
+                let metadata = input.metadata()?;
+                if !metadata.is_file() {
+                    return Err(Error::NotAFile(req.path).into());
+                }
+                if let Some(max_bytes) = req.max_bytes {
+                    if metadata.len() > max_bytes {
+                        return Err(Error::FileTooLarge {
+                            path: req.path,
+                            len: metadata.len(),
+                            max_bytes,
+                        }
+                        .into());
+                    }
+                }
+                let rows = match req.format {
+                    ImportFormat::Csv => {
+                        table::import_csv(bufmgr, &table, input, req.has_header, req.max_rows)?
+                    }
+                    ImportFormat::Jsonl if req.has_header => return Err(Error::HeaderNotCsv.into()),
+                    ImportFormat::Jsonl => {
+                        table::import_jsonl(bufmgr, &table, input, req.max_rows)?
+                    }
+                };
+                Ok(Response::Import { rows })
+            }
+            Request::Analyze(req) => {
+                let mut table = req.table.open_table(bufmgr)?;
+                Ok(Response::Analyze(table.analyze(bufmgr)?))
+            }
+            Request::ListTables => {
+                let tables = catalog::list_tables(bufmgr)?;
+                let summaries = tables
+                    .iter()
+                    .map(|table| TableSummary::new(bufmgr, table))
+                    .collect::<Result<_>>()?;
+                Ok(Response::ListTables(summaries))
+            }
+            Request::DescribeTable(req) => {
+                let table = catalog::open_table(bufmgr, &req.name)?;
+                Ok(Response::DescribeTable(TableDescription::new(
+                    bufmgr, table,
+                )?))
+            }
+            Request::Begin => {
+                bufmgr.begin()?;
+                Ok(Response::Begin {
+                    guarantee: TRANSACTION_GUARANTEE,
+                })
+            }
+            Request::Commit => {
+                let commit = bufmgr.commit()?;
+                Ok(Response::Commit {
+                    pages: commit.pages,
+                    durable: commit.durable,
+                })
+            }
+            Request::Rollback => Ok(Response::Rollback {
+                pages: bufmgr.rollback()?,
+            }),
+            Request::Set(_) | Request::Show(_) => Err(Error::NoSession {
+                request: self.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Runs `requests` one after another and returns what each of them gave.
+/// A request that fails does not stop the ones after it, and one that
+/// fails within a transaction leaves it open, as in the REPL.
+pub fn run_script(bufmgr: &mut BufferPoolManager, requests: &[Request]) -> Vec<Result<Response>> {
+    requests
+        .iter()
+        .map(|req| req.clone().execute(bufmgr))
+        .collect()
+}
+
+/// What `BTree::verify` finds wrong with the primary btree and the indices
+/// of every table, each problem prefixed with the meta page id of its
+/// btree. A heap file with no catalog has no tables to verify.
+pub fn verify_tables(bufmgr: &mut BufferPoolManager) -> Result<Vec<String>> {
+    let tables = match catalog::list_tables(bufmgr) {
+        Ok(tables) => tables,
+        Err(crate::Error::Catalog(catalog::Error::NoCatalog)) => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let mut problems = vec![];
+    for table in tables {
+        let meta_page_ids = std::iter::once(table.meta_page_id)
+            .chain(table.unique_indices.iter().map(|index| index.meta_page_id))
+            .chain(
+                table
+                    .secondary_indices
+                    .iter()
+                    .map(|index| index.meta_page_id),
+            );
+        for meta_page_id in meta_page_ids {
+            let verification = BTree::new(meta_page_id).verify(bufmgr)?;
+            problems.extend(
+                verification
+                    .problems
+                    .into_iter()
+                    .map(|problem| format!("btree {}: {}", meta_page_id.to_u64(), problem)),
+            );
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::io::Write;
+
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::{self, BufferPool};
+    use crate::disk::DiskManager;
+
+    fn execute(bufmgr: &mut BufferPoolManager, json: &str) -> Result<Response> {
+        let req: Request = serde_json::from_str(json)?;
+        req.execute(bufmgr)
+    }
+
+    fn rows(resp: Response) -> Vec<Vec<String>> {
+        match resp {
+            Response::Query(QueryResponse { rows, .. }) => rows
+                .into_iter()
+                .map(|row| {
+                    row.0
+                        .into_iter()
+                        .map(|elem| match elem {
+                            Some(elem) => String::from_utf8(elem).unwrap(),
+                            None => "NULL".to_string(),
+                        })
+                        .collect()
+                })
+                .collect(),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    }
+
+    fn create_people(bufmgr: &mut BufferPoolManager) -> u64 {
+        let resp = execute(
+            bufmgr,
+            r#"{"CreateTable": {"name": "people", "num_key_elems": 1, "num_columns": 3}}"#,
+        )
+        .unwrap();
+        let meta_page_id = match resp {
+            Response::CreateTable { meta_page_id, .. } => meta_page_id,
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        for record in &[
+            r#"["z", "Alice", "Smith"]"#,
+            r#"["x", "Bob", "Johnson"]"#,
+            r#"["y", "Charlie", "Williams"]"#,
+            r#"["w", "Dave", "Miller"]"#,
+            r#"["v", "Eve", "Brown"]"#,
+        ] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            );
+            execute(bufmgr, &json).unwrap();
+        }
+        meta_page_id.to_u64()
+    }
+
+    #[test]
+    fn test_build_plan_node() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"Filter": {{
+                "cond": {{"Lt": [{{"Column": 1}}, {{"Literal": "Dave"}}]}},
+                "from": {{"SeqScan": {{"table": {}, "key": ["w"], "while": {{"Lt": ["z"]}}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![
+                vec!["x", "Bob", "Johnson"],
+                vec!["y", "Charlie", "Williams"],
+            ],
+            rows(resp)
+        );
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"Limit": {{"limit": 2, "offset": 1, "from":
+                {{"Sort": {{"by": [[2, "Desc"]], "from":
+                    {{"Filter": {{
+                        "cond": {{"Ne": [{{"Column": 1}}, {{"Literal": "Dave"}}]}},
+                        "from": {{"SeqScan": {{"table": {}}}}}
+                    }}}}
+                }}}}
+            }}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![vec!["z", "Alice", "Smith"], vec!["x", "Bob", "Johnson"]],
+            rows(resp)
+        );
+        let err = execute(&mut bufmgr, &json.replace(r#"[[2, "Desc"]]"#, "[]")).unwrap_err();
+        assert!(err.to_string().contains("sort key is empty"), "{}", err);
+        let err = execute(&mut bufmgr, &json.replace("[[2,", "[[3,")).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Expr(crate::expr::Error::ColumnOutOfRange { column: 3, len: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_join() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"Join": {{
+                "on": {{"Lt": [{{"Column": 0}}, {{"Column": 3}}]}},
+                "left": {{"SeqScan": {{"table": {0}, "key": ["x"], "while": {{"Le": ["y"]}}}}}},
+                "right": {{"SeqScan": {{"table": {0}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![
+                vec!["x", "Bob", "Johnson", "y", "Charlie", "Williams"],
+                vec!["x", "Bob", "Johnson", "z", "Alice", "Smith"],
+                vec!["y", "Charlie", "Williams", "z", "Alice", "Smith"],
+            ],
+            rows(resp)
+        );
+    }
+
+    #[test]
+    fn test_index_join() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let people = create_people(&mut bufmgr);
+        let resp = execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "pets", "num_key_elems": 1, "num_columns": 3}}"#,
+        )
+        .unwrap();
+        let pets = match resp {
+            Response::CreateTable { meta_page_id, .. } => meta_page_id.to_u64(),
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        for record in &[
+            r#"["p1", "Rex", "y"]"#,
+            r#"["p2", "Tom", "q"]"#,
+            r#"["p3", "Kit", null]"#,
+            r#"["p4", "Max", "v"]"#,
+        ] {
+            let json = format!(r#"{{"Insert": {{"table": "pets", "record": {}}}}}"#, record);
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let query = |unmatched: &str| {
+            format!(
+                r#"{{"Query": {{"plan": {{"IndexJoin": {{
+                    "from": {{"SeqScan": {{"table": {}}}}},
+                    "table": {},
+                    "key": [2]{}
+                }}}}}}}}"#,
+                pets, people, unmatched
+            )
+        };
+        let resp = execute(&mut bufmgr, &query("")).unwrap();
+        assert_eq!(
+            vec![
+                vec!["p1", "Rex", "y", "y", "Charlie", "Williams"],
+                vec!["p4", "Max", "v", "v", "Eve", "Brown"],
+            ],
+            rows(resp)
+        );
+        let resp = execute(&mut bufmgr, &query(r#", "unmatched": {"PadNull": 3}"#)).unwrap();
+        assert_eq!(
+            vec![
+                vec!["p1", "Rex", "y", "y", "Charlie", "Williams"],
+                vec!["p2", "Tom", "q", "NULL", "NULL", "NULL"],
+                vec!["p3", "Kit", "NULL", "NULL", "NULL", "NULL"],
+                vec!["p4", "Max", "v", "v", "Eve", "Brown"],
+            ],
+            rows(resp)
+        );
+    }
+
+    #[test]
+    fn test_semi_join() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let people = create_people(&mut bufmgr);
+        let mut create = |name: &str| {
+            let json = format!(
+                r#"{{"CreateTable": {{"name": "{}", "num_key_elems": 1, "num_columns": 3}}}}"#,
+                name
+            );
+            match execute(&mut bufmgr, &json).unwrap() {
+                Response::CreateTable { meta_page_id, .. } => meta_page_id.to_u64(),
+                resp => panic!("unexpected response: {:?}", resp),
+            }
+        };
+        let pets = create("pets");
+        let empty = create("empty");
+        // two pets of "y" still give one row for "y"
+        for record in &[
+            r#"["p1", "Rex", "y"]"#,
+            r#"["p2", "Tom", "q"]"#,
+            r#"["p3", "Kit", null]"#,
+            r#"["p4", "Max", "y"]"#,
+        ] {
+            let json = format!(r#"{{"Insert": {{"table": "pets", "record": {}}}}}"#, record);
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let query = |node: &str, from: u64, table: u64, key: usize| {
+            format!(
+                r#"{{"Query": {{"plan": {{"{}": {{
+                    "from": {{"SeqScan": {{"table": {}}}}},
+                    "table": {},
+                    "key": [{}]
+                }}}}}}}}"#,
+                node, from, table, key
+            )
+        };
+        let resp = execute(&mut bufmgr, &query("Exists", pets, people, 2)).unwrap();
+        assert_eq!(
+            vec![vec!["p1", "Rex", "y"], vec!["p4", "Max", "y"]],
+            rows(resp)
+        );
+        let resp = execute(&mut bufmgr, &query("NotExists", pets, people, 2)).unwrap();
+        assert_eq!(
+            vec![vec!["p2", "Tom", "q"], vec!["p3", "Kit", "NULL"]],
+            rows(resp)
+        );
+        let resp = execute(&mut bufmgr, &query("NotExists", people, empty, 0)).unwrap();
+        assert_eq!(5, rows(resp).len());
+        let resp = execute(&mut bufmgr, &query("Exists", people, empty, 0)).unwrap();
+        assert!(rows(resp).is_empty());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let resp = execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "people", "num_key_elems": 1, "num_columns": 3,
+                "schema": ["Utf8", "Utf8", "I64"]}}"#,
+        )
+        .unwrap();
+        let table = match resp {
+            Response::CreateTable { meta_page_id, .. } => meta_page_id.to_u64(),
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        for record in &[
+            r#"["z", "Smith", "30"]"#,
+            r#"["x", "Brown", "-5"]"#,
+            r#"["y", "Smith", "12"]"#,
+            r#"["w", "Smith", null]"#,
+            r#"["v", "Brown", "7"]"#,
+        ] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let query = |group_by: &str, cond: &str| {
+            format!(
+                r#"{{"Query": {{"plan": {{"Aggregate": {{
+                    "group_by": {},
+                    "aggs": [{{"Count": 0}}, {{"Count": 2}}, {{"CountDistinct": 1}},
+                        {{"Sum": 2}}, {{"Min": 2}}, {{"Max": 0}}],
+                    "from": {{"Filter": {{"cond": {}, "from": {{"SeqScan": {{"table": {}}}}}}}}}
+                }}}}}}}}"#,
+                group_by, cond, table
+            )
+        };
+        let decode = |rows: Vec<Record>| -> Vec<Vec<String>> {
+            rows.into_iter()
+                .map(|row| {
+                    let values = row.0.iter().zip(&[
+                        ColumnType::Utf8,
+                        ColumnType::U64,
+                        ColumnType::U64,
+                        ColumnType::U64,
+                        ColumnType::I64,
+                        ColumnType::I64,
+                        ColumnType::Utf8,
+                    ]);
+                    values
+                        .map(
+                            |(elem, &ty)| match tuple::decode_value(ty, elem.as_deref()) {
+                                Ok(Value::Utf8(s)) => s,
+                                Ok(Value::U64(n)) => n.to_string(),
+                                Ok(Value::I64(n)) => n.to_string(),
+                                Ok(Value::Null) => "NULL".to_string(),
+                                value => panic!("unexpected value: {:?}", value),
+                            },
+                        )
+                        .collect()
+                })
+                .collect()
+        };
+        let always = r#"{"Not": {"IsNull": {"Column": 0}}}"#;
+        match execute(&mut bufmgr, &query("[1]", always)).unwrap() {
+            Response::Query(query) => assert_eq!(
+                vec![
+                    vec!["Brown", "2", "2", "1", "2", "-5", "x"],
+                    vec!["Smith", "3", "2", "1", "42", "12", "z"],
+                ],
+                decode(query.rows)
+            ),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+
+        let never = r#"{"IsNull": {"Column": 0}}"#;
+        match execute(&mut bufmgr, &query("[]", never)).unwrap() {
+            Response::Query(query) => {
+                assert_eq!(1, query.rows.len());
+                assert_eq!(
+                    vec![
+                        Some(tuple::encode_u64(0).to_vec()),
+                        Some(tuple::encode_u64(0).to_vec()),
+                        Some(tuple::encode_u64(0).to_vec()),
+                        None,
+                        None,
+                        None
+                    ],
+                    query.rows[0].0
+                );
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        assert!(execute(&mut bufmgr, &query("[]", always)).is_ok());
+        assert!(execute(
+            &mut bufmgr,
+            &query("[1]", always).replace(r#""Sum": 2"#, r#""Sum": 1"#)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_scan_to() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let query = |to: &str| {
+            format!(
+                r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "from": ["w"], "to": {}}}}}}}}}"#,
+                table, to
+            )
+        };
+        let resp = execute(&mut bufmgr, &query(r#"{"key": ["y"]}"#)).unwrap();
+        let ids: Vec<_> = rows(resp).into_iter().map(|row| row[0].clone()).collect();
+        assert_eq!(vec!["w", "x"], ids);
+        let resp = execute(&mut bufmgr, &query(r#"{"key": ["y"], "inclusive": true}"#)).unwrap();
+        let ids: Vec<_> = rows(resp).into_iter().map(|row| row[0].clone()).collect();
+        assert_eq!(vec!["w", "x", "y"], ids);
+    }
+
+    #[test]
+    fn test_desc_scan() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut ids = |scan: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "direction": "Desc"{}}}}}}}}}"#,
+                table, scan
+            );
+            let resp = execute(&mut bufmgr, &json).unwrap();
+            rows(resp)
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["z", "y", "x", "w", "v"], ids(""));
+        assert_eq!(vec!["x", "w", "v"], ids(r#", "from": ["x"]"#));
+        assert_eq!(vec!["x", "w", "v"], ids(r#", "from": ["xa"]"#));
+        assert_eq!(vec!["z", "y", "x", "w", "v"], ids(r#", "from": ["zz"]"#));
+        // before every key, so there is nothing below it
+        assert!(ids(r#", "from": ["a"]"#).is_empty());
+        assert_eq!(
+            vec!["y", "x"],
+            ids(r#", "from": ["y"], "while": {"Ge": ["x"]}"#)
+        );
+        assert_eq!(vec!["y"], ids(r#", "from": ["y"], "to": {"key": ["x"]}"#));
+        assert_eq!(
+            vec!["y", "x"],
+            ids(r#", "from": ["y"], "to": {"key": ["x"], "inclusive": true}"#)
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut count = |scan: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Count": {{"table": {}{}}}}}}}}}"#,
+                table, scan
+            );
+            match execute(&mut bufmgr, &json).unwrap() {
+                Response::Query(QueryResponse { rows, .. }) => {
+                    assert_eq!(1, rows.len());
+                    let count = rows[0].0[0].as_deref().unwrap();
+                    tuple::decode_u64(count.try_into().unwrap())
+                }
+                resp => panic!("unexpected response: {:?}", resp),
+            }
+        };
+        assert_eq!(5, count(""));
+        assert_eq!(3, count(r#", "from": ["x"]"#));
+        assert_eq!(2, count(r#", "from": ["w"], "while": {"Lt": ["y"]}"#));
+        assert_eq!(
+            3,
+            count(r#", "from": ["w"], "to": {"key": ["y"], "inclusive": true}"#)
+        );
+        assert_eq!(
+            1,
+            count(r#", "from": ["w"], "to": {"key": ["y"]}, "while": {"Eq": ["w"]}"#)
+        );
+        assert_eq!(0, count(r#", "from": ["zz"]"#));
+    }
+
+    #[test]
+    fn test_materialize() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        // the rows of `plan` and the number of pages fetched to get them
+        let mut run = |plan: &str| {
+            let plan: PlanNode = serde_json::from_str(plan).unwrap();
+            let plan = plan.build(&HashMap::new());
+            let before = bufmgr.stats().fetches();
+            let mut exec = plan.start(&mut bufmgr).unwrap();
+            let mut rows = vec![];
+            while let Some(row) = exec.next(&mut bufmgr).unwrap() {
+                rows.push(row);
+            }
+            (rows, bufmgr.stats().fetches() - before)
+        };
+        let scan = format!(r#"{{"SeqScan": {{"table": {}}}}}"#, table);
+        let join = |right: &str| {
+            format!(
+                r#"{{"Join": {{"on": {{"Lt": [{{"Column": 0}}, {{"Column": 3}}]}}, "left": {}, "right": {}}}}}"#,
+                scan, right
+            )
+        };
+        let (_, scan_fetches) = run(&scan);
+        let (rows, fetches) = run(&join(&scan));
+        let (materialized_rows, materialized_fetches) = run(&join(&format!(
+            r#"{{"Materialize": {{"from": {}}}}}"#,
+            scan
+        )));
+        assert_eq!(10, rows.len());
+        assert_eq!(rows, materialized_rows);
+        // the right side is scanned once, instead of once per left row
+        assert_eq!(2 * scan_fetches, materialized_fetches);
+        assert!(fetches > materialized_fetches);
+
+        let plan: PlanNode = serde_json::from_str(&format!(
+            r#"{{"Materialize": {{"from": {{"Filter": {{
+                "cond": {{"IsNull": {{"Column": 3}}}},
+                "from": {}
+            }}}}}}}}"#,
+            scan
+        ))
+        .unwrap();
+        let plan = plan.build(&HashMap::new());
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let err = exec.next(&mut bufmgr).unwrap_err();
+        assert!(matches!(err, crate::Error::Expr(_)));
+        exec.rewind(&mut bufmgr).unwrap();
+        let err = exec.next(&mut bufmgr).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Query(query::Error::MaterializeFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_planner() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![table::UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![2],
+            }],
+            ..Table::new("items", 1, 3)
+        };
+        let table = table.create(&mut bufmgr).unwrap().to_u64();
+        for record in &[
+            r#"["1", "apple", "120"]"#,
+            r#"["2", "banana", "80"]"#,
+            r#"["3", "cherry", "300"]"#,
+            r#"["4", "date", "250"]"#,
+        ] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "items", "record": {}}}}}"#,
+                record
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let mut run = |plan: &str| {
+            let plan = plan.replace(
+                "TABLE",
+                &format!(r#"{{"SeqScan": {{"table": {}}}}}"#, table),
+            );
+            let explain = format!(r#"{{"Explain": {{"plan": {}}}}}"#, plan);
+            let description = match execute(&mut bufmgr, &explain).unwrap() {
+                Response::Explain(description) => description.to_string(),
+                resp => panic!("unexpected response: {:?}", resp),
+            };
+            let query = format!(r#"{{"Query": {{"plan": {}}}}}"#, plan);
+            let optimized = rows(execute(&mut bufmgr, &query).unwrap());
+            let query = format!(r#"{{"Query": {{"plan": {}, "optimize": false}}}}"#, plan);
+            let as_written = rows(execute(&mut bufmgr, &query).unwrap());
+            assert_eq!(as_written, optimized, "{}", plan);
+            let nodes: Vec<_> = description
+                .lines()
+                .map(|line| line.split_whitespace().next().unwrap().to_string())
+                .collect();
+            (description, nodes, optimized)
+        };
+
+        // a pkey equality becomes a keyed scan
+        let (description, nodes, rows) = run(
+            r#"{"Filter": {"cond": {"Eq": [{"Column": 0}, {"Literal": "2"}]}, "from": TABLE}}"#,
+        );
+        assert_eq!(vec!["SeqScan"], nodes);
+        assert!(description.contains("search_mode=Key"), "{}", description);
+        assert!(description.contains("while_bound=true"), "{}", description);
+        assert_eq!(vec![vec!["2", "banana", "80"]], rows);
+
+        // an equality on the unique index reads the table through it
+        let (description, nodes, rows) = run(
+            r#"{"Filter": {"cond": {"Eq": [{"Column": 1}, {"Literal": "cherry"}]}, "from": TABLE}}"#,
+        );
+        assert_eq!(vec!["Filter", "IndexScan"], nodes);
+        assert!(description.contains("end=<="), "{}", description);
+        assert_eq!(vec![vec!["3", "cherry", "300"]], rows);
+
+        // a range on it, asking only for columns the index holds
+        let (_, nodes, rows) = run(
+            r#"{"Project": {"columns": [2, 1], "from": {"Filter": {"cond": {"And": [
+                {"Ge": [{"Column": 1}, {"Literal": "b"}]},
+                {"Lt": [{"Column": 1}, {"Literal": "d"}]}
+            ]}, "from": TABLE}}}}"#,
+        );
+        assert_eq!(vec!["Project", "Filter", "IndexOnlyScan"], nodes);
+        assert_eq!(vec![vec!["80", "banana"], vec!["300", "cherry"]], rows);
+
+        // nothing to use for the price
+        let (description, nodes, rows) = run(
+            r#"{"Filter": {"cond": {"Gt": [{"Column": 2}, {"Literal": "2"}]}, "from": TABLE}}"#,
+        );
+        assert_eq!(vec!["SeqScan"], nodes);
+        assert!(description.contains("search_mode=Start"), "{}", description);
+        assert_eq!(
+            vec![
+                vec!["2", "banana", "80"],
+                vec!["3", "cherry", "300"],
+                vec!["4", "date", "250"]
+            ],
+            rows
+        );
+
+        // opting out runs the plan as written
+        let explain = format!(
+            r#"{{"Explain": {{"optimize": false, "plan": {{"Filter": {{
+                "cond": {{"Eq": [{{"Column": 1}}, {{"Literal": "cherry"}}]}},
+                "from": {{"SeqScan": {{"table": {}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        match execute(&mut bufmgr, &explain).unwrap() {
+            Response::Explain(description) => assert_eq!("SeqScan", description.name),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut map = |exprs: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Map": {{
+                    "exprs": {},
+                    "from": {{"SeqScan": {{"table": {}, "from": ["x"], "to": {{"key": ["y"]}}}}}}
+                }}}}}}}}"#,
+                exprs, table
+            );
+            execute(&mut bufmgr, &json).map(|resp| match resp {
+                Response::Query(QueryResponse { rows, .. }) => rows
+                    .into_iter()
+                    .map(|row| {
+                        row.0
+                            .into_iter()
+                            .map(|elem| elem.map(|bytes| String::from_utf8(bytes).unwrap()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>(),
+                resp => panic!("unexpected response: {:?}", resp),
+            })
+        };
+        let full_name = r#"{"Concat": [{"Column": 1}, {"Literal": " "}, {"Column": 2}]}"#;
+        let rows = map(&format!(
+            r#"[{}, {{"Len": {}}}, {{"Slice": [{{"Column": 2}}, 1, 3]}}, {{"Slice": [{{"Column": 1}}, 1, null]}}]"#,
+            full_name, full_name
+        ))
+        .unwrap();
+        let expected = [["Bob Johnson", "11", "oh", "ob"]];
+        assert_eq!(
+            expected
+                .iter()
+                .map(|row| row.iter().map(|s| Some(s.to_string())).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            rows
+        );
+        let rows = map(r#"[{"Concat": [{"Column": 0}, "Null"]}, {"Len": "Null"}]"#).unwrap();
+        assert_eq!(vec![vec![None, None]], rows);
+
+        let err = map(r#"[{"Slice": [{"Column": 1}, 2, 4]}]"#).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Expr(crate::expr::Error::SliceOutOfRange {
+                start: 2,
+                end: 4,
+                len: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_top_k() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        for record in &[r#"["u", "Bob", "Adams"]"#, r#"["t", "Alice", "Jones"]"#] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let mut top_k = |sort_key: &str, k: usize| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"TopK": {{
+                    "sort_key": {}, "k": {},
+                    "from": {{"SeqScan": {{"table": {}}}}}
+                }}}}}}}}"#,
+                sort_key, k, table
+            );
+            match execute(&mut bufmgr, &json).unwrap() {
+                Response::Query(QueryResponse { rows, .. }) => rows
+                    .into_iter()
+                    .map(|row| String::from_utf8(row.0[0].clone().unwrap()).unwrap())
+                    .collect::<Vec<_>>(),
+                resp => panic!("unexpected response: {:?}", resp),
+            }
+        };
+        // "t" and "z" tie on "Alice", as do "u" and "x" on "Bob"; ties come
+        // in scan order, so "t" before "z" and "u" before "x"
+        assert_eq!(vec!["t", "z", "u"], top_k(r#"[[1, "Asc"]]"#, 3));
+        assert_eq!(vec!["v", "w"], top_k(r#"[[1, "Desc"]]"#, 2));
+        assert_eq!(
+            vec!["z", "t", "x", "u", "y", "w", "v"],
+            top_k(r#"[[1, "Asc"], [2, "Desc"]]"#, 10)
+        );
+        assert_eq!(Vec::<String>::new(), top_k(r#"[[1, "Asc"]]"#, 0));
+    }
+
+    #[test]
+    fn test_query_metrics() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut metrics = |analyze: bool| {
+            let json = format!(
+                r#"{{"Query": {{"analyze": {}, "optimize": false, "plan": {{"Filter": {{
+                    "cond": {{"Ne": [{{"Column": 1}}, {{"Literal": "Bob"}}]}},
+                    "from": {{"Materialize": {{"from": {{"SeqScan": {{"table": {}}}}}}}}}
+                }}}}}}}}"#,
+                analyze, table
+            );
+            match execute(&mut bufmgr, &json).unwrap() {
+                Response::Query(QueryResponse { rows, metrics, .. }) => {
+                    assert_eq!(4, rows.len());
+                    metrics
+                }
+                resp => panic!("unexpected response: {:?}", resp),
+            }
+        };
+        assert_eq!(None, metrics(false));
+        let filter = metrics(true).unwrap();
+        assert_eq!(
+            ("Filter", 4, 5, 0),
+            (
+                &filter.name[..],
+                filter.rows,
+                filter.examined,
+                filter.fetches
+            )
+        );
+        let materialize = &filter.children[0];
+        assert_eq!(
+            ("Materialize", 5, 5, 0),
+            (
+                &materialize.name[..],
+                materialize.rows,
+                materialize.examined,
+                materialize.fetches
+            )
+        );
+        let scan = &materialize.children[0];
+        assert_eq!(
+            ("SeqScan", 5, 5),
+            (&scan.name[..], scan.rows, scan.examined)
+        );
+        assert!(scan.fetches > 0);
+        assert!(scan.children.is_empty());
+    }
+
+    #[test]
+    fn test_row_number() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        for record in &[r#"["u", "Bob", "Adams"]"#, r#"["t", "Alice", "Jones"]"#] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+
+        let mut row_numbers = |partition_by: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Project": {{"columns": [1, 3], "from": {{"RowNumber": {{
+                    "from": {{"TopK": {{
+                        "sort_key": [[1, "Asc"], [0, "Asc"]], "k": 10,
+                        "from": {{"SeqScan": {{"table": {}}}}}
+                    }}}}{}
+                }}}}}}}}}}}}"#,
+                table, partition_by
+            );
+            let resp = execute(&mut bufmgr, &json).unwrap();
+            rows(resp)
+        };
+        assert_eq!(
+            vec![
+                vec!["Alice", "1"],
+                vec!["Alice", "2"],
+                vec!["Bob", "1"],
+                vec!["Bob", "2"],
+                vec!["Charlie", "1"],
+                vec!["Dave", "1"],
+                vec!["Eve", "1"],
+            ],
+            row_numbers(r#", "partition_by": [1]"#)
+        );
+        let numbers: Vec<_> = row_numbers("")
+            .into_iter()
+            .map(|row| row[1].clone())
+            .collect();
+        assert_eq!(vec!["1", "2", "3", "4", "5", "6", "7"], numbers);
+    }
+
+    #[test]
+    fn test_delete() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut delete = |plan: &str| {
+            let json = format!(r#"{{"Delete": {{"table": "people", "plan": {}}}}}"#, plan);
+            execute(&mut bufmgr, &json).map(|resp| match resp {
+                Response::Delete { rows } => rows,
+                resp => panic!("unexpected response: {:?}", resp),
+            })
+        };
+        let filter = |cond: &str| {
+            format!(
+                r#"{{"Filter": {{"cond": {}, "from": {{"SeqScan": {{"table": {}}}}}}}}}"#,
+                cond, table
+            )
+        };
+        let err = delete(&format!(
+            r#"{{"Project": {{"columns": [0], "from": {}}}}}"#,
+            filter(r#"{"Eq": [{"Column": 1}, {"Literal": "Bob"}]}"#)
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(table::Error::ColumnCountMismatch {
+                len: 1,
+                num_columns: 3
+            })
+        ));
+        let cond = r#"{"Lt": [{"Column": 1}, {"Literal": "D"}]}"#;
+        assert_eq!(3, delete(&filter(cond)).unwrap());
+        assert_eq!(0, delete(&filter(cond)).unwrap());
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}}}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![vec!["v", "Eve", "Brown"], vec!["w", "Dave", "Miller"]],
+            rows(resp)
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let mut update = |set: &str, cond: &str| {
+            let json = format!(
+                r#"{{"Update": {{
+                    "table": "people",
+                    "set": {},
+                    "where": {},
+                    "from": {{"SeqScan": {{"table": {}}}}}
+                }}}}"#,
+                set, cond, table
+            );
+            execute(&mut bufmgr, &json).map(|resp| match resp {
+                Response::Update { rows } => rows,
+                resp => panic!("unexpected response: {:?}", resp),
+            })
+        };
+        // both expressions see the old row, so the columns are swapped
+        let swap = r#"[[1, {"Column": 2}], [2, {"Column": 1}]]"#;
+        let cond = r#"{"Ge": [{"Column": 1}, {"Literal": "D"}]}"#;
+        assert_eq!(2, update(swap, cond).unwrap());
+        assert_eq!(0, update(swap, r#"{"IsNull": {"Column": 0}}"#).unwrap());
+        let err = update(r#"[[0, {"Literal": "a"}]]"#, cond).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Query(query::Error::SetKeyColumn(0))
+        ));
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}}}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![
+                vec!["v", "Brown", "Eve"],
+                vec!["w", "Miller", "Dave"],
+                vec!["x", "Bob", "Johnson"],
+                vec!["y", "Charlie", "Williams"],
+                vec!["z", "Alice", "Smith"],
+            ],
+            rows(resp)
+        );
+    }
+
+    #[test]
+    fn test_create_index() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let resp = execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {
+                "name": "people", "num_key_elems": 1, "num_columns": 3,
+                "indices": [{"skey": [1], "unique": true}]
+            }}"#,
+        )
+        .unwrap();
+        let (table, by_first) = match resp {
+            Response::CreateTable {
+                meta_page_id,
+                index_meta_page_ids,
+            } => {
+                assert_eq!(1, index_meta_page_ids.len());
+                (meta_page_id.to_u64(), index_meta_page_ids[0].to_u64())
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        for record in &[
+            r#"["z", "Alice", "Smith"]"#,
+            r#"["x", "Bob", "Johnson"]"#,
+            r#"["y", "Charlie", "Smith"]"#,
+        ] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "people", "record": {}}}}}"#,
+                record
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+        let resp = execute(
+            &mut bufmgr,
+            r#"{"CreateIndex": {"table": "people", "skey": [2], "directions": ["Desc"]}}"#,
+        )
+        .unwrap();
+        let by_last = match resp {
+            Response::CreateIndex { meta_page_id } => meta_page_id.to_u64(),
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        // a unique index over duplicate last names fails
+        let err = execute(
+            &mut bufmgr,
+            r#"{"CreateIndex": {"table": "people", "skey": [2], "unique": true}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(table::Error::DuplicateIndexKey(1))
+        ));
+
+        let mut scan = |index: u64, directions: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"IndexScan": {{
+                    "table": {}, "index": {}, "directions": {}
+                }}}}}}}}"#,
+                table, index, directions
+            );
+            let resp = execute(&mut bufmgr, &json).unwrap();
+            rows(resp)
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["z", "x", "y"], scan(by_first, "[]"));
+        assert_eq!(vec!["y", "z", "x"], scan(by_last, r#"["Desc"]"#));
+    }
+
+    #[test]
+    fn test_column_out_of_range() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"Filter": {{
+                "cond": {{"IsNull": {{"Column": 3}}}},
+                "from": {{"SeqScan": {{"table": {}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        let err = execute(&mut bufmgr, &json).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Expr(crate::expr::Error::ColumnOutOfRange { column: 3, len: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let scan = format!(
+            r#"{{"SeqScan": {{"table": {}, "key": ["w"], "while": {{"Lt": ["z"]}}}}}}"#,
+            table
+        );
+        for plan in &[
+            scan.clone(),
+            format!(
+                r#"{{"Filter": {{"cond": {{"Ne": [{{"Column": 0}}, {{"Literal": "x"}}]}}, "from": {}}}}}"#,
+                scan
+            ),
+            format!(
+                r#"{{"Join": {{"on": {{"Lt": [{{"Column": 0}}, {{"Column": 3}}]}}, "left": {0}, "right": {0}}}}}"#,
+                scan
+            ),
+            format!(
+                r#"{{"Aggregate": {{"aggs": [{{"Count": 0}}, {{"Max": 1}}], "from": {}}}}}"#,
+                scan
+            ),
+        ] {
+            let plan: PlanNode = serde_json::from_str(plan).unwrap();
+            let plan = plan.build(&HashMap::new());
+            let mut exec = plan.start(&mut bufmgr).unwrap();
+            let drain = |exec: &mut query::BoxExecutor, bufmgr: &mut BufferPoolManager| {
+                let mut rows = vec![];
+                while let Some(row) = exec.next(bufmgr).unwrap() {
+                    rows.push(row);
+                }
+                rows
+            };
+            let first = drain(&mut exec, &mut bufmgr);
+            assert!(!first.is_empty());
+            exec.rewind(&mut bufmgr).unwrap();
+            assert_eq!(first, drain(&mut exec, &mut bufmgr));
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let json = format!(
+            r#"{{"Explain": {{"plan": {{"Join": {{
+                "on": {{"Lt": [{{"Column": 0}}, {{"Column": 3}}]}},
+                "left": {{"Filter": {{
+                    "cond": {{"Lt": [{{"Column": 1}}, {{"Literal": "Dave"}}]}},
+                    "from": {{"SeqScan": {{"table": {0}, "key": ["w"]}}}}
+                }}}},
+                "right": {{"SeqScan": {{"table": {0}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        let description = match execute(&mut bufmgr, &json).unwrap() {
+            Response::Explain(description) => description,
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        assert_eq!("NestedLoopJoin", description.name);
+        assert_eq!(2, description.children.len());
+        let scan = &description.children[0];
+        assert_eq!("SeqScan", scan.name);
+        assert!(scan
+            .properties
+            .contains(&("filter".to_string(), "true".to_string())));
+        assert!(scan.properties.iter().any(|(name, _)| name == "cond"));
+        let lines: Vec<_> = description.to_string().lines().map(String::from).collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("NestedLoopJoin strategy=nested loop on="));
+        assert!(lines[1].starts_with("  SeqScan table="));
+        assert!(lines[2].starts_with(&format!(
+            "  SeqScan table={} search_mode=Start direction=Forward while_bound=false filter=false",
+            table
+        )));
+    }
+
+    #[test]
+    fn test_query_display() {
+        let resp = QueryResponse {
+            rows: vec![
+                Record(vec![Some(b"caf\xc3\xa9".to_vec()), Some(vec![0xff, 0x00])]),
+                Record(vec![Some(b"a\tb".to_vec()), Some(vec![0xfe; 16])]),
+            ],
+            metrics: None,
+            columns: None,
+        };
+        assert_eq!(
+            "0    | 1
+-----+-------------------------
+café | 0xff00 (2 bytes)
+a\\tb | 0xfefefefefe… (16 bytes)
+(2 rows)",
+            resp.display(24).to_string()
+        );
+        let resp = QueryResponse {
+            rows: vec![Record(vec![Some(b"abcdefghij".to_vec())])],
+            metrics: None,
+            columns: Some(vec!["name".to_string()]),
+        };
+        assert_eq!(
+            "name
+------
+abcde…
+(1 rows)",
+            resp.display(6).to_string()
+        );
+        let resp = QueryResponse {
+            rows: vec![],
+            metrics: None,
+            columns: None,
+        };
+        assert_eq!("(0 rows)", resp.to_string());
+    }
+
+    #[test]
+    fn test_explain_analyze() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+
+        let json = r#"{"Explain": {"analyze": true, "plan": {"Filter": {
+            "cond": {"Ge": [{"Column": 0}, {"Literal": "y"}]},
+            "from": {"SeqScan": {"table": "people"}}
+        }}}}"#;
+        let resp = execute(&mut bufmgr, json).unwrap();
+        let value = serde_json::to_value(&resp).unwrap();
+        assert_eq!("SeqScan", value["ExplainAnalyze"]["plan"]["name"]);
+        assert_eq!(2, value["ExplainAnalyze"]["rows"]);
+        let metrics = match resp {
+            Response::ExplainAnalyze { metrics, .. } => metrics,
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        assert_eq!(2, metrics.rows);
+
+        // without analyze, nothing runs
+        let json = json.replace(r#""analyze": true"#, r#""analyze": false"#);
+        assert!(matches!(
+            execute(&mut bufmgr, &json).unwrap(),
+            Response::Explain(_)
+        ));
+    }
+
+    #[test]
+    fn test_encoded_key() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+
+        let key = tuple::KeyBuilder::new().push_str("x").finish();
+        let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "key": "{}"}}}}}}}}"#,
+            table, hex
+        );
+        let resp = execute(&mut bufmgr, &json).unwrap();
+        assert_eq!(
+            vec![
+                vec!["x", "Bob", "Johnson"],
+                vec!["y", "Charlie", "Williams"],
+                vec!["z", "Alice", "Smith"],
+            ],
+            rows(resp)
+        );
+
+        let json = format!(
+            r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "key": "zz"}}}}}}}}"#,
+            table
+        );
+        assert!(execute(&mut bufmgr, &json).is_err());
+    }
+
+    #[test]
+    fn test_analyze() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+        let resp = execute(&mut bufmgr, r#"{"Analyze": {"table": "people"}}"#).unwrap();
+        assert_eq!(
+            "ANALYZE rows=5 avg_key_size=9.0 avg_value_size=18.0 distinct={}",
+            resp.to_string()
+        );
+    }
+
+    #[test]
+    fn test_drop_table() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+        execute(&mut bufmgr, r#"{"DropTable": {"name": "people"}}"#).unwrap();
+        assert!(execute(&mut bufmgr, r#"{"DropTable": {"name": "people"}}"#).is_err());
+        assert!(execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["a", "b", "c"]}}"#
+        )
+        .is_err());
+        create_people(&mut bufmgr);
+    }
+
+    #[test]
+    fn test_null() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["u", null, "Jones"]}}"#,
+        )
+        .unwrap();
+
+        let query = |cond: &str| {
+            format!(
+                r#"{{"Query": {{"plan": {{"Filter": {{
+                    "cond": {},
+                    "from": {{"SeqScan": {{"table": {}}}}}
+                }}}}}}}}"#,
+                cond, table
+            )
+        };
+        let resp = execute(
+            &mut bufmgr,
+            &query(r#"{"Not": {"Eq": [{"Column": 1}, {"Literal": "Alice"}]}}"#),
+        )
+        .unwrap();
+        assert_eq!(4, rows(resp).len());
+        let resp = execute(
+            &mut bufmgr,
+            &query(r#"{"Or": [{"Eq": [{"Column": 1}, "Null"]}, {"IsNull": {"Column": 1}}]}"#),
+        )
+        .unwrap();
+        assert_eq!(vec![vec!["u", "NULL", "Jones"]], rows(resp));
+    }
+
+    #[test]
+    fn test_binary_key() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": [{"hex": "00ff"}, null, "Doe"]}}"#,
+        )
+        .unwrap();
+        let resp = execute(
+            &mut bufmgr,
+            &format!(
+                r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "while": {{"Lt": ["a"]}}}}}}}}}}"#,
+                table
+            ),
+        )
+        .unwrap();
+        match resp {
+            Response::Query(query) => {
+                assert_eq!(
+                    r#"[[{"hex":"00ff"},null,"Doe"]]"#,
+                    query.to_json().to_string()
+                );
+                let json = serde_json::to_string(&query).unwrap();
+                assert_eq!(r#"{"rows":[[{"hex":"00ff"},null,"Doe"]]}"#, json);
+                let parsed: QueryResponse = serde_json::from_str(&json).unwrap();
+                assert_eq!(query.rows, parsed.rows);
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        assert!(execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": [1, "a", "b"]}}"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_binary_literals() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": [{"b64": "7CzdDg=="}, "Ann", {"hex": "ff"}]}}"#,
+        )
+        .unwrap();
+        let scan = |key: &str| {
+            format!(
+                r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "key": [{}], "while": {{"Eq": [{}]}}}}}}}}}}"#,
+                table, key, key
+            )
+        };
+        let json = |resp| match resp {
+            Response::Query(query) => query.to_json().to_string(),
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        let expected = r#"[[{"hex":"ec2cdd0e"},"Ann",{"hex":"ff"}]]"#;
+        let resp = execute(&mut bufmgr, &scan(r#"{"hex": "ec2cdd0e"}"#)).unwrap();
+        assert_eq!(expected, json(resp));
+        let filter = format!(
+            r#"{{"Query": {{"plan": {{"Filter": {{
+                "cond": {{"Eq": [{{"Column": 2}}, {{"Literal": {{"b64": "/w=="}}}}]}},
+                "from": {{"SeqScan": {{"table": {}}}}}
+            }}}}}}}}"#,
+            table
+        );
+        assert_eq!(expected, json(execute(&mut bufmgr, &filter).unwrap()));
+
+        let err = execute(
+            &mut bufmgr,
+            &filter.replace(r#"{"b64": "/w=="}"#, r#"{"hex": "f"}"#),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(r#"invalid JSON tuple element: {"hex":"f"}"#),
+            "{}",
+            err
+        );
+        assert!(execute(&mut bufmgr, &scan(r#"{"hex": "zz"}"#)).is_err());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let path = path.to_str().unwrap();
+        let resp = execute(
+            &mut bufmgr,
+            &format!(r#"{{"Export": {{"table": "people", "path": {:?}}}}}"#, path),
+        )
+        .unwrap();
+        assert!(matches!(resp, Response::Export { rows: 5 }));
+
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "copy", "num_key_elems": 1, "num_columns": 3}}"#,
+        )
+        .unwrap();
+        let import = format!(r#"{{"Import": {{"table": "copy", "path": {:?}}}}}"#, path);
+        let resp = execute(&mut bufmgr, &import).unwrap();
+        assert!(matches!(resp, Response::Import { rows: 5 }));
+        assert!(execute(&mut bufmgr, &import).is_err());
+
+        let scan = |table: u64| {
+            format!(
+                r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}}}}}}}}}"#,
+                table
+            )
+        };
+        let copy = catalog::open_table(&mut bufmgr, "copy").unwrap();
+        assert_eq!(
+            rows(execute(&mut bufmgr, &scan(table)).unwrap()),
+            rows(execute(&mut bufmgr, &scan(copy.meta_page_id.to_u64())).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_import_jsonl() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}"#,
+        )
+        .unwrap();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "[\"a\", \"x\"]\n[\"b\", {{\"hex\": \"ff\"}}]\n[\"c\"").unwrap();
+        let path = file.path().to_str().unwrap();
+        let import = |options: &str| {
+            format!(
+                r#"{{"Import": {{"table": "t", "path": {:?}, "format": "jsonl"{}}}}}"#,
+                path, options
+            )
+        };
+
+        let err = execute(&mut bufmgr, &import(r#", "max_bytes": 16"#)).unwrap_err();
+        assert!(
+            err.to_string().contains("more than max_bytes 16"),
+            "{}",
+            err
+        );
+        let err = execute(&mut bufmgr, &import("")).unwrap_err();
+        assert_eq!("import failed at line 3 after 2 rows", err.to_string());
+        let err = execute(&mut bufmgr, &import(r#", "has_header": true"#)).unwrap_err();
+        assert_eq!("has_header is only for csv", err.to_string());
+
+        let dir = tempfile::tempdir().unwrap();
+        let import = format!(
+            r#"{{"Import": {{"table": "t", "path": {:?}, "format": "jsonl"}}}}"#,
+            dir.path().to_str().unwrap()
+        );
+        let err = execute(&mut bufmgr, &import).unwrap_err();
+        assert!(err.to_string().contains("is not a regular file"), "{}", err);
+    }
+
+    #[test]
+    fn test_constraints() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "people", "num_key_elems": 1, "num_columns": 3,
+                "constraints": [{"name": "non_empty_name", "expr": {"Ne": [{"Column": 1}, {"Literal": ""}]}}]}}"#,
+        )
+        .unwrap();
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["z", "Alice", "Smith"]}}"#,
+        )
+        .unwrap();
+        let err = execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["y", "", "Jones"]}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("non_empty_name"));
+    }
+
+    #[test]
+    fn test_list_and_describe_tables() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let people = create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {
+                "name": "pets", "num_key_elems": 2, "num_columns": 3,
+                "schema": ["Utf8", "I64", "Utf8"]
+            }}"#,
+        )
+        .unwrap();
+        execute(
+            &mut bufmgr,
+            r#"{"CreateIndex": {"table": "people", "skey": [2], "directions": ["Desc"]}}"#,
+        )
+        .unwrap();
+        let resp = execute(&mut bufmgr, r#""ListTables""#).unwrap();
+        let tables = match &resp {
+            Response::ListTables(tables) => tables,
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        let mut names: Vec<_> = tables
+            .iter()
+            .map(|table| (table.name.as_str(), table.rows, table.indices.len()))
+            .collect();
+        names.sort();
+        assert_eq!(vec![("people", 5, 1), ("pets", 0, 0)], names);
+        let output = resp.to_string();
+        assert!(output.contains("index(2 desc)"), "{}", output);
+        assert!(output.ends_with("(2 tables)"), "{}", output);
+
+        let resp = execute(&mut bufmgr, r#"{"DescribeTable": {"name": "pets"}}"#).unwrap();
+        let description = match &resp {
+            Response::DescribeTable(description) => description,
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+        let columns: Vec<_> = description
+            .columns
+            .iter()
+            .map(|column| (column.ty, column.key))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(ColumnType::Utf8), true),
+                (Some(ColumnType::I64), true),
+                (Some(ColumnType::Utf8), false),
+            ],
+            columns
+        );
+        let output = resp.to_string();
+        assert!(output.contains("1      |      | I64  | key"), "{}", output);
+
+        let resp = execute(&mut bufmgr, r#"{"DescribeTable": {"name": "people"}}"#).unwrap();
+        match resp {
+            Response::DescribeTable(description) => {
+                assert_eq!(people, description.summary.meta_page_id);
+                assert!(description.columns.iter().all(|column| column.ty.is_none()));
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        assert!(execute(&mut bufmgr, r#"{"DescribeTable": {"name": "nope"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_numeric_comparisons() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        for (key, age) in &[
+            ("z", "9"),
+            ("x", "10"),
+            ("y", "-3"),
+            ("w", "2.5"),
+            ("v", "-10"),
+        ] {
+            let json = format!(
+                r#"{{"Update": {{"table": "people", "set": [[2, {{"Literal": "{}"}}]],
+                    "where": {{"Eq": [{{"Column": 0}}, {{"Literal": "{}"}}]}},
+                    "from": {{"SeqScan": {{"table": {}}}}}}}}}"#,
+                age, key, table
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+        let mut filter = |cond: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Project": {{"columns": [2],
+                    "from": {{"Filter": {{"cond": {}, "from": {{"SeqScan": {{"table": {}}}}}}}}}
+                }}}}}}}}"#,
+                cond, table
+            );
+            execute(&mut bufmgr, &json).map(rows)
+        };
+        // as bytes, "10" < "9" and "-10" < "-3"
+        assert_eq!(
+            vec![vec!["-10"], vec!["2.5"], vec!["10"], vec!["-3"]],
+            filter(r#"{"Lt": [{"Column": 2}, {"Literal": "9"}]}"#).unwrap()
+        );
+        assert_eq!(
+            vec![vec!["-10"], vec!["2.5"], vec!["-3"]],
+            filter(r#"{"NumLt": [{"Column": 2}, {"Literal": "9"}]}"#).unwrap()
+        );
+        assert_eq!(
+            vec![vec!["10"], vec!["9"]],
+            filter(r#"{"NumGe": [{"Column": 2}, {"Literal": "09"}]}"#).unwrap()
+        );
+        assert_eq!(
+            vec![vec!["-10"]],
+            filter(r#"{"NumLt": [{"Column": 2}, {"Literal": "-3.5"}]}"#).unwrap()
+        );
+        assert_eq!(
+            vec![vec!["2.5"]],
+            filter(r#"{"NumEq": [{"Column": 2}, {"Literal": "2.50"}]}"#).unwrap()
+        );
+        let err = filter(r#"{"NumLt": [{"Column": 1}, {"Literal": "9"}]}"#).unwrap_err();
+        assert!(err.to_string().contains("is not a number"), "{}", err);
+
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "scores", "num_key_elems": 1, "num_columns": 2,
+                "schema": ["I64", "Utf8"]}}"#,
+        )
+        .unwrap();
+        let scores = catalog::open_table(&mut bufmgr, "scores")
+            .unwrap()
+            .meta_page_id
+            .to_u64();
+        for n in &["9", "10", "-3", "-10", "100"] {
+            let json = format!(
+                r#"{{"Insert": {{"table": "scores", "record": ["{}", "n{}"]}}}}"#,
+                n, n
+            );
+            execute(&mut bufmgr, &json).unwrap();
+        }
+        let mut scan = |key: &str, while_expr: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Project": {{"columns": [1],
+                    "from": {{"SeqScan": {{"table": {}, "key": {}, "while": {}}}}}
+                }}}}}}}}"#,
+                scores, key, while_expr
+            );
+            rows(execute(&mut bufmgr, &json).unwrap())
+        };
+        assert_eq!(
+            vec![vec!["n-10"], vec!["n-3"], vec!["n9"]],
+            scan("null", r#"{"NumLt": [10]}"#)
+        );
+        assert_eq!(
+            vec![vec!["n-3"], vec!["n9"], vec!["n10"]],
+            scan("[-5]", r#"{"NumLe": [10]}"#)
+        );
+        assert_eq!(vec![vec!["n-3"]], scan("[-3]", r#"{"NumEq": [-3]}"#));
+    }
+
+    #[test]
+    fn test_response_json() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        let to_json = |resp: Response| serde_json::to_string(&resp).unwrap();
+
+        let resp = execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": [{"hex": "00ff"}, null, "Doe"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(r#""Insert""#, to_json(resp));
+        let query = format!(
+            r#"{{"Query": {{"plan": {{"SeqScan": {{"table": {}, "while": {{"Lt": ["a"]}}}}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &query).unwrap();
+        assert_eq!(
+            r#"{"Query":{"rows":[[{"hex":"00ff"},null,"Doe"]]}}"#,
+            to_json(resp)
+        );
+        let resp = execute(&mut bufmgr, &query.replace("Query", "Explain")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&to_json(resp)).unwrap();
+        assert_eq!("SeqScan", json["Explain"]["name"]);
+        let delete = format!(
+            r#"{{"Delete": {{"table": "people", "plan": {{"SeqScan": {{"table": {}, "while": {{"Lt": ["a"]}}}}}}}}}}"#,
+            table
+        );
+        let resp = execute(&mut bufmgr, &delete).unwrap();
+        assert_eq!(r#"{"Delete":{"rows":1}}"#, to_json(resp));
+    }
+
+    #[test]
+    fn test_names() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"CreateIndex": {"table": "people", "name": "people_by_last", "skey": [2],
+                "directions": ["Desc"], "include": [1]}}"#,
+        )
+        .unwrap();
+        let err = execute(
+            &mut bufmgr,
+            r#"{"CreateIndex": {"table": "people", "name": "people_by_last", "skey": [1]}}"#,
+        )
+        .unwrap_err();
+        assert_eq!(r#"index "people_by_last" already exists"#, err.to_string());
+
+        let mut query = |plan: &str| {
+            let json = format!(r#"{{"Query": {{"plan": {}}}}}"#, plan);
+            execute(&mut bufmgr, &json).map(rows)
+        };
+        let by_id = query(&format!(r#"{{"SeqScan": {{"table": {}}}}}"#, table)).unwrap();
+        let by_name = query(r#"{"SeqScan": {"table": "people"}}"#).unwrap();
+        assert_eq!(5, by_name.len());
+        assert_eq!(by_id, by_name);
+        // the directions and included columns come from the catalog
+        assert_eq!(
+            vec![vec!["z", "Alice", "Smith"]],
+            query(
+                r#"{"IndexScan": {"table": "people", "index": "people_by_last",
+                    "key": ["Smith"], "while": {"Eq": ["Smith"]}}}"#
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            vec!["Williams", "Smith", "Miller", "Johnson", "Brown"],
+            query(r#"{"IndexOnlyScan": {"index": "people_by_last"}}"#)
+                .unwrap()
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        );
+        let err = query(r#"{"SeqScan": {"table": "nobody"}}"#).unwrap_err();
+        assert_eq!(r#"table "nobody" not found"#, err.to_string());
+        let err = query(r#"{"IndexOnlyScan": {"index": "nothing"}}"#).unwrap_err();
+        assert_eq!(r#"index "nothing" not found"#, err.to_string());
+
+        let insert = format!(
+            r#"{{"Insert": {{"table": {}, "record": ["u", "Fay", "Jones"]}}}}"#,
+            table
+        );
+        execute(&mut bufmgr, &insert).unwrap();
+        let delete = format!(
+            r#"{{"Delete": {{"table": {}, "plan": {{"Filter": {{
+                "cond": {{"Eq": [{{"Column": 1}}, {{"Literal": "Fay"}}]}},
+                "from": {{"SeqScan": {{"table": "people"}}}}
+            }}}}}}}}"#,
+            table
+        );
+        match execute(&mut bufmgr, &delete).unwrap() {
+            Response::Delete { rows } => assert_eq!(1, rows),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        let err = execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": 12345, "record": ["u", "Fay", "Jones"]}}"#,
+        )
+        .unwrap_err();
+        assert_eq!("no table has meta page id 12345", err.to_string());
+    }
+
+    #[test]
+    fn test_join_kinds() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["u", "Fay", "Smith"]}}"#,
+        )
+        .unwrap();
+        let mut join = |kind: &str, left_columns: &str, on: &str| {
+            let json = format!(
+                r#"{{"Query": {{"plan": {{"Join": {{
+                    "kind": "{}", "left_columns": {}, "on": {},
+                    "left": {{"SeqScan": {{"table": "people"}}}},
+                    "right": {{"SeqScan": {{"table": "people"}}}}
+                }}}}}}}}"#,
+                kind, left_columns, on
+            );
+            execute(&mut bufmgr, &json).map(rows)
+        };
+        // the right row's columns start at 3, after the 3 left columns
+        let same_last = r#"{"And": [
+            {"Eq": [{"Column": 2}, {"Column": 5}]},
+            {"Lt": [{"Column": 0}, {"Column": 3}]}
+        ]}"#;
+        let expected = vec![vec!["u", "Fay", "Smith", "z", "Alice", "Smith"]];
+        assert_eq!(expected, join("NestedLoop", "null", same_last).unwrap());
+        assert_eq!(expected, join("NestedLoop", "3", same_last).unwrap());
+        assert_eq!(expected, join("Hash", "3", same_last).unwrap());
+
+        let same_key = r#"{"Eq": [{"Column": 3}, {"Column": 0}]}"#;
+        let by_pk = join("IndexByPk", "3", same_key).unwrap();
+        assert_eq!(6, by_pk.len());
+        assert_eq!(vec!["u", "Fay", "Smith", "u", "Fay", "Smith"], by_pk[0]);
+        assert_eq!(by_pk, join("Hash", "3", same_key).unwrap());
+        assert_eq!(by_pk, join("NestedLoop", "3", same_key).unwrap());
+
+        let err = join("NestedLoop", "2", same_last).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Query(query::Error::JoinArity {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        let err = join("Hash", "null", same_last).unwrap_err();
+        assert!(err.to_string().contains("needs left_columns"), "{}", err);
+        // with 2 left columns, Column(2) is on the right
+        let err = join("Hash", "2", r#"{"Eq": [{"Column": 2}, {"Column": 4}]}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("needs `on` to have an Eq"),
+            "{}",
+            err
+        );
+        let err = join("IndexByPk", "3", same_last).unwrap_err();
+        assert!(err.to_string().contains("pkey column 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_transactions() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+        let ids = |bufmgr: &mut BufferPoolManager| {
+            let resp = execute(
+                bufmgr,
+                r#"{"Query": {"plan": {"Project": {"columns": [0],
+                    "from": {"SeqScan": {"table": "people"}}}}}}"#,
+            );
+            rows(resp.unwrap()).concat().join("")
+        };
+
+        match execute(&mut bufmgr, r#""Begin""#).unwrap() {
+            Response::Begin { guarantee } => assert!(guarantee.starts_with("buffered")),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["u", "Fay", "Smith"]}}"#,
+        )
+        .unwrap();
+        execute(
+            &mut bufmgr,
+            r#"{"Delete": {"table": "people", "plan": {"SeqScan": {"table": "people", "key": ["x"], "to": {"key": ["y"]}}}}}"#,
+        )
+        .unwrap();
+        assert_eq!("uvwyz", ids(&mut bufmgr));
+        assert!(matches!(
+            execute(&mut bufmgr, r#""Rollback""#).unwrap(),
+            Response::Rollback { pages } if pages > 0
+        ));
+        assert_eq!("vwxyz", ids(&mut bufmgr));
+
+        execute(&mut bufmgr, r#""Begin""#).unwrap();
+        // a failed request leaves the transaction open
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["v", "Eve", "Brown"]}}"#,
+        )
+        .unwrap_err();
+        assert!(bufmgr.in_transaction());
+        execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["u", "Fay", "Smith"]}}"#,
+        )
+        .unwrap();
+        execute(&mut bufmgr, r#""Commit""#).unwrap();
+        assert_eq!("uvwxyz", ids(&mut bufmgr));
+        let err = execute(&mut bufmgr, r#""Commit""#).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Buffer(buffer::Error::NoTransaction)
+        ));
+    }
+
+    #[test]
+    fn test_key_arity_mismatch() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        create_people(&mut bufmgr);
+        // a catalog record that disagrees with how the rows were written
+        let catalog = catalog::Catalog::open(&mut bufmgr).unwrap();
+        let mut people = catalog.get(&mut bufmgr, "people").unwrap().unwrap();
+        people.num_key_elems = 2;
+        catalog.update(&mut bufmgr, &people).unwrap();
+
+        let mismatch = "table \"people\" is recorded with 2 key elements but its btree with 1";
+        let err = execute(
+            &mut bufmgr,
+            r#"{"Query": {"plan": {"SeqScan": {"table": "people"}}}}"#,
+        )
+        .unwrap_err();
+        assert_eq!(mismatch, err.to_string());
+        let err = execute(
+            &mut bufmgr,
+            r#"{"Insert": {"table": "people", "record": ["u", "Frank", "Davis"]}}"#,
+        )
+        .unwrap_err();
+        assert_eq!(mismatch, err.to_string());
+    }
+
+    fn script(requests: &[&str]) -> Vec<Request> {
+        requests
+            .iter()
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_run_script_index_churn() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+        let mut requests = script(&[
+            r#"{"CreateTable": {"name": "a", "num_key_elems": 1, "num_columns": 3,
+                "indices": [{"skey": [1], "unique": true}, {"skey": [2, 1], "directions": ["Desc"]}]}}"#,
+            r#""Begin""#,
+        ]);
+        for i in 0..200 {
+            let json = format!(
+                r#"{{"Insert": {{"table": "a", "record": ["{:04}", "{}", "{}"]}}}}"#,
+                i,
+                i % 150,
+                "x".repeat(i % 300)
+            );
+            requests.push(serde_json::from_str(&json).unwrap());
+        }
+        requests.extend(script(&[
+            r#"{"Update": {"table": "a", "set": [[1, {"Literal": "0"}]], "from": {"SeqScan": {"table": "a", "key": ["0100"]}}}}"#,
+            r#"{"Delete": {"table": "a", "plan": {"SeqScan": {"table": "a", "while": {"Lt": ["0050"]}}}}}"#,
+            r#""Rollback""#,
+            r#"{"Update": {"table": "a", "set": [[2, {"Column": 1}]], "from": {"SeqScan": {"table": "a"}}}}"#,
+            r#"{"Delete": {"table": "a", "plan": {"SeqScan": {"table": "a"}}}}"#,
+        ]));
+        let results = run_script(&mut bufmgr, &requests);
+        assert_eq!(requests.len(), results.len());
+        // the duplicate values of the unique index
+        assert_eq!(51, results.iter().filter(|res| res.is_err()).count());
+        assert_eq!(Vec::<String>::new(), verify_tables(&mut bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_run_script_bad_references() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(crate::btree::MIN_POOL_SIZE));
+        assert_eq!(Vec::<String>::new(), verify_tables(&mut bufmgr).unwrap());
+        let requests = script(&[
+            r#"{"Query": {"plan": {"SeqScan": {"table": 63}}}}"#,
+            r#"{"CreateTable": {"name": "a", "num_key_elems": 0, "num_columns": 0}}"#,
+            r#"{"CreateTable": {"name": "b", "num_key_elems": 3, "num_columns": 2}}"#,
+            r#"{"CreateTable": {"name": "c", "num_key_elems": 1, "num_columns": 2,
+                "indices": [{"skey": [7]}]}}"#,
+            r#"{"CreateTable": {"name": "d", "num_key_elems": 1, "num_columns": 2}}"#,
+            r#"{"CreateIndex": {"table": "d", "skey": [5]}}"#,
+            r#"{"Insert": {"table": "d", "record": ["k"]}}"#,
+            r#"{"Insert": {"table": "d", "record": ["k", "v", "w"]}}"#,
+            r#"{"Query": {"plan": {"SeqScan": {"table": 2}}}}"#,
+            r#"{"Query": {"plan": {"IndexScan": {"table": "d", "index": "e"}}}}"#,
+            r#"{"Update": {"table": "d", "set": [[6, {"Literal": "x"}]], "from": {"SeqScan": {"table": "d"}}}}"#,
+            r#"{"Delete": {"table": "d", "plan": {"SeqScan": {"table": "a"}}}}"#,
+            r#""Commit""#,
+        ]);
+        let results = run_script(&mut bufmgr, &requests);
+        let ok: Vec<_> = results.iter().map(|res| res.is_ok()).collect();
+        assert_eq!(
+            vec![
+                false, true, false, false, true, false, false, false, false, false, false, true,
+                false
+            ],
+            ok
+        );
+        assert_eq!(Vec::<String>::new(), verify_tables(&mut bufmgr).unwrap());
+    }
+}
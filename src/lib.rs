@@ -1,9 +1,25 @@
 mod bsearch;
 pub mod btree;
 pub mod buffer;
+pub mod catalog;
 pub mod disk;
+mod error;
+pub mod expr;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+pub mod lang;
+pub mod lock;
 mod memcmpable;
 pub mod query;
+pub mod repl;
+pub mod server;
 mod slotted;
+#[cfg(feature = "sql")]
+pub mod sql;
 pub mod table;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testing;
+pub mod transaction;
 pub mod tuple;
+
+pub use error::{Error, Result};
@@ -0,0 +1,405 @@
+//! Row locks, keyed by a table and the encoded primary key of a row.
+//!
+//! Locks are taken by transactions and held until they end: a
+//! `BufferPoolManager` given a `LockManager` keeps the `LockGuard`s its
+//! transaction acquires and drops them at commit and rollback. Writes take
+//! exclusive locks. Point reads, `Table::get` and `Table::get_by_index`,
+//! take shared ones when `set_lock_point_reads` says so; scans and the
+//! query executors built on them lock nothing, so they do not wait for a
+//! row a writer holds.
+//!
+//! `acquire` never blocks. A request that conflicts with the locks granted
+//! is queued, and fails with `LockError::Wait` until the transaction asks
+//! again once the locks it waits for are released and the requests queued
+//! before it are granted. There is no deadlock detection: a request still
+//! queued `timeout` after it first was fails with `LockError::Timeout`,
+//! and its transaction is expected to roll back, releasing its locks.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::disk::PageId;
+
+pub type TxnId = u64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LockError {
+    #[error("row is locked by transaction {holder}; the request is queued behind it")]
+    Wait { holder: TxnId },
+    #[error("gave up on a row lock after waiting {0:?} for it")]
+    Timeout(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+impl LockMode {
+    /// Whether a lock held in `self` also gives what `mode` asks for.
+    pub fn covers(self, mode: LockMode) -> bool {
+        self == LockMode::Exclusive || mode == LockMode::Shared
+    }
+}
+
+type LockKey = (PageId, Vec<u8>);
+
+#[derive(Debug)]
+struct Grant {
+    txn_id: TxnId,
+    mode: LockMode,
+    /// The number of live `LockGuard`s on the grant.
+    num_guards: usize,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    txn_id: TxnId,
+    mode: LockMode,
+    since: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Lock {
+    granted: Vec<Grant>,
+    waiting: VecDeque<Waiter>,
+}
+
+impl Lock {
+    /// The first transaction other than `txn_id` holding a lock that
+    /// conflicts with `mode`.
+    fn conflict(&self, txn_id: TxnId, mode: LockMode) -> Option<TxnId> {
+        self.granted
+            .iter()
+            .find(|grant| {
+                grant.txn_id != txn_id
+                    && (mode == LockMode::Exclusive || grant.mode == LockMode::Exclusive)
+            })
+            .map(|grant| grant.txn_id)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    locks: HashMap<LockKey, Lock>,
+    timeout: Duration,
+    lock_point_reads: bool,
+    last_txn_id: TxnId,
+}
+
+/// Clones share their locks, as the connections of a server would.
+#[derive(Debug, Clone)]
+pub struct LockManager {
+    state: Rc<RefCell<State>>,
+}
+
+impl LockManager {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(State {
+                locks: HashMap::new(),
+                timeout,
+                lock_point_reads: false,
+                last_txn_id: 0,
+            })),
+        }
+    }
+
+    /// A transaction id no other transaction of the manager was given.
+    pub fn new_txn_id(&self) -> TxnId {
+        let mut state = self.state.borrow_mut();
+        state.last_txn_id += 1;
+        state.last_txn_id
+    }
+
+    /// Whether point reads in a transaction take shared locks on the rows
+    /// they look up. Off by default, so that a row read can change before
+    /// the transaction ends. Scans take no locks either way.
+    pub fn set_lock_point_reads(&self, lock_point_reads: bool) {
+        self.state.borrow_mut().lock_point_reads = lock_point_reads;
+    }
+
+    pub fn lock_point_reads(&self) -> bool {
+        self.state.borrow().lock_point_reads
+    }
+
+    /// Locks the row of table `table_id` keyed by `pkey` for `txn_id`.
+    /// A lock the transaction holds already is acquired again, and a
+    /// shared lock it alone holds is upgraded to an exclusive one without
+    /// waiting in the queue.
+    pub fn acquire(
+        &self,
+        txn_id: TxnId,
+        table_id: PageId,
+        pkey: &[u8],
+        mode: LockMode,
+    ) -> Result<LockGuard, LockError> {
+        let mut state = self.state.borrow_mut();
+        let timeout = state.timeout;
+        let key = (table_id, pkey.to_vec());
+        let lock = state.locks.entry(key.clone()).or_default();
+        let held = lock.granted.iter().position(|grant| grant.txn_id == txn_id);
+        let queued = lock
+            .waiting
+            .iter()
+            .position(|waiter| waiter.txn_id == txn_id && waiter.mode == mode);
+        let conflict = lock.conflict(txn_id, mode);
+        // requests of its own queued before it do not hold it back
+        let queued_before = lock
+            .waiting
+            .iter()
+            .take(queued.unwrap_or(lock.waiting.len()))
+            .find(|waiter| waiter.txn_id != txn_id)
+            .map(|waiter| waiter.txn_id);
+        let is_next = queued_before.is_none() || (queued.is_none() && held.is_some());
+        if let (None, true) = (conflict, is_next) {
+            if let Some(pos) = queued {
+                lock.waiting.remove(pos);
+            }
+            match held {
+                Some(pos) => {
+                    let grant = &mut lock.granted[pos];
+                    if !grant.mode.covers(mode) {
+                        grant.mode = mode;
+                    }
+                    grant.num_guards += 1;
+                }
+                None => lock.granted.push(Grant {
+                    txn_id,
+                    mode,
+                    num_guards: 1,
+                }),
+            }
+            return Ok(LockGuard {
+                manager: self.clone(),
+                txn_id,
+                key,
+                mode,
+            });
+        }
+        // whoever it waits for, a holder or a request queued before it
+        let holder = conflict.or(queued_before).unwrap();
+        match queued {
+            Some(pos) => {
+                let waited = lock.waiting[pos].since.elapsed();
+                if waited >= timeout {
+                    lock.waiting.remove(pos);
+                    if lock.granted.is_empty() && lock.waiting.is_empty() {
+                        state.locks.remove(&key);
+                    }
+                    return Err(LockError::Timeout(waited));
+                }
+            }
+            None => lock.waiting.push_back(Waiter {
+                txn_id,
+                mode,
+                since: Instant::now(),
+            }),
+        }
+        Err(LockError::Wait { holder })
+    }
+
+    /// Takes the requests of `txn_id` out of the queues, as it ends.
+    pub fn cancel_waits(&self, txn_id: TxnId) {
+        self.state.borrow_mut().locks.retain(|_, lock| {
+            lock.waiting.retain(|waiter| waiter.txn_id != txn_id);
+            !lock.granted.is_empty() || !lock.waiting.is_empty()
+        });
+    }
+
+    /// The transactions holding a lock on the row, and in which mode.
+    pub fn holders(&self, table_id: PageId, pkey: &[u8]) -> Vec<(TxnId, LockMode)> {
+        let state = self.state.borrow();
+        state
+            .locks
+            .get(&(table_id, pkey.to_vec()))
+            .map_or(vec![], |lock| {
+                lock.granted
+                    .iter()
+                    .map(|grant| (grant.txn_id, grant.mode))
+                    .collect()
+            })
+    }
+
+    /// The requests queued for the row, first to be granted first.
+    pub fn waiters(&self, table_id: PageId, pkey: &[u8]) -> Vec<(TxnId, LockMode)> {
+        let state = self.state.borrow();
+        state
+            .locks
+            .get(&(table_id, pkey.to_vec()))
+            .map_or(vec![], |lock| {
+                lock.waiting
+                    .iter()
+                    .map(|waiter| (waiter.txn_id, waiter.mode))
+                    .collect()
+            })
+    }
+
+    /// The number of rows locked or waited for.
+    pub fn num_locks(&self) -> usize {
+        self.state.borrow().locks.len()
+    }
+
+    fn release(&self, txn_id: TxnId, key: &LockKey) {
+        let mut state = self.state.borrow_mut();
+        let lock = match state.locks.get_mut(key) {
+            Some(lock) => lock,
+            None => return,
+        };
+        if let Some(pos) = lock.granted.iter().position(|grant| grant.txn_id == txn_id) {
+            lock.granted[pos].num_guards -= 1;
+            if lock.granted[pos].num_guards == 0 {
+                lock.granted.remove(pos);
+            }
+        }
+        if lock.granted.is_empty() && lock.waiting.is_empty() {
+            state.locks.remove(key);
+        }
+    }
+}
+
+/// A lock acquired on a row, released when every guard acquired on it by
+/// the transaction is dropped. A lock upgraded to exclusive stays so until
+/// then.
+#[derive(Debug)]
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct LockGuard {
+    manager: LockManager,
+    txn_id: TxnId,
+    key: LockKey,
+    mode: LockMode,
+}
+
+impl LockGuard {
+    pub fn txn_id(&self) -> TxnId {
+        self.txn_id
+    }
+
+    /// The mode the lock was acquired in.
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.manager.release(self.txn_id, &self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: PageId = PageId(1);
+
+    fn manager() -> LockManager {
+        LockManager::new(Duration::from_secs(3600))
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let locks = manager();
+        let (t1, t2) = (locks.new_txn_id(), locks.new_txn_id());
+        let s1 = locks.acquire(t1, TABLE, b"a", LockMode::Shared).unwrap();
+        let s2 = locks.acquire(t2, TABLE, b"a", LockMode::Shared).unwrap();
+        assert_eq!(
+            vec![(t1, LockMode::Shared), (t2, LockMode::Shared)],
+            locks.holders(TABLE, b"a")
+        );
+        // t2 holds a shared lock too, so t1 cannot upgrade yet
+        assert_eq!(
+            Err(LockError::Wait { holder: t2 }),
+            locks
+                .acquire(t1, TABLE, b"a", LockMode::Exclusive)
+                .map(drop)
+        );
+        assert_eq!(vec![(t1, LockMode::Exclusive)], locks.waiters(TABLE, b"a"));
+        drop(s2);
+        let x1 = locks.acquire(t1, TABLE, b"a", LockMode::Exclusive).unwrap();
+        assert_eq!(vec![(t1, LockMode::Exclusive)], locks.holders(TABLE, b"a"));
+        assert!(locks.waiters(TABLE, b"a").is_empty());
+
+        // other rows and other tables are not locked
+        let x2 = locks.acquire(t2, TABLE, b"b", LockMode::Exclusive).unwrap();
+        let _ = locks
+            .acquire(t2, PageId(2), b"a", LockMode::Exclusive)
+            .unwrap();
+        assert_eq!(
+            Err(LockError::Wait { holder: t1 }),
+            locks.acquire(t2, TABLE, b"a", LockMode::Shared).map(drop)
+        );
+        assert_eq!(
+            Err(LockError::Wait { holder: t2 }),
+            locks.acquire(t1, TABLE, b"b", LockMode::Shared).map(drop)
+        );
+        // the lock stays exclusive while t1 holds a guard on it
+        drop(s1);
+        assert_eq!(vec![(t1, LockMode::Exclusive)], locks.holders(TABLE, b"a"));
+        drop(x1);
+        let _ = locks.acquire(t2, TABLE, b"a", LockMode::Shared).unwrap();
+        drop(x2);
+        let _ = locks.acquire(t1, TABLE, b"b", LockMode::Shared).unwrap();
+        assert_eq!(0, locks.num_locks());
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let locks = manager();
+        let (t1, t2, t3) = (locks.new_txn_id(), locks.new_txn_id(), locks.new_txn_id());
+        let x1 = locks.acquire(t1, TABLE, b"a", LockMode::Exclusive).unwrap();
+        assert!(locks.acquire(t2, TABLE, b"a", LockMode::Exclusive).is_err());
+        assert!(locks.acquire(t3, TABLE, b"a", LockMode::Shared).is_err());
+        drop(x1);
+        // t3 could share the row with nobody, but t2 came first
+        assert_eq!(
+            Err(LockError::Wait { holder: t2 }),
+            locks.acquire(t3, TABLE, b"a", LockMode::Shared).map(drop)
+        );
+        // a new request waits behind both
+        assert_eq!(
+            Err(LockError::Wait { holder: t2 }),
+            locks.acquire(t1, TABLE, b"a", LockMode::Shared).map(drop)
+        );
+        let x2 = locks.acquire(t2, TABLE, b"a", LockMode::Exclusive).unwrap();
+        assert_eq!(
+            vec![(t3, LockMode::Shared), (t1, LockMode::Shared)],
+            locks.waiters(TABLE, b"a")
+        );
+        // t1 gives up, as it would when it rolls back
+        locks.cancel_waits(t1);
+        drop(x2);
+        let _ = locks.acquire(t3, TABLE, b"a", LockMode::Shared).unwrap();
+        assert!(locks.waiters(TABLE, b"a").is_empty());
+    }
+
+    #[test]
+    fn test_deadlock_timeout() {
+        let locks = LockManager::new(Duration::ZERO);
+        let (t1, t2) = (locks.new_txn_id(), locks.new_txn_id());
+        let x1 = locks.acquire(t1, TABLE, b"a", LockMode::Exclusive).unwrap();
+        let x2 = locks.acquire(t2, TABLE, b"b", LockMode::Exclusive).unwrap();
+        assert!(matches!(
+            locks.acquire(t1, TABLE, b"b", LockMode::Exclusive),
+            Err(LockError::Wait { holder }) if holder == t2
+        ));
+        assert!(matches!(
+            locks.acquire(t2, TABLE, b"a", LockMode::Exclusive),
+            Err(LockError::Wait { holder }) if holder == t1
+        ));
+        // each waits for the other; the first to ask again gives up
+        assert!(matches!(
+            locks.acquire(t2, TABLE, b"a", LockMode::Exclusive),
+            Err(LockError::Timeout(_))
+        ));
+        assert!(locks.waiters(TABLE, b"a").is_empty());
+        drop(x2);
+        let _ = locks.acquire(t1, TABLE, b"b", LockMode::Exclusive).unwrap();
+        drop(x1);
+    }
+}
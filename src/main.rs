@@ -0,0 +1,249 @@
+use std::env;
+use std::fs;
+use std::io::{self, StdinLock, Write};
+use std::net::TcpListener;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Context, Result};
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog::Catalog;
+use relly::disk::DiskManager;
+use relly::lang::FileAccess;
+use relly::repl::{self, Output, Repl, Step};
+use relly::server::Server;
+
+/// Set by SIGINT or SIGTERM; the loop stops before the next request.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_os = "linux")]
+fn install_interrupt_handler() -> io::Result<()> {
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // No SA_RESTART, so a blocked read returns EINTR and the loop can
+        // see the flag; a second signal kills the process as usual.
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+        for &signal in &[libc::SIGINT, libc::SIGTERM] {
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn install_interrupt_handler() -> io::Result<()> {
+    Ok(())
+}
+
+/// Where the REPL reads its lines from: a terminal with line editing and
+/// history when built with the `rustyline` feature, or stdin as it is.
+enum Input<'a> {
+    Plain(StdinLock<'a>),
+    #[cfg(feature = "rustyline")]
+    Editor(rustyline::Editor<()>),
+}
+
+impl<'a> Input<'a> {
+    #[cfg(feature = "rustyline")]
+    fn new(stdin: StdinLock<'a>) -> Self {
+        use std::io::IsTerminal;
+
+        if stdin.is_terminal() {
+            Input::Editor(rustyline::Editor::new())
+        } else {
+            Input::Plain(stdin)
+        }
+    }
+
+    #[cfg(not(feature = "rustyline"))]
+    fn new(stdin: StdinLock<'a>) -> Self {
+        Input::Plain(stdin)
+    }
+
+    /// Returns the line without its line terminator, or `None` at EOF or
+    /// once interrupted. The editor reads the terminal in raw mode, where
+    /// Ctrl-C raises no SIGINT, so it sets `INTERRUPTED` itself.
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        match self {
+            Input::Plain(stdin) => {
+                {
+                    let stdout = io::stdout();
+                    let mut stdout = stdout.lock();
+                    write!(stdout, "{}", prompt)?;
+                    stdout.flush()?;
+                }
+                let line = repl::read_line(stdin, &INTERRUPTED)?;
+                Ok(line.map(|line| line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+            }
+            #[cfg(feature = "rustyline")]
+            Input::Editor(editor) => match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        editor.add_history_entry(line.as_str());
+                    }
+                    Ok(Some(line))
+                }
+                Err(rustyline::error::ReadlineError::Interrupted) => {
+                    INTERRUPTED.store(true, Ordering::SeqCst);
+                    Ok(None)
+                }
+                Err(rustyline::error::ReadlineError::Eof) => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
+}
+
+fn repl(
+    bufmgr: &mut BufferPoolManager,
+    output: Output,
+    flush_every: Option<usize>,
+    max_width: Option<usize>,
+    read_only: bool,
+    file_access: FileAccess,
+) -> Result<()> {
+    let stdin = io::stdin();
+    let mut input = Input::new(stdin.lock());
+    let stdout = io::stdout();
+    let mut repl = Repl::new(output);
+    repl.flush_every = flush_every;
+    repl.read_only = read_only;
+    repl.file_access = file_access;
+    if let Some(max_width) = max_width {
+        repl.max_cell_width = max_width;
+    }
+    loop {
+        let line = match input.read_line(repl.prompt())? {
+            Some(line) => line,
+            None => break,
+        };
+        let step = repl.feed_line(bufmgr, &line, &mut stdout.lock())?;
+        if step == Step::Exit || INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        println!();
+    }
+    repl.finish(bufmgr, &mut stdout.lock())?;
+    Ok(())
+}
+
+/// `relly [--json] [--flush-every N] [--max-width N] [--read-only]
+/// [--allow-file-io DIR] [FILE]` runs the REPL on stdin, and
+/// `relly serve --listen ADDR [--flush-every N] [--read-only]
+/// [--allow-file-io DIR] [FILE]` serves the same requests over TCP. With
+/// `--read-only`, the heap file is opened without write access, must
+/// already exist, and is never flushed. `Export` and `Import` may name any
+/// file at the REPL and none over TCP; `--allow-file-io` limits both to the
+/// files inside `DIR`.
+fn main() -> Result<()> {
+    let mut output = Output::Text;
+    let mut flush_every = None;
+    let mut max_width = None;
+    let mut read_only = false;
+    let mut serve = false;
+    let mut listen = None;
+    let mut file_dir = None;
+    let mut path = None;
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("serve") {
+        args.next();
+        serve = true;
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => output = Output::Json,
+            "--read-only" => read_only = true,
+            "--listen" => listen = Some(args.next().context("--listen needs an address")?),
+            "--allow-file-io" => {
+                let dir = args.next().context("--allow-file-io needs a directory")?;
+                let dir = fs::canonicalize(&dir)
+                    .with_context(|| format!("failed to resolve {:?}", dir))?;
+                file_dir = Some(dir);
+            }
+            "--flush-every" => {
+                let n = args.next().context("--flush-every needs a number")?;
+                let n = n
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .with_context(|| {
+                        format!("--flush-every needs a positive number, got {:?}", n)
+                    })?;
+                flush_every = Some(n);
+            }
+            "--max-width" => {
+                let n = args.next().context("--max-width needs a number")?;
+                let n = n
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .with_context(|| format!("--max-width needs a positive number, got {:?}", n))?;
+                max_width = Some(n);
+            }
+            _ if arg.starts_with('-') => bail!("unknown option {:?}", arg),
+            _ if path.is_some() => bail!("more than one heap file given: {:?}", arg),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| "relly.rly".to_string());
+    let file_access = match file_dir {
+        Some(dir) => FileAccess::Within(dir),
+        None if serve => FileAccess::Refused,
+        None => FileAccess::Any,
+    };
+    let disk = if read_only {
+        DiskManager::open_read_only(&path).with_context(|| format!("failed to open {:?}", path))?
+    } else {
+        DiskManager::open(&path)?
+    };
+    let pool = BufferPool::new(100);
+    let mut bufmgr = BufferPoolManager::new(disk, pool);
+    if read_only {
+        Catalog::open(&mut bufmgr)?;
+    } else {
+        Catalog::open_or_create(&mut bufmgr)?;
+    }
+    install_interrupt_handler().context("failed to install the signal handler")?;
+
+    if serve {
+        let listen = listen.context("serve needs --listen ADDR")?;
+        let listener = TcpListener::bind(&listen)
+            .with_context(|| format!("failed to listen on {}", listen))?;
+        eprintln!("relly: listening on {}", listener.local_addr()?);
+        Server {
+            flush_every,
+            read_only,
+            file_access,
+        }
+        .serve(&mut bufmgr, &listener, &INTERRUPTED)?;
+    } else {
+        repl(
+            &mut bufmgr,
+            output,
+            flush_every,
+            max_width,
+            read_only,
+            file_access,
+        )?;
+    }
+    if read_only {
+        bufmgr.leak_on_drop();
+        return Ok(());
+    }
+    if let Err(err) = bufmgr.close() {
+        eprintln!("relly: failed to flush {:?} on shutdown: {}", path, err);
+        process::exit(1);
+    }
+    Ok(())
+}
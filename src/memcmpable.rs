@@ -1,9 +1,38 @@
+use std::borrow::Cow;
 use std::cmp;
 
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("encoded element is truncated")]
+    Truncated,
+    #[error("invalid block length {0}")]
+    InvalidLength(u8),
+    #[error("nonzero padding in final block")]
+    NonZeroPadding,
+    #[error("malformed NULL block")]
+    InvalidNull,
+    #[error("unexpected NULL element")]
+    UnexpectedNull,
+    #[error("invalid element marker {0:#04x}")]
+    InvalidMarker(u8),
+    #[error("invalid escape sequence")]
+    InvalidEscape,
+    #[error("decoded size exceeds the limit of {0} bytes")]
+    TooLarge(usize),
+}
+
 const ESCAPE_LENGTH: usize = 9;
+// A block ending in this marker cannot be confused with a value block, whose
+// last byte is at most ESCAPE_LENGTH. Filling the block with it makes NULL
+// sort after every value.
+const NULL_MARKER: u8 = 0xff;
 
+/// The exact size `encode` produces for `len` bytes. An empty element
+/// still takes a block, which is also the size of `encode_null`.
 pub fn encoded_size(len: usize) -> usize {
-    (len + (ESCAPE_LENGTH - 1)) / (ESCAPE_LENGTH - 1) * ESCAPE_LENGTH
+    (cmp::max(len, 1) + (ESCAPE_LENGTH - 2)) / (ESCAPE_LENGTH - 1) * ESCAPE_LENGTH
 }
 
 pub fn encode(mut src: &[u8], dst: &mut Vec<u8>) {
@@ -23,21 +52,265 @@ pub fn encode(mut src: &[u8], dst: &mut Vec<u8>) {
     }
 }
 
-pub fn decode(src: &mut &[u8], dst: &mut Vec<u8>) {
+/// Like `encode`, but complements every byte so that the encodings sort in
+/// reverse order of `src`. The result is still self-delimiting.
+pub fn encode_desc(src: &[u8], dst: &mut Vec<u8>) {
+    let start = dst.len();
+    encode(src, dst);
+    dst[start..].iter_mut().for_each(|b| *b = !*b);
+}
+
+pub fn encode_null(dst: &mut Vec<u8>) {
+    dst.extend_from_slice(&[NULL_MARKER; ESCAPE_LENGTH]);
+}
+
+/// Splits off the next block of `src`.
+fn next_block<'a>(src: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    if src.len() < ESCAPE_LENGTH {
+        return Err(DecodeError::Truncated);
+    }
+    let (block, rest) = src.split_at(ESCAPE_LENGTH);
+    *src = rest;
+    Ok(block)
+}
+
+/// Decodes one element into `dst`, returning `false` if it is NULL.
+pub fn decode_nullable(src: &mut &[u8], dst: &mut Vec<u8>) -> Result<bool, DecodeError> {
+    match src.get(ESCAPE_LENGTH - 1) {
+        Some(&NULL_MARKER) => {
+            let block = next_block(src)?;
+            if block.iter().any(|&b| b != NULL_MARKER) {
+                return Err(DecodeError::InvalidNull);
+            }
+            Ok(false)
+        }
+        _ => {
+            decode(src, dst)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Decodes one element whose encoding was complemented to invert its
+/// order, returning `false` if it is NULL.
+pub fn decode_nullable_inverted(src: &mut &[u8], dst: &mut Vec<u8>) -> Result<bool, DecodeError> {
+    match src.get(ESCAPE_LENGTH - 1) {
+        Some(&b) if !b == NULL_MARKER => {
+            let block = next_block(src)?;
+            if block.iter().any(|&b| !b != NULL_MARKER) {
+                return Err(DecodeError::InvalidNull);
+            }
+            Ok(false)
+        }
+        _ => {
+            decode_desc(src, dst)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Decodes one element encoded by `encode_desc`.
+pub fn decode_desc(src: &mut &[u8], dst: &mut Vec<u8>) -> Result<(), DecodeError> {
+    let mut blocks = vec![];
     loop {
-        let extra = src[ESCAPE_LENGTH - 1];
-        let len = cmp::min(ESCAPE_LENGTH - 1, extra as usize);
-        dst.extend_from_slice(&src[..len]);
-        *src = &src[ESCAPE_LENGTH..];
-        if extra < ESCAPE_LENGTH as u8 {
+        let block = next_block(src)?;
+        blocks.extend(block.iter().map(|b| !b));
+        if !block[ESCAPE_LENGTH - 1] != ESCAPE_LENGTH as u8 {
+            break;
+        }
+    }
+    decode(&mut &blocks[..], dst)
+}
+
+/// The length of the first element of `src`, checked like `decode_nullable`
+/// (or `decode_nullable_inverted` if `inverted`) but without copying it.
+pub fn element_len(src: &[u8], inverted: bool) -> Result<usize, DecodeError> {
+    let mask = if inverted { 0xff } else { 0 };
+    let mut rest = src;
+    loop {
+        let mut block = [0; ESCAPE_LENGTH];
+        for (b, &encoded) in block.iter_mut().zip(next_block(&mut rest)?) {
+            *b = encoded ^ mask;
+        }
+        let is_first = rest.len() + ESCAPE_LENGTH == src.len();
+        if is_first && block[ESCAPE_LENGTH - 1] == NULL_MARKER {
+            if block.iter().any(|&b| b != NULL_MARKER) {
+                return Err(DecodeError::InvalidNull);
+            }
+            break;
+        }
+        if !block_data(&block)?.1 {
             break;
         }
     }
+    Ok(src.len() - rest.len())
+}
+
+/// Checks a value block and returns its data and whether another block
+/// of the same element follows.
+fn block_data(block: &[u8]) -> Result<(&[u8], bool), DecodeError> {
+    let extra = block[ESCAPE_LENGTH - 1];
+    if extra == NULL_MARKER {
+        return Err(DecodeError::UnexpectedNull);
+    }
+    if extra > ESCAPE_LENGTH as u8 {
+        return Err(DecodeError::InvalidLength(extra));
+    }
+    let len = cmp::min(ESCAPE_LENGTH - 1, extra as usize);
+    if block[len..ESCAPE_LENGTH - 1].iter().any(|&b| b != 0) {
+        return Err(DecodeError::NonZeroPadding);
+    }
+    Ok((&block[..len], extra == ESCAPE_LENGTH as u8))
+}
+
+/// Decodes one element into `dst`. The element must not be NULL.
+pub fn decode(src: &mut &[u8], dst: &mut Vec<u8>) -> Result<(), DecodeError> {
+    decode_with_limit(src, dst, usize::MAX)
+}
+
+/// Like `decode`, but fails with `DecodeError::TooLarge` before `dst`
+/// would grow past `max_total_bytes`.
+pub fn decode_with_limit(
+    src: &mut &[u8],
+    dst: &mut Vec<u8>,
+    max_total_bytes: usize,
+) -> Result<(), DecodeError> {
+    loop {
+        let (data, more) = block_data(next_block(src)?)?;
+        if data.len() > max_total_bytes.saturating_sub(dst.len()) {
+            return Err(DecodeError::TooLarge(max_total_bytes));
+        }
+        dst.extend_from_slice(data);
+        if !more {
+            return Ok(());
+        }
+    }
+}
+
+/// Like `decode_nullable`, but borrows the element from `src` when it
+/// fits in a single block.
+pub fn decode_borrowed<'a>(src: &mut &'a [u8]) -> Result<Option<Cow<'a, [u8]>>, DecodeError> {
+    if src.get(ESCAPE_LENGTH - 1) == Some(&NULL_MARKER) {
+        decode_nullable(src, &mut vec![])?;
+        return Ok(None);
+    }
+    let (data, more) = block_data(next_block(src)?)?;
+    if !more {
+        return Ok(Some(Cow::Borrowed(data)));
+    }
+    let mut dst = data.to_vec();
+    decode(src, &mut dst)?;
+    Ok(Some(Cow::Owned(dst)))
+}
+
+// The escaped encoding starts each element with a marker byte. A value then
+// has each 0x00 escaped as 0x00 0xff and ends with 0x00 0x01, which sorts
+// before any continuation, so shorter values sort first.
+const ESCAPED_VALUE: u8 = 0x01;
+const ESCAPED_NULL: u8 = 0x02;
+const ESCAPED_ZERO: [u8; 2] = [0x00, 0xff];
+const ESCAPED_TERMINATOR: [u8; 2] = [0x00, 0x01];
+
+/// Like `encode`, but takes `2 + len` bytes plus one per 0x00 in `src`
+/// rather than a multiple of 9.
+pub fn encode_escaped(src: &[u8], dst: &mut Vec<u8>) {
+    dst.push(ESCAPED_VALUE);
+    for &b in src {
+        if b == 0 {
+            dst.extend_from_slice(&ESCAPED_ZERO);
+        } else {
+            dst.push(b);
+        }
+    }
+    dst.extend_from_slice(&ESCAPED_TERMINATOR);
+}
+
+/// The exact size `encode_escaped` produces for `src`.
+pub fn escaped_size(src: &[u8]) -> usize {
+    src.len() + src.iter().filter(|&&b| b == 0).count() + 3
+}
+
+pub fn encode_escaped_null(dst: &mut Vec<u8>) {
+    dst.push(ESCAPED_NULL);
+}
+
+/// The length of the first element of `src`, checked like `decode_escaped`
+/// but without copying it.
+pub fn escaped_element_len(src: &[u8], inverted: bool) -> Result<usize, DecodeError> {
+    let mask = if inverted { 0xff } else { 0 };
+    let byte = |i: usize| src.get(i).map(|b| b ^ mask).ok_or(DecodeError::Truncated);
+    match byte(0)? {
+        ESCAPED_VALUE => {}
+        ESCAPED_NULL => return Ok(1),
+        marker => return Err(DecodeError::InvalidMarker(marker)),
+    }
+    let mut i = 1;
+    loop {
+        if byte(i)? != 0 {
+            i += 1;
+            continue;
+        }
+        match byte(i + 1)? {
+            b if b == ESCAPED_ZERO[1] => i += 2,
+            b if b == ESCAPED_TERMINATOR[1] => return Ok(i + 2),
+            _ => return Err(DecodeError::InvalidEscape),
+        }
+    }
+}
+
+/// Decodes one element encoded by `encode_escaped` into `dst`, returning
+/// `false` if it is NULL. If `inverted`, the encoding was complemented.
+pub fn decode_escaped(
+    src: &mut &[u8],
+    dst: &mut Vec<u8>,
+    inverted: bool,
+) -> Result<bool, DecodeError> {
+    let mask = if inverted { 0xff } else { 0 };
+    let len = escaped_element_len(src, inverted)?;
+    let (elem, rest) = src.split_at(len);
+    *src = rest;
+    if len == 1 {
+        return Ok(false);
+    }
+    let mut escaped = false;
+    for &b in &elem[1..len - ESCAPED_TERMINATOR.len()] {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        let b = b ^ mask;
+        dst.push(b);
+        escaped = b == 0;
+    }
+    Ok(true)
+}
+
+/// Like `decode_escaped`, but borrows the element from `src` when it
+/// contains no escaped 0x00.
+pub fn decode_escaped_borrowed<'a>(
+    src: &mut &'a [u8],
+) -> Result<Option<Cow<'a, [u8]>>, DecodeError> {
+    if src.first() == Some(&ESCAPED_VALUE) {
+        if let Some(end) = src.iter().position(|&b| b == 0) {
+            if src.get(end + 1) == Some(&ESCAPED_TERMINATOR[1]) {
+                let data = &src[1..end];
+                *src = &src[end + 2..];
+                return Ok(Some(Cow::Borrowed(data)));
+            }
+        }
+    }
+    let mut dst = vec![];
+    if decode_escaped(src, &mut dst, false)? {
+        Ok(Some(Cow::Owned(dst)))
+    } else {
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tuple::testing::Rng;
 
     #[test]
     fn test() {
@@ -51,10 +324,243 @@ mod tests {
         let mut rest = &enc[..];
 
         let mut dec1 = vec![];
-        decode(&mut rest, &mut dec1);
+        decode(&mut rest, &mut dec1).unwrap();
         assert_eq!(org1, dec1.as_slice());
         let mut dec2 = vec![];
-        decode(&mut rest, &mut dec2);
+        decode(&mut rest, &mut dec2).unwrap();
         assert_eq!(org2, dec2.as_slice());
     }
+
+    #[test]
+    fn test_encoded_size() {
+        for len in 0..40 {
+            let src = vec![0; len];
+            let mut enc = vec![];
+            encode(&src, &mut enc);
+            assert_eq!(enc.len(), encoded_size(len), "{}", len);
+            let mut enc = vec![];
+            encode_escaped(&src, &mut enc);
+            assert_eq!(enc.len(), escaped_size(&src), "{}", len);
+        }
+        let mut null = vec![];
+        encode_null(&mut null);
+        assert_eq!(null.len(), encoded_size(0));
+    }
+
+    #[test]
+    fn test_null() {
+        let mut null = vec![];
+        encode_null(&mut null);
+        for org in &[&b""[..], b"\xff\xff\xff\xff\xff\xff\xff\xff\xff"] {
+            let mut enc = vec![];
+            encode(org, &mut enc);
+            assert!(enc < null);
+        }
+
+        let mut enc = null.clone();
+        encode(b"", &mut enc);
+        let mut rest = &enc[..];
+        let mut dec = vec![];
+        assert!(!decode_nullable(&mut rest, &mut dec).unwrap());
+        assert!(decode_nullable(&mut rest, &mut dec).unwrap());
+        assert!(dec.is_empty());
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_desc_properties() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let a = rng.bytes();
+            let b = rng.bytes();
+            let (mut enc_a, mut enc_b) = (vec![], vec![]);
+            encode_desc(&a, &mut enc_a);
+            encode_desc(&b, &mut enc_b);
+            assert_eq!(a.cmp(&b).reverse(), enc_a.cmp(&enc_b), "{:?} {:?}", a, b);
+
+            // (a ASC, b DESC) tuples sort by a, then by b reversed
+            let c = rng.bytes();
+            let d = rng.bytes();
+            let mut tuple1 = vec![];
+            encode(&a, &mut tuple1);
+            encode_desc(&b, &mut tuple1);
+            let mut tuple2 = vec![];
+            encode(&c, &mut tuple2);
+            encode_desc(&d, &mut tuple2);
+            let expected = a.cmp(&c).then(b.cmp(&d).reverse());
+            assert_eq!(
+                expected,
+                tuple1.cmp(&tuple2),
+                "{:?} {:?}",
+                (&a, &b),
+                (&c, &d)
+            );
+
+            let mut rest = &tuple1[..];
+            let (mut dec_a, mut dec_b) = (vec![], vec![]);
+            decode(&mut rest, &mut dec_a).unwrap();
+            decode_desc(&mut rest, &mut dec_b).unwrap();
+            assert_eq!((a, b), (dec_a, dec_b));
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_inverted() {
+        let orgs: [&[u8]; 4] = [b"", b"a", b"abcdefgh", b"abcdefghi"];
+        let mut encs = vec![];
+        for org in &orgs {
+            let mut enc = vec![];
+            encode(org, &mut enc);
+            enc.push(0);
+            enc.iter_mut().for_each(|b| *b = !*b);
+            encs.push(enc);
+        }
+        assert!(encs.windows(2).all(|w| w[0] > w[1]));
+        for (org, enc) in orgs.iter().zip(&encs) {
+            let mut rest = &enc[..];
+            let mut dec = vec![];
+            assert!(decode_nullable_inverted(&mut rest, &mut dec).unwrap());
+            assert_eq!(org, &dec.as_slice());
+            assert_eq!(1, rest.len());
+        }
+    }
+
+    #[test]
+    fn test_escaped_properties() {
+        let mut rng = Rng(0x1234_5678_9abc_def1);
+        for _ in 0..10_000 {
+            let (a, b, c, d) = (rng.bytes(), rng.bytes(), rng.bytes(), rng.bytes());
+            let (mut tuple1, mut tuple2) = (vec![], vec![]);
+            encode_escaped(&a, &mut tuple1);
+            encode_escaped(&b, &mut tuple1);
+            encode_escaped(&c, &mut tuple2);
+            encode_escaped(&d, &mut tuple2);
+            let expected = a.cmp(&c).then(b.cmp(&d));
+            assert_eq!(
+                expected,
+                tuple1.cmp(&tuple2),
+                "{:?} {:?}",
+                (&a, &b),
+                (&c, &d)
+            );
+
+            let mut null = vec![];
+            encode_escaped_null(&mut null);
+            assert!(tuple1 < null);
+
+            let mut rest = &tuple1[..];
+            let (mut dec_a, mut dec_b) = (vec![], vec![]);
+            assert!(decode_escaped(&mut rest, &mut dec_a, false).unwrap());
+            let dec_b2 = decode_escaped_borrowed(&mut { rest }).unwrap().unwrap();
+            assert!(decode_escaped(&mut rest, &mut dec_b, false).unwrap());
+            assert_eq!((&a, &b), (&dec_a, &dec_b));
+            assert_eq!(b, dec_b2.into_owned());
+            assert!(rest.is_empty());
+
+            let mut inverted: Vec<_> = tuple2.iter().map(|b| !b).collect();
+            inverted.push(!ESCAPED_NULL);
+            let mut rest = &inverted[..];
+            let mut dec_c = vec![];
+            assert!(decode_escaped(&mut rest, &mut dec_c, true).unwrap());
+            assert_eq!(c, dec_c);
+            assert!(decode_escaped(&mut rest, &mut vec![], true).unwrap());
+            assert!(!decode_escaped(&mut rest, &mut vec![], true).unwrap());
+            assert!(rest.is_empty());
+        }
+
+        let mut rest = &b"\x01a\x00\x02"[..];
+        assert_eq!(
+            Err(DecodeError::InvalidEscape),
+            decode_escaped(&mut rest, &mut vec![], false)
+        );
+        let mut rest = &b"\x01a"[..];
+        assert_eq!(
+            Err(DecodeError::Truncated),
+            decode_escaped(&mut rest, &mut vec![], false)
+        );
+        let mut rest = &b"\x03"[..];
+        assert_eq!(
+            Err(DecodeError::InvalidMarker(3)),
+            decode_escaped(&mut rest, &mut vec![], false)
+        );
+    }
+
+    #[test]
+    fn test_decode_with_limit() {
+        // A chain of full blocks that each claim another one follows.
+        let mut chained = vec![];
+        for _ in 0..1000 {
+            chained.extend_from_slice(b"abcdefgh\x09");
+        }
+        chained.extend_from_slice(b"ab\0\0\0\0\0\0\x02");
+        let mut dst = vec![];
+        assert_eq!(
+            Err(DecodeError::TooLarge(100)),
+            decode_with_limit(&mut &chained[..], &mut dst, 100)
+        );
+        assert!(dst.len() <= 100);
+
+        let mut dst = vec![];
+        decode_with_limit(&mut &chained[..], &mut dst, 8002).unwrap();
+        assert_eq!(8002, dst.len());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let decode_all = |bytes: &[u8]| {
+            let mut rest = bytes;
+            let mut dec = vec![];
+            decode(&mut rest, &mut dec).map(|()| dec)
+        };
+        assert_eq!(Err(DecodeError::Truncated), decode_all(b""));
+        assert_eq!(Err(DecodeError::Truncated), decode_all(b"abcdefgh"));
+        assert_eq!(Err(DecodeError::Truncated), decode_all(b"abcdefgh\x09abc"));
+        assert_eq!(
+            Err(DecodeError::InvalidLength(10)),
+            decode_all(b"abcdefgh\x0a")
+        );
+        assert_eq!(
+            Err(DecodeError::NonZeroPadding),
+            decode_all(b"ab\0\0\0\0\0x\x02")
+        );
+        assert_eq!(Ok(b"ab".to_vec()), decode_all(b"ab\0\0\0\0\0\0\x02"));
+        let mut rest = &b"\xff\xff\xff\x00\xff\xff\xff\xff\xff"[..];
+        assert_eq!(
+            Err(DecodeError::InvalidNull),
+            decode_nullable(&mut rest, &mut vec![])
+        );
+        let mut null = vec![];
+        encode_null(&mut null);
+        null.iter_mut().for_each(|b| *b = !*b);
+        assert_eq!(
+            Err(DecodeError::UnexpectedNull),
+            decode_desc(&mut &null[..], &mut vec![])
+        );
+
+        // arbitrary bytes either decode or fail, but never panic
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let len = (rng.next_u64() % 40) as usize;
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| match rng.next_u64() % 4 {
+                    0 => 0,
+                    1 => 0xff,
+                    2 => (rng.next_u64() % 10) as u8,
+                    _ => rng.next_u64() as u8,
+                })
+                .collect();
+            for decode_one in &[decode_nullable, decode_nullable_inverted] {
+                let mut rest = &bytes[..];
+                while !rest.is_empty() {
+                    if decode_one(&mut rest, &mut vec![]).is_err() {
+                        break;
+                    }
+                }
+            }
+            let _ = element_len(&bytes, false);
+            let _ = element_len(&bytes, true);
+            let _ = escaped_element_len(&bytes, false);
+        }
+    }
 }
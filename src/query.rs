@@ -1,98 +1,693 @@
-use anyhow::Result;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::btree::{self, BTree, SearchMode};
 use crate::buffer::BufferPoolManager;
 use crate::disk::PageId;
-use crate::tuple;
+use crate::expr;
+use crate::table::{self, Expiry, Table, TableIter};
+use crate::tuple::{self, ColumnType, Direction, Format, Value};
+use crate::Result;
+
+pub use crate::btree::ScanDirection;
+
+pub mod plan;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("executor cannot be rewound")]
+    RewindUnsupported,
+    #[error("sum overflows i64")]
+    SumOverflow,
+    #[error("materialized input failed: {0}")]
+    MaterializeFailed(String),
+    #[error(
+        "index entry {} references missing pkey {} in table page {table}",
+        tuple::to_hex(.skey),
+        tuple::to_hex(.pkey)
+    )]
+    MissingTableRow {
+        skey: Vec<u8>,
+        pkey: Vec<u8>,
+        table: u64,
+    },
+    #[error("column {0} is part of the pkey and cannot be set")]
+    SetKeyColumn(usize),
+    #[error("join expects {expected} left columns but a left row has {actual}")]
+    JoinArity { expected: usize, actual: usize },
+}
 
-pub type Tuple = Vec<Vec<u8>>;
-pub type TupleSlice<'a> = &'a [Vec<u8>];
+/// A decoded row. `None` elements are NULL.
+pub type Tuple = Vec<Option<Vec<u8>>>;
+pub type TupleSlice<'a> = &'a [Option<Vec<u8>>];
 
-pub enum TupleSearchMode<'a> {
+#[derive(Debug, Clone)]
+pub enum TupleSearchMode {
     Start,
-    Key(&'a [&'a [u8]]),
+    Key(Tuple),
+    /// A key already encoded in the format and directions of the btree,
+    /// such as one from `tuple::KeyBuilder`.
+    Encoded(Vec<u8>),
 }
 
-impl<'a> TupleSearchMode<'a> {
-    fn encode(&self) -> SearchMode {
+impl TupleSearchMode {
+    fn describe(&self) -> String {
+        match self {
+            TupleSearchMode::Start => "Start".to_string(),
+            TupleSearchMode::Key(key) => format!("Key {:?}", tuple::Pretty(key)),
+            TupleSearchMode::Encoded(key) => format!("Encoded {:02x?}", key),
+        }
+    }
+
+    fn encode(&self, format: Format, directions: &[Direction]) -> SearchMode {
         match self {
             TupleSearchMode::Start => SearchMode::Start,
             TupleSearchMode::Key(tuple) => {
                 let mut key = vec![];
-                tuple::encode(tuple.iter(), &mut key);
+                format.encode_directed(tuple.iter(), directions, &mut key);
                 SearchMode::Key(key)
             }
+            TupleSearchMode::Encoded(key) => SearchMode::Key(key.clone()),
         }
     }
 }
 
 pub trait Executor {
     fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>>;
+
+    /// Makes `next` start over from the first row, as if just started.
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        Err(Error::RewindUnsupported.into())
+    }
+
+    /// Up to `max_rows` rows, which are fewer only once the rows run out;
+    /// an empty batch is the end. `max_rows` must not be 0.
+    fn next_batch(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_rows: usize,
+    ) -> Result<Vec<Tuple>> {
+        let mut batch = vec![];
+        while batch.len() < max_rows {
+            match self.next(bufmgr)? {
+                Some(row) => batch.push(row),
+                None => break,
+            }
+        }
+        Ok(batch)
+    }
+
+    /// What the executor has done so far, if its plan collects metrics
+    /// (see `CollectMetrics`).
+    fn metrics(&self) -> Option<Metrics> {
+        None
+    }
 }
 
 pub type BoxExecutor<'a> = Box<dyn Executor + 'a>;
 
 pub trait PlanNode {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor>;
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>>;
+
+    fn explain(&self) -> PlanDescription;
+}
+
+pub type BoxPlanNode<'a> = Box<dyn PlanNode + 'a>;
+
+/// What `PlanNode::explain` reports about a node and its inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanDescription {
+    pub name: String,
+    pub properties: Vec<(String, String)>,
+    pub children: Vec<PlanDescription>,
+}
+
+impl PlanDescription {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            properties: vec![],
+            children: vec![],
+        }
+    }
+
+    fn property(mut self, name: &str, value: impl ToString) -> Self {
+        self.properties.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn child(mut self, child: PlanDescription) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}{}", "", self.name, indent = depth * 2)?;
+        for (name, value) in &self.properties {
+            write!(f, " {}={}", name, value)?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per node, with its inputs indented below it.
+impl fmt::Display for PlanDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Adds properties to what `inner` explains about itself, for what only
+/// the builder of the plan knows, such as the source of a closure.
+pub struct Annotated<'a> {
+    pub inner: BoxPlanNode<'a>,
+    pub properties: Vec<(String, String)>,
+}
+
+impl<'a> PlanNode for Annotated<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        self.inner.start(bufmgr)
+    }
+
+    fn explain(&self) -> PlanDescription {
+        let mut description = self.inner.explain();
+        description
+            .properties
+            .extend(self.properties.iter().cloned());
+        description
+    }
+}
+
+/// What an executor and the executors of its inputs did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub name: String,
+    /// Rows returned by `next`.
+    pub rows: u64,
+    /// Rows read from the inputs, so a `Filter` dropped `examined - rows`.
+    /// A node without inputs that collect metrics counts its own rows.
+    pub examined: u64,
+    /// Pages fetched by this node, not counting its inputs.
+    pub fetches: u64,
+    pub children: Vec<Metrics>,
+}
+
+impl Metrics {
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} rows={} examined={} fetches={}",
+            "",
+            self.name,
+            self.rows,
+            self.examined,
+            self.fetches,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per node, with its inputs indented below it.
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// The counts of a `CollectMetrics` node, shared with its executor and
+/// with the node that reads from it.
+#[derive(Debug, Default)]
+pub struct Meter {
+    name: String,
+    rows: Cell<u64>,
+    /// Including the pages fetched by the inputs.
+    fetches: Cell<u64>,
+    inputs: Vec<Rc<Meter>>,
+}
+
+impl Meter {
+    fn measure<T>(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        f: impl FnOnce(&mut BufferPoolManager) -> T,
+    ) -> T {
+        let before = bufmgr.stats().fetches();
+        let result = f(bufmgr);
+        self.fetches
+            .set(self.fetches.get() + bufmgr.stats().fetches() - before);
+        result
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let children: Vec<_> = self.inputs.iter().map(|input| input.metrics()).collect();
+        let examined = if self.inputs.is_empty() {
+            self.rows.get()
+        } else {
+            self.inputs.iter().map(|input| input.rows.get()).sum()
+        };
+        let input_fetches: u64 = self.inputs.iter().map(|input| input.fetches.get()).sum();
+        Metrics {
+            name: self.name.clone(),
+            rows: self.rows.get(),
+            examined,
+            fetches: self.fetches.get() - input_fetches,
+            children,
+        }
+    }
+}
+
+/// Counts `Metrics` for `inner`. The plan builder wraps each node it wants
+/// counted, passing the meters of the wrapped plans the node reads from as
+/// `inputs`; plans without it pay nothing for metrics.
+pub struct CollectMetrics<'a> {
+    inner: BoxPlanNode<'a>,
+    meter: Rc<Meter>,
+}
+
+impl<'a> CollectMetrics<'a> {
+    pub fn new(inner: BoxPlanNode<'a>, inputs: Vec<Rc<Meter>>) -> Self {
+        let meter = Rc::new(Meter {
+            name: inner.explain().name,
+            inputs,
+            ..Meter::default()
+        });
+        Self { inner, meter }
+    }
+
+    pub fn meter(&self) -> Rc<Meter> {
+        self.meter.clone()
+    }
+}
+
+impl<'a> PlanNode for CollectMetrics<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        self.meter.rows.set(0);
+        self.meter.fetches.set(0);
+        let inner_iter = self
+            .meter
+            .measure(bufmgr, |bufmgr| self.inner.start(bufmgr))?;
+        Ok(Box::new(ExecCollectMetrics {
+            inner_iter,
+            meter: &self.meter,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        self.inner.explain()
+    }
+}
+
+pub struct ExecCollectMetrics<'a> {
+    inner_iter: BoxExecutor<'a>,
+    meter: &'a Meter,
+}
+
+impl<'a> Executor for ExecCollectMetrics<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let inner_iter = &mut self.inner_iter;
+        let row = self
+            .meter
+            .measure(bufmgr, |bufmgr| inner_iter.next(bufmgr))?;
+        if row.is_some() {
+            self.meter.rows.set(self.meter.rows.get() + 1);
+        }
+        Ok(row)
+    }
+
+    fn next_batch(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_rows: usize,
+    ) -> Result<Vec<Tuple>> {
+        let inner_iter = &mut self.inner_iter;
+        let batch = self
+            .meter
+            .measure(bufmgr, |bufmgr| inner_iter.next_batch(bufmgr, max_rows))?;
+        self.meter
+            .rows
+            .set(self.meter.rows.get() + batch.len() as u64);
+        Ok(batch)
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        let inner_iter = &mut self.inner_iter;
+        self.meter
+            .measure(bufmgr, |bufmgr| inner_iter.rewind(bufmgr))
+    }
+
+    fn metrics(&self) -> Option<Metrics> {
+        Some(self.meter.metrics())
+    }
+}
+
+pub type Predicate<'a> = Box<dyn Fn(TupleSlice) -> Result<bool> + 'a>;
+/// Takes an outer row and an inner row.
+pub type JoinPredicate<'a> = Box<dyn Fn(TupleSlice, TupleSlice) -> Result<bool> + 'a>;
+
+/// A tuple element that may borrow from the page it was decoded from.
+pub type BorrowedElem<'a> = Option<Cow<'a, [u8]>>;
+pub type BorrowedPredicate<'a> = Box<dyn Fn(&[BorrowedElem]) -> Result<bool> + 'a>;
+
+/// A condition on the leading pkey elements that `SeqScan` checks against
+/// the encoded pkey, so it decodes nothing for the row that ends the scan.
+pub struct KeyBound {
+    pub key: Tuple,
+    /// Applied to the pkey compared with `key`, as by
+    /// `tuple::cmp_encoded_prefix`.
+    pub accept: fn(Ordering) -> bool,
 }
 
 pub struct SeqScan<'a> {
     pub table_meta_page_id: PageId,
-    pub search_mode: TupleSearchMode<'a>,
-    pub while_cond: &'a dyn Fn(TupleSlice) -> bool,
+    pub search_mode: TupleSearchMode,
+    /// `Backward` walks down from the search key, so `while_bound` and
+    /// `while_cond` are bounds from below.
+    pub direction: ScanDirection,
+    /// Checked before `while_cond`, which it can replace for key ranges.
+    pub while_bound: Option<KeyBound>,
+    pub while_cond: Predicate<'a>,
+    /// Hides expired rows of a table with an expiry column.
+    pub expiry: Option<Expiry>,
+    /// Skips the rows for which it is false before they are copied out of
+    /// the page, which is cheaper than a `Filter` on top.
+    pub filter: Option<BorrowedPredicate<'a>>,
 }
 
 impl<'a> PlanNode for SeqScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
-        let btree = BTree::new(self.table_meta_page_id);
-        let table_iter = btree.search(bufmgr, self.search_mode.encode())?;
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let format = table::btree_format(bufmgr, self.table_meta_page_id)?;
+        let search_mode = self.search_mode.encode(format, &[]);
+        let table_iter = TableIter::new(
+            bufmgr,
+            self.table_meta_page_id,
+            search_mode.clone(),
+            self.direction,
+        )?;
+        let while_bound = self.while_bound.as_ref().map(|bound| {
+            let mut key = vec![];
+            format.encode(bound.key.iter(), &mut key);
+            (key, bound)
+        });
         Ok(Box::new(ExecSeqScan {
+            table_meta_page_id: self.table_meta_page_id,
+            search_mode,
+            direction: self.direction,
             table_iter,
-            while_cond: self.while_cond,
+            checks: RowChecks {
+                while_bound,
+                while_cond: &*self.while_cond,
+                expiry: self.expiry,
+                filter: self.filter.as_ref(),
+            },
         }))
     }
+
+    fn explain(&self) -> PlanDescription {
+        let mut description = PlanDescription::new("SeqScan")
+            .property("table", self.table_meta_page_id.to_u64())
+            .property("search_mode", self.search_mode.describe())
+            .property("direction", format!("{:?}", self.direction))
+            .property("while_bound", self.while_bound.is_some());
+        if let Some(expiry) = self.expiry {
+            description = description.property("expiry_column", expiry.column);
+        }
+        description.property("filter", self.filter.is_some())
+    }
 }
 
 pub struct ExecSeqScan<'a> {
-    table_iter: btree::Iter,
-    while_cond: &'a dyn Fn(TupleSlice) -> bool,
+    table_meta_page_id: PageId,
+    search_mode: SearchMode,
+    direction: ScanDirection,
+    table_iter: TableIter,
+    checks: RowChecks<'a>,
+}
+
+/// What `ExecSeqScan` checks each row against.
+struct RowChecks<'a> {
+    /// The encoded key of `SeqScan::while_bound`.
+    while_bound: Option<(Vec<u8>, &'a KeyBound)>,
+    while_cond: &'a dyn Fn(TupleSlice) -> Result<bool>,
+    expiry: Option<Expiry>,
+    filter: Option<&'a BorrowedPredicate<'a>>,
+}
+
+enum RowCheck {
+    Emit(Tuple),
+    Skip,
+    End,
+}
+
+impl<'a> RowChecks<'a> {
+    fn check(&self, format: Format, pkey_bytes: &[u8], tuple_bytes: &[u8]) -> Result<RowCheck> {
+        if let Some((key, bound)) = &self.while_bound {
+            if !(bound.accept)(format.cmp_encoded_prefix(pkey_bytes, key)?) {
+                return Ok(RowCheck::End);
+            }
+        }
+        let mut pkey = vec![];
+        format.decode_with_limit(pkey_bytes, &[], &mut pkey, btree::max_entry_size())?;
+        if !(self.while_cond)(&pkey)? {
+            return Ok(RowCheck::End);
+        }
+        let mut elems: Vec<_> = pkey.into_iter().map(|elem| elem.map(Cow::Owned)).collect();
+        for elem in format.elements(tuple_bytes) {
+            elems.push(elem?);
+        }
+        if let Some(expiry) = self.expiry {
+            if expiry.is_expired(&elems) {
+                return Ok(RowCheck::Skip);
+            }
+        }
+        if let Some(filter) = self.filter {
+            if !filter(&elems)? {
+                return Ok(RowCheck::Skip);
+            }
+        }
+        let tuple = elems
+            .into_iter()
+            .map(|elem| elem.map(Cow::into_owned))
+            .collect();
+        Ok(RowCheck::Emit(tuple))
+    }
 }
 
 impl<'a> Executor for ExecSeqScan<'a> {
     fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (pkey_bytes, tuple_bytes) = match self.table_iter.next(bufmgr)? {
-            Some(pair) => pair,
-            None => return Ok(None),
-        };
-        let mut pkey = vec![];
-        tuple::decode(&pkey_bytes, &mut pkey);
-        if !(self.while_cond)(&pkey) {
+        loop {
+            let (pkey_bytes, tuple_bytes) = match self.table_iter.next_raw(bufmgr)? {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            let format = self.table_iter.format();
+            match self.checks.check(format, &pkey_bytes, &tuple_bytes)? {
+                RowCheck::Emit(tuple) => return Ok(Some(tuple)),
+                RowCheck::Skip => continue,
+                RowCheck::End => return Ok(None),
+            }
+        }
+    }
+
+    /// Checks the rows of a leaf in the page, so only those emitted are
+    /// copied out of it.
+    fn next_batch(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_rows: usize,
+    ) -> Result<Vec<Tuple>> {
+        let format = self.table_iter.format();
+        let checks = &self.checks;
+        let mut batch = vec![];
+        let mut error = None;
+        let mut ended = false;
+        while !ended && batch.len() < max_rows {
+            let seen = self.table_iter.next_raw_in_leaf_with(
+                bufmgr,
+                max_rows - batch.len(),
+                |pkey_bytes, tuple_bytes| match checks.check(format, pkey_bytes, tuple_bytes) {
+                    Ok(RowCheck::Emit(tuple)) => {
+                        batch.push(tuple);
+                        true
+                    }
+                    Ok(RowCheck::Skip) => true,
+                    Ok(RowCheck::End) => {
+                        ended = true;
+                        false
+                    }
+                    Err(err) => {
+                        error = Some(err);
+                        false
+                    }
+                },
+            )?;
+            if let Some(err) = error.take() {
+                return Err(err);
+            }
+            ended |= seen == 0;
+        }
+        Ok(batch)
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.table_iter = TableIter::new(
+            bufmgr,
+            self.table_meta_page_id,
+            self.search_mode.clone(),
+            self.direction,
+        )?;
+        Ok(())
+    }
+}
+
+/// Counts the rows from `search_mode` on and emits the count as a single
+/// `tuple::encode_u64` column. It compares the pkeys in the pages, so
+/// unless `while_cond` or `expiry` is given it copies and decodes nothing.
+pub struct CountScan<'a> {
+    pub table_meta_page_id: PageId,
+    pub search_mode: TupleSearchMode,
+    /// See `SeqScan::while_bound`.
+    pub while_bound: Option<KeyBound>,
+    /// Decodes every pkey, so a key range is better given as `while_bound`.
+    pub while_cond: Option<Predicate<'a>>,
+    /// Leaves out expired rows, at the cost of decoding every row.
+    pub expiry: Option<Expiry>,
+}
+
+impl<'a> PlanNode for CountScan<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let format = table::btree_format(bufmgr, self.table_meta_page_id)?;
+        let while_bound = self.while_bound.as_ref().map(|bound| {
+            let mut key = vec![];
+            format.encode(bound.key.iter(), &mut key);
+            (key, bound)
+        });
+        Ok(Box::new(ExecCountScan {
+            btree: BTree::new(self.table_meta_page_id),
+            search_mode: self.search_mode.encode(format, &[]),
+            format,
+            while_bound,
+            while_cond: self.while_cond.as_ref(),
+            expiry: self.expiry,
+            done: false,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        let mut description = PlanDescription::new("CountScan")
+            .property("table", self.table_meta_page_id.to_u64())
+            .property("search_mode", self.search_mode.describe())
+            .property("while_bound", self.while_bound.is_some())
+            .property("while_cond", self.while_cond.is_some());
+        if let Some(expiry) = self.expiry {
+            description = description.property("expiry_column", expiry.column);
+        }
+        description
+    }
+}
+
+pub struct ExecCountScan<'a> {
+    btree: BTree,
+    search_mode: SearchMode,
+    format: Format,
+    /// The encoded key of `CountScan::while_bound`.
+    while_bound: Option<(Vec<u8>, &'a KeyBound)>,
+    while_cond: Option<&'a Predicate<'a>>,
+    expiry: Option<Expiry>,
+    done: bool,
+}
+
+impl<'a> ExecCountScan<'a> {
+    /// Whether the row counts, or `None` if the scan ends before it.
+    fn check(&self, pkey_bytes: &[u8], tuple_bytes: &[u8]) -> Result<Option<bool>> {
+        if let Some((key, bound)) = &self.while_bound {
+            if !(bound.accept)(self.format.cmp_encoded_prefix(pkey_bytes, key)?) {
+                return Ok(None);
+            }
+        }
+        if self.while_cond.is_none() && self.expiry.is_none() {
+            return Ok(Some(true));
+        }
+        let limit = btree::max_entry_size();
+        let mut tuple = vec![];
+        self.format
+            .decode_with_limit(pkey_bytes, &[], &mut tuple, limit)?;
+        if let Some(while_cond) = self.while_cond {
+            if !while_cond(&tuple)? {
+                return Ok(None);
+            }
+        }
+        match self.expiry {
+            Some(expiry) => {
+                self.format
+                    .decode_with_limit(tuple_bytes, &[], &mut tuple, limit)?;
+                Ok(Some(!expiry.is_expired(&tuple)))
+            }
+            None => Ok(Some(true)),
+        }
+    }
+}
+
+impl<'a> Executor for ExecCountScan<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        if self.done {
             return Ok(None);
         }
-        let mut tuple = pkey;
-        tuple::decode(&tuple_bytes, &mut tuple);
-        Ok(Some(tuple))
+        let mut iter = self.btree.search(bufmgr, self.search_mode.clone())?;
+        let mut count = 0u64;
+        while let Some(counts) = iter.next_with(bufmgr, |pkey, value| self.check(pkey, value))? {
+            match counts? {
+                Some(counts) => count += counts as u64,
+                None => break,
+            }
+        }
+        self.done = true;
+        Ok(Some(vec![Some(tuple::encode_u64(count).to_vec())]))
+    }
+
+    /// Counts the rows again on the next call to `next`.
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.done = false;
+        Ok(())
     }
 }
 
 pub struct Filter<'a> {
-    pub inner_plan: &'a dyn PlanNode,
-    pub cond: &'a dyn Fn(TupleSlice) -> bool,
+    pub inner_plan: BoxPlanNode<'a>,
+    pub cond: Predicate<'a>,
 }
 
 impl<'a> PlanNode for Filter<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
         let inner_iter = self.inner_plan.start(bufmgr)?;
         Ok(Box::new(ExecFilter {
             inner_iter,
-            cond: self.cond,
+            cond: &*self.cond,
         }))
     }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Filter").child(self.inner_plan.explain())
+    }
 }
 
 pub struct ExecFilter<'a> {
     inner_iter: BoxExecutor<'a>,
-    cond: &'a dyn Fn(TupleSlice) -> bool,
+    cond: &'a dyn Fn(TupleSlice) -> Result<bool>,
 }
 
 impl<'a> Executor for ExecFilter<'a> {
@@ -100,7 +695,7 @@ impl<'a> Executor for ExecFilter<'a> {
         loop {
             match self.inner_iter.next(bufmgr)? {
                 Some(tuple) => {
-                    if (self.cond)(&tuple) {
+                    if (self.cond)(&tuple)? {
                         return Ok(Some(tuple));
                     }
                 }
@@ -108,91 +703,1516 @@ impl<'a> Executor for ExecFilter<'a> {
             }
         }
     }
+
+    fn next_batch(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_rows: usize,
+    ) -> Result<Vec<Tuple>> {
+        let mut batch = vec![];
+        while batch.len() < max_rows {
+            let inner_batch = self.inner_iter.next_batch(bufmgr, max_rows - batch.len())?;
+            if inner_batch.is_empty() {
+                break;
+            }
+            for tuple in inner_batch {
+                if (self.cond)(&tuple)? {
+                    batch.push(tuple);
+                }
+            }
+        }
+        Ok(batch)
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.rewind(bufmgr)
+    }
 }
 
-pub struct IndexScan<'a> {
-    pub table_meta_page_id: PageId,
-    pub index_meta_page_id: PageId,
-    pub search_mode: TupleSearchMode<'a>,
-    pub while_cond: &'a dyn Fn(TupleSlice) -> bool,
+/// Emits the `columns` of each row of `inner_plan`, in that order.
+pub struct Project<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub columns: Vec<usize>,
 }
 
-impl<'a> PlanNode for IndexScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
-        let table_btree = BTree::new(self.table_meta_page_id);
-        let index_btree = BTree::new(self.index_meta_page_id);
-        let index_iter = index_btree.search(bufmgr, self.search_mode.encode())?;
-        Ok(Box::new(ExecIndexScan {
-            table_btree,
-            index_iter,
-            while_cond: self.while_cond,
+impl<'a> PlanNode for Project<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecProject {
+            inner_iter,
+            columns: &self.columns,
         }))
     }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Project")
+            .property("columns", format!("{:?}", self.columns))
+            .child(self.inner_plan.explain())
+    }
 }
 
-pub struct ExecIndexScan<'a> {
-    table_btree: BTree,
-    index_iter: btree::Iter,
-    while_cond: &'a dyn Fn(TupleSlice) -> bool,
+pub struct ExecProject<'a> {
+    inner_iter: BoxExecutor<'a>,
+    columns: &'a [usize],
 }
 
-impl<'a> Executor for ExecIndexScan<'a> {
+impl<'a> Executor for ExecProject<'a> {
     fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr)? {
-            Some(pair) => pair,
+        let row = match self.inner_iter.next(bufmgr)? {
+            Some(row) => row,
             None => return Ok(None),
         };
-        let mut skey = vec![];
-        tuple::decode(&skey_bytes, &mut skey);
-        if !(self.while_cond)(&skey) {
-            return Ok(None);
-        }
-        let mut table_iter = self
-            .table_btree
-            .search(bufmgr, SearchMode::Key(pkey_bytes))?;
-        let (pkey_bytes, tuple_bytes) = table_iter.next(bufmgr)?.unwrap();
-        let mut tuple = vec![];
-        tuple::decode(&pkey_bytes, &mut tuple);
-        tuple::decode(&tuple_bytes, &mut tuple);
-        Ok(Some(tuple))
+        let projected = self
+            .columns
+            .iter()
+            .map(|&column| {
+                row.get(column)
+                    .cloned()
+                    .ok_or(expr::Error::ColumnOutOfRange {
+                        column,
+                        len: row.len(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(projected))
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.rewind(bufmgr)
     }
 }
 
-pub struct IndexOnlyScan<'a> {
-    pub index_meta_page_id: PageId,
-    pub search_mode: TupleSearchMode<'a>,
-    pub while_cond: &'a dyn Fn(TupleSlice) -> bool,
+/// Evaluates `exprs` on each row of `inner_plan`, one output column each.
+pub struct Map<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub exprs: Vec<expr::BytesExpr>,
 }
 
-impl<'a> PlanNode for IndexOnlyScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
-        let btree = BTree::new(self.index_meta_page_id);
-        let index_iter = btree.search(bufmgr, self.search_mode.encode())?;
-        Ok(Box::new(ExecIndexOnlyScan {
-            index_iter,
-            while_cond: self.while_cond,
+impl<'a> PlanNode for Map<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecMap {
+            inner_iter,
+            exprs: &self.exprs,
         }))
     }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Map")
+            .property("exprs", format!("{:?}", self.exprs))
+            .child(self.inner_plan.explain())
+    }
 }
 
-pub struct ExecIndexOnlyScan<'a> {
-    index_iter: btree::Iter,
-    while_cond: &'a dyn Fn(TupleSlice) -> bool,
+pub struct ExecMap<'a> {
+    inner_iter: BoxExecutor<'a>,
+    exprs: &'a [expr::BytesExpr],
 }
 
-impl<'a> Executor for ExecIndexOnlyScan<'a> {
+impl<'a> Executor for ExecMap<'a> {
     fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr)? {
-            Some(pair) => pair,
+        let row = match self.inner_iter.next(bufmgr)? {
+            Some(row) => row,
             None => return Ok(None),
         };
-        let mut skey = vec![];
-        tuple::decode(&skey_bytes, &mut skey);
-        if !(self.while_cond)(&skey) {
-            return Ok(None);
+        let mapped = self
+            .exprs
+            .iter()
+            .map(|expr| Ok(expr.eval(&row)?.map(Cow::into_owned)))
+            .collect::<Result<_, expr::Error>>()?;
+        Ok(Some(mapped))
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.rewind(bufmgr)
+    }
+}
+
+/// Appends to each row of `inner_plan` its 1-based number, as a decimal
+/// string, among the rows with the same `partition_by` columns. A
+/// partition ends where those columns change, so the input must already
+/// be sorted by them; without any, every row is in one partition.
+pub struct RowNumber<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub partition_by: Vec<usize>,
+}
+
+impl<'a> PlanNode for RowNumber<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecRowNumber {
+            inner_iter,
+            partition_by: &self.partition_by,
+            partition: None,
+            row_number: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("RowNumber")
+            .property("partition_by", format!("{:?}", self.partition_by))
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecRowNumber<'a> {
+    inner_iter: BoxExecutor<'a>,
+    partition_by: &'a [usize],
+    /// The `partition_by` columns of the last row.
+    partition: Option<Tuple>,
+    row_number: u64,
+}
+
+impl<'a> Executor for ExecRowNumber<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let mut row = match self.inner_iter.next(bufmgr)? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let partition = self
+            .partition_by
+            .iter()
+            .map(|&column| {
+                row.get(column)
+                    .cloned()
+                    .ok_or(expr::Error::ColumnOutOfRange {
+                        column,
+                        len: row.len(),
+                    })
+            })
+            .collect::<Result<Tuple, _>>()?;
+        if self.partition.as_ref() == Some(&partition) {
+            self.row_number += 1;
+        } else {
+            self.partition = Some(partition);
+            self.row_number = 1;
         }
-        let mut tuple = skey;
-        tuple::decode(&pkey_bytes, &mut tuple);
-        Ok(Some(tuple))
+        row.push(Some(self.row_number.to_string().into_bytes()));
+        Ok(Some(row))
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.rewind(bufmgr)?;
+        self.partition = None;
+        self.row_number = 0;
+        Ok(())
+    }
+}
+
+/// Deletes the rows of `table` that `inner_plan` yields, with
+/// `Table::delete`, and emits the number deleted as a single
+/// `tuple::encode_u64` column. It reads all of the pkeys before deleting
+/// any, so the deletes do not change the tree under the scan. A row
+/// already deleted by a cascade from an earlier one is not counted.
+pub struct DeleteWhere<'a> {
+    pub table: Table,
+    pub inner_plan: BoxPlanNode<'a>,
+}
+
+impl<'a> PlanNode for DeleteWhere<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecDeleteWhere {
+            table: &self.table,
+            inner_iter: Some(inner_iter),
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("DeleteWhere")
+            .property("table", &self.table.name)
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecDeleteWhere<'a> {
+    table: &'a Table,
+    /// Dropped once its rows are read, so it holds no page while deleting.
+    inner_iter: Option<BoxExecutor<'a>>,
+}
+
+impl<'a> ExecDeleteWhere<'a> {
+    fn collect_pkeys(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        inner_iter: &mut BoxExecutor<'a>,
+    ) -> Result<Vec<Tuple>> {
+        let mut pkeys = vec![];
+        while let Some(mut row) = inner_iter.next(bufmgr)? {
+            self.table.pad(&mut row)?;
+            row.truncate(self.table.num_key_elems);
+            pkeys.push(row);
+        }
+        Ok(pkeys)
+    }
+}
+
+impl<'a> Executor for ExecDeleteWhere<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let mut inner_iter = match self.inner_iter.take() {
+            Some(inner_iter) => inner_iter,
+            None => return Ok(None),
+        };
+        let pkeys = self.collect_pkeys(bufmgr, &mut inner_iter)?;
+        drop(inner_iter);
+        let referencing_tables = self.table.referencing_tables(bufmgr)?;
+        let mut num_deleted = 0;
+        for pkey in pkeys {
+            let pkey_elems = pkey
+                .iter()
+                .enumerate()
+                .map(|(column, elem)| elem.as_deref().ok_or(table::Error::NullKey(column)))
+                .collect::<Result<Vec<_>, _>>()?;
+            match self
+                .table
+                .delete_with(bufmgr, &pkey_elems, &referencing_tables)
+            {
+                Ok(()) => num_deleted += 1,
+                Err(crate::Error::Table(table::Error::RowNotFound)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Some(vec![Some(tuple::encode_u64(num_deleted).to_vec())]))
+    }
+}
+
+/// Sets the columns of the rows of `table` that `inner_plan` yields to the
+/// `set` expressions, each evaluated on the row as it was, and emits the
+/// number of rows updated as a single `tuple::encode_u64` column. Like
+/// `DeleteWhere`, it reads all of the rows before updating any. The pkey
+/// columns cannot be set.
+pub struct UpdateWhere<'a> {
+    pub table: Table,
+    pub inner_plan: BoxPlanNode<'a>,
+    pub set: Vec<(usize, expr::BytesExpr)>,
+}
+
+impl<'a> PlanNode for UpdateWhere<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        for &(column, _) in &self.set {
+            if column < self.table.num_key_elems {
+                return Err(Error::SetKeyColumn(column).into());
+            }
+            if column >= self.table.num_columns {
+                return Err(expr::Error::ColumnOutOfRange {
+                    column,
+                    len: self.table.num_columns,
+                }
+                .into());
+            }
+        }
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecUpdateWhere {
+            table: &self.table,
+            set: &self.set,
+            inner_iter: Some(inner_iter),
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("UpdateWhere")
+            .property("table", &self.table.name)
+            .property("set", format!("{:?}", self.set))
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecUpdateWhere<'a> {
+    table: &'a Table,
+    set: &'a [(usize, expr::BytesExpr)],
+    /// Dropped once its rows are read, so it holds no page while updating.
+    inner_iter: Option<BoxExecutor<'a>>,
+}
+
+impl<'a> ExecUpdateWhere<'a> {
+    /// The rows to write, with the `set` columns replaced.
+    fn collect_rows(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        inner_iter: &mut BoxExecutor<'a>,
+    ) -> Result<Vec<Tuple>> {
+        let mut rows = vec![];
+        while let Some(mut row) = inner_iter.next(bufmgr)? {
+            self.table.pad(&mut row)?;
+            let mut new_row = row.clone();
+            for (column, expr) in self.set {
+                new_row[*column] = expr.eval(&row)?.map(Cow::into_owned);
+            }
+            rows.push(new_row);
+        }
+        Ok(rows)
+    }
+}
+
+impl<'a> Executor for ExecUpdateWhere<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let mut inner_iter = match self.inner_iter.take() {
+            Some(inner_iter) => inner_iter,
+            None => return Ok(None),
+        };
+        let rows = self.collect_rows(bufmgr, &mut inner_iter)?;
+        drop(inner_iter);
+        for row in &rows {
+            let record: Vec<_> = row.iter().map(Option::as_deref).collect();
+            self.table.update_nullable(bufmgr, &record)?;
+        }
+        Ok(Some(vec![Some(
+            tuple::encode_u64(rows.len() as u64).to_vec(),
+        )]))
+    }
+}
+
+/// Reads all of the rows of `inner_plan` into memory on the first call to
+/// `next`, then serves `next` and `rewind` from them, so a plan that is
+/// read many times, like the inner plan of a `NestedLoopJoin`, runs once.
+pub struct Materialize<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+}
+
+impl<'a> PlanNode for Materialize<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecMaterialize {
+            inner_iter,
+            rows: None,
+            error: None,
+            pos: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Materialize").child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecMaterialize<'a> {
+    inner_iter: BoxExecutor<'a>,
+    rows: Option<Vec<Tuple>>,
+    /// The error that ended reading the input, raised again by every later
+    /// call rather than serving the rows read before it.
+    error: Option<String>,
+    pos: usize,
+}
+
+impl<'a> ExecMaterialize<'a> {
+    fn drain(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>> {
+        let mut rows = vec![];
+        while let Some(row) = self.inner_iter.next(bufmgr)? {
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+}
+
+impl<'a> Executor for ExecMaterialize<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        if let Some(error) = &self.error {
+            return Err(Error::MaterializeFailed(error.clone()).into());
+        }
+        let rows = match &self.rows {
+            Some(rows) => rows,
+            None => match self.drain(bufmgr) {
+                Ok(rows) => self.rows.insert(rows),
+                Err(err) => {
+                    self.error = Some(format!("{:#}", err));
+                    return Err(err);
+                }
+            },
+        };
+        let row = rows.get(self.pos).cloned();
+        self.pos += 1;
+        Ok(row)
+    }
+
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+/// Emits each outer row followed by each inner row it matches, in the
+/// order of the outer plan. The inner plan is rewound for every outer row,
+/// so it must support `Executor::rewind`; a `Materialize` over it makes
+/// that cheap.
+pub struct NestedLoopJoin<'a> {
+    pub outer_plan: BoxPlanNode<'a>,
+    pub inner_plan: BoxPlanNode<'a>,
+    pub cond: JoinPredicate<'a>,
+}
+
+impl<'a> PlanNode for NestedLoopJoin<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let outer_iter = self.outer_plan.start(bufmgr)?;
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecNestedLoopJoin {
+            outer_iter,
+            inner_iter,
+            cond: &*self.cond,
+            outer: None,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("NestedLoopJoin")
+            .property("strategy", "nested loop")
+            .child(self.outer_plan.explain())
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecNestedLoopJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    inner_iter: BoxExecutor<'a>,
+    cond: &'a dyn Fn(TupleSlice, TupleSlice) -> Result<bool>,
+    /// The outer row being joined.
+    outer: Option<Tuple>,
+}
+
+impl<'a> Executor for ExecNestedLoopJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let outer = match &self.outer {
+                Some(outer) => outer,
+                None => match self.outer_iter.next(bufmgr)? {
+                    Some(outer) => self.outer.insert(outer),
+                    None => return Ok(None),
+                },
+            };
+            match self.inner_iter.next(bufmgr)? {
+                Some(inner) => {
+                    if (self.cond)(outer, &inner)? {
+                        let mut tuple = outer.clone();
+                        tuple.extend(inner);
+                        return Ok(Some(tuple));
+                    }
+                }
+                None => {
+                    self.inner_iter.rewind(bufmgr)?;
+                    self.outer = None;
+                }
+            }
+        }
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.rewind(bufmgr)?;
+        self.inner_iter.rewind(bufmgr)?;
+        self.outer = None;
+        Ok(())
+    }
+}
+
+/// Emits each outer row followed by each inner row whose `inner_key`
+/// columns equal its `outer_key` columns and that `cond` accepts, in the
+/// order of the outer plan and then of the inner plan. The inner rows are
+/// read once into a hash table; NULL key columns match nothing.
+pub struct HashJoin<'a> {
+    pub outer_plan: BoxPlanNode<'a>,
+    pub inner_plan: BoxPlanNode<'a>,
+    pub outer_key: Vec<usize>,
+    pub inner_key: Vec<usize>,
+    pub cond: JoinPredicate<'a>,
+}
+
+impl<'a> PlanNode for HashJoin<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let mut inner_iter = self.inner_plan.start(bufmgr)?;
+        let mut table: HashMap<Tuple, Vec<Tuple>> = HashMap::new();
+        while let Some(inner) = inner_iter.next(bufmgr)? {
+            if let Some(key) = join_key(&inner, &self.inner_key)? {
+                table.entry(key).or_default().push(inner);
+            }
+        }
+        let outer_iter = self.outer_plan.start(bufmgr)?;
+        Ok(Box::new(ExecHashJoin {
+            outer_iter,
+            outer_key: &self.outer_key,
+            cond: &*self.cond,
+            table,
+            outer: None,
+            pos: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("HashJoin")
+            .property("strategy", "hash")
+            .property("outer_key", format!("{:?}", self.outer_key))
+            .property("inner_key", format!("{:?}", self.inner_key))
+            .child(self.outer_plan.explain())
+            .child(self.inner_plan.explain())
+    }
+}
+
+/// The `columns` of `row`, or `None` if one of them is NULL.
+fn join_key(row: TupleSlice, columns: &[usize]) -> Result<Option<Tuple>, expr::Error> {
+    let mut key = vec![];
+    for &column in columns {
+        match row.get(column) {
+            Some(Some(elem)) => key.push(Some(elem.clone())),
+            Some(None) => return Ok(None),
+            None => {
+                return Err(expr::Error::ColumnOutOfRange {
+                    column,
+                    len: row.len(),
+                })
+            }
+        }
+    }
+    Ok(Some(key))
+}
+
+pub struct ExecHashJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    outer_key: &'a [usize],
+    cond: &'a dyn Fn(TupleSlice, TupleSlice) -> Result<bool>,
+    table: HashMap<Tuple, Vec<Tuple>>,
+    /// The outer row being joined and its key.
+    outer: Option<(Tuple, Option<Tuple>)>,
+    /// The next of the inner rows with the outer row's key.
+    pos: usize,
+}
+
+impl<'a> Executor for ExecHashJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            if self.outer.is_none() {
+                let outer = match self.outer_iter.next(bufmgr)? {
+                    Some(outer) => outer,
+                    None => return Ok(None),
+                };
+                let key = join_key(&outer, self.outer_key)?;
+                self.outer = Some((outer, key));
+                self.pos = 0;
+            }
+            let (outer, key) = self.outer.as_ref().unwrap();
+            let table = &self.table;
+            let matches = key.as_ref().and_then(|key| table.get(key));
+            match matches.and_then(|matches| matches.get(self.pos)) {
+                Some(inner) => {
+                    self.pos += 1;
+                    if (self.cond)(outer, inner)? {
+                        let mut tuple = outer.clone();
+                        tuple.extend(inner.iter().cloned());
+                        return Ok(Some(tuple));
+                    }
+                }
+                None => self.outer = None,
+            }
+        }
+    }
+
+    /// Rewinds the outer input only; the hash table is kept.
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.rewind(bufmgr)?;
+        self.outer = None;
+        Ok(())
+    }
+}
+
+/// What `IndexJoin` does with an outer row that has no inner row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Unmatched {
+    #[default]
+    Skip,
+    /// Emits the outer row followed by this many NULLs, as in a left outer
+    /// join.
+    PadNull(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+}
+
+/// Joins each outer row to the inner table row whose pkey is made of the
+/// `key_columns` of the outer row, looking it up by exact match.
+pub struct IndexJoin<'a> {
+    pub outer_plan: BoxPlanNode<'a>,
+    pub inner_table_meta_page_id: PageId,
+    pub key_columns: Vec<usize>,
+    pub unmatched: Unmatched,
+    /// Treats expired inner rows as missing.
+    pub inner_expiry: Option<Expiry>,
+}
+
+impl<'a> PlanNode for IndexJoin<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner = PkeyLookup::new(
+            bufmgr,
+            self.inner_table_meta_page_id,
+            &self.key_columns,
+            self.inner_expiry,
+        )?;
+        let outer_iter = self.outer_plan.start(bufmgr)?;
+        Ok(Box::new(ExecIndexJoin {
+            outer_iter,
+            inner,
+            unmatched: self.unmatched,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("IndexJoin")
+            .property("strategy", "pkey lookup")
+            .property("inner_table", self.inner_table_meta_page_id.to_u64())
+            .property("key_columns", format!("{:?}", self.key_columns))
+            .property("unmatched", format!("{:?}", self.unmatched))
+            .child(self.outer_plan.explain())
+    }
+}
+
+/// Looks up the inner table row whose pkey is made of the `key_columns` of
+/// an outer row, for `IndexJoin` and `SemiJoin`.
+struct PkeyLookup<'a> {
+    btree: BTree,
+    format: Format,
+    key_columns: &'a [usize],
+    /// Treats expired rows as missing.
+    expiry: Option<Expiry>,
+}
+
+impl<'a> PkeyLookup<'a> {
+    fn new(
+        bufmgr: &mut BufferPoolManager,
+        meta_page_id: PageId,
+        key_columns: &'a [usize],
+        expiry: Option<Expiry>,
+    ) -> Result<Self> {
+        Ok(Self {
+            btree: BTree::new(meta_page_id),
+            format: table::btree_format(bufmgr, meta_page_id)?,
+            key_columns,
+            expiry,
+        })
+    }
+
+    fn lookup(&self, bufmgr: &mut BufferPoolManager, outer: TupleSlice) -> Result<Option<Tuple>> {
+        let key = match self.key(outer)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let value = match self.btree.get(bufmgr, &key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut inner = vec![];
+        let limit = btree::max_entry_size();
+        self.format
+            .decode_with_limit(&key, &[], &mut inner, limit)?;
+        self.format
+            .decode_with_limit(&value, &[], &mut inner, limit)?;
+        match self.expiry {
+            Some(expiry) if expiry.is_expired(&inner) => Ok(None),
+            _ => Ok(Some(inner)),
+        }
+    }
+
+    /// Whether `lookup` would find a row, without decoding it unless it
+    /// may have expired.
+    fn exists(&self, bufmgr: &mut BufferPoolManager, outer: TupleSlice) -> Result<bool> {
+        if self.expiry.is_some() {
+            return Ok(self.lookup(bufmgr, outer)?.is_some());
+        }
+        match self.key(outer)? {
+            Some(key) => Ok(self.btree.get(bufmgr, &key)?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    /// The encoded pkey to look up, or `None` if a key column is NULL,
+    /// which never equals a pkey element.
+    fn key(&self, outer: TupleSlice) -> Result<Option<Vec<u8>>, expr::Error> {
+        let mut key_elems = vec![];
+        for &column in self.key_columns {
+            match outer.get(column) {
+                Some(Some(elem)) => key_elems.push(elem),
+                Some(None) => return Ok(None),
+                None => {
+                    return Err(expr::Error::ColumnOutOfRange {
+                        column,
+                        len: outer.len(),
+                    })
+                }
+            }
+        }
+        let mut key = vec![];
+        self.format.encode(key_elems.into_iter(), &mut key);
+        Ok(Some(key))
+    }
+}
+
+pub struct ExecIndexJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    inner: PkeyLookup<'a>,
+    unmatched: Unmatched,
+}
+
+impl<'a> Executor for ExecIndexJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let mut outer = match self.outer_iter.next(bufmgr)? {
+                Some(outer) => outer,
+                None => return Ok(None),
+            };
+            match (self.inner.lookup(bufmgr, &outer)?, self.unmatched) {
+                (Some(inner), _) => outer.extend(inner),
+                (None, Unmatched::Skip) => continue,
+                (None, Unmatched::PadNull(n)) => outer.resize(outer.len() + n, None),
+            }
+            return Ok(Some(outer));
+        }
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.rewind(bufmgr)
+    }
+}
+
+/// Emits each outer row that has an inner table row whose pkey is made of
+/// its `key_columns`, once and unchanged, or with `negated` each that has
+/// none. An outer row with a NULL key column has no match.
+pub struct SemiJoin<'a> {
+    pub outer_plan: BoxPlanNode<'a>,
+    pub inner_table_meta_page_id: PageId,
+    pub key_columns: Vec<usize>,
+    pub negated: bool,
+    /// Treats expired inner rows as missing.
+    pub inner_expiry: Option<Expiry>,
+}
+
+impl<'a> PlanNode for SemiJoin<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner = PkeyLookup::new(
+            bufmgr,
+            self.inner_table_meta_page_id,
+            &self.key_columns,
+            self.inner_expiry,
+        )?;
+        let outer_iter = self.outer_plan.start(bufmgr)?;
+        Ok(Box::new(ExecSemiJoin {
+            outer_iter,
+            inner,
+            negated: self.negated,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        let name = if self.negated { "AntiJoin" } else { "SemiJoin" };
+        PlanDescription::new(name)
+            .property("strategy", "pkey lookup")
+            .property("inner_table", self.inner_table_meta_page_id.to_u64())
+            .property("key_columns", format!("{:?}", self.key_columns))
+            .child(self.outer_plan.explain())
+    }
+}
+
+pub struct ExecSemiJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    inner: PkeyLookup<'a>,
+    negated: bool,
+}
+
+impl<'a> Executor for ExecSemiJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let outer = match self.outer_iter.next(bufmgr)? {
+                Some(outer) => outer,
+                None => return Ok(None),
+            };
+            if self.inner.exists(bufmgr, &outer)? != self.negated {
+                return Ok(Some(outer));
+            }
+        }
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.rewind(bufmgr)
+    }
+}
+
+/// An aggregate over a column. Each ignores NULLs and gives NULL if there
+/// is nothing else, except the counts, which give a `tuple::encode_u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AggSpec {
+    Count(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+    CountDistinct(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+    /// Of `tuple::encode_i64` values, giving one.
+    Sum(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+    /// In byte order, which is value order for the `tuple::encode_*` types.
+    Min(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+    Max(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::column))] usize),
+}
+
+enum Acc {
+    Count(u64),
+    CountDistinct(HashSet<Vec<u8>>),
+    Sum(Option<i64>),
+    Min(Option<Vec<u8>>),
+    Max(Option<Vec<u8>>),
+}
+
+impl AggSpec {
+    fn column(self) -> usize {
+        match self {
+            AggSpec::Count(column)
+            | AggSpec::CountDistinct(column)
+            | AggSpec::Sum(column)
+            | AggSpec::Min(column)
+            | AggSpec::Max(column) => column,
+        }
+    }
+
+    fn init(self) -> Acc {
+        match self {
+            AggSpec::Count(_) => Acc::Count(0),
+            AggSpec::CountDistinct(_) => Acc::CountDistinct(HashSet::new()),
+            AggSpec::Sum(_) => Acc::Sum(None),
+            AggSpec::Min(_) => Acc::Min(None),
+            AggSpec::Max(_) => Acc::Max(None),
+        }
+    }
+}
+
+impl Acc {
+    fn add(&mut self, elem: &[u8]) -> Result<()> {
+        match self {
+            Acc::Count(count) => *count += 1,
+            Acc::CountDistinct(seen) => {
+                if !seen.contains(elem) {
+                    seen.insert(elem.to_vec());
+                }
+            }
+            Acc::Sum(sum) => {
+                let n = match tuple::decode_value(ColumnType::I64, Some(elem))? {
+                    Value::I64(n) => n,
+                    _ => unreachable!(),
+                };
+                let total = sum.unwrap_or(0).checked_add(n);
+                *sum = Some(total.ok_or(Error::SumOverflow)?);
+            }
+            Acc::Min(min) => {
+                if min.as_deref().is_none_or(|min| elem < min) {
+                    *min = Some(elem.to_vec());
+                }
+            }
+            Acc::Max(max) => {
+                if max.as_deref().is_none_or(|max| elem > max) {
+                    *max = Some(elem.to_vec());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<Vec<u8>> {
+        match self {
+            Acc::Count(count) => Some(tuple::encode_u64(count).to_vec()),
+            Acc::CountDistinct(seen) => Some(tuple::encode_u64(seen.len() as u64).to_vec()),
+            Acc::Sum(sum) => sum.map(|sum| tuple::encode_i64(sum).to_vec()),
+            Acc::Min(elem) | Acc::Max(elem) => elem,
+        }
+    }
+}
+
+/// Emits a row per distinct value of the `group_by` columns, in the order
+/// they first appear: the group columns followed by the `aggs`. Without
+/// `group_by` there is a single group, which is emitted even if there are
+/// no rows.
+pub struct Aggregate<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub group_by: Vec<usize>,
+    pub aggs: Vec<AggSpec>,
+}
+
+impl<'a> PlanNode for Aggregate<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecAggregate {
+            inner_iter,
+            group_by: &self.group_by,
+            aggs: &self.aggs,
+            groups: None,
+            pos: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Aggregate")
+            .property("group_by", format!("{:?}", self.group_by))
+            .property("aggs", format!("{:?}", self.aggs))
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecAggregate<'a> {
+    inner_iter: BoxExecutor<'a>,
+    group_by: &'a [usize],
+    aggs: &'a [AggSpec],
+    /// Filled from the whole input on the first call to `next`.
+    groups: Option<Vec<Tuple>>,
+    pos: usize,
+}
+
+impl<'a> ExecAggregate<'a> {
+    fn aggregate(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>> {
+        let mut index = HashMap::new();
+        let mut groups: Vec<(Tuple, Vec<Acc>)> = vec![];
+        if self.group_by.is_empty() {
+            index.insert(vec![], 0);
+            groups.push((vec![], self.aggs.iter().map(|agg| agg.init()).collect()));
+        }
+        while let Some(tuple) = self.inner_iter.next(bufmgr)? {
+            let key: Tuple = self.group_by.iter().map(|&i| tuple[i].clone()).collect();
+            let i = match index.get(&key) {
+                Some(&i) => i,
+                None => {
+                    index.insert(key.clone(), groups.len());
+                    groups.push((key, self.aggs.iter().map(|agg| agg.init()).collect()));
+                    groups.len() - 1
+                }
+            };
+            for (agg, acc) in self.aggs.iter().zip(&mut groups[i].1) {
+                if let Some(elem) = &tuple[agg.column()] {
+                    acc.add(elem)?;
+                }
+            }
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(mut key, accs)| {
+                key.extend(accs.into_iter().map(Acc::finish));
+                key
+            })
+            .collect())
+    }
+}
+
+impl<'a> Executor for ExecAggregate<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let groups = match &self.groups {
+            Some(groups) => groups,
+            None => {
+                let groups = self.aggregate(bufmgr)?;
+                self.groups.insert(groups)
+            }
+        };
+        let group = groups.get(self.pos).cloned();
+        self.pos += 1;
+        Ok(group)
+    }
+
+    /// Emits the groups again without reading the input again.
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+/// Emits the `k` rows of `inner_plan` that come first in the order of the
+/// `sort_key` columns, in that order; rows that tie keep their input order.
+/// Only `k` rows are held in memory at a time, so a large `k` costs as
+/// much as sorting the whole input.
+pub struct TopK<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub sort_key: Vec<(usize, Direction)>,
+    pub k: usize,
+}
+
+impl<'a> PlanNode for TopK<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecTopK {
+            inner_iter,
+            sort_key: &self.sort_key,
+            k: self.k,
+            rows: None,
+            pos: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("TopK")
+            .property("sort_key", format!("{:?}", self.sort_key))
+            .property("k", self.k.to_string())
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecTopK<'a> {
+    inner_iter: BoxExecutor<'a>,
+    sort_key: &'a [(usize, Direction)],
+    k: usize,
+    /// Filled from the whole input on the first call to `next`.
+    rows: Option<Vec<Tuple>>,
+    pos: usize,
+}
+
+/// Encodes the `sort_key` columns of `row` so that the encodings compare
+/// in the order of the rows.
+fn encode_sort_key(
+    row: &[Option<Vec<u8>>],
+    sort_key: &[(usize, Direction)],
+    directions: &[Direction],
+) -> Result<Vec<u8>, expr::Error> {
+    let key_elems = sort_key
+        .iter()
+        .map(|&(column, _)| {
+            row.get(column).ok_or(expr::Error::ColumnOutOfRange {
+                column,
+                len: row.len(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut key = vec![];
+    tuple::encode_directed(key_elems.into_iter(), directions, &mut key);
+    Ok(key)
+}
+
+fn sort_directions(sort_key: &[(usize, Direction)]) -> Vec<Direction> {
+    sort_key.iter().map(|&(_, direction)| direction).collect()
+}
+
+impl<'a> ExecTopK<'a> {
+    fn select(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>> {
+        let directions = sort_directions(self.sort_key);
+        // a max-heap of the best rows so far, so the worst one is popped
+        // first; the sequence number makes a later row lose a tie
+        let mut heap = BinaryHeap::new();
+        let mut seq = 0u64;
+        while let Some(row) = self.inner_iter.next(bufmgr)? {
+            let key = encode_sort_key(&row, self.sort_key, &directions)?;
+            heap.push((key, seq, row));
+            seq += 1;
+            if heap.len() > self.k {
+                heap.pop();
+            }
+        }
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, _, row)| row)
+            .collect())
+    }
+}
+
+impl<'a> Executor for ExecTopK<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let rows = match &self.rows {
+            Some(rows) => rows,
+            None => {
+                let rows = self.select(bufmgr)?;
+                self.rows.insert(rows)
+            }
+        };
+        let row = rows.get(self.pos).cloned();
+        self.pos += 1;
+        Ok(row)
+    }
+
+    /// Emits the rows again without reading the input again.
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+/// Emits all the rows of `inner_plan` in the order of the `sort_key`
+/// columns; rows that tie keep their input order. The whole input is held
+/// in memory.
+pub struct Sort<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub sort_key: Vec<(usize, Direction)>,
+}
+
+impl<'a> PlanNode for Sort<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecSort {
+            inner_iter,
+            sort_key: &self.sort_key,
+            rows: None,
+            pos: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Sort")
+            .property("sort_key", format!("{:?}", self.sort_key))
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecSort<'a> {
+    inner_iter: BoxExecutor<'a>,
+    sort_key: &'a [(usize, Direction)],
+    /// Filled from the whole input on the first call to `next`.
+    rows: Option<Vec<Tuple>>,
+    pos: usize,
+}
+
+impl<'a> ExecSort<'a> {
+    fn sort(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>> {
+        let directions = sort_directions(self.sort_key);
+        let mut keyed = vec![];
+        while let Some(row) = self.inner_iter.next(bufmgr)? {
+            keyed.push((encode_sort_key(&row, self.sort_key, &directions)?, row));
+        }
+        // stable, so ties keep their input order
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keyed.into_iter().map(|(_, row)| row).collect())
+    }
+}
+
+impl<'a> Executor for ExecSort<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let rows = match &self.rows {
+            Some(rows) => rows,
+            None => {
+                let rows = self.sort(bufmgr)?;
+                self.rows.insert(rows)
+            }
+        };
+        let row = rows.get(self.pos).cloned();
+        self.pos += 1;
+        Ok(row)
+    }
+
+    /// Emits the rows again without reading the input again.
+    fn rewind(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+/// Skips the first `offset` rows of `inner_plan` and emits at most `limit`
+/// of the rest. It stops reading the input once it has emitted them.
+pub struct Limit<'a> {
+    pub inner_plan: BoxPlanNode<'a>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl<'a> PlanNode for Limit<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(bufmgr)?;
+        Ok(Box::new(ExecLimit {
+            inner_iter,
+            limit: self.limit,
+            offset: self.offset,
+            skipped: 0,
+            emitted: 0,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("Limit")
+            .property("limit", self.limit.to_string())
+            .property("offset", self.offset.to_string())
+            .child(self.inner_plan.explain())
+    }
+}
+
+pub struct ExecLimit<'a> {
+    inner_iter: BoxExecutor<'a>,
+    limit: usize,
+    offset: usize,
+    skipped: usize,
+    emitted: usize,
+}
+
+impl<'a> Executor for ExecLimit<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        if self.emitted >= self.limit {
+            return Ok(None);
+        }
+        while self.skipped < self.offset {
+            if self.inner_iter.next(bufmgr)?.is_none() {
+                return Ok(None);
+            }
+            self.skipped += 1;
+        }
+        let row = self.inner_iter.next(bufmgr)?;
+        if row.is_some() {
+            self.emitted += 1;
+        }
+        Ok(row)
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.rewind(bufmgr)?;
+        self.skipped = 0;
+        self.emitted = 0;
+        Ok(())
+    }
+}
+
+/// The upper bound of an index scan, or the lower bound of a backward one.
+/// It applies to the leading skey elements, so an exclusive end of `["m"]`
+/// stops at the first skey that starts with "m".
+#[derive(Debug, Clone)]
+pub struct RangeEnd {
+    pub key: Tuple,
+    pub inclusive: bool,
+}
+
+impl RangeEnd {
+    fn encode(
+        &self,
+        format: Format,
+        directions: &[Direction],
+        scan_direction: ScanDirection,
+    ) -> EncodedEnd {
+        let mut key = vec![];
+        format.encode_directed(self.key.iter(), directions, &mut key);
+        EncodedEnd {
+            key,
+            inclusive: self.inclusive,
+            scan_direction,
+        }
+    }
+}
+
+fn describe_end(end: &Option<RangeEnd>, direction: ScanDirection) -> String {
+    let end = match end {
+        Some(end) => end,
+        None => return "None".to_string(),
+    };
+    let op = match (direction, end.inclusive) {
+        (ScanDirection::Forward, true) => "<=",
+        (ScanDirection::Forward, false) => "<",
+        (ScanDirection::Backward, true) => ">=",
+        (ScanDirection::Backward, false) => ">",
+    };
+    format!("{} {:?}", op, tuple::Pretty(&end.key))
+}
+
+struct EncodedEnd {
+    key: Vec<u8>,
+    inclusive: bool,
+    scan_direction: ScanDirection,
+}
+
+impl EncodedEnd {
+    fn is_past(&self, format: Format, skey: &[u8], directions: &[Direction]) -> Result<bool> {
+        let ordering = format.cmp_encoded_prefix_directed(skey, &self.key, directions)?;
+        let ordering = match self.scan_direction {
+            ScanDirection::Forward => ordering,
+            ScanDirection::Backward => ordering.reverse(),
+        };
+        Ok(ordering.is_gt() || ordering.is_eq() && !self.inclusive)
+    }
+}
+
+pub struct IndexScan<'a> {
+    pub table_meta_page_id: PageId,
+    pub index_meta_page_id: PageId,
+    /// Sort directions of the index's skey columns.
+    pub directions: Vec<Direction>,
+    /// Number of included columns stored after the pkey in each entry.
+    pub num_included: usize,
+    pub search_mode: TupleSearchMode,
+    pub direction: ScanDirection,
+    /// Where to stop, checked against the encoded skey before anything is
+    /// decoded; see `RangeEnd`.
+    pub end: Option<RangeEnd>,
+    pub while_cond: Predicate<'a>,
+    /// Hides expired rows of a table with an expiry column.
+    pub expiry: Option<Expiry>,
+}
+
+impl<'a> PlanNode for IndexScan<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let format = table::btree_format(bufmgr, self.index_meta_page_id)?;
+        let table_btree = BTree::new(self.table_meta_page_id);
+        let index_btree = BTree::new(self.index_meta_page_id);
+        let search_mode = self.search_mode.encode(format, &self.directions);
+        let index_iter =
+            index_btree.search_directed(bufmgr, search_mode.clone(), self.direction)?;
+        Ok(Box::new(ExecIndexScan {
+            end: self
+                .end
+                .as_ref()
+                .map(|end| end.encode(format, &self.directions, self.direction)),
+            table_btree,
+            index_btree,
+            search_mode,
+            direction: self.direction,
+            index_iter,
+            format,
+            directions: &self.directions,
+            num_included: self.num_included,
+            while_cond: &*self.while_cond,
+            expiry: self.expiry,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("IndexScan")
+            .property("table", self.table_meta_page_id.to_u64())
+            .property("index", self.index_meta_page_id.to_u64())
+            .property("directions", format!("{:?}", self.directions))
+            .property("search_mode", self.search_mode.describe())
+            .property("direction", format!("{:?}", self.direction))
+            .property("end", describe_end(&self.end, self.direction))
+    }
+}
+
+pub struct ExecIndexScan<'a> {
+    end: Option<EncodedEnd>,
+    table_btree: BTree,
+    index_btree: BTree,
+    search_mode: SearchMode,
+    direction: ScanDirection,
+    index_iter: btree::Iter,
+    format: Format,
+    directions: &'a [Direction],
+    num_included: usize,
+    while_cond: &'a dyn Fn(TupleSlice) -> Result<bool>,
+    expiry: Option<Expiry>,
+}
+
+impl<'a> Executor for ExecIndexScan<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let (skey_bytes, value) = match self.index_iter.next(bufmgr)? {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            if let Some(end) = &self.end {
+                if end.is_past(self.format, &skey_bytes, self.directions)? {
+                    return Ok(None);
+                }
+            }
+            let mut skey = vec![];
+            self.format.decode_with_limit(
+                &skey_bytes,
+                self.directions,
+                &mut skey,
+                btree::max_entry_size(),
+            )?;
+            if !(self.while_cond)(&skey)? {
+                return Ok(None);
+            }
+            let pkey_bytes = self
+                .format
+                .split_last(&value, self.num_included)?
+                .0
+                .to_vec();
+            let tuple_bytes = match self.table_btree.get(bufmgr, &pkey_bytes)? {
+                Some(tuple_bytes) => tuple_bytes,
+                None => {
+                    return Err(Error::MissingTableRow {
+                        skey: skey_bytes,
+                        pkey: pkey_bytes,
+                        table: self.table_btree.meta_page_id.to_u64(),
+                    }
+                    .into())
+                }
+            };
+            let mut tuple = vec![];
+            let limit = btree::max_entry_size();
+            self.format
+                .decode_with_limit(&pkey_bytes, &[], &mut tuple, limit)?;
+            self.format
+                .decode_with_limit(&tuple_bytes, &[], &mut tuple, limit)?;
+            match self.expiry {
+                Some(expiry) if expiry.is_expired(&tuple) => continue,
+                _ => return Ok(Some(tuple)),
+            }
+        }
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.index_iter =
+            self.index_btree
+                .search_directed(bufmgr, self.search_mode.clone(), self.direction)?;
+        Ok(())
+    }
+}
+
+/// Emits each index entry as its skey followed by its value: the pkey, then
+/// any included columns. It never visits the table, so it cannot hide
+/// expired rows.
+pub struct IndexOnlyScan<'a> {
+    pub index_meta_page_id: PageId,
+    /// Sort directions of the index's skey columns.
+    pub directions: Vec<Direction>,
+    pub search_mode: TupleSearchMode,
+    pub direction: ScanDirection,
+    /// Where to stop; see `RangeEnd`.
+    pub end: Option<RangeEnd>,
+    pub while_cond: Predicate<'a>,
+}
+
+impl<'a> PlanNode for IndexOnlyScan<'a> {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let format = table::btree_format(bufmgr, self.index_meta_page_id)?;
+        let index_btree = BTree::new(self.index_meta_page_id);
+        let search_mode = self.search_mode.encode(format, &self.directions);
+        let index_iter =
+            index_btree.search_directed(bufmgr, search_mode.clone(), self.direction)?;
+        Ok(Box::new(ExecIndexOnlyScan {
+            end: self
+                .end
+                .as_ref()
+                .map(|end| end.encode(format, &self.directions, self.direction)),
+            index_btree,
+            search_mode,
+            direction: self.direction,
+            index_iter,
+            format,
+            directions: &self.directions,
+            while_cond: &*self.while_cond,
+        }))
+    }
+
+    fn explain(&self) -> PlanDescription {
+        PlanDescription::new("IndexOnlyScan")
+            .property("index", self.index_meta_page_id.to_u64())
+            .property("directions", format!("{:?}", self.directions))
+            .property("search_mode", self.search_mode.describe())
+            .property("direction", format!("{:?}", self.direction))
+            .property("end", describe_end(&self.end, self.direction))
+    }
+}
+
+pub struct ExecIndexOnlyScan<'a> {
+    end: Option<EncodedEnd>,
+    index_btree: BTree,
+    search_mode: SearchMode,
+    direction: ScanDirection,
+    index_iter: btree::Iter,
+    format: Format,
+    directions: &'a [Direction],
+    while_cond: &'a dyn Fn(TupleSlice) -> Result<bool>,
+}
+
+impl<'a> Executor for ExecIndexOnlyScan<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr)? {
+            Some(pair) => pair,
+            None => return Ok(None),
+        };
+        if let Some(end) = &self.end {
+            if end.is_past(self.format, &skey_bytes, self.directions)? {
+                return Ok(None);
+            }
+        }
+        let limit = btree::max_entry_size();
+        let mut skey = vec![];
+        self.format
+            .decode_with_limit(&skey_bytes, self.directions, &mut skey, limit)?;
+        if !(self.while_cond)(&skey)? {
+            return Ok(None);
+        }
+        let mut tuple = skey;
+        self.format
+            .decode_with_limit(&pkey_bytes, &[], &mut tuple, limit)?;
+        Ok(Some(tuple))
+    }
+
+    fn rewind(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        self.index_iter =
+            self.index_btree
+                .search_directed(bufmgr, self.search_mode.clone(), self.direction)?;
+        Ok(())
     }
 }
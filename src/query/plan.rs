@@ -0,0 +1,406 @@
+//! Chooses how to read the tables of a query from the indexes the catalog
+//! knows about.
+//!
+//! A filter over a table becomes, in order of preference, a `SeqScan` over
+//! the pkey range its condition allows, an `IndexScan` over such a range of
+//! a unique index, or an `IndexOnlyScan` of it when the index holds every
+//! column the query needs. Otherwise the whole table is scanned. The
+//! condition is always checked in full on the rows the scan finds, so a
+//! scan only has to find a superset of them.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::{
+    Annotated, BoxPlanNode, Filter, IndexOnlyScan, IndexScan, KeyBound, Project, RangeEnd,
+    ScanDirection, SeqScan, Tuple, TupleSearchMode,
+};
+use crate::disk::PageId;
+use crate::expr::{BoolExpr, BytesExpr};
+use crate::table::{Table, UniqueIndex};
+use crate::tuple::Direction;
+
+/// A query as the rows it asks for rather than how to find them.
+#[derive(Debug, Clone)]
+pub enum LogicalPlan {
+    /// Every row of the table with this meta page id.
+    Scan { table: u64 },
+    Filter {
+        cond: BoolExpr,
+        from: Box<LogicalPlan>,
+    },
+    /// The `columns` of each row, in that order.
+    Project {
+        columns: Vec<usize>,
+        from: Box<LogicalPlan>,
+    },
+}
+
+/// Plans `plan` against `tables`, hiding the rows expired at `now`. A
+/// table that is not in `tables` is scanned in full.
+pub fn optimize(plan: LogicalPlan, tables: &[Table], now: u64) -> BoxPlanNode<'static> {
+    Planner { tables, now }.build(&plan)
+}
+
+struct Planner<'a> {
+    tables: &'a [Table],
+    now: u64,
+}
+
+impl<'a> Planner<'a> {
+    fn build(&self, plan: &LogicalPlan) -> BoxPlanNode<'static> {
+        if let Some((table, cond)) = as_access(plan) {
+            return self.access(table, cond, None);
+        }
+        match plan {
+            LogicalPlan::Project { columns, from } => match as_access(from) {
+                Some((table, cond)) => self.access(table, cond, Some(columns)),
+                None => project(columns.clone(), self.build(from)),
+            },
+            LogicalPlan::Filter { cond, from } => filter(cond.clone(), self.build(from)),
+            LogicalPlan::Scan { .. } => unreachable!("a scan is an access"),
+        }
+    }
+
+    /// Reads the rows of `table` that `cond` accepts, then keeps `columns`.
+    fn access(
+        &self,
+        table_id: u64,
+        cond: Option<&BoolExpr>,
+        columns: Option<&[usize]>,
+    ) -> BoxPlanNode<'static> {
+        let table = match self
+            .tables
+            .iter()
+            .find(|table| table.meta_page_id.to_u64() == table_id)
+        {
+            Some(table) => table,
+            None => {
+                let scan = self.seq_scan(PageId(table_id), None, None, cond);
+                return with_columns(scan, columns);
+            }
+        };
+        let ranges = cond.map(ColumnRanges::of).unwrap_or_default();
+        let plan = if let Some(plan) = self.pkey_scan(table, &ranges, cond) {
+            plan
+        } else if let Some((index, range)) = table
+            .unique_indices
+            .iter()
+            .find_map(|index| Some((index, ranges.index_range(index)?)))
+        {
+            if let Some(plan) = self.index_only_scan(table, index, &range, cond, columns) {
+                return plan;
+            }
+            self.index_scan(table, index, range, cond)
+        } else {
+            self.seq_scan(table.meta_page_id, Some(table), None, cond)
+        };
+        with_columns(plan, columns)
+    }
+
+    /// A `SeqScan` over the pkey range of `ranges`, if it has one.
+    fn pkey_scan(
+        &self,
+        table: &Table,
+        ranges: &ColumnRanges,
+        cond: Option<&BoolExpr>,
+    ) -> Option<BoxPlanNode<'static>> {
+        let pkey: Vec<_> = (0..table.num_key_elems).collect();
+        let range = ranges.key_range(&pkey, &[])?;
+        Some(self.seq_scan(table.meta_page_id, Some(table), Some(range), cond))
+    }
+
+    fn seq_scan(
+        &self,
+        table_meta_page_id: PageId,
+        table: Option<&Table>,
+        range: Option<KeyRange>,
+        cond: Option<&BoolExpr>,
+    ) -> BoxPlanNode<'static> {
+        let (search_mode, while_bound) = match range {
+            Some(range) => {
+                let while_bound = range.end.map(|end| KeyBound {
+                    key: end.key,
+                    accept: if end.inclusive {
+                        Ordering::is_le
+                    } else {
+                        Ordering::is_lt
+                    },
+                });
+                (range.start, while_bound)
+            }
+            None => (TupleSearchMode::Start, None),
+        };
+        let scan = SeqScan {
+            table_meta_page_id,
+            search_mode,
+            direction: ScanDirection::Forward,
+            while_bound,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: table.and_then(|table| table.expiry(self.now)),
+            filter: cond
+                .cloned()
+                .map(|cond| -> super::BorrowedPredicate<'static> {
+                    Box::new(move |record| Ok(cond.eval(record)? == Some(true)))
+                }),
+        };
+        match cond {
+            Some(cond) => annotated(cond, Box::new(scan)),
+            None => Box::new(scan),
+        }
+    }
+
+    fn index_scan(
+        &self,
+        table: &Table,
+        index: &UniqueIndex,
+        range: KeyRange,
+        cond: Option<&BoolExpr>,
+    ) -> BoxPlanNode<'static> {
+        let scan = Box::new(IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index_meta_page_id: index.meta_page_id,
+            directions: index.directions.clone(),
+            num_included: index.include.len(),
+            search_mode: range.start,
+            direction: ScanDirection::Forward,
+            end: range.end,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: table.expiry(self.now),
+        });
+        match cond {
+            Some(cond) => filter(cond.clone(), scan),
+            None => scan,
+        }
+    }
+
+    /// An `IndexOnlyScan` giving `columns`, if the index holds every column
+    /// they and `cond` need. It cannot hide expired rows, so it is not used
+    /// for tables with an expiry column.
+    fn index_only_scan(
+        &self,
+        table: &Table,
+        index: &UniqueIndex,
+        range: &KeyRange,
+        cond: Option<&BoolExpr>,
+        columns: Option<&[usize]>,
+    ) -> Option<BoxPlanNode<'static>> {
+        let columns = columns?;
+        if table.expiry_column.is_some() {
+            return None;
+        }
+        // the table column of each element of an IndexOnlyScan row
+        let layout: Vec<usize> = index
+            .skey
+            .iter()
+            .copied()
+            .chain(0..table.num_key_elems)
+            .chain(index.include.iter().copied())
+            .collect();
+        let position = |column| layout.iter().position(|&c| c == column);
+        let mut cond_columns = vec![];
+        if let Some(cond) = cond {
+            cond.collect_columns(&mut cond_columns);
+        }
+        if !cond_columns.iter().all(|&c| position(c).is_some()) {
+            return None;
+        }
+        let positions: Vec<_> = columns
+            .iter()
+            .map(|&c| position(c))
+            .collect::<Option<_>>()?;
+        let scan: BoxPlanNode<'static> = Box::new(IndexOnlyScan {
+            index_meta_page_id: index.meta_page_id,
+            directions: index.directions.clone(),
+            search_mode: range.start.clone(),
+            direction: ScanDirection::Forward,
+            end: range.end.clone(),
+            while_cond: Box::new(|_| Ok(true)),
+        });
+        let scan = match cond {
+            Some(cond) => {
+                let num_columns = table.num_columns;
+                let owned = cond.clone();
+                let filter = Filter {
+                    inner_plan: scan,
+                    // evaluated on the row put back in table column order
+                    cond: Box::new(move |row| {
+                        let mut record = vec![None; num_columns];
+                        for (elem, &column) in row.iter().zip(&layout) {
+                            record[column] = elem.as_deref();
+                        }
+                        Ok(owned.eval(&record)? == Some(true))
+                    }),
+                };
+                annotated(cond, Box::new(filter))
+            }
+            None => scan,
+        };
+        Some(project(positions, scan))
+    }
+}
+
+/// The table and condition of a `Scan`, or of a `Filter` right over one.
+fn as_access(plan: &LogicalPlan) -> Option<(u64, Option<&BoolExpr>)> {
+    match plan {
+        LogicalPlan::Scan { table } => Some((*table, None)),
+        LogicalPlan::Filter { cond, from } => match **from {
+            LogicalPlan::Scan { table } => Some((table, Some(cond))),
+            _ => None,
+        },
+        LogicalPlan::Project { .. } => None,
+    }
+}
+
+fn with_columns(plan: BoxPlanNode<'static>, columns: Option<&[usize]>) -> BoxPlanNode<'static> {
+    match columns {
+        Some(columns) => project(columns.to_vec(), plan),
+        None => plan,
+    }
+}
+
+fn project(columns: Vec<usize>, inner_plan: BoxPlanNode<'static>) -> BoxPlanNode<'static> {
+    Box::new(Project {
+        inner_plan,
+        columns,
+    })
+}
+
+fn filter(cond: BoolExpr, inner_plan: BoxPlanNode<'static>) -> BoxPlanNode<'static> {
+    let annotation = cond.clone();
+    let filter = Filter {
+        inner_plan,
+        cond: Box::new(move |record| Ok(cond.eval(record)? == Some(true))),
+    };
+    annotated(&annotation, Box::new(filter))
+}
+
+/// Shows `cond` in what `plan` explains; the plan only sees a closure.
+fn annotated(cond: &BoolExpr, plan: BoxPlanNode<'static>) -> BoxPlanNode<'static> {
+    Box::new(Annotated {
+        inner: plan,
+        properties: vec![("cond".to_string(), format!("{:?}", cond))],
+    })
+}
+
+/// Where a scan over a key starts and ends.
+struct KeyRange {
+    start: TupleSearchMode,
+    end: Option<RangeEnd>,
+}
+
+/// The bounds a condition puts on single columns, from the comparisons
+/// of a column with a literal that it requires to hold.
+#[derive(Default)]
+struct ColumnRanges(HashMap<usize, ColumnRange>);
+
+/// Bounds on the values of a column. When a condition has several of a
+/// kind, any one of them will do, as the condition is checked anyway.
+#[derive(Default)]
+struct ColumnRange {
+    eq: Option<Vec<u8>>,
+    /// The bound and whether it is inclusive.
+    lower: Option<(Vec<u8>, bool)>,
+    upper: Option<(Vec<u8>, bool)>,
+}
+
+impl ColumnRanges {
+    fn of(cond: &BoolExpr) -> Self {
+        let mut ranges = Self::default();
+        ranges.add(cond);
+        ranges
+    }
+
+    fn add(&mut self, cond: &BoolExpr) {
+        let (lhs, rhs, ordering, inclusive) = match cond {
+            BoolExpr::And(conds) => {
+                for cond in conds {
+                    self.add(cond);
+                }
+                return;
+            }
+            BoolExpr::Eq(lhs, rhs) => (lhs, rhs, Ordering::Equal, true),
+            BoolExpr::Lt(lhs, rhs) => (lhs, rhs, Ordering::Less, false),
+            BoolExpr::Le(lhs, rhs) => (lhs, rhs, Ordering::Less, true),
+            BoolExpr::Gt(lhs, rhs) => (lhs, rhs, Ordering::Greater, false),
+            BoolExpr::Ge(lhs, rhs) => (lhs, rhs, Ordering::Greater, true),
+            _ => return,
+        };
+        // `ordering` is how the column compares to the literal
+        let (column, literal, ordering) = match (lhs, rhs) {
+            (BytesExpr::Column(column), BytesExpr::Literal(literal)) => (column, literal, ordering),
+            (BytesExpr::Literal(literal), BytesExpr::Column(column)) => {
+                (column, literal, ordering.reverse())
+            }
+            _ => return,
+        };
+        let range = self.0.entry(*column).or_default();
+        let bound = Some((literal.as_bytes().to_vec(), inclusive));
+        match ordering {
+            Ordering::Equal => range.eq = range.eq.take().or(Some(literal.as_bytes().to_vec())),
+            Ordering::Less => range.upper = range.upper.take().or(bound),
+            Ordering::Greater => range.lower = range.lower.take().or(bound),
+        }
+    }
+
+    /// The range of a key made of `columns`: equal values for its leading
+    /// columns, then a range of the next one if it is ascending.
+    fn key_range(&self, columns: &[usize], directions: &[Direction]) -> Option<KeyRange> {
+        let mut prefix: Tuple = vec![];
+        for (i, column) in columns.iter().enumerate() {
+            let range = self.0.get(column);
+            if let Some(eq) = range.and_then(|range| range.eq.as_ref()) {
+                prefix.push(Some(eq.clone()));
+                continue;
+            }
+            let range = range.filter(|_| directions.get(i) != Some(&Direction::Desc));
+            let lower = range.and_then(|range| range.lower.as_ref());
+            let upper = range.and_then(|range| range.upper.as_ref());
+            if prefix.is_empty() && lower.is_none() && upper.is_none() {
+                return None;
+            }
+            let with = |value: &[u8]| -> Tuple {
+                let mut key = prefix.clone();
+                key.push(Some(value.to_vec()));
+                key
+            };
+            let start = match lower {
+                Some((value, _)) => TupleSearchMode::Key(with(value)),
+                None if prefix.is_empty() => TupleSearchMode::Start,
+                None => TupleSearchMode::Key(prefix.clone()),
+            };
+            let end = match upper {
+                Some((value, inclusive)) => Some(RangeEnd {
+                    key: with(value),
+                    inclusive: *inclusive,
+                }),
+                None => Some(RangeEnd {
+                    key: prefix,
+                    inclusive: true,
+                })
+                .filter(|end| !end.key.is_empty()),
+            };
+            return Some(KeyRange { start, end });
+        }
+        if prefix.is_empty() {
+            return None;
+        }
+        Some(KeyRange {
+            start: TupleSearchMode::Key(prefix.clone()),
+            end: Some(RangeEnd {
+                key: prefix,
+                inclusive: true,
+            }),
+        })
+    }
+
+    /// The range of `index` to scan, if the condition bounds its leading
+    /// column. Rows with a NULL skey element are not in the index, so
+    /// every skey column has to be compared with a literal as well.
+    fn index_range(&self, index: &UniqueIndex) -> Option<KeyRange> {
+        if !index.skey.iter().all(|column| self.0.contains_key(column)) {
+            return None;
+        }
+        self.key_range(&index.skey, &index.directions)
+    }
+}
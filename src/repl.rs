@@ -0,0 +1,598 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::de::IgnoredAny;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::btree::BTree;
+use crate::buffer::BufferPoolManager;
+use crate::catalog;
+use crate::lang::{
+    self, FileAccess, Request, Response, SettingValue, TextTable, DEFAULT_MAX_CELL_WIDTH,
+};
+use crate::Result;
+
+pub const PROMPT: &str = "> ";
+pub const CONTINUATION_PROMPT: &str = "... ";
+/// The prompts while a transaction is open.
+pub const TRANSACTION_PROMPT: &str = "*> ";
+pub const TRANSACTION_CONTINUATION_PROMPT: &str = "*... ";
+
+const HELP: &str = "\
+Enter a JSON request, which may span several lines, or a SQL statement
+on one line.
+.exit           flush and quit
+.flush          write dirty pages to disk
+.stats          show buffer pool and disk I/O counters
+.buffers        list the pages in the buffer pool
+.tables         list the tables
+.verify TABLE   check the btrees of a table
+.help           show this message";
+
+/// How responses and errors are printed: for people, or as one JSON value
+/// per line for other programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl Output {
+    fn response(self, out: &mut dyn Write, resp: &Response, max_cell_width: usize) -> Result<()> {
+        match (self, resp) {
+            (Output::Text, Response::Query(resp)) => {
+                writeln!(out, "{}", resp.display(max_cell_width))?
+            }
+            (Output::Text, _) => writeln!(out, "{}", resp)?,
+            (Output::Json, _) => writeln!(out, "{}", serde_json::to_string(resp)?)?,
+        }
+        Ok(())
+    }
+
+    /// The output of a meta-command: as it is, or as `{"output": ...}`.
+    fn text(self, out: &mut dyn Write, text: &dyn fmt::Display) -> io::Result<()> {
+        match self {
+            Output::Text => write!(out, "{}", text),
+            Output::Json => writeln!(out, "{}", json!({ "output": text.to_string() })),
+        }
+    }
+
+    /// `kind` is "parse" for a malformed request, "execute" for a request
+    /// that failed, "read_only" for one refused in read-only mode,
+    /// "setting" for a bad `Set` or `Show` and "command" for a bad
+    /// meta-command.
+    fn error(self, out: &mut dyn Write, kind: &str, err: &dyn fmt::Display) -> io::Result<()> {
+        match self {
+            Output::Text => writeln!(out, "ERROR: {}", err),
+            Output::Json => {
+                let message = format!("{:#}", err);
+                writeln!(
+                    out,
+                    "{}",
+                    json!({ "error": { "kind": kind, "message": message } })
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unknown setting {name:?}, expected one of {}", SETTINGS.join(", "))]
+    UnknownSetting { name: String },
+    #[error("invalid value {value:?} for {name}, expected {expected}")]
+    InvalidValue {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("read_only cannot be turned off once on")]
+    ReadOnlyLocked,
+}
+
+/// The names of the settings `Request::Set` and `Request::Show` know, in
+/// the order `Show` lists them.
+pub const SETTINGS: &[&str] = &[
+    "analyze",
+    "flush_every",
+    "max_cell_width",
+    "output",
+    "read_only",
+];
+
+/// A setting of a `Repl`, parsed and validated from a `Request::Set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    /// `on` or `off`: runs every `Query` and `Explain` with `analyze`.
+    Analyze(bool),
+    /// A number of mutating requests, or `off`; see `Repl::flush_every`.
+    FlushEvery(Option<usize>),
+    MaxCellWidth(usize),
+    /// `text` or `json`.
+    Output(Output),
+    /// Only `on`; see `Repl::read_only`.
+    ReadOnly(bool),
+}
+
+impl Setting {
+    pub fn parse(name: &str, value: &str) -> Result<Self, Error> {
+        let name = *SETTINGS
+            .iter()
+            .find(|&&known| known == name)
+            .ok_or_else(|| Error::UnknownSetting {
+                name: name.to_string(),
+            })?;
+        let invalid = |expected| Error::InvalidValue {
+            name,
+            value: value.to_string(),
+            expected,
+        };
+        let on_off = |value: &str| match value {
+            "on" | "true" => Ok(true),
+            "off" | "false" => Ok(false),
+            _ => Err(invalid("on or off")),
+        };
+        let positive = |value: &str| value.parse().ok().filter(|&n: &usize| n > 0);
+        Ok(match name {
+            "analyze" => Setting::Analyze(on_off(value)?),
+            "flush_every" => match value {
+                "off" => Setting::FlushEvery(None),
+                _ => Setting::FlushEvery(Some(
+                    positive(value).ok_or_else(|| invalid("a positive number or off"))?,
+                )),
+            },
+            "max_cell_width" => {
+                Setting::MaxCellWidth(positive(value).ok_or_else(|| invalid("a positive number"))?)
+            }
+            "output" => match value {
+                "text" => Setting::Output(Output::Text),
+                "json" => Setting::Output(Output::Json),
+                _ => return Err(invalid("text or json")),
+            },
+            _ => Setting::ReadOnly(on_off(value)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Continue,
+    Exit,
+}
+
+/// Accumulates input lines until they form complete requests and runs
+/// them. With the `sql` feature, a line that starts with a letter is a SQL
+/// statement instead. A malformed request discards whatever is buffered,
+/// so the next line starts afresh.
+pub struct Repl {
+    pub output: Output,
+    /// Flushes the buffer pool after this many mutating requests, so that
+    /// a crash loses at most that many.
+    pub flush_every: Option<usize>,
+    /// How many characters of a cell a query result shows in text output.
+    pub max_cell_width: usize,
+    /// Refuses requests that are not `Request::is_read_only`, and `.flush`.
+    pub read_only: bool,
+    /// Sets `analyze` on every `Query` and `Explain`.
+    pub analyze: bool,
+    /// The files `Export` and `Import` may name.
+    pub file_access: FileAccess,
+    buffer: String,
+    unflushed: usize,
+    in_transaction: bool,
+}
+
+impl Repl {
+    pub fn new(output: Output) -> Self {
+        Self {
+            output,
+            flush_every: None,
+            max_cell_width: DEFAULT_MAX_CELL_WIDTH,
+            read_only: false,
+            analyze: false,
+            file_access: FileAccess::Any,
+            buffer: String::new(),
+            unflushed: 0,
+            in_transaction: false,
+        }
+    }
+
+    /// The prompt for the next line: a continuation prompt while a request
+    /// is incomplete, marked while a transaction is open, and none at all
+    /// for JSON output.
+    pub fn prompt(&self) -> &'static str {
+        let continuation = !self.buffer.trim().is_empty();
+        match (self.output, self.in_transaction, continuation) {
+            (Output::Json, _, _) => "",
+            (Output::Text, false, false) => PROMPT,
+            (Output::Text, false, true) => CONTINUATION_PROMPT,
+            (Output::Text, true, false) => TRANSACTION_PROMPT,
+            (Output::Text, true, true) => TRANSACTION_CONTINUATION_PROMPT,
+        }
+    }
+
+    pub fn feed_line(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        line: &str,
+        out: &mut dyn Write,
+    ) -> Result<Step> {
+        if self.buffer.trim().is_empty() && line.trim_start().starts_with('.') {
+            self.buffer.clear();
+            return self.command(bufmgr, line.trim(), out);
+        }
+        #[cfg(feature = "sql")]
+        {
+            if self.buffer.trim().is_empty() && line.trim_start().starts_with(char::is_alphabetic) {
+                self.buffer.clear();
+                match crate::sql::compile(bufmgr, line) {
+                    Ok(req) => self.execute(bufmgr, req, out)?,
+                    Err(err) => self.output.error(out, "parse", &err)?,
+                }
+                return Ok(Step::Continue);
+            }
+        }
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        loop {
+            let mut values = serde_json::Deserializer::from_str(&self.buffer).into_iter();
+            match values.next() {
+                None => {
+                    self.buffer.clear();
+                    return Ok(Step::Continue);
+                }
+                Some(Ok(IgnoredAny)) => {
+                    let end = values.byte_offset();
+                    let req = serde_json::from_str::<Request>(&self.buffer[..end]);
+                    self.buffer.drain(..end);
+                    match req {
+                        Ok(req) => self.execute(bufmgr, req, out)?,
+                        Err(err) => self.output.error(out, "parse", &err)?,
+                    }
+                }
+                Some(Err(err)) if err.is_eof() => return Ok(Step::Continue),
+                Some(Err(err)) => {
+                    self.buffer.clear();
+                    self.output.error(out, "parse", &err)?;
+                    return Ok(Step::Continue);
+                }
+            }
+        }
+    }
+
+    /// Changes a setting, refusing to turn `read_only` off.
+    pub fn set(&mut self, setting: Setting) -> Result<(), Error> {
+        match setting {
+            Setting::Analyze(analyze) => self.analyze = analyze,
+            Setting::FlushEvery(flush_every) => self.flush_every = flush_every,
+            Setting::MaxCellWidth(max_cell_width) => self.max_cell_width = max_cell_width,
+            Setting::Output(output) => self.output = output,
+            Setting::ReadOnly(false) if self.read_only => return Err(Error::ReadOnlyLocked),
+            Setting::ReadOnly(read_only) => self.read_only = read_only,
+        }
+        Ok(())
+    }
+
+    /// The current value of a setting, in the form `Setting::parse` takes.
+    pub fn show(&self, name: &str) -> Result<SettingValue, Error> {
+        let on_off = |on| if on { "on" } else { "off" }.to_string();
+        let (name, value) = match name {
+            "analyze" => ("analyze", on_off(self.analyze)),
+            "flush_every" => (
+                "flush_every",
+                self.flush_every
+                    .map_or_else(|| "off".to_string(), |n| n.to_string()),
+            ),
+            "max_cell_width" => ("max_cell_width", self.max_cell_width.to_string()),
+            "output" => (
+                "output",
+                match self.output {
+                    Output::Text => "text".to_string(),
+                    Output::Json => "json".to_string(),
+                },
+            ),
+            "read_only" => ("read_only", on_off(self.read_only)),
+            _ => {
+                return Err(Error::UnknownSetting {
+                    name: name.to_string(),
+                })
+            }
+        };
+        Ok(SettingValue { name, value })
+    }
+
+    /// Runs `Set` and `Show` against the settings of the session.
+    fn settings(&mut self, req: Request) -> Result<Response, Error> {
+        match req {
+            Request::Set(req) => {
+                self.set(Setting::parse(&req.name, &req.value_text())?)?;
+                Ok(Response::Set(self.show(&req.name)?))
+            }
+            Request::Show(req) => match req.name {
+                Some(name) => Ok(Response::Show(vec![self.show(&name)?])),
+                None => Ok(Response::Show(
+                    SETTINGS
+                        .iter()
+                        .map(|name| self.show(name))
+                        .collect::<Result<_, _>>()?,
+                )),
+            },
+            _ => unreachable!("only Set and Show change settings"),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        mut req: Request,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        if let Request::Set(_) | Request::Show(_) = req {
+            match self.settings(req) {
+                Ok(resp) => self.output.response(out, &resp, self.max_cell_width)?,
+                Err(err) => self.output.error(out, "setting", &err)?,
+            }
+            return Ok(());
+        }
+        if let Request::Query(query) | Request::Explain(query) = &mut req {
+            query.analyze |= self.analyze;
+        }
+        let is_mutating = req.is_mutating();
+        let resp = if let Err(err) = req.check_file_access(&self.file_access) {
+            Err(err.into())
+        } else if self.read_only {
+            req.execute_read_only(bufmgr)
+        } else {
+            req.execute(bufmgr)
+        };
+        match resp {
+            Ok(resp) => self.output.response(out, &resp, self.max_cell_width)?,
+            Err(err @ crate::Error::Lang(lang::Error::ReadOnly { .. })) => {
+                self.output.error(out, "read_only", &err)?
+            }
+            Err(err) => self.output.error(out, "execute", &err)?,
+        }
+        // a failed request leaves an open transaction open
+        self.in_transaction = bufmgr.in_transaction();
+        if is_mutating && !self.read_only {
+            self.unflushed += 1;
+        }
+        match self.flush_every {
+            Some(n) if self.unflushed >= n => {
+                bufmgr.flush()?;
+                self.unflushed = 0;
+            }
+            _ => {}
+        }
+        // a commit group commit holds back is synced at the first request
+        // after its delay
+        bufmgr.tick()?;
+        Ok(())
+    }
+
+    /// Reports a request left incomplete at the end of the input, and
+    /// rolls back a transaction left open.
+    pub fn finish(&mut self, bufmgr: &mut BufferPoolManager, out: &mut dyn Write) -> Result<()> {
+        if !self.buffer.trim().is_empty() {
+            self.buffer.clear();
+            self.output
+                .error(out, "parse", &"EOF while parsing a request")?;
+        }
+        if bufmgr.in_transaction() {
+            self.execute(bufmgr, Request::Rollback, out)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a meta-command line such as `.stats` or `.verify t`.
+    pub fn command(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        line: &str,
+        out: &mut dyn Write,
+    ) -> Result<Step> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<_> = words.collect();
+        let text = match (command, args.as_slice()) {
+            (".exit", []) => return Ok(Step::Exit),
+            (".flush", []) if self.read_only => {
+                Err(lang::Error::ReadOnly { request: ".flush" }.into())
+            }
+            (".flush", []) => match bufmgr.flush() {
+                Ok(()) => {
+                    self.unflushed = 0;
+                    Ok(String::new())
+                }
+                Err(err) => Err(err.into()),
+            },
+            (".help", []) => Ok(format!("{}\n", HELP)),
+            (".stats", []) => Ok(stats(bufmgr)),
+            (".buffers", []) => Ok(buffers(bufmgr)),
+            (".tables", []) => {
+                self.execute(bufmgr, Request::ListTables, out)?;
+                return Ok(Step::Continue);
+            }
+            (".verify", [table]) => verify(bufmgr, table),
+            _ => {
+                self.output.error(
+                    out,
+                    "command",
+                    &format!("unknown command {:?}, try .help", line),
+                )?;
+                return Ok(Step::Continue);
+            }
+        };
+        match text {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => self.output.text(out, &text)?,
+            Err(err @ crate::Error::Lang(lang::Error::ReadOnly { .. })) => {
+                self.output.error(out, "read_only", &err)?
+            }
+            Err(err) => self.output.error(out, "execute", &err)?,
+        }
+        Ok(Step::Continue)
+    }
+
+    /// Runs `input` line by line as if it were typed at the prompt,
+    /// writing responses to `out`.
+    pub fn run_script(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        input: &str,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        for line in input.lines() {
+            if self.feed_line(bufmgr, line, out)? == Step::Exit {
+                break;
+            }
+        }
+        self.finish(bufmgr, out)
+    }
+}
+
+/// The `.stats` table: the fetch counters of the buffer pool and the I/O
+/// counters of the heap file.
+fn stats(bufmgr: &BufferPoolManager) -> String {
+    let stats = bufmgr.stats();
+    let io_stats = bufmgr.io_stats();
+    let resident = bufmgr.resident_pages();
+    let dirty = resident.iter().filter(|page| page.is_dirty).count();
+    let counters = [
+        ("pool_size", bufmgr.pool_size() as u64),
+        ("resident_pages", resident.len() as u64),
+        ("dirty_pages", dirty as u64),
+        ("hits", stats.hits),
+        ("misses", stats.misses),
+        ("disk_reads", io_stats.reads),
+        ("disk_writes", io_stats.writes),
+        ("disk_syncs", io_stats.syncs),
+        ("heap_pages", bufmgr.num_pages()),
+    ];
+    let rows = counters
+        .iter()
+        .map(|(name, value)| vec![name.to_string(), value.to_string()])
+        .collect();
+    TextTable {
+        header: &["counter", "value"],
+        rows,
+    }
+    .to_string()
+}
+
+/// The `.buffers` table: one row per page in the pool.
+fn buffers(bufmgr: &BufferPoolManager) -> String {
+    let rows = bufmgr
+        .resident_pages()
+        .iter()
+        .map(|page| {
+            vec![
+                page.page_id.to_u64().to_string(),
+                page.is_dirty.to_string(),
+                page.pins.to_string(),
+                page.usage_count.to_string(),
+            ]
+        })
+        .collect();
+    TextTable {
+        header: &["page_id", "dirty", "pins", "usage_count"],
+        rows,
+    }
+    .to_string()
+}
+
+/// The `.verify` table: what `BTree::verify` found in the primary btree
+/// and each index of `name`.
+fn verify(bufmgr: &mut BufferPoolManager, name: &str) -> Result<String> {
+    let table = catalog::open_table(bufmgr, name)?;
+    let mut btrees = vec![("primary".to_string(), table.meta_page_id)];
+    for index in &table.unique_indices {
+        let name = index.name.clone();
+        btrees.push((
+            name.unwrap_or_else(|| "unique".to_string()),
+            index.meta_page_id,
+        ));
+    }
+    for index in &table.secondary_indices {
+        let name = index.name.clone();
+        btrees.push((
+            name.unwrap_or_else(|| "secondary".to_string()),
+            index.meta_page_id,
+        ));
+    }
+    let mut rows = vec![];
+    for (name, meta_page_id) in btrees {
+        let verification = BTree::new(meta_page_id).verify(bufmgr)?;
+        let problems = if verification.problems.is_empty() {
+            "ok".to_string()
+        } else {
+            verification.problems.join("; ")
+        };
+        rows.push(vec![
+            name,
+            meta_page_id.to_u64().to_string(),
+            verification.num_pages.to_string(),
+            verification.num_entries.to_string(),
+            verification.depth.to_string(),
+            problems,
+        ]);
+    }
+    Ok(TextTable {
+        header: &[
+            "btree",
+            "meta_page_id",
+            "pages",
+            "entries",
+            "depth",
+            "problems",
+        ],
+        rows,
+    }
+    .to_string())
+}
+
+pub fn run_script(
+    bufmgr: &mut BufferPoolManager,
+    output: Output,
+    input: &str,
+    out: &mut dyn Write,
+) -> Result<()> {
+    Repl::new(output).run_script(bufmgr, input, out)
+}
+
+/// Like `BufRead::read_line`, but a read interrupted by a signal gives up
+/// on the line if `stop` is set instead of retrying. Returns `None` at EOF
+/// or once stopped.
+pub fn read_line(input: &mut impl BufRead, stop: &AtomicBool) -> io::Result<Option<String>> {
+    let mut line = vec![];
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let available = match input.fill_buf() {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if available.is_empty() {
+            break;
+        }
+        let (len, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false),
+        };
+        line.extend_from_slice(&available[..len]);
+        input.consume(len);
+        if done {
+            break;
+        }
+    }
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let line =
+        String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Some(line))
+}
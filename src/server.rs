@@ -0,0 +1,99 @@
+//! Serves the REPL's JSON-lines protocol over TCP: each line a client
+//! sends is a request, or a SQL statement with the `sql` feature, and each
+//! response or error comes back as one JSON line. The engine is
+//! single-threaded, so connections are served one at a time, in the order
+//! they are accepted.
+
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::buffer::BufferPoolManager;
+use crate::lang::FileAccess;
+use crate::repl::{self, Output, Repl, Step};
+use crate::Result;
+
+/// How long the accept loop sleeps between checks for a new connection or
+/// for `stop`.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Server {
+    /// Passed on to each connection's `Repl`; the pool is also flushed
+    /// whenever a connection closes.
+    pub flush_every: Option<usize>,
+    /// Passed on to each connection's `Repl`, and skips the flush.
+    pub read_only: bool,
+    /// Passed on to each connection's `Repl`. Anything but
+    /// `FileAccess::Refused` lets clients read and write files on this
+    /// host with the server's privileges.
+    pub file_access: FileAccess,
+}
+
+impl Server {
+    /// Serves connections until `stop` is set, then returns once the
+    /// current connection, if any, has stopped at a line boundary.
+    pub fn serve(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        listener: &TcpListener,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        while !stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(err) = self.serve_connection(bufmgr, stream, stop) {
+                        eprintln!("relly: connection from {} failed: {:#}", peer, err);
+                    }
+                    // a transaction does not outlive its connection
+                    if bufmgr.in_transaction() {
+                        bufmgr.rollback()?;
+                    }
+                    if !self.read_only {
+                        bufmgr.flush()?;
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    bufmgr.tick()?;
+                    thread::sleep(POLL_INTERVAL)
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the lines of one connection until the client closes it or
+    /// sends `.exit`, then rolls back a transaction it left open.
+    pub fn serve_connection(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        stream: TcpStream,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let mut repl = Repl::new(Output::Json);
+        repl.flush_every = self.flush_every;
+        repl.read_only = self.read_only;
+        repl.file_access = self.file_access.clone();
+        while let Some(line) = repl::read_line(&mut reader, stop)? {
+            let step = repl.feed_line(
+                bufmgr,
+                line.trim_end_matches(&['\r', '\n'][..]),
+                &mut writer,
+            )?;
+            writer.flush()?;
+            if step == Step::Exit {
+                break;
+            }
+        }
+        repl.finish(bufmgr, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -63,6 +63,29 @@ impl<B: ByteSlice> Slotted<B> {
     fn data(&self, pointer: Pointer) -> &[u8] {
         &self.body[pointer.range()]
     }
+
+    /// Checks what the other methods take for granted of a page read from
+    /// disk: that the pointers fit before the free space and the slots
+    /// they point at pack the rest of the body without overlapping.
+    pub fn check(&self) -> Result<(), &'static str> {
+        let free_space_offset = self.header.free_space_offset as usize;
+        if free_space_offset > self.body.len() || self.pointers_size() > free_space_offset {
+            return Err("slot pointers overlap the data");
+        }
+        let mut ranges: Vec<_> = self.pointers().iter().map(Pointer::range).collect();
+        ranges.sort_by_key(|range| (range.start, range.end));
+        let mut end = free_space_offset;
+        for range in ranges {
+            if range.start != end {
+                return Err("slots overlap or leave gaps");
+            }
+            end = range.end;
+        }
+        if end != self.body.len() {
+            return Err("slots overlap or leave gaps");
+        }
+        Ok(())
+    }
 }
 
 impl<B: ByteSliceMut> Slotted<B> {
@@ -162,7 +185,7 @@ mod tests {
             slotted[index].copy_from_slice(buf);
         };
         let push = |slotted: &mut Slotted<&mut [u8]>, buf: &[u8]| {
-            let index = slotted.num_slots() as usize;
+            let index = slotted.num_slots();
             insert(slotted, index, buf);
         };
         slotted.initialize();
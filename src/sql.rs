@@ -0,0 +1,826 @@
+//! A small SQL subset, lowered into `lang` requests:
+//!
+//! ```text
+//! CREATE TABLE t (k, a, b, KEY(k))
+//! INSERT INTO t VALUES ('x', 42, NULL)
+//! SELECT a, b FROM t WHERE a >= 10 AND b IS NOT NULL ORDER BY b DESC LIMIT 5 OFFSET 10
+//! DELETE FROM t WHERE k = 'x'
+//! BEGIN, COMMIT, ROLLBACK
+//! ```
+//!
+//! Values are uninterpreted bytes as elsewhere, so a comparison with a
+//! number literal compares decimal numbers (`NumLt` and so on) and any
+//! other comparison compares bytes.
+
+use thiserror::Error;
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog;
+use crate::lang::{
+    BoolExpr, BytesExpr, CreateTableRequest, DeleteRequest, FilterPlan, InsertRequest, LimitPlan,
+    ObjectRef, PlanNode, ProjectPlan, QueryRequest, Request, SeqScanPlan, SortPlan,
+};
+use crate::table::Table;
+use crate::tuple::{BytesValue, Direction, Record};
+use crate::Result;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unexpected character {ch:?} at offset {pos}")]
+    UnexpectedChar { ch: char, pos: usize },
+    #[error("unterminated {0} starting at offset {1}")]
+    Unterminated(&'static str, usize),
+    #[error("expected {expected} at offset {pos} but found {found}")]
+    Unexpected {
+        expected: &'static str,
+        found: String,
+        pos: usize,
+    },
+    #[error("KEY columns must be the first columns of the table, in order")]
+    KeyNotPrefix,
+    #[error("table {table:?} has no column {column:?}")]
+    UnknownColumn { table: String, column: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    CreateTable {
+        name: String,
+        columns: Vec<String>,
+        key: Vec<String>,
+    },
+    Insert {
+        table: String,
+        values: Vec<Literal>,
+    },
+    Select {
+        /// `None` for `*`.
+        columns: Option<Vec<String>>,
+        table: String,
+        where_cond: Option<Cond>,
+        order_by: Vec<(String, Direction)>,
+        limit: Option<(usize, usize)>,
+    },
+    Delete {
+        table: String,
+        where_cond: Option<Cond>,
+    },
+    Begin,
+    Commit,
+    Rollback,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    /// Kept as written; values are stored as their decimal text.
+    Number(String),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cond {
+    Compare(CmpOp, Operand, Operand),
+    IsNull(Operand),
+    And(Vec<Cond>),
+    Or(Vec<Cond>),
+    Not(Box<Cond>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A keyword or an unquoted identifier.
+    Word(String),
+    /// A `"quoted"` identifier, never a keyword.
+    Quoted(String),
+    String(String),
+    Number(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => word.clone(),
+            Token::Quoted(ident) => format!("{:?}", ident),
+            Token::String(string) => format!("'{}'", string),
+            Token::Number(number) => number.clone(),
+            Token::Symbol(symbol) => symbol.to_string(),
+            Token::End => "end of input".to_string(),
+        }
+    }
+}
+
+const SYMBOLS: &[&str] = &[
+    "<>", "!=", "<=", ">=", "(", ")", ",", "*", ";", "=", "<", ">",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while let Some(ch) = sql[pos..].chars().next() {
+        let rest = &sql[pos..];
+        let starts_number = ch.is_ascii_digit()
+            || (ch == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()));
+        let (token, len) = if ch.is_whitespace() {
+            pos += ch.len_utf8();
+            continue;
+        } else if ch.is_alphabetic() || ch == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Word(rest[..len].to_string()), len)
+        } else if starts_number {
+            let len = 1 + rest[1..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len() - 1);
+            (Token::Number(rest[..len].to_string()), len)
+        } else if ch == '\'' {
+            let (text, len) = quoted(rest, ch).ok_or(Error::Unterminated("string", pos))?;
+            (Token::String(text), len)
+        } else if ch == '"' {
+            let (text, len) = quoted(rest, ch).ok_or(Error::Unterminated("identifier", pos))?;
+            (Token::Quoted(text), len)
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or(Error::UnexpectedChar { ch, pos })?;
+            (Token::Symbol(symbol), symbol.len())
+        };
+        tokens.push((token, pos));
+        pos += len;
+    }
+    tokens.push((Token::End, sql.len()));
+    Ok(tokens)
+}
+
+/// The text between the `quote` at the start of `s` and the matching one,
+/// where a doubled quote stands for itself, and the length it takes up.
+fn quoted(s: &str, quote: char) -> Option<(String, usize)> {
+    let mut text = String::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((pos, ch)) = chars.next() {
+        if ch != quote {
+            text.push(ch);
+        } else if chars.peek().map(|&(_, next)| next) == Some(quote) {
+            text.push(quote);
+            chars.next();
+        } else {
+            return Some((text, pos + 1));
+        }
+    }
+    None
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &'static str) -> Error {
+        let (token, pos) = &self.tokens[self.pos];
+        Error::Unexpected {
+            expected,
+            found: token.describe(),
+            pos: *pos,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &'static str) -> Result<(), Error> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &'static str) -> bool {
+        let found = *self.peek() == Token::Symbol(symbol);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<(), Error> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(symbol))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Token::Word(_) | Token::Quoted(_) => match self.advance() {
+                Token::Word(ident) | Token::Quoted(ident) => Ok(ident),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    /// One or more of `item` separated by commas.
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut items = vec![item(self)?];
+        while self.eat_symbol(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        match self.peek() {
+            Token::Number(number) => match number.parse() {
+                Ok(n) => {
+                    self.advance();
+                    Ok(n)
+                }
+                Err(_) => Err(self.unexpected("a non-negative integer")),
+            },
+            _ => Err(self.unexpected("a non-negative integer")),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        let statement = if self.eat_keyword("CREATE") {
+            self.create_table()?
+        } else if self.eat_keyword("INSERT") {
+            self.insert()?
+        } else if self.eat_keyword("SELECT") {
+            self.select()?
+        } else if self.eat_keyword("DELETE") {
+            self.expect_keyword("FROM")?;
+            let table = self.ident()?;
+            let where_cond = self.where_cond()?;
+            Statement::Delete { table, where_cond }
+        } else if self.eat_keyword("BEGIN") {
+            self.eat_keyword("TRANSACTION");
+            Statement::Begin
+        } else if self.eat_keyword("COMMIT") {
+            Statement::Commit
+        } else if self.eat_keyword("ROLLBACK") {
+            Statement::Rollback
+        } else {
+            return Err(self.unexpected("a statement"));
+        };
+        self.eat_symbol(";");
+        match self.peek() {
+            Token::End => Ok(statement),
+            _ => Err(self.unexpected("end of input")),
+        }
+    }
+
+    fn create_table(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("TABLE")?;
+        let name = self.ident()?;
+        self.expect_symbol("(")?;
+        let mut columns = vec![];
+        let mut key = vec![];
+        loop {
+            if self.eat_keyword("KEY") {
+                self.expect_symbol("(")?;
+                key = self.list(Self::ident)?;
+                self.expect_symbol(")")?;
+            } else {
+                columns.push(self.ident()?);
+            }
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        if key.is_empty() || !columns.starts_with(&key) {
+            return Err(Error::KeyNotPrefix);
+        }
+        Ok(Statement::CreateTable { name, columns, key })
+    }
+
+    fn insert(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("INTO")?;
+        let table = self.ident()?;
+        self.expect_keyword("VALUES")?;
+        self.expect_symbol("(")?;
+        let values = self.list(|parser| match parser.operand()? {
+            Operand::Literal(literal) => Ok(literal),
+            Operand::Column(_) => Err(parser.unexpected("a value")),
+        })?;
+        self.expect_symbol(")")?;
+        Ok(Statement::Insert { table, values })
+    }
+
+    fn select(&mut self) -> Result<Statement, Error> {
+        let columns = if self.eat_symbol("*") {
+            None
+        } else {
+            Some(self.list(Self::ident)?)
+        };
+        self.expect_keyword("FROM")?;
+        let table = self.ident()?;
+        let where_cond = self.where_cond()?;
+        let mut order_by = vec![];
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order_by = self.list(|parser| {
+                let column = parser.ident()?;
+                let direction = if parser.eat_keyword("DESC") {
+                    Direction::Desc
+                } else {
+                    parser.eat_keyword("ASC");
+                    Direction::Asc
+                };
+                Ok((column, direction))
+            })?;
+        }
+        let mut limit = None;
+        if self.eat_keyword("LIMIT") {
+            let n = self.usize()?;
+            let offset = if self.eat_keyword("OFFSET") {
+                self.usize()?
+            } else {
+                0
+            };
+            limit = Some((n, offset));
+        }
+        Ok(Statement::Select {
+            columns,
+            table,
+            where_cond,
+            order_by,
+            limit,
+        })
+    }
+
+    fn where_cond(&mut self) -> Result<Option<Cond>, Error> {
+        if self.eat_keyword("WHERE") {
+            Ok(Some(self.or()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn or(&mut self) -> Result<Cond, Error> {
+        let mut conds = vec![self.and()?];
+        while self.eat_keyword("OR") {
+            conds.push(self.and()?);
+        }
+        Ok(if conds.len() == 1 {
+            conds.pop().unwrap()
+        } else {
+            Cond::Or(conds)
+        })
+    }
+
+    fn and(&mut self) -> Result<Cond, Error> {
+        let mut conds = vec![self.not()?];
+        while self.eat_keyword("AND") {
+            conds.push(self.not()?);
+        }
+        Ok(if conds.len() == 1 {
+            conds.pop().unwrap()
+        } else {
+            Cond::And(conds)
+        })
+    }
+
+    fn not(&mut self) -> Result<Cond, Error> {
+        if self.eat_keyword("NOT") {
+            return Ok(Cond::Not(Box::new(self.not()?)));
+        }
+        if self.eat_symbol("(") {
+            let cond = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(cond);
+        }
+        let lhs = self.operand()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            let cond = Cond::IsNull(lhs);
+            return Ok(if negated {
+                Cond::Not(Box::new(cond))
+            } else {
+                cond
+            });
+        }
+        let op = match self.peek() {
+            Token::Symbol("=") => CmpOp::Eq,
+            Token::Symbol("<>") | Token::Symbol("!=") => CmpOp::Ne,
+            Token::Symbol("<") => CmpOp::Lt,
+            Token::Symbol("<=") => CmpOp::Le,
+            Token::Symbol(">") => CmpOp::Gt,
+            Token::Symbol(">=") => CmpOp::Ge,
+            _ => return Err(self.unexpected("a comparison")),
+        };
+        self.advance();
+        let rhs = self.operand()?;
+        Ok(Cond::Compare(op, lhs, rhs))
+    }
+
+    fn operand(&mut self) -> Result<Operand, Error> {
+        if self.eat_keyword("NULL") {
+            return Ok(Operand::Literal(Literal::Null));
+        }
+        match self.peek() {
+            Token::String(_) | Token::Number(_) => match self.advance() {
+                Token::String(string) => Ok(Operand::Literal(Literal::String(string))),
+                Token::Number(number) => Ok(Operand::Literal(Literal::Number(number))),
+                _ => unreachable!(),
+            },
+            Token::Word(_) | Token::Quoted(_) => Ok(Operand::Column(self.ident()?)),
+            _ => Err(self.unexpected("a column or a value")),
+        }
+    }
+}
+
+pub fn parse(sql: &str) -> Result<Statement, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+    };
+    parser.statement()
+}
+
+impl Literal {
+    fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Literal::String(string) | Literal::Number(string) => Some(string.into_bytes()),
+            Literal::Null => None,
+        }
+    }
+}
+
+/// Looks up column names in a table of the catalog.
+struct Columns<'a>(&'a Table);
+
+impl Columns<'_> {
+    fn index(&self, column: &str) -> Result<usize, Error> {
+        self.0
+            .column_index(column)
+            .ok_or_else(|| Error::UnknownColumn {
+                table: self.0.name.clone(),
+                column: column.to_string(),
+            })
+    }
+
+    fn operand(&self, operand: Operand) -> Result<BytesExpr, Error> {
+        Ok(match operand {
+            Operand::Column(column) => BytesExpr::Column(self.index(&column)?),
+            Operand::Literal(literal) => match literal.into_bytes() {
+                Some(bytes) => BytesExpr::Literal(BytesValue(bytes)),
+                None => BytesExpr::Null,
+            },
+        })
+    }
+
+    fn cond(&self, cond: Cond) -> Result<BoolExpr, Error> {
+        let conds = |conds: Vec<Cond>| {
+            conds
+                .into_iter()
+                .map(|cond| self.cond(cond))
+                .collect::<Result<_, _>>()
+        };
+        Ok(match cond {
+            Cond::Compare(op, lhs, rhs) => {
+                let numeric = [&lhs, &rhs]
+                    .iter()
+                    .any(|operand| matches!(operand, Operand::Literal(Literal::Number(_))));
+                let (lhs, rhs) = (self.operand(lhs)?, self.operand(rhs)?);
+                match (op, numeric) {
+                    (CmpOp::Eq, false) => BoolExpr::Eq(lhs, rhs),
+                    (CmpOp::Ne, false) => BoolExpr::Ne(lhs, rhs),
+                    (CmpOp::Lt, false) => BoolExpr::Lt(lhs, rhs),
+                    (CmpOp::Le, false) => BoolExpr::Le(lhs, rhs),
+                    (CmpOp::Gt, false) => BoolExpr::Gt(lhs, rhs),
+                    (CmpOp::Ge, false) => BoolExpr::Ge(lhs, rhs),
+                    (CmpOp::Eq, true) => BoolExpr::NumEq(lhs, rhs),
+                    (CmpOp::Ne, true) => BoolExpr::NumNe(lhs, rhs),
+                    (CmpOp::Lt, true) => BoolExpr::NumLt(lhs, rhs),
+                    (CmpOp::Le, true) => BoolExpr::NumLe(lhs, rhs),
+                    (CmpOp::Gt, true) => BoolExpr::NumGt(lhs, rhs),
+                    (CmpOp::Ge, true) => BoolExpr::NumGe(lhs, rhs),
+                }
+            }
+            Cond::IsNull(operand) => BoolExpr::IsNull(self.operand(operand)?),
+            Cond::And(and) => BoolExpr::And(conds(and)?),
+            Cond::Or(or) => BoolExpr::Or(conds(or)?),
+            Cond::Not(cond) => BoolExpr::Not(Box::new(self.cond(*cond)?)),
+        })
+    }
+
+    /// The rows of the table that `where_cond` accepts.
+    fn scan(&self, where_cond: Option<Cond>) -> Result<PlanNode, Error> {
+        let scan = PlanNode::SeqScan(SeqScanPlan {
+            table: ObjectRef::Name(self.0.name.clone()),
+            key: None,
+            to: None,
+            while_expr: Default::default(),
+            direction: Direction::Asc,
+        });
+        Ok(match where_cond {
+            Some(cond) => PlanNode::Filter(FilterPlan {
+                cond: self.cond(cond)?,
+                from: Box::new(scan),
+            }),
+            None => scan,
+        })
+    }
+}
+
+impl Statement {
+    /// Lowers the statement into a request, looking up column names in
+    /// `tables`.
+    pub fn into_request(self, tables: &[Table]) -> Result<Request> {
+        let find = |name: &str| {
+            tables
+                .iter()
+                .find(|table| table.name == name)
+                .map(Columns)
+                .ok_or_else(|| catalog::Error::TableNotFound(name.to_string()))
+        };
+        Ok(match self {
+            Statement::CreateTable { name, columns, key } => {
+                Request::CreateTable(CreateTableRequest {
+                    name,
+                    num_key_elems: key.len(),
+                    num_columns: columns.len(),
+                    column_names: columns,
+                    schema: None,
+                    constraints: vec![],
+                    foreign_keys: vec![],
+                    expiry_column: None,
+                    format: Default::default(),
+                    indices: vec![],
+                })
+            }
+            Statement::Insert { table, values } => Request::Insert(InsertRequest {
+                table: ObjectRef::Name(table),
+                record: Record(values.into_iter().map(Literal::into_bytes).collect()),
+            }),
+            Statement::Select {
+                columns,
+                table,
+                where_cond,
+                order_by,
+                limit,
+            } => {
+                let table = find(&table)?;
+                let mut plan = table.scan(where_cond)?;
+                if !order_by.is_empty() {
+                    let by = order_by
+                        .iter()
+                        .map(|(column, direction)| Ok((table.index(column)?, *direction)))
+                        .collect::<Result<_, Error>>()?;
+                    plan = PlanNode::Sort(SortPlan {
+                        by,
+                        from: Box::new(plan),
+                    });
+                }
+                if let Some((limit, offset)) = limit {
+                    plan = PlanNode::Limit(LimitPlan {
+                        limit,
+                        offset,
+                        from: Box::new(plan),
+                    });
+                }
+                if let Some(columns) = columns {
+                    let columns = columns
+                        .iter()
+                        .map(|column| table.index(column))
+                        .collect::<Result<_, _>>()?;
+                    plan = PlanNode::Project(ProjectPlan {
+                        columns,
+                        from: Box::new(plan),
+                    });
+                }
+                Request::Query(QueryRequest {
+                    plan,
+                    optimize: true,
+                    analyze: false,
+                })
+            }
+            Statement::Delete { table, where_cond } => {
+                let table = find(&table)?;
+                Request::Delete(DeleteRequest {
+                    table: ObjectRef::Name(table.0.name.clone()),
+                    plan: table.scan(where_cond)?,
+                })
+            }
+            Statement::Begin => Request::Begin,
+            Statement::Commit => Request::Commit,
+            Statement::Rollback => Request::Rollback,
+        })
+    }
+}
+
+/// Parses `sql` and lowers it against the tables in the catalog.
+pub fn compile(bufmgr: &mut BufferPoolManager, sql: &str) -> Result<Request> {
+    let statement = parse(sql)?;
+    statement.into_request(&catalog::list_tables(bufmgr)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::catalog::Catalog;
+    use crate::disk::DiskManager;
+    use crate::lang::Response;
+
+    fn column(name: &str) -> Operand {
+        Operand::Column(name.to_string())
+    }
+
+    fn number(number: &str) -> Operand {
+        Operand::Literal(Literal::Number(number.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Statement::CreateTable {
+                name: "t".to_string(),
+                columns: vec!["k".to_string(), "a".to_string(), "b".to_string()],
+                key: vec!["k".to_string()],
+            },
+            parse("create table t (k, a, b, KEY(k));").unwrap()
+        );
+        assert_eq!(
+            Statement::Insert {
+                table: "t".to_string(),
+                values: vec![
+                    Literal::String("it's".to_string()),
+                    Literal::Number("-1.5".to_string()),
+                    Literal::Null,
+                ],
+            },
+            parse("INSERT INTO t VALUES ('it''s', -1.5, null)").unwrap()
+        );
+        assert_eq!(
+            Statement::Select {
+                columns: Some(vec!["a".to_string(), "select".to_string()]),
+                table: "t".to_string(),
+                where_cond: Some(Cond::Or(vec![
+                    Cond::And(vec![
+                        Cond::Compare(CmpOp::Ge, column("a"), number("10")),
+                        Cond::Not(Box::new(Cond::IsNull(column("b")))),
+                    ]),
+                    Cond::Not(Box::new(Cond::Compare(
+                        CmpOp::Ne,
+                        column("k"),
+                        Operand::Literal(Literal::String("x".to_string())),
+                    ))),
+                ])),
+                order_by: vec![
+                    ("b".to_string(), Direction::Desc),
+                    ("k".to_string(), Direction::Asc),
+                ],
+                limit: Some((5, 10)),
+            },
+            parse(
+                r#"SELECT a, "select" FROM t
+                   WHERE (a >= 10 AND b IS NOT NULL) OR NOT k <> 'x'
+                   ORDER BY b DESC, k LIMIT 5 OFFSET 10"#
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Statement::Delete {
+                table: "t".to_string(),
+                where_cond: None,
+            },
+            parse("DELETE FROM t").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |sql| parse(sql).unwrap_err().to_string();
+        assert_eq!(
+            "expected a statement at offset 0 but found UPDATE",
+            error("UPDATE t SET a = 1")
+        );
+        assert_eq!(
+            "expected a comparison at offset 23 but found end of input",
+            error("SELECT * FROM t WHERE a")
+        );
+        assert_eq!(
+            "expected end of input at offset 16 but found x",
+            error("SELECT * FROM t x")
+        );
+        assert_eq!(
+            "expected a non-negative integer at offset 22 but found -1",
+            error("SELECT * FROM t LIMIT -1")
+        );
+        assert_eq!(
+            "unterminated string starting at offset 22",
+            error("INSERT INTO t VALUES ('a)")
+        );
+        assert_eq!(
+            "unexpected character '?' at offset 26",
+            error("SELECT * FROM t WHERE a = ?")
+        );
+        assert_eq!(
+            "KEY columns must be the first columns of the table, in order",
+            error("CREATE TABLE t (k, a, KEY(a))")
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        Catalog::open_or_create(&mut bufmgr).unwrap();
+        let mut execute = |sql: &str| compile(&mut bufmgr, sql)?.execute(&mut bufmgr);
+        let rows = |resp: Result<Response>| match resp.unwrap() {
+            Response::Query(resp) => serde_json::to_value(&resp.rows).unwrap(),
+            resp => panic!("unexpected response: {:?}", resp),
+        };
+
+        execute("CREATE TABLE people (id, first_name, last_name, age, KEY(id))").unwrap();
+        for values in &[
+            "('z', 'Alice', 'Smith', 31)",
+            "('x', 'Bob', 'Johnson', 9)",
+            "('y', 'Charlie', 'Williams', 100)",
+            "('w', 'Dave', 'Miller', NULL)",
+            "('v', 'Eve', 'Brown', 45)",
+        ] {
+            execute(&format!("INSERT INTO people VALUES {}", values)).unwrap();
+        }
+        assert_eq!(
+            serde_json::json!([["Eve"], ["Bob"], ["Alice"]]),
+            rows(execute(
+                "SELECT first_name FROM people WHERE age < 50 ORDER BY last_name"
+            ))
+        );
+        assert_eq!(
+            serde_json::json!([["Miller", "w"], ["Brown", "v"]]),
+            rows(execute(
+                "SELECT last_name, id FROM people
+                 WHERE age IS NULL OR first_name >= 'Charlie' ORDER BY id DESC LIMIT 2 OFFSET 1"
+            ))
+        );
+
+        execute("DELETE FROM people WHERE age >= 45 OR age IS NULL").unwrap();
+        assert_eq!(
+            serde_json::json!([["x"], ["z"]]),
+            rows(execute("SELECT id FROM people"))
+        );
+
+        let err = execute("SELECT height FROM people").unwrap_err();
+        assert_eq!("table \"people\" has no column \"height\"", err.to_string());
+        let err = execute("SELECT * FROM nobody").unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Catalog(catalog::Error::TableNotFound(_))
+        ));
+    }
+}
@@ -1,91 +1,3825 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::btree::BTree;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::btree::{self, BTree, ScanDirection, SearchMode};
 use crate::buffer::BufferPoolManager;
+use crate::catalog::{self, Catalog};
 use crate::disk::PageId;
-use crate::tuple;
+use crate::expr::{self, BoolExpr};
+use crate::lock::LockMode;
+use crate::query::Tuple;
+use crate::tuple::{self, ColumnType, DecodeError, Direction, Element, Format, Value};
+use crate::Result;
 
-#[derive(Debug)]
-pub struct SimpleTable {
-    pub meta_page_id: PageId,
-    pub num_key_elems: usize,
+mod csv;
+mod jsonl;
+
+pub use csv::{export_csv, import_csv, CsvEncoding};
+pub use jsonl::import_jsonl;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("row not found")]
+    RowNotFound,
+    #[error("duplicate key in unique index #{0}")]
+    DuplicateIndexKey(usize),
+    #[error("record has {len} columns but num_key_elems is {num_key_elems}")]
+    TooFewColumns { len: usize, num_key_elems: usize },
+    #[error("record has {len} columns but the table has {num_columns}")]
+    ColumnCountMismatch { len: usize, num_columns: usize },
+    #[error("key has {len} elements but num_key_elems is {num_key_elems}")]
+    KeyArityMismatch { len: usize, num_key_elems: usize },
+    #[error("index key has {len} elements but the index has {num_skey_elems} columns")]
+    SkeyArityMismatch { len: usize, num_skey_elems: usize },
+    #[error("no unique index #{0}")]
+    IndexNotFound(usize),
+    #[error("unique index #{0} refers to a row that is not in the table")]
+    DanglingIndexEntry(usize),
+    #[error("index references column {column} of a {len}-column record")]
+    IndexColumnOutOfRange { column: usize, len: usize },
+    #[error("index has {len} directions but {num_skey_elems} key columns")]
+    TooManyDirections { len: usize, num_skey_elems: usize },
+    #[error("primary key column {0} is NULL")]
+    NullKey(usize),
+    #[error("batch insert stopped after {inserted} rows")]
+    BatchInsert {
+        inserted: usize,
+        source: Box<crate::Error>,
+    },
+    #[error("import failed at line {line} after {inserted} rows")]
+    ImportFailed {
+        line: usize,
+        inserted: usize,
+        source: Box<crate::Error>,
+    },
+    #[error("more than {0} rows")]
+    TooManyRows(usize),
+    #[error("constraint {name:?} is violated by {:?}", tuple::Pretty(.record))]
+    ConstraintViolation { name: String, record: Tuple },
+    #[error("constraint {name:?} references column {column} of a {len}-column table")]
+    ConstraintColumnOutOfRange {
+        name: String,
+        column: usize,
+        len: usize,
+    },
+    #[error(transparent)]
+    Expr(#[from] expr::Error),
+    #[error("foreign key has {len} columns but the parent key has {num_key_elems}")]
+    ForeignKeyArityMismatch { len: usize, num_key_elems: usize },
+    #[error("no row in parent table {0:?} matches the foreign key")]
+    ParentRowNotFound(String),
+    #[error("row is referenced by table {0:?}")]
+    RowReferenced(String),
+    #[error("table is referenced by a foreign key of table {0:?}")]
+    TableReferenced(String),
+    #[error("table {0:?} has no index on its foreign key columns")]
+    NoForeignKeyIndex(String),
+    #[error("table has no schema")]
+    NoSchema,
+    #[error("{len} column names given but the table has {num_columns} columns")]
+    ColumnNamesMismatch { len: usize, num_columns: usize },
+    #[error("schema has {len} columns but the table has {num_columns}")]
+    SchemaMismatch { len: usize, num_columns: usize },
+    #[error("column {column} expects {expected:?} but got {actual:?}")]
+    TypeMismatch {
+        column: usize,
+        expected: ColumnType,
+        actual: ColumnType,
+    },
+    #[error("column {0:?} already exists")]
+    DuplicateColumn(String),
+    #[error("index {0:?} already exists")]
+    DuplicateIndexName(String),
+    #[error("expiry column {0} is not a value column")]
+    InvalidExpiryColumn(usize),
+    #[error("expiry column {0} must be NULL or an 8-byte big-endian u64")]
+    InvalidExpiry(usize),
+    #[error("btree records unknown tuple format {0}")]
+    UnknownFormat(u64),
+    #[error("encoded row does not fit in a page")]
+    RowTooLarge,
+    #[error("odd number of hex digits")]
+    OddHexDigits,
+    #[error("invalid hex digits {0:?}")]
+    InvalidHexDigits(String),
+    #[error("line {0}: unterminated quoted field")]
+    UnterminatedField(usize),
 }
 
-impl SimpleTable {
-    pub fn create(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
-        let btree = BTree::create(bufmgr)?;
-        self.meta_page_id = btree.meta_page_id;
-        Ok(())
+const BATCH_CHUNK_SIZE: usize = 1 << 16;
+
+/// Records that the record at `pos` is rejected, keeping the earliest
+/// rejection and, for the same record, the first error reported.
+fn reject(rejection: &mut Option<(usize, crate::Error)>, pos: usize, err: crate::Error) {
+    match rejection {
+        Some((rejected, _)) if *rejected <= pos => {}
+        _ => *rejection = Some((pos, err)),
     }
+}
 
-    pub fn insert(&self, bufmgr: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
-        let btree = BTree::new(self.meta_page_id);
-        let mut key = vec![];
-        tuple::encode(record[..self.num_key_elems].iter(), &mut key);
-        let mut value = vec![];
-        tuple::encode(record[self.num_key_elems..].iter(), &mut value);
-        btree.insert(bufmgr, &key, &value)?;
-        Ok(())
+/// A record read by an import, or why it could not be read, along with the
+/// line it starts on.
+type ImportRecord = (usize, Result<Vec<Option<Vec<u8>>>>);
+
+/// Inserts the records of an import with `Table::insert_batch`, a chunk at
+/// a time, and returns the number of rows inserted. The first record that
+/// cannot be read or inserted, or that goes past `max_rows`, stops the
+/// import with `Error::ImportFailed` after every record before it.
+fn import_records(
+    bufmgr: &mut BufferPoolManager,
+    table: &Table,
+    records: impl Iterator<Item = Result<ImportRecord>>,
+    max_rows: Option<usize>,
+) -> Result<usize> {
+    let mut inserted = 0;
+    let mut chunk = vec![];
+    let mut lines = vec![];
+    for record in records {
+        let (line, record) = record?;
+        let record = match max_rows {
+            Some(max_rows) if inserted + chunk.len() >= max_rows => {
+                Err(Error::TooManyRows(max_rows).into())
+            }
+            _ => record,
+        };
+        match record {
+            Ok(record) => {
+                chunk.push(record);
+                lines.push(line);
+            }
+            Err(err) => {
+                inserted += import_chunk(bufmgr, table, &mut chunk, &mut lines, inserted)?;
+                return Err(Error::ImportFailed {
+                    line,
+                    inserted,
+                    source: err.into(),
+                }
+                .into());
+            }
+        }
+        if chunk.len() == BATCH_CHUNK_SIZE {
+            inserted += import_chunk(bufmgr, table, &mut chunk, &mut lines, inserted)?;
+        }
+    }
+    inserted += import_chunk(bufmgr, table, &mut chunk, &mut lines, inserted)?;
+    Ok(inserted)
+}
+
+/// Inserts and clears the records `import_records` has collected.
+fn import_chunk(
+    bufmgr: &mut BufferPoolManager,
+    table: &Table,
+    chunk: &mut Vec<Vec<Option<Vec<u8>>>>,
+    lines: &mut Vec<usize>,
+    inserted: usize,
+) -> Result<usize> {
+    match table.insert_batch(bufmgr, chunk.drain(..)) {
+        Ok(len) => {
+            lines.clear();
+            Ok(len)
+        }
+        Err(crate::Error::Table(Error::BatchInsert {
+            inserted: len,
+            source,
+        })) => Err(Error::ImportFailed {
+            line: lines[len],
+            inserted: inserted + len,
+            source,
+        }
+        .into()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Encodes an index entry's value: the pkey followed by the included columns.
+fn encode_index_value(
+    format: Format,
+    pkey: &[u8],
+    include: &[usize],
+    record: &[impl Element],
+) -> Vec<u8> {
+    let mut value = pkey.to_vec();
+    format.encode(include.iter().map(|&index| &record[index]), &mut value);
+    value
+}
+
+/// The tuple format recorded in the meta page of a table or index btree.
+pub(crate) fn btree_format(bufmgr: &mut BufferPoolManager, meta_page_id: PageId) -> Result<Format> {
+    let tag = BTree::new(meta_page_id).key_format(bufmgr)?;
+    Ok(Format::from_tag(tag).ok_or(Error::UnknownFormat(tag))?)
+}
+
+fn pad_record(
+    record: &mut Tuple,
+    num_columns: usize,
+    padding: &[Option<Vec<u8>>],
+) -> Result<(), DecodeError> {
+    let first_padded = num_columns - padding.len();
+    if record.len() < first_padded {
+        return Err(DecodeError::Truncated);
+    }
+    while record.len() < num_columns {
+        record.push(padding[record.len() - first_padded].clone());
     }
+    Ok(())
+}
+
+fn validate_skey(skey: &[usize], directions: &[Direction], len: usize) -> Result<(), Error> {
+    if directions.len() > skey.len() {
+        return Err(Error::TooManyDirections {
+            len: directions.len(),
+            num_skey_elems: skey.len(),
+        });
+    }
+    match skey.iter().find(|&&column| column >= len) {
+        Some(&column) => Err(Error::IndexColumnOutOfRange { column, len }),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VacuumReport {
+    /// Pages of the table and index btrees, meta pages included.
+    pub pages_before: u64,
+    pub pages_after: u64,
+    pub rows_copied: u64,
+}
+
+/// A snapshot of the table taken by `Table::analyze`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub row_count: u64,
+    /// Average encoded size in bytes of the primary key.
+    pub avg_key_size: f64,
+    /// Average encoded size in bytes of the non-key columns.
+    pub avg_value_size: f64,
+    /// The number of distinct values, NULL included, of each column that
+    /// appears in an index skey.
+    pub distinct_values: BTreeMap<usize, u64>,
+}
+
+/// Hides rows whose expiry column holds a time, in seconds since the Unix
+/// epoch, at or before `now`. Rows with a NULL expiry never expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expiry {
+    pub column: usize,
+    pub now: u64,
+}
+
+impl Expiry {
+    pub fn is_expired(&self, record: &[impl Element]) -> bool {
+        match record[self.column].as_elem().map(TryInto::try_into) {
+            Some(Ok(bytes)) => u64::from_be_bytes(bytes) <= self.now,
+            _ => false,
+        }
+    }
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// A check constraint. Like SQL `CHECK`, a row is rejected only when
+/// `expr` evaluates to false; an unknown result lets it through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Constraint {
+    pub name: String,
+    pub expr: BoolExpr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OnDelete {
+    /// Deleting a referenced parent row fails.
+    #[default]
+    Restrict,
+    /// Deleting a parent row deletes the rows referencing it.
+    Cascade,
+}
+
+/// A column appended by `Table::add_column`. Rows stored before it was
+/// added lack the column and read back with `default`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddedColumn {
+    pub name: String,
+    pub ty: ColumnType,
+    pub default: Value,
+}
+
+/// A reference from `columns` to the primary key of table `parent`. Rows
+/// with a NULL in any of `columns` reference nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ForeignKey {
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::columns))]
+    pub columns: Vec<usize>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzz::name))]
+    pub parent: String,
+    #[serde(default)]
+    pub on_delete: OnDelete,
 }
 
 #[derive(Debug)]
 pub struct Table {
+    pub name: String,
     pub meta_page_id: PageId,
     pub num_key_elems: usize,
+    pub num_columns: usize,
+    pub schema: Option<Vec<ColumnType>>,
+    pub constraints: Vec<Constraint>,
+    /// `create` adds a secondary index on the columns of each foreign key
+    /// that lacks one, so parent deletes can find referencing rows.
+    pub foreign_keys: Vec<ForeignKey>,
+    /// The names of the columns the table was created with, or empty if
+    /// they are unnamed.
+    pub column_names: Vec<String>,
+    /// The trailing columns added after the table was created, oldest first.
+    pub added_columns: Vec<AddedColumn>,
+    /// Set by `analyze`.
+    pub stats: Option<TableStats>,
+    /// A value column holding each row's expiry time as a big-endian u64;
+    /// see `Expiry`. `create` adds a secondary index on it if one is missing.
+    pub expiry_column: Option<usize>,
+    /// The encoding of the keys and values of the table and its indices.
+    pub format: Format,
     pub unique_indices: Vec<UniqueIndex>,
+    pub secondary_indices: Vec<SecondaryIndex>,
 }
 
 impl Table {
-    pub fn create(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
-        let btree = BTree::create(bufmgr)?;
+    /// A table yet to be `create`d, without a schema, constraints or
+    /// indices; set those with struct update syntax.
+    pub fn new(name: &str, num_key_elems: usize, num_columns: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems,
+            num_columns,
+            schema: None,
+            constraints: vec![],
+            foreign_keys: vec![],
+            column_names: vec![],
+            added_columns: vec![],
+            stats: None,
+            expiry_column: None,
+            format: Format::Blocks,
+            unique_indices: vec![],
+            secondary_indices: vec![],
+        }
+    }
+
+    /// The position of the column called `name`, whether it was named when
+    /// the table was created or added later.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        let first_added = self.num_columns - self.added_columns.len();
+        self.column_names
+            .iter()
+            .position(|column| column == name)
+            .or_else(|| {
+                self.added_columns
+                    .iter()
+                    .position(|column| column.name == name)
+                    .map(|i| first_added + i)
+            })
+    }
+
+    /// The defaults of the added columns, which pad rows stored before
+    /// those columns existed.
+    fn padding(&self) -> Vec<Option<Vec<u8>>> {
+        self.added_columns
+            .iter()
+            .map(|column| tuple::encode_value(&column.default))
+            .collect()
+    }
+
+    /// Pads a row as stored, which lacks the columns added after it was
+    /// written, to `num_columns`. Fails for any other length.
+    pub(crate) fn pad(&self, record: &mut Tuple) -> Result<(), Error> {
+        let padding = self.padding();
+        let first_padded = self.num_columns - padding.len();
+        if record.len() < first_padded || record.len() > self.num_columns {
+            return Err(Error::ColumnCountMismatch {
+                len: record.len(),
+                num_columns: self.num_columns,
+            });
+        }
+        let missing = &padding[record.len() - first_padded..];
+        record.extend(missing.iter().cloned());
+        Ok(())
+    }
+
+    fn decode_row(&self, key: &[u8], value: &[u8]) -> Result<Tuple, DecodeError> {
+        let mut record = vec![];
+        self.format.decode_nullable(key, &mut record)?;
+        self.format.decode_nullable(value, &mut record)?;
+        pad_record(&mut record, self.num_columns, &self.padding())?;
+        Ok(record)
+    }
+
+    fn validate_record(&self, record: &[impl Element]) -> Result<(), Error> {
+        if record.len() < self.num_key_elems {
+            return Err(Error::TooFewColumns {
+                len: record.len(),
+                num_key_elems: self.num_key_elems,
+            });
+        }
+        if record.len() != self.num_columns {
+            return Err(Error::ColumnCountMismatch {
+                len: record.len(),
+                num_columns: self.num_columns,
+            });
+        }
+        let null_key = record[..self.num_key_elems]
+            .iter()
+            .position(|elem| elem.as_elem().is_none());
+        if let Some(column) = null_key {
+            return Err(Error::NullKey(column));
+        }
+        if let Some(column) = self.expiry_column {
+            match record[column].as_elem() {
+                Some(elem) if elem.len() != 8 => return Err(Error::InvalidExpiry(column)),
+                _ => {}
+            }
+        }
+        if !self.fits(record) {
+            return Err(Error::RowTooLarge);
+        }
+        if self.constraints.is_empty() {
+            return Ok(());
+        }
+        let record: Tuple = record
+            .iter()
+            .map(|elem| elem.as_elem().map(<[u8]>::to_vec))
+            .collect();
+        for constraint in &self.constraints {
+            if constraint.expr.eval(&record)? == Some(false) {
+                return Err(Error::ConstraintViolation {
+                    name: constraint.name.clone(),
+                    record,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the row and its index entries fit in btree pages, computed
+    /// without encoding them.
+    fn fits(&self, record: &[impl Element]) -> bool {
+        let len = |columns: &[usize]| {
+            self.format
+                .encoded_len(columns.iter().map(|&column| &record[column]))
+        };
+        let key_len = self.format.encoded_len(record[..self.num_key_elems].iter());
+        let value_len = self.format.encoded_len(record[self.num_key_elems..].iter());
+        btree::fits(key_len, value_len)
+            && self
+                .unique_indices
+                .iter()
+                .all(|index| btree::fits(len(&index.skey), key_len + len(&index.include)))
+            && self
+                .secondary_indices
+                .iter()
+                .all(|index| btree::fits(len(&index.skey) + key_len, key_len + len(&index.include)))
+    }
+
+    fn validate_pkey(&self, pkey_elems: &[&[u8]]) -> Result<(), Error> {
+        if pkey_elems.len() != self.num_key_elems {
+            return Err(Error::KeyArityMismatch {
+                len: pkey_elems.len(),
+                num_key_elems: self.num_key_elems,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that every foreign key of `record` matches a parent row. A
+    /// key of this table may also match one of `pending_keys`, the rows
+    /// inserted before it in the same batch.
+    fn check_references(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        record: &[impl Element],
+        pending_keys: &HashSet<Vec<u8>>,
+    ) -> Result<()> {
+        for foreign_key in &self.foreign_keys {
+            let elems = foreign_key
+                .columns
+                .iter()
+                .map(|&column| record[column].as_elem())
+                .collect::<Option<Vec<_>>>();
+            let elems = match elems {
+                Some(elems) => elems,
+                None => continue,
+            };
+            let (parent_meta_page_id, parent_format) = if foreign_key.parent == self.name {
+                (self.meta_page_id, self.format)
+            } else {
+                let parent = catalog::open_table(bufmgr, &foreign_key.parent)?;
+                (parent.meta_page_id, parent.format)
+            };
+            let mut key = vec![];
+            parent_format.encode(elems.iter(), &mut key);
+            if foreign_key.parent == self.name && pending_keys.contains(&key) {
+                continue;
+            }
+            if BTree::new(parent_meta_page_id).get(bufmgr, &key)?.is_none() {
+                return Err(Error::ParentRowNotFound(foreign_key.parent.clone()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The filter hiding rows expired at `now`, if the table has an expiry column.
+    pub fn expiry(&self, now: u64) -> Option<Expiry> {
+        self.expiry_column.map(|column| Expiry { column, now })
+    }
+
+    fn expiry_index(&self) -> Option<&SecondaryIndex> {
+        let column = self.expiry_column?;
+        self.secondary_indices.iter().find(|index| {
+            index.skey == [column] && index.directions.iter().all(|&d| d == Direction::Asc)
+        })
+    }
+
+    fn foreign_key_index(&self, foreign_key: &ForeignKey) -> Result<&SecondaryIndex, Error> {
+        self.secondary_indices
+            .iter()
+            .find(|index| index.skey == foreign_key.columns)
+            .ok_or_else(|| Error::NoForeignKeyIndex(self.name.clone()))
+    }
+
+    /// Creates the table btree and its indices, records the table in the
+    /// catalog and returns the table's meta page id.
+    pub fn create(&mut self, bufmgr: &mut BufferPoolManager) -> Result<PageId> {
+        if self.num_key_elems > self.num_columns {
+            return Err(Error::TooFewColumns {
+                len: self.num_columns,
+                num_key_elems: self.num_key_elems,
+            }
+            .into());
+        }
+        if let Some(schema) = &self.schema {
+            if schema.len() != self.num_columns {
+                return Err(Error::SchemaMismatch {
+                    len: schema.len(),
+                    num_columns: self.num_columns,
+                }
+                .into());
+            }
+        }
+        if !self.column_names.is_empty() && self.column_names.len() != self.num_columns {
+            return Err(Error::ColumnNamesMismatch {
+                len: self.column_names.len(),
+                num_columns: self.num_columns,
+            }
+            .into());
+        }
+        for (i, name) in self.column_names.iter().enumerate() {
+            if self.column_names[..i].contains(name) {
+                return Err(Error::DuplicateColumn(name.clone()).into());
+            }
+        }
+        for constraint in &self.constraints {
+            match constraint.expr.max_column() {
+                Some(column) if column >= self.num_columns => {
+                    return Err(Error::ConstraintColumnOutOfRange {
+                        name: constraint.name.clone(),
+                        column,
+                        len: self.num_columns,
+                    }
+                    .into());
+                }
+                _ => {}
+            }
+        }
+        for unique_index in &self.unique_indices {
+            validate_skey(
+                &unique_index.skey,
+                &unique_index.directions,
+                self.num_columns,
+            )?;
+            validate_skey(&unique_index.include, &[], self.num_columns)?;
+        }
+        for secondary_index in &self.secondary_indices {
+            validate_skey(
+                &secondary_index.skey,
+                &secondary_index.directions,
+                self.num_columns,
+            )?;
+            validate_skey(&secondary_index.include, &[], self.num_columns)?;
+        }
+        for foreign_key in &self.foreign_keys {
+            validate_skey(&foreign_key.columns, &[], self.num_columns)?;
+        }
+        if let Some(column) = self.expiry_column {
+            if column < self.num_key_elems || column >= self.num_columns {
+                return Err(Error::InvalidExpiryColumn(column).into());
+            }
+            match self.schema.as_ref().map(|schema| schema[column]) {
+                Some(actual) if actual != ColumnType::U64 => {
+                    return Err(Error::TypeMismatch {
+                        column,
+                        expected: ColumnType::U64,
+                        actual,
+                    }
+                    .into());
+                }
+                _ => {}
+            }
+        }
+        let catalog = Catalog::open_or_create(bufmgr)?;
+        if catalog.get(bufmgr, &self.name)?.is_some() {
+            return Err(catalog::Error::TableExists(self.name.clone()).into());
+        }
+        for foreign_key in &self.foreign_keys {
+            let num_key_elems = if foreign_key.parent == self.name {
+                self.num_key_elems
+            } else {
+                catalog
+                    .get(bufmgr, &foreign_key.parent)?
+                    .ok_or_else(|| catalog::Error::TableNotFound(foreign_key.parent.clone()))?
+                    .num_key_elems
+            };
+            if foreign_key.columns.len() != num_key_elems {
+                return Err(Error::ForeignKeyArityMismatch {
+                    len: foreign_key.columns.len(),
+                    num_key_elems,
+                }
+                .into());
+            }
+        }
+        for foreign_key in &self.foreign_keys {
+            if self.foreign_key_index(foreign_key).is_err() {
+                self.secondary_indices.push(SecondaryIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: foreign_key.columns.clone(),
+                    directions: vec![],
+                    include: vec![],
+                });
+            }
+        }
+        if let Some(column) = self.expiry_column {
+            if self.expiry_index().is_none() {
+                self.secondary_indices.push(SecondaryIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: vec![column],
+                    directions: vec![],
+                    include: vec![],
+                });
+            }
+        }
+        let btree = BTree::create_with_key_format(bufmgr, self.format.tag())?;
+        btree.set_key_arity(bufmgr, self.num_key_elems as u64)?;
         self.meta_page_id = btree.meta_page_id;
         for unique_index in &mut self.unique_indices {
-            unique_index.create(bufmgr)?;
+            unique_index.create(bufmgr, self.format)?;
         }
+        for secondary_index in &mut self.secondary_indices {
+            secondary_index.create(bufmgr, self.format)?;
+        }
+        catalog.insert(bufmgr, self)?;
+        Ok(self.meta_page_id)
+    }
+
+    /// Destroys the table and all of its indices and removes it from the
+    /// catalog. Indices that were never created are skipped. A table that
+    /// another table references through a foreign key is not dropped.
+    pub fn drop(self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        let referencing_tables = self.referencing_tables(bufmgr)?;
+        if let Some(child) = referencing_tables
+            .iter()
+            .find(|table| table.name != self.name)
+        {
+            return Err(Error::TableReferenced(child.name.clone()).into());
+        }
+        let index_meta_page_ids = self
+            .unique_indices
+            .iter()
+            .map(|index| index.meta_page_id)
+            .chain(
+                self.secondary_indices
+                    .iter()
+                    .map(|index| index.meta_page_id),
+            );
+        for meta_page_id in index_meta_page_ids {
+            if meta_page_id.valid().is_some() {
+                BTree::new(meta_page_id).destroy(bufmgr)?;
+            }
+        }
+        if self.meta_page_id.valid().is_some() {
+            BTree::new(self.meta_page_id).destroy(bufmgr)?;
+        }
+        match Catalog::open(bufmgr) {
+            Ok(catalog) => {
+                if catalog.get(bufmgr, &self.name)?.is_some() {
+                    catalog.remove(bufmgr, &self.name)?;
+                }
+            }
+            Err(crate::Error::Catalog(catalog::Error::NoCatalog)) => {}
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Removes every row from the table and its indices. All meta page ids
+    /// stay the same, so the catalog entry remains valid. Rows of other
+    /// tables referencing the rows removed are deleted or keep the table
+    /// from being truncated, as `delete` has them, and every row is checked
+    /// before anything is removed.
+    pub fn truncate(&self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        let tables = self.referencing_tables(bufmgr)?;
+        if !tables.is_empty() {
+            let mut keys = vec![];
+            let mut iter = BTree::new(self.meta_page_id).search(bufmgr, SearchMode::Start)?;
+            while let Some((key, _)) = iter.next(bufmgr)? {
+                keys.push(key);
+            }
+            drop(iter);
+            let mut referencing_rows = BTreeSet::new();
+            for key in keys {
+                referencing_rows.extend(self.find_referencing_rows(bufmgr, &tables, &key)?);
+            }
+            for (table_no, child_key) in referencing_rows {
+                // the rows of the table itself go with the rest
+                if tables[table_no].name != self.name {
+                    tables[table_no].delete_row(bufmgr, &child_key)?;
+                }
+            }
+        }
+        let index_meta_page_ids = self
+            .unique_indices
+            .iter()
+            .map(|index| index.meta_page_id)
+            .chain(
+                self.secondary_indices
+                    .iter()
+                    .map(|index| index.meta_page_id),
+            );
+        for meta_page_id in index_meta_page_ids {
+            BTree::new(meta_page_id).recreate(bufmgr)?;
+        }
+        BTree::new(self.meta_page_id).recreate(bufmgr)?;
         Ok(())
     }
 
+    /// Rebuilds the table btree and each index btree with fully packed
+    /// pages. Every btree keeps its meta page id, so the catalog is not
+    /// touched, and a crash leaves each btree either entirely old or
+    /// entirely new. Refused with `btree::Error::InTransaction` in a
+    /// transaction, before anything is rebuilt.
+    pub fn vacuum(&self, bufmgr: &mut BufferPoolManager) -> Result<VacuumReport> {
+        let compaction = BTree::new(self.meta_page_id).compact(bufmgr)?;
+        let mut report = VacuumReport {
+            pages_before: compaction.num_pages_before,
+            pages_after: compaction.num_pages_after,
+            rows_copied: compaction.num_entries,
+        };
+        let index_meta_page_ids = self
+            .unique_indices
+            .iter()
+            .map(|index| index.meta_page_id)
+            .chain(
+                self.secondary_indices
+                    .iter()
+                    .map(|index| index.meta_page_id),
+            );
+        for meta_page_id in index_meta_page_ids {
+            let compaction = BTree::new(meta_page_id).compact(bufmgr)?;
+            report.pages_before += compaction.num_pages_before;
+            report.pages_after += compaction.num_pages_after;
+        }
+        Ok(report)
+    }
+
+    /// Builds a new index over the rows already in the table and returns
+    /// the meta page id of its btree. A unique index that turns out to have
+    /// duplicate keys is destroyed again and the table is left unchanged.
+    pub fn create_index(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        name: Option<String>,
+        skey: Vec<usize>,
+        directions: Vec<Direction>,
+        include: Vec<usize>,
+        unique: bool,
+    ) -> Result<PageId> {
+        validate_skey(&skey, &directions, self.num_columns)?;
+        validate_skey(&include, &[], self.num_columns)?;
+        if let Some(name) = &name {
+            let tables = match catalog::list_tables(bufmgr) {
+                Ok(tables) => tables,
+                Err(crate::Error::Catalog(catalog::Error::NoCatalog)) => vec![],
+                Err(err) => return Err(err),
+            };
+            let mut tables = tables.iter().chain(std::iter::once(&*self));
+            if tables.any(|table| table.index_by_name(name).is_some()) {
+                return Err(Error::DuplicateIndexName(name.clone()).into());
+            }
+        }
+        let index_btree = BTree::create_with_key_format(bufmgr, self.format.tag())?;
+        let index_meta_page_id = index_btree.meta_page_id;
+        let btree = BTree::new(self.meta_page_id);
+        let mut iter = btree.search(bufmgr, SearchMode::Start)?;
+        while let Some((key, value)) = iter.next(bufmgr)? {
+            let record = self.decode_row(&key, &value)?;
+            let result = if unique {
+                let unique_index = UniqueIndex {
+                    name: None,
+                    meta_page_id: index_meta_page_id,
+                    skey: skey.clone(),
+                    directions: directions.clone(),
+                    include: include.clone(),
+                };
+                unique_index.insert(bufmgr, &key, &record)
+            } else {
+                let secondary_index = SecondaryIndex {
+                    name: None,
+                    meta_page_id: index_meta_page_id,
+                    skey: skey.clone(),
+                    directions: directions.clone(),
+                    include: include.clone(),
+                };
+                secondary_index.insert(bufmgr, &key, &record)
+            };
+            if let Err(err) = result {
+                drop(iter);
+                BTree::new(index_meta_page_id).destroy(bufmgr)?;
+                return match err {
+                    crate::Error::BTree(btree::Error::DuplicateKey) => {
+                        Err(Error::DuplicateIndexKey(self.unique_indices.len()).into())
+                    }
+                    err => Err(err),
+                };
+            }
+        }
+        if unique {
+            self.unique_indices.push(UniqueIndex {
+                name,
+                meta_page_id: index_meta_page_id,
+                skey,
+                directions,
+                include,
+            });
+        } else {
+            self.secondary_indices.push(SecondaryIndex {
+                name,
+                meta_page_id: index_meta_page_id,
+                skey,
+                directions,
+                include,
+            });
+        }
+        self.update_catalog(bufmgr)?;
+        Ok(index_meta_page_id)
+    }
+
+    /// The meta page id, directions and included columns of the index
+    /// named `name`, if the table has one.
+    pub fn index_by_name(&self, name: &str) -> Option<(PageId, &[Direction], &[usize])> {
+        let unique = self.unique_indices.iter().map(|index| {
+            (
+                &index.name,
+                index.meta_page_id,
+                &index.directions,
+                &index.include,
+            )
+        });
+        let secondary = self.secondary_indices.iter().map(|index| {
+            (
+                &index.name,
+                index.meta_page_id,
+                &index.directions,
+                &index.include,
+            )
+        });
+        unique
+            .chain(secondary)
+            .find(|(index_name, ..)| index_name.as_deref() == Some(name))
+            .map(|(_, meta_page_id, directions, include)| {
+                (meta_page_id, &directions[..], &include[..])
+            })
+    }
+
+    /// Appends a column without rewriting existing rows, which read back
+    /// with `default` in the new column. Requires a schema.
+    pub fn add_column(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        name: &str,
+        ty: ColumnType,
+        default: Value,
+    ) -> Result<()> {
+        if self.column_index(name).is_some() {
+            return Err(Error::DuplicateColumn(name.to_string()).into());
+        }
+        let schema = self.schema.as_mut().ok_or(Error::NoSchema)?;
+        match default.column_type() {
+            Some(actual) if actual != ty => {
+                return Err(Error::TypeMismatch {
+                    column: self.num_columns,
+                    expected: ty,
+                    actual,
+                }
+                .into());
+            }
+            _ => {}
+        }
+        schema.push(ty);
+        self.num_columns += 1;
+        self.added_columns.push(AddedColumn {
+            name: name.to_string(),
+            ty,
+            default,
+        });
+        self.update_catalog(bufmgr)
+    }
+
+    /// Saves the table definition if the table is in the catalog.
+    fn update_catalog(&self, bufmgr: &mut BufferPoolManager) -> Result<()> {
+        match Catalog::open(bufmgr) {
+            Ok(catalog) => {
+                if catalog.get(bufmgr, &self.name)?.is_some() {
+                    catalog.update(bufmgr, self)?;
+                }
+                Ok(())
+            }
+            Err(crate::Error::Catalog(catalog::Error::NoCatalog)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
     pub fn insert(&self, bufmgr: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
+        self.insert_record(bufmgr, record)
+    }
+
+    /// Like `insert`, but `None` columns are stored as NULL. NULLs are
+    /// rejected in primary key columns.
+    pub fn insert_nullable(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        record: &[Option<&[u8]>],
+    ) -> Result<()> {
+        self.insert_record(bufmgr, record)
+    }
+
+    fn insert_record(&self, bufmgr: &mut BufferPoolManager, record: &[impl Element]) -> Result<()> {
+        self.validate_record(record)?;
+        self.check_references(bufmgr, record, &HashSet::new())?;
         let btree = BTree::new(self.meta_page_id);
         let mut key = vec![];
-        tuple::encode(record[..self.num_key_elems].iter(), &mut key);
+        self.format
+            .encode(record[..self.num_key_elems].iter(), &mut key);
+        bufmgr.lock_row(self.meta_page_id, &key, LockMode::Exclusive)?;
+        // Check every unique index before mutating anything so a collision
+        // cannot leave the row or earlier index entries behind.
+        for (index_no, unique_index) in self.unique_indices.iter().enumerate() {
+            if let Some(skey) = unique_index.encode_skey(self.format, record) {
+                if unique_index.contains(bufmgr, &skey)? {
+                    return Err(Error::DuplicateIndexKey(index_no).into());
+                }
+            }
+        }
         let mut value = vec![];
-        tuple::encode(record[self.num_key_elems..].iter(), &mut value);
+        self.format
+            .encode(record[self.num_key_elems..].iter(), &mut value);
         btree.insert(bufmgr, &key, &value)?;
         for unique_index in &self.unique_indices {
             unique_index.insert(bufmgr, &key, record)?;
         }
+        for secondary_index in &self.secondary_indices {
+            secondary_index.insert(bufmgr, &key, record)?;
+        }
         Ok(())
     }
-}
 
-#[derive(Debug)]
-pub struct UniqueIndex {
-    pub meta_page_id: PageId,
-    pub skey: Vec<usize>,
-}
+    /// Inserts `records` in chunks, writing each chunk to the table btree
+    /// and then to each index btree in key order. Stops at the first record
+    /// that `insert` would reject, after inserting every record before it,
+    /// and reports how many were inserted in `Error::BatchInsert`.
+    pub fn insert_batch<E: Element>(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        records: impl Iterator<Item = Vec<E>>,
+    ) -> Result<usize> {
+        let mut records = records.peekable();
+        let mut inserted = 0;
+        while records.peek().is_some() {
+            let chunk: Vec<_> = records.by_ref().take(BATCH_CHUNK_SIZE).collect();
+            let (len, rejection) = self.insert_chunk(bufmgr, chunk)?;
+            inserted += len;
+            if let Some(err) = rejection {
+                return Err(Error::BatchInsert {
+                    inserted,
+                    source: err.into(),
+                }
+                .into());
+            }
+        }
+        Ok(inserted)
+    }
 
-impl UniqueIndex {
-    pub fn create(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
-        let btree = BTree::create(bufmgr)?;
-        self.meta_page_id = btree.meta_page_id;
-        Ok(())
+    /// Inserts the longest prefix of `chunk` that single inserts would
+    /// accept and returns its length along with the error for the record
+    /// that ended it, if any. Every check runs over keys in sorted order so
+    /// that lookups walk each btree from left to right.
+    fn insert_chunk<E: Element>(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        chunk: Vec<Vec<E>>,
+    ) -> Result<(usize, Option<crate::Error>)> {
+        let mut rejection = None;
+        let mut limit = chunk.len();
+        // Every record before a rejected one is inserted, so a record may
+        // reference the keys of those before it like a single insert would.
+        let self_referencing = self.foreign_keys.iter().any(|fk| fk.parent == self.name);
+        let mut pending_keys = HashSet::new();
+        for (pos, record) in chunk.iter().enumerate() {
+            let result = self
+                .validate_record(record)
+                .map_err(Into::into)
+                .and_then(|()| self.check_references(bufmgr, record, &pending_keys));
+            if let Err(err) = result {
+                rejection = Some((pos, err));
+                limit = pos;
+                break;
+            }
+            if self_referencing {
+                let mut key = vec![];
+                self.format
+                    .encode(record[..self.num_key_elems].iter(), &mut key);
+                pending_keys.insert(key);
+            }
+        }
+        let records = &chunk[..limit];
+
+        // A record colliding with an earlier one in the chunk is rejected
+        // even if the earlier one was rejected too; that can only happen
+        // after the first rejection, whose position is all that matters.
+        let mut unique_entries = vec![];
+        for (index_no, unique_index) in self.unique_indices.iter().enumerate() {
+            let mut entries: Vec<_> = records
+                .iter()
+                .enumerate()
+                .filter_map(|(pos, record)| {
+                    Some((unique_index.encode_skey(self.format, record)?, pos))
+                })
+                .collect();
+            entries.sort();
+            for (i, (skey, pos)) in entries.iter().enumerate() {
+                if (i > 0 && entries[i - 1].0 == *skey) || unique_index.contains(bufmgr, skey)? {
+                    reject(
+                        &mut rejection,
+                        *pos,
+                        Error::DuplicateIndexKey(index_no).into(),
+                    );
+                }
+            }
+            unique_entries.push(entries);
+        }
+        let btree = BTree::new(self.meta_page_id);
+        let mut keys: Vec<_> = records
+            .iter()
+            .enumerate()
+            .map(|(pos, record)| {
+                let mut key = vec![];
+                self.format
+                    .encode(record[..self.num_key_elems].iter(), &mut key);
+                (key, pos)
+            })
+            .collect();
+        keys.sort();
+        for (i, (key, pos)) in keys.iter().enumerate() {
+            if (i > 0 && keys[i - 1].0 == *key) || btree.get(bufmgr, key)?.is_some() {
+                reject(&mut rejection, *pos, btree::Error::DuplicateKey.into());
+            }
+        }
+
+        let limit = rejection.as_ref().map_or(limit, |(pos, _)| *pos);
+        for (key, _) in keys.iter().filter(|(_, pos)| *pos < limit) {
+            bufmgr.lock_row(self.meta_page_id, key, LockMode::Exclusive)?;
+        }
+        let mut pkeys = vec![vec![]; limit];
+        for (key, pos) in keys.into_iter().filter(|(_, pos)| *pos < limit) {
+            let mut value = vec![];
+            self.format
+                .encode(chunk[pos][self.num_key_elems..].iter(), &mut value);
+            btree.insert(bufmgr, &key, &value)?;
+            pkeys[pos] = key;
+        }
+        for (unique_index, entries) in self.unique_indices.iter().zip(unique_entries) {
+            let index_btree = BTree::new(unique_index.meta_page_id);
+            for (skey, pos) in entries.into_iter().filter(|(_, pos)| *pos < limit) {
+                let value = encode_index_value(
+                    self.format,
+                    &pkeys[pos],
+                    &unique_index.include,
+                    &chunk[pos],
+                );
+                index_btree.insert(bufmgr, &skey, &value)?;
+            }
+        }
+        for secondary_index in &self.secondary_indices {
+            let mut entries: Vec<_> = pkeys
+                .iter()
+                .enumerate()
+                .map(|(pos, pkey)| {
+                    let mut skey = secondary_index.encode_skey(self.format, &chunk[pos]);
+                    skey.extend_from_slice(pkey);
+                    (skey, pos)
+                })
+                .collect();
+            entries.sort();
+            let index_btree = BTree::new(secondary_index.meta_page_id);
+            for (skey, pos) in entries {
+                let value = encode_index_value(
+                    self.format,
+                    &pkeys[pos],
+                    &secondary_index.include,
+                    &chunk[pos],
+                );
+                index_btree.insert(bufmgr, &skey, &value)?;
+            }
+        }
+        Ok((limit, rejection.map(|(_, err)| err)))
     }
 
-    pub fn insert(
+    pub fn insert_typed(&self, bufmgr: &mut BufferPoolManager, values: &[Value]) -> Result<()> {
+        let schema = self.schema.as_ref().ok_or(Error::NoSchema)?;
+        if values.len() != schema.len() {
+            return Err(Error::ColumnCountMismatch {
+                len: values.len(),
+                num_columns: schema.len(),
+            }
+            .into());
+        }
+        for (column, (value, &expected)) in values.iter().zip(schema).enumerate() {
+            match value.column_type() {
+                Some(actual) if actual != expected => {
+                    return Err(Error::TypeMismatch {
+                        column,
+                        expected,
+                        actual,
+                    }
+                    .into());
+                }
+                _ => {}
+            }
+        }
+        let record: Vec<_> = values.iter().map(tuple::encode_value).collect();
+        self.insert_record(bufmgr, &record)
+    }
+
+    /// The row count kept up to date by insert and delete. It can drift
+    /// if a write is interrupted; `recount` repairs it.
+    pub fn approx_row_count(&self, bufmgr: &mut BufferPoolManager) -> Result<u64> {
+        let btree = BTree::new(self.meta_page_id);
+        Ok(btree.num_entries(bufmgr)?)
+    }
+
+    /// Counts the rows with a full scan and stores the result as the row count.
+    pub fn recount(&self, bufmgr: &mut BufferPoolManager) -> Result<u64> {
+        let mut iter = self.scan(bufmgr)?;
+        let mut row_count = 0;
+        while iter.next_raw(bufmgr)?.is_some() {
+            row_count += 1;
+        }
+        drop(iter);
+        let btree = BTree::new(self.meta_page_id);
+        btree.set_num_entries(bufmgr, row_count)?;
+        Ok(row_count)
+    }
+
+    /// Scans the whole table to compute its statistics and saves them in
+    /// the catalog.
+    pub fn analyze(&mut self, bufmgr: &mut BufferPoolManager) -> Result<TableStats> {
+        let mut columns: Vec<usize> = self
+            .unique_indices
+            .iter()
+            .flat_map(|index| &index.skey)
+            .chain(self.secondary_indices.iter().flat_map(|index| &index.skey))
+            .copied()
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        let mut values = vec![HashSet::new(); columns.len()];
+        let mut row_count = 0;
+        let mut key_size = 0;
+        let mut value_size = 0;
+        let mut iter = self.scan(bufmgr)?;
+        while let Some((key, value)) = iter.next_raw(bufmgr)? {
+            row_count += 1;
+            key_size += key.len();
+            value_size += value.len();
+            if columns.is_empty() {
+                continue;
+            }
+            let mut record = self.decode_row(&key, &value)?;
+            for (values, &column) in values.iter_mut().zip(&columns) {
+                values.insert(record[column].take());
+            }
+        }
+        drop(iter);
+        let avg = |size: usize| match row_count {
+            0 => 0.0,
+            n => size as f64 / n as f64,
+        };
+        let stats = TableStats {
+            row_count,
+            avg_key_size: avg(key_size),
+            avg_value_size: avg(value_size),
+            distinct_values: columns
+                .into_iter()
+                .zip(values)
+                .map(|(column, values)| (column, values.len() as u64))
+                .collect(),
+        };
+        self.stats = Some(stats.clone());
+        self.update_catalog(bufmgr)?;
+        Ok(stats)
+    }
+
+    /// The statistics from the last `analyze`, however stale.
+    pub fn stats(&self) -> Option<&TableStats> {
+        self.stats.as_ref()
+    }
+
+    pub fn scan(&self, bufmgr: &mut BufferPoolManager) -> Result<TableIter> {
+        self.scan_at(bufmgr, unix_now())
+    }
+
+    /// Like `scan`, but skips the rows expired at `now` rather than at the
+    /// current time.
+    pub fn scan_at(&self, bufmgr: &mut BufferPoolManager, now: u64) -> Result<TableIter> {
+        let iter = TableIter::new(
+            bufmgr,
+            self.meta_page_id,
+            SearchMode::Start,
+            ScanDirection::Forward,
+        )?;
+        Ok(iter
+            .padded(self.num_columns, self.padding())
+            .filtered(self.expiry(now)))
+    }
+
+    /// Scans from the first row whose primary key is not less than
+    /// `pkey_elems`, which may be a prefix of the key.
+    pub fn scan_from(
         &self,
         bufmgr: &mut BufferPoolManager,
-        pkey: &[u8],
-        record: &[impl AsRef<[u8]>],
+        pkey_elems: &[&[u8]],
+    ) -> Result<TableIter> {
+        if pkey_elems.len() > self.num_key_elems {
+            return Err(Error::KeyArityMismatch {
+                len: pkey_elems.len(),
+                num_key_elems: self.num_key_elems,
+            }
+            .into());
+        }
+        let mut key = vec![];
+        self.format.encode(pkey_elems.iter(), &mut key);
+        let iter = TableIter::new(
+            bufmgr,
+            self.meta_page_id,
+            SearchMode::Key(key),
+            ScanDirection::Forward,
+        )?;
+        Ok(iter
+            .padded(self.num_columns, self.padding())
+            .filtered(self.expiry(unix_now())))
+    }
+
+    /// Looks up the row whose primary key is exactly `pkey_elems`. In a
+    /// transaction, the row is locked shared if the lock manager locks
+    /// point reads.
+    pub fn get(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey_elems: &[&[u8]],
+    ) -> Result<Option<Tuple>> {
+        self.get_at(bufmgr, pkey_elems, unix_now())
+    }
+
+    /// Like `get`, but treats rows expired at `now` as missing.
+    pub fn get_at(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey_elems: &[&[u8]],
+        now: u64,
+    ) -> Result<Option<Tuple>> {
+        self.validate_pkey(pkey_elems)?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut key = vec![];
+        self.format.encode(pkey_elems.iter(), &mut key);
+        bufmgr.lock_row(self.meta_page_id, &key, LockMode::Shared)?;
+        let value = match btree.get(bufmgr, &key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let record = self.decode_row(&key, &value)?;
+        Ok(self.unexpired(record, now))
+    }
+
+    fn unexpired(&self, record: Tuple, now: u64) -> Option<Tuple> {
+        match self.expiry(now) {
+            Some(expiry) if expiry.is_expired(&record) => None,
+            _ => Some(record),
+        }
+    }
+
+    /// Looks up the row whose skey in unique index `index_no` is exactly
+    /// `skey_elems`. The row is locked as `get` locks it.
+    pub fn get_by_index(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        index_no: usize,
+        skey_elems: &[&[u8]],
+    ) -> Result<Option<Tuple>> {
+        let unique_index = self
+            .unique_indices
+            .get(index_no)
+            .ok_or(Error::IndexNotFound(index_no))?;
+        let key = match unique_index.lookup(bufmgr, skey_elems)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        bufmgr.lock_row(self.meta_page_id, &key, LockMode::Shared)?;
+        let btree = BTree::new(self.meta_page_id);
+        let value = btree
+            .get(bufmgr, &key)?
+            .ok_or(Error::DanglingIndexEntry(index_no))?;
+        let record = self.decode_row(&key, &value)?;
+        Ok(self.unexpired(record, unix_now()))
+    }
+
+    /// Deletes every row expired at `now`, walking the expiry column's
+    /// index in batches, and returns the number of rows deleted. Rows
+    /// referencing them through cascading foreign keys go too.
+    pub fn purge_expired(&self, bufmgr: &mut BufferPoolManager, now: u64) -> Result<usize> {
+        const BATCH_SIZE: usize = 256;
+
+        let index = match self.expiry_index() {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+        let index_btree = BTree::new(index.meta_page_id);
+        let expiry = Expiry { column: 0, now };
+        let tables = self.referencing_tables(bufmgr)?;
+        let mut num_purged = 0;
+        loop {
+            let mut pkeys = vec![];
+            let mut iter = index_btree.search(bufmgr, SearchMode::Start)?;
+            while pkeys.len() < BATCH_SIZE {
+                let (key, value) = match iter.next(bufmgr)? {
+                    Some(pair) => pair,
+                    None => break,
+                };
+                let mut skey = vec![];
+                self.format.decode_nullable(&key, &mut skey)?;
+                if !expiry.is_expired(&skey) {
+                    break;
+                }
+                pkeys.push(
+                    self.format
+                        .split_last(&value, index.include.len())?
+                        .0
+                        .to_vec(),
+                );
+            }
+            drop(iter);
+            for pkey in &pkeys {
+                let mut pkey_elems = vec![];
+                self.format.decode(pkey, &mut pkey_elems)?;
+                let pkey_elems: Vec<_> = pkey_elems.iter().map(Vec::as_slice).collect();
+                self.delete_with(bufmgr, &pkey_elems, &tables)?;
+            }
+            num_purged += pkeys.len();
+            if pkeys.len() < BATCH_SIZE {
+                return Ok(num_purged);
+            }
+        }
+    }
+
+    /// Deletes the row along with the rows that reference it through a
+    /// cascading foreign key. Every reference is checked before anything
+    /// is deleted, so a restricting reference anywhere leaves all rows in place.
+    /// In a transaction, every row is locked before any is deleted too.
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, pkey_elems: &[&[u8]]) -> Result<()> {
+        let tables = self.referencing_tables(bufmgr)?;
+        self.delete_with(bufmgr, pkey_elems, &tables)
+    }
+
+    /// Like `delete`, given the tables `referencing_tables` returned, so
+    /// that deleting many rows looks them up once.
+    pub fn delete_with(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey_elems: &[&[u8]],
+        referencing_tables: &[Table],
     ) -> Result<()> {
+        self.validate_pkey(pkey_elems)?;
         let btree = BTree::new(self.meta_page_id);
-        let mut skey = vec![];
-        tuple::encode(
-            self.skey.iter().map(|&index| record[index].as_ref()),
-            &mut skey,
-        );
-        btree.insert(bufmgr, &skey, pkey)?;
+        let mut key = vec![];
+        self.format.encode(pkey_elems.iter(), &mut key);
+        bufmgr.lock_row(self.meta_page_id, &key, LockMode::Exclusive)?;
+        if btree.get(bufmgr, &key)?.is_none() {
+            return Err(Error::RowNotFound.into());
+        }
+        let tables = referencing_tables;
+        let referencing_rows = self.find_referencing_rows(bufmgr, tables, &key)?;
+        for (table_no, child_key) in &referencing_rows {
+            bufmgr.lock_row(
+                tables[*table_no].meta_page_id,
+                child_key,
+                LockMode::Exclusive,
+            )?;
+        }
+        for (table_no, child_key) in referencing_rows {
+            tables[table_no].delete_row(bufmgr, &child_key)?;
+        }
+        self.delete_row(bufmgr, &key)
+    }
+
+    /// The tables whose rows a delete from this table can cascade to or be
+    /// restricted by, through foreign keys of their own or of tables in
+    /// between. Empty, without reading the tables of the catalog, if it
+    /// never recorded a table with a foreign key.
+    pub fn referencing_tables(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Table>> {
+        let catalog = match Catalog::open(bufmgr) {
+            Ok(catalog) => catalog,
+            Err(crate::Error::Catalog(catalog::Error::NoCatalog)) => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        if !catalog.may_have_foreign_keys(bufmgr)? {
+            return Ok(vec![]);
+        }
+        let mut remaining = catalog.tables(bufmgr)?;
+        let mut referencing = vec![];
+        let mut parents = vec![self.name.clone()];
+        while let Some(parent) = parents.pop() {
+            let (children, rest) = remaining.into_iter().partition(|table: &Table| {
+                table
+                    .foreign_keys
+                    .iter()
+                    .any(|foreign_key| foreign_key.parent == parent)
+            });
+            remaining = rest;
+            for child in children {
+                catalog::check_key_arity(bufmgr, &child)?;
+                parents.push(child.name.clone());
+                referencing.push(child);
+            }
+        }
+        Ok(referencing)
+    }
+
+    /// Collects the rows that deleting the row keyed by `key` would cascade
+    /// to, as positions in `tables` paired with their keys.
+    fn find_referencing_rows(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        tables: &[Table],
+        key: &[u8],
+    ) -> Result<Vec<(usize, Vec<u8>)>> {
+        if tables.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut rows = vec![];
+        let mut visited = HashSet::new();
+        visited.insert((self.name.clone(), key.to_vec()));
+        let mut queue = VecDeque::new();
+        queue.push_back((self.name.clone(), key.to_vec()));
+        while let Some((parent, key)) = queue.pop_front() {
+            let format = tables
+                .iter()
+                .find(|table| table.name == parent)
+                .map_or(self.format, |table| table.format);
+            let mut pkey_elems = vec![];
+            format.decode(&key, &mut pkey_elems)?;
+            let pkey_elems: Vec<_> = pkey_elems.iter().map(Vec::as_slice).collect();
+            for (table_no, child) in tables.iter().enumerate() {
+                for foreign_key in &child.foreign_keys {
+                    if foreign_key.parent != parent {
+                        continue;
+                    }
+                    let index = child.foreign_key_index(foreign_key)?;
+                    let mut iter = index.lookup(bufmgr, &pkey_elems)?;
+                    while let Some(child_key) = iter.next(bufmgr)? {
+                        if foreign_key.on_delete == OnDelete::Restrict {
+                            return Err(Error::RowReferenced(child.name.clone()).into());
+                        }
+                        if visited.insert((child.name.clone(), child_key.clone())) {
+                            queue.push_back((child.name.clone(), child_key.clone()));
+                            rows.push((table_no, child_key));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    fn delete_row(&self, bufmgr: &mut BufferPoolManager, key: &[u8]) -> Result<()> {
+        let btree = BTree::new(self.meta_page_id);
+        let value = btree.get(bufmgr, key)?.ok_or(Error::RowNotFound)?;
+        let record = self.decode_row(key, &value)?;
+        btree.delete(bufmgr, key)?;
+        for unique_index in &self.unique_indices {
+            unique_index.delete(bufmgr, &record)?;
+        }
+        for secondary_index in &self.secondary_indices {
+            secondary_index.delete(bufmgr, key, &record)?;
+        }
         Ok(())
     }
+
+    pub fn update(&self, bufmgr: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
+        self.update_record(bufmgr, record)
+    }
+
+    pub fn update_nullable(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        record: &[Option<&[u8]>],
+    ) -> Result<()> {
+        self.update_record(bufmgr, record)
+    }
+
+    fn update_record(&self, bufmgr: &mut BufferPoolManager, record: &[impl Element]) -> Result<()> {
+        self.validate_record(record)?;
+        self.check_references(bufmgr, record, &HashSet::new())?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut key = vec![];
+        self.format
+            .encode(record[..self.num_key_elems].iter(), &mut key);
+        bufmgr.lock_row(self.meta_page_id, &key, LockMode::Exclusive)?;
+        let old_value = btree.get(bufmgr, &key)?.ok_or(Error::RowNotFound)?;
+        let old_record = self.decode_row(&key, &old_value)?;
+
+        let mut changed_skeys = vec![];
+        let mut changed_values = vec![];
+        for (index_no, unique_index) in self.unique_indices.iter().enumerate() {
+            let old_skey = unique_index.encode_skey(self.format, &old_record);
+            let new_skey = unique_index.encode_skey(self.format, record);
+            if old_skey == new_skey {
+                let new_value =
+                    encode_index_value(self.format, &key, &unique_index.include, record);
+                match new_skey {
+                    Some(skey)
+                        if encode_index_value(
+                            self.format,
+                            &key,
+                            &unique_index.include,
+                            &old_record,
+                        ) != new_value =>
+                    {
+                        changed_values.push((unique_index.meta_page_id, skey, new_value));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(new_skey) = &new_skey {
+                if unique_index.contains(bufmgr, new_skey)? {
+                    return Err(Error::DuplicateIndexKey(index_no).into());
+                }
+            }
+            changed_skeys.push((unique_index, old_skey, new_skey));
+        }
+
+        let mut value = vec![];
+        self.format
+            .encode(record[self.num_key_elems..].iter(), &mut value);
+        btree.update(bufmgr, &key, &value)?;
+        for (unique_index, old_skey, new_skey) in changed_skeys {
+            let index_btree = BTree::new(unique_index.meta_page_id);
+            if let Some(old_skey) = old_skey {
+                index_btree.delete(bufmgr, &old_skey)?;
+            }
+            if let Some(new_skey) = new_skey {
+                let value = encode_index_value(self.format, &key, &unique_index.include, record);
+                index_btree.insert(bufmgr, &new_skey, &value)?;
+            }
+        }
+        for (meta_page_id, skey, value) in changed_values {
+            BTree::new(meta_page_id).update(bufmgr, &skey, &value)?;
+        }
+        for secondary_index in &self.secondary_indices {
+            let old_skey = secondary_index.encode_skey(self.format, &old_record);
+            if old_skey != secondary_index.encode_skey(self.format, record) {
+                secondary_index.delete(bufmgr, &key, &old_record)?;
+                secondary_index.insert(bufmgr, &key, record)?;
+                continue;
+            }
+            let value = encode_index_value(self.format, &key, &secondary_index.include, record);
+            if encode_index_value(self.format, &key, &secondary_index.include, &old_record) != value
+            {
+                let mut index_key = old_skey;
+                index_key.extend_from_slice(&key);
+                BTree::new(secondary_index.meta_page_id).update(bufmgr, &index_key, &value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TableIter {
+    iter: btree::Iter,
+    format: Format,
+    num_columns: usize,
+    padding: Vec<Option<Vec<u8>>>,
+    expiry: Option<Expiry>,
+}
+
+impl TableIter {
+    pub(crate) fn new(
+        bufmgr: &mut BufferPoolManager,
+        meta_page_id: PageId,
+        search_mode: SearchMode,
+        direction: ScanDirection,
+    ) -> Result<Self> {
+        let format = btree_format(bufmgr, meta_page_id)?;
+        let btree = BTree::new(meta_page_id);
+        let iter = btree.search_directed(bufmgr, search_mode, direction)?;
+        Ok(Self {
+            iter,
+            format,
+            num_columns: 0,
+            padding: vec![],
+            expiry: None,
+        })
+    }
+
+    /// Makes `next` pad rows shorter than `num_columns` with the trailing
+    /// elements of `padding`.
+    fn padded(self, num_columns: usize, padding: Vec<Option<Vec<u8>>>) -> Self {
+        Self {
+            num_columns,
+            padding,
+            ..self
+        }
+    }
+
+    /// Makes `next` skip the rows `expiry` hides.
+    fn filtered(self, expiry: Option<Expiry>) -> Self {
+        Self { expiry, ..self }
+    }
+
+    /// The format the rows `next_raw` returns are encoded in.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns the next row as its encoded pkey and encoded value columns.
+    /// Expired rows are not skipped.
+    pub fn next_raw(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.iter.next(bufmgr)?)
+    }
+
+    /// Like `next_raw`, but hands the rows left in the current leaf to `f`
+    /// in the page; see `btree::Iter::next_in_leaf_with`.
+    pub fn next_raw_in_leaf_with(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        max_rows: usize,
+        f: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<usize> {
+        Ok(self.iter.next_in_leaf_with(bufmgr, max_rows, f)?)
+    }
+
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let (key, value) = match self.next_raw(bufmgr)? {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            let mut record = vec![];
+            self.format.decode_nullable(&key, &mut record)?;
+            self.format.decode_nullable(&value, &mut record)?;
+            pad_record(&mut record, self.num_columns, &self.padding)?;
+            match self.expiry {
+                Some(expiry) if expiry.is_expired(&record) => continue,
+                _ => return Ok(Some(record)),
+            }
+        }
+    }
+}
+
+/// A unique index. Rows with a NULL in any skey column never collide
+/// and are left out of the index altogether.
+#[derive(Debug)]
+pub struct UniqueIndex {
+    /// Lets requests refer to the index by name; unique among the indices
+    /// of all tables.
+    pub name: Option<String>,
+    pub meta_page_id: PageId,
+    pub skey: Vec<usize>,
+    /// Sort direction of each skey column; missing entries are ascending.
+    pub directions: Vec<Direction>,
+    /// Columns stored after the pkey in each entry's value so that
+    /// `IndexOnlyScan` can return them without visiting the table.
+    pub include: Vec<usize>,
+}
+
+impl UniqueIndex {
+    pub fn create(&mut self, bufmgr: &mut BufferPoolManager, format: Format) -> Result<()> {
+        let btree = BTree::create_with_key_format(bufmgr, format.tag())?;
+        self.meta_page_id = btree.meta_page_id;
+        Ok(())
+    }
+
+    fn encode_skey(&self, format: Format, record: &[impl Element]) -> Option<Vec<u8>> {
+        let elems = self
+            .skey
+            .iter()
+            .map(|&index| record[index].as_elem())
+            .collect::<Option<Vec<_>>>()?;
+        let mut skey = vec![];
+        format.encode_directed(elems.iter(), &self.directions, &mut skey);
+        Some(skey)
+    }
+
+    fn contains(&self, bufmgr: &mut BufferPoolManager, skey: &[u8]) -> Result<bool, btree::Error> {
+        let btree = BTree::new(self.meta_page_id);
+        Ok(btree.get(bufmgr, skey)?.is_some())
+    }
+
+    /// Returns the encoded pkey of the row whose skey is exactly `skey_elems`.
+    pub fn lookup(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        skey_elems: &[&[u8]],
+    ) -> Result<Option<Vec<u8>>> {
+        if skey_elems.len() != self.skey.len() {
+            return Err(Error::SkeyArityMismatch {
+                len: skey_elems.len(),
+                num_skey_elems: self.skey.len(),
+            }
+            .into());
+        }
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut skey = vec![];
+        format.encode_directed(skey_elems.iter(), &self.directions, &mut skey);
+        match btree.get(bufmgr, &skey)? {
+            Some(value) => Ok(Some(
+                format.split_last(&value, self.include.len())?.0.to_vec(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey: &[u8],
+        record: &[impl Element],
+    ) -> Result<()> {
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        if let Some(skey) = self.encode_skey(format, record) {
+            let value = encode_index_value(format, pkey, &self.include, record);
+            btree.insert(bufmgr, &skey, &value)?;
+        }
+        Ok(())
+    }
+
+    /// The table column behind each element of the rows `IndexOnlyScan`
+    /// returns for this index: the skey, the pkey, then the included columns.
+    pub fn output_columns(&self, num_key_elems: usize) -> Vec<usize> {
+        let mut columns = self.skey.clone();
+        columns.extend(0..num_key_elems);
+        columns.extend_from_slice(&self.include);
+        columns
+    }
+
+    /// Whether `IndexOnlyScan` over this index returns every one of `columns`.
+    pub fn covers(&self, num_key_elems: usize, columns: &[usize]) -> bool {
+        let output_columns = self.output_columns(num_key_elems);
+        columns.iter().all(|column| output_columns.contains(column))
+    }
+
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, record: &[impl Element]) -> Result<()> {
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        if let Some(skey) = self.encode_skey(format, record) {
+            btree.delete(bufmgr, &skey)?;
+        }
+        Ok(())
+    }
+}
+
+/// A non-unique index. Its btree is keyed by the encoded skey columns
+/// followed by the encoded pkey, so rows sharing an skey stay distinct;
+/// the pkey is also stored as the value so `IndexScan` can use it as is.
+#[derive(Debug)]
+pub struct SecondaryIndex {
+    /// See `UniqueIndex::name`.
+    pub name: Option<String>,
+    pub meta_page_id: PageId,
+    pub skey: Vec<usize>,
+    /// Sort direction of each skey column; missing entries are ascending.
+    pub directions: Vec<Direction>,
+    /// Columns stored after the pkey in each entry's value.
+    pub include: Vec<usize>,
+}
+
+impl SecondaryIndex {
+    pub fn create(&mut self, bufmgr: &mut BufferPoolManager, format: Format) -> Result<()> {
+        let btree = BTree::create_with_key_format(bufmgr, format.tag())?;
+        self.meta_page_id = btree.meta_page_id;
+        Ok(())
+    }
+
+    fn encode_skey(&self, format: Format, record: &[impl Element]) -> Vec<u8> {
+        let mut skey = vec![];
+        format.encode_directed(
+            self.skey.iter().map(|&index| &record[index]),
+            &self.directions,
+            &mut skey,
+        );
+        skey
+    }
+
+    pub fn insert(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey: &[u8],
+        record: &[impl Element],
+    ) -> Result<()> {
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut key = self.encode_skey(format, record);
+        key.extend_from_slice(pkey);
+        let value = encode_index_value(format, pkey, &self.include, record);
+        btree.insert(bufmgr, &key, &value)?;
+        Ok(())
+    }
+
+    pub fn delete(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        pkey: &[u8],
+        record: &[impl Element],
+    ) -> Result<()> {
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut key = self.encode_skey(format, record);
+        key.extend_from_slice(pkey);
+        btree.delete(bufmgr, &key)?;
+        Ok(())
+    }
+
+    pub fn lookup(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        skey_elems: &[&[u8]],
+    ) -> Result<SecondaryIndexIter> {
+        let format = btree_format(bufmgr, self.meta_page_id)?;
+        let btree = BTree::new(self.meta_page_id);
+        let mut prefix = vec![];
+        format.encode_directed(skey_elems.iter(), &self.directions, &mut prefix);
+        let iter = btree.search(bufmgr, SearchMode::Key(prefix.clone()))?;
+        Ok(SecondaryIndexIter {
+            iter,
+            format,
+            prefix,
+            num_included: self.include.len(),
+        })
+    }
+
+    /// Like `UniqueIndex::output_columns`, but the pkey appears twice: once
+    /// at the end of the btree key and once at the start of the value.
+    pub fn output_columns(&self, num_key_elems: usize) -> Vec<usize> {
+        let mut columns = self.skey.clone();
+        columns.extend(0..num_key_elems);
+        columns.extend(0..num_key_elems);
+        columns.extend_from_slice(&self.include);
+        columns
+    }
+
+    pub fn covers(&self, num_key_elems: usize, columns: &[usize]) -> bool {
+        let output_columns = self.output_columns(num_key_elems);
+        columns.iter().all(|column| output_columns.contains(column))
+    }
+}
+
+pub struct SecondaryIndexIter {
+    iter: btree::Iter,
+    format: Format,
+    prefix: Vec<u8>,
+    num_included: usize,
+}
+
+impl SecondaryIndexIter {
+    /// Returns the encoded pkey of the next row whose skey matches.
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Vec<u8>>> {
+        match self.iter.next(bufmgr)? {
+            Some((key, value)) if key.starts_with(&self.prefix) => Ok(Some(
+                self.format
+                    .split_last(&value, self.num_included)?
+                    .0
+                    .to_vec(),
+            )),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::expr::BytesExpr;
+
+    fn skey(elems: &[&[u8]]) -> Vec<u8> {
+        let mut skey = vec![];
+        tuple::encode(elems.iter(), &mut skey);
+        skey
+    }
+
+    #[test]
+    fn test_update() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![
+                UniqueIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: vec![1],
+                    directions: vec![],
+                    include: vec![],
+                },
+                UniqueIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: vec![2],
+                    directions: vec![],
+                    include: vec![],
+                },
+            ],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])
+            .unwrap();
+
+        table
+            .update(&mut bufmgr, &[b"z", b"Alice", b"Jones"])
+            .unwrap();
+        let table_btree = BTree::new(table.meta_page_id);
+        let first_name_btree = BTree::new(table.unique_indices[0].meta_page_id);
+        let last_name_btree = BTree::new(table.unique_indices[1].meta_page_id);
+        assert_eq!(
+            Some(skey(&[b"Alice", b"Jones"])),
+            table_btree.get(&mut bufmgr, &skey(&[b"z"])).unwrap()
+        );
+        assert_eq!(
+            Some(skey(&[b"z"])),
+            first_name_btree
+                .get(&mut bufmgr, &skey(&[b"Alice"]))
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            last_name_btree
+                .get(&mut bufmgr, &skey(&[b"Smith"]))
+                .unwrap()
+        );
+        assert_eq!(
+            Some(skey(&[b"z"])),
+            last_name_btree
+                .get(&mut bufmgr, &skey(&[b"Jones"]))
+                .unwrap()
+        );
+
+        let err = table
+            .update(&mut bufmgr, &[b"x", b"Bob", b"Jones"])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::DuplicateIndexKey(1))
+        ));
+        assert_eq!(
+            Some(skey(&[b"Bob", b"Johnson"])),
+            table_btree.get(&mut bufmgr, &skey(&[b"x"])).unwrap()
+        );
+
+        let err = table
+            .update(&mut bufmgr, &[b"y", b"Charlie", b"Williams"])
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Table(Error::RowNotFound)));
+    }
+
+    #[test]
+    fn test_secondary_index() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"y", b"Charlie", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"w", b"Dave", b"Smith"])
+            .unwrap();
+
+        let index = &table.secondary_indices[0];
+        let lookup = |bufmgr: &mut BufferPoolManager, last_name: &[u8]| {
+            let mut iter = index.lookup(bufmgr, &[last_name]).unwrap();
+            let mut pkeys = vec![];
+            while let Some(pkey) = iter.next(bufmgr).unwrap() {
+                pkeys.push(pkey);
+            }
+            pkeys
+        };
+        assert_eq!(
+            vec![skey(&[b"w"]), skey(&[b"y"]), skey(&[b"z"])],
+            lookup(&mut bufmgr, b"Smith")
+        );
+        assert_eq!(vec![skey(&[b"x"])], lookup(&mut bufmgr, b"Johnson"));
+        assert!(lookup(&mut bufmgr, b"Brown").is_empty());
+
+        table.delete(&mut bufmgr, &[b"y"]).unwrap();
+        table
+            .update(&mut bufmgr, &[b"w", b"Dave", b"Miller"])
+            .unwrap();
+        assert_eq!(vec![skey(&[b"z"])], lookup(&mut bufmgr, b"Smith"));
+        assert_eq!(vec![skey(&[b"w"])], lookup(&mut bufmgr, b"Miller"));
+        let err = table.delete(&mut bufmgr, &[b"y"]).unwrap_err();
+        assert!(matches!(err, crate::Error::Table(Error::RowNotFound)));
+    }
+
+    #[test]
+    fn test_create_index() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("people", 1, 3);
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"y", b"Charlie", b"Smith"])
+            .unwrap();
+
+        let index_meta_page_id = table
+            .create_index(&mut bufmgr, None, vec![1], vec![], vec![], true)
+            .unwrap();
+        assert_eq!(index_meta_page_id, table.unique_indices[0].meta_page_id);
+        let index_btree = BTree::new(index_meta_page_id);
+        assert_eq!(
+            Some(skey(&[b"y"])),
+            index_btree.get(&mut bufmgr, &skey(&[b"Charlie"])).unwrap()
+        );
+
+        let err = table
+            .create_index(&mut bufmgr, None, vec![2], vec![], vec![], true)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::DuplicateIndexKey(1))
+        ));
+        assert_eq!(1, table.unique_indices.len());
+        // the pages of the abandoned index are reused
+        let num_pages = bufmgr.num_pages();
+        let by_last = table
+            .create_index(
+                &mut bufmgr,
+                Some("by_last".to_string()),
+                vec![2],
+                vec![],
+                vec![],
+                false,
+            )
+            .unwrap();
+        assert_eq!(num_pages, bufmgr.num_pages());
+        assert_eq!(
+            Some(by_last),
+            table.index_by_name("by_last").map(|(id, ..)| id)
+        );
+        let err = table
+            .create_index(
+                &mut bufmgr,
+                Some("by_last".to_string()),
+                vec![1],
+                vec![],
+                vec![],
+                false,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::DuplicateIndexName(_))
+        ));
+
+        table
+            .insert(&mut bufmgr, &[b"w", b"Dave", b"Smith"])
+            .unwrap();
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[b"Smith"])
+            .unwrap();
+        let mut pkeys = vec![];
+        while let Some(pkey) = iter.next(&mut bufmgr).unwrap() {
+            pkeys.push(pkey);
+        }
+        assert_eq!(vec![skey(&[b"w"]), skey(&[b"y"]), skey(&[b"z"])], pkeys);
+
+        let opened = catalog::open_table(&mut bufmgr, "people").unwrap();
+        assert_eq!(1, opened.unique_indices.len());
+        assert_eq!(1, opened.secondary_indices.len());
+        assert!(opened.index_by_name("by_last").is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![5],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        let num_pages = bufmgr.num_pages();
+        let err = table.create(&mut bufmgr).unwrap_err();
+        assert_eq!(
+            "index references column 5 of a 3-column record",
+            err.to_string()
+        );
+        assert_eq!(num_pages, bufmgr.num_pages());
+
+        table.unique_indices[0].skey = vec![2];
+        table.num_key_elems = 2;
+        table.create(&mut bufmgr).unwrap();
+        let err = table.insert(&mut bufmgr, &[b"z"]).unwrap_err();
+        assert_eq!(
+            "record has 1 columns but num_key_elems is 2",
+            err.to_string()
+        );
+        let err = table.insert(&mut bufmgr, &[b"z", b"Alice"]).unwrap_err();
+        assert_eq!("record has 2 columns but the table has 3", err.to_string());
+        assert!(table
+            .create_index(&mut bufmgr, None, vec![3], vec![], vec![], false)
+            .is_err());
+        assert!(table.delete(&mut bufmgr, &[b"z"]).is_err());
+        let long = vec![b'x'; 2000];
+        let err = table
+            .insert(&mut bufmgr, &[b"z", b"Alice", &long])
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Table(Error::RowTooLarge)));
+
+        let btree = BTree::new(table.meta_page_id);
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_drop() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        for i in 0u32..100 {
+            let id = i.to_be_bytes();
+            table
+                .insert(&mut bufmgr, &[&id, &[b'a'; 200], &id])
+                .unwrap();
+        }
+        let old_meta_page_id = table.meta_page_id;
+        table.unique_indices.push(UniqueIndex {
+            name: None,
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            skey: vec![1],
+            directions: vec![],
+            include: vec![],
+        });
+        let num_pages = bufmgr.num_pages();
+        table.drop(&mut bufmgr).unwrap();
+        assert!(catalog::open_table(&mut bufmgr, "people").is_err());
+
+        let mut table = Table::new("people", 1, 3);
+        table.create(&mut bufmgr).unwrap();
+        assert_eq!(num_pages, bufmgr.num_pages());
+        assert_eq!(old_meta_page_id, table.meta_page_id);
+        let btree = BTree::new(table.meta_page_id);
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_insert_typed() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            schema: Some(vec![ColumnType::I64]),
+            ..Table::new("scores", 1, 2)
+        };
+        let err = table.create(&mut bufmgr).unwrap_err();
+        assert_eq!("schema has 1 columns but the table has 2", err.to_string());
+        table.schema = Some(vec![ColumnType::I64, ColumnType::Utf8]);
+        table.create(&mut bufmgr).unwrap();
+        for &n in &[10, -3, 9, 0] {
+            table
+                .insert_typed(&mut bufmgr, &[Value::I64(n), Value::Utf8(n.to_string())])
+                .unwrap();
+        }
+        let err = table
+            .insert_typed(&mut bufmgr, &[Value::U64(1), Value::Utf8("1".to_string())])
+            .unwrap_err();
+        assert_eq!("column 0 expects I64 but got U64", err.to_string());
+        assert!(table.insert_typed(&mut bufmgr, &[Value::I64(1)]).is_err());
+
+        let table = catalog::open_table(&mut bufmgr, "scores").unwrap();
+        let schema = table.schema.unwrap();
+        let btree = BTree::new(table.meta_page_id);
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+            let mut elems = vec![];
+            tuple::decode(&key, &mut elems).unwrap();
+            keys.push(tuple::decode_value(schema[0], Some(&elems[0])).unwrap());
+        }
+        assert_eq!(
+            vec![Value::I64(-3), Value::I64(0), Value::I64(9), Value::I64(10)],
+            keys
+        );
+    }
+
+    #[test]
+    fn test_null() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        let err = table
+            .insert_nullable(&mut bufmgr, &[None, Some(b"Alice"), Some(b"Smith")])
+            .unwrap_err();
+        assert_eq!("primary key column 0 is NULL", err.to_string());
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"z"), Some(b"Alice"), None])
+            .unwrap();
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"y"), None, None])
+            .unwrap();
+        table.insert(&mut bufmgr, &[b"x", b"Bob", b""]).unwrap();
+
+        let btree = BTree::new(table.meta_page_id);
+        let mut key = vec![];
+        tuple::encode([b"y"].iter(), &mut key);
+        let value = btree.get(&mut bufmgr, &key).unwrap().unwrap();
+        let mut record = vec![];
+        tuple::decode_nullable(&value, &mut record).unwrap();
+        assert_eq!(vec![None, None], record);
+
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[b""])
+            .unwrap();
+        assert_eq!(skey(&[b"x"]), iter.next(&mut bufmgr).unwrap().unwrap());
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+
+        table
+            .update_nullable(&mut bufmgr, &[Some(b"y"), None, Some(b"Smith")])
+            .unwrap();
+        let err = table
+            .update(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap_err();
+        assert_eq!("duplicate key in unique index #0", err.to_string());
+        table
+            .update_nullable(&mut bufmgr, &[Some(b"y"), None, None])
+            .unwrap();
+        table
+            .update(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table.delete(&mut bufmgr, &[b"y"]).unwrap();
+
+        let unique_btree = BTree::new(table.unique_indices[0].meta_page_id);
+        let mut iter = unique_btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut skeys = vec![];
+        while let Some((skey, _)) = iter.next(&mut bufmgr).unwrap() {
+            skeys.push(skey);
+        }
+        assert_eq!(vec![skey(&[b""]), skey(&[b"Smith"])], skeys);
+    }
+
+    #[test]
+    fn test_insert_atomic() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![
+                UniqueIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: vec![1],
+                    directions: vec![],
+                    include: vec![],
+                },
+                UniqueIndex {
+                    name: None,
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    skey: vec![2],
+                    directions: vec![],
+                    include: vec![],
+                },
+            ],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+
+        let err = table
+            .insert(&mut bufmgr, &[b"y", b"Bob", b"Smith"])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::DuplicateIndexKey(1))
+        ));
+
+        let meta_page_ids = [
+            table.meta_page_id,
+            table.unique_indices[0].meta_page_id,
+            table.unique_indices[1].meta_page_id,
+            table.secondary_indices[0].meta_page_id,
+        ];
+        for &meta_page_id in &meta_page_ids {
+            let btree = BTree::new(meta_page_id);
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+            assert!(iter.next(&mut bufmgr).unwrap().is_some());
+            assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        }
+        table
+            .insert(&mut bufmgr, &[b"y", b"Bob", b"Jones"])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_get() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"x"), None, Some(b"Johnson")])
+            .unwrap();
+
+        assert_eq!(
+            Some(vec![Some(b"x".to_vec()), None, Some(b"Johnson".to_vec())]),
+            table.get(&mut bufmgr, &[b"x"]).unwrap()
+        );
+        assert_eq!(None, table.get(&mut bufmgr, &[b"y"]).unwrap());
+        assert!(table.get(&mut bufmgr, &[b"x", b"y"]).is_err());
+
+        let unique_index = &table.unique_indices[0];
+        assert_eq!(
+            Some(skey(&[b"z"])),
+            unique_index.lookup(&mut bufmgr, &[b"Smith"]).unwrap()
+        );
+        assert_eq!(None, unique_index.lookup(&mut bufmgr, &[b"Jones"]).unwrap());
+        assert!(unique_index.lookup(&mut bufmgr, &[b"Smith", b"z"]).is_err());
+
+        assert_eq!(
+            Some(vec![
+                Some(b"z".to_vec()),
+                Some(b"Alice".to_vec()),
+                Some(b"Smith".to_vec())
+            ]),
+            table.get_by_index(&mut bufmgr, 0, &[b"Smith"]).unwrap()
+        );
+        assert_eq!(
+            None,
+            table.get_by_index(&mut bufmgr, 0, &[b"Smit"]).unwrap()
+        );
+        assert!(table.get_by_index(&mut bufmgr, 1, &[b"Smith"]).is_err());
+        BTree::new(table.meta_page_id)
+            .delete(&mut bufmgr, &skey(&[b"z"]))
+            .unwrap();
+        let err = table.get_by_index(&mut bufmgr, 0, &[b"Smith"]).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::DanglingIndexEntry(0))
+        ));
+    }
+
+    #[test]
+    fn test_scan() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("people", 2, 3);
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"y", b"Charlie", b"Williams"])
+            .unwrap();
+
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        let mut names = vec![];
+        while let Some(record) = iter.next(&mut bufmgr).unwrap() {
+            names.push(record[1].clone().unwrap());
+        }
+        assert_eq!(
+            vec![b"Bob".to_vec(), b"Charlie".to_vec(), b"Alice".to_vec()],
+            names
+        );
+
+        let mut iter = table.scan_from(&mut bufmgr, &[b"y"]).unwrap();
+        let (key, value) = iter.next_raw(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(skey(&[b"y", b"Charlie"]), key);
+        assert_eq!(skey(&[b"Williams"]), value);
+        assert_eq!(
+            vec![
+                Some(b"z".to_vec()),
+                Some(b"Alice".to_vec()),
+                Some(b"Smith".to_vec())
+            ],
+            iter.next(&mut bufmgr).unwrap().unwrap()
+        );
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        assert!(table.scan_from(&mut bufmgr, &[b"y", b"a", b"b"]).is_err());
+    }
+
+    #[test]
+    fn test_scan_batches() {
+        use std::cmp::Ordering;
+
+        use crate::query::{Filter, KeyBound, PlanNode, SeqScan, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("items", 1, 2);
+        table.create(&mut bufmgr).unwrap();
+        // long values spread the rows over many leaves
+        for i in 0..500u32 {
+            let name = format!("{:03}", i % 7).repeat(40);
+            table
+                .insert(&mut bufmgr, &[&i.to_be_bytes()[..], name.as_bytes()])
+                .unwrap();
+        }
+
+        for &direction in &[ScanDirection::Forward, ScanDirection::Backward] {
+            let scan = || SeqScan {
+                table_meta_page_id: table.meta_page_id,
+                search_mode: TupleSearchMode::Start,
+                direction,
+                while_bound: Some(KeyBound {
+                    key: vec![Some(400u32.to_be_bytes().to_vec())],
+                    accept: match direction {
+                        ScanDirection::Forward => Ordering::is_lt,
+                        ScanDirection::Backward => Ordering::is_ge,
+                    },
+                }),
+                while_cond: Box::new(|_| Ok(true)),
+                expiry: None,
+                filter: Some(Box::new(|record| {
+                    Ok(record[1].as_deref() != Some(&b"000"[..]))
+                })),
+            };
+            let plan = Filter {
+                inner_plan: Box::new(scan()),
+                cond: Box::new(|record| Ok(record[0].as_deref().unwrap()[3] % 2 == 0)),
+            };
+            let mut exec = plan.start(&mut bufmgr).unwrap();
+            let mut expected = vec![];
+            while let Some(row) = exec.next(&mut bufmgr).unwrap() {
+                expected.push(row);
+            }
+            assert!(!expected.is_empty());
+            for &max_rows in &[1, 7, 1000] {
+                let mut exec = plan.start(&mut bufmgr).unwrap();
+                let mut rows = vec![];
+                loop {
+                    let batch = exec.next_batch(&mut bufmgr, max_rows).unwrap();
+                    assert!(batch.len() <= max_rows);
+                    if batch.is_empty() {
+                        break;
+                    }
+                    rows.extend(batch);
+                }
+                assert_eq!(expected, rows, "{:?} {}", direction, max_rows);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table.insert(&mut bufmgr, &[b"a", b"Alice", b"a"]).unwrap();
+
+        let records = (0u32..10000).rev().map(|i| {
+            let id = i.to_be_bytes().to_vec();
+            vec![id.clone(), vec![b'x'; (i % 7) as usize], id]
+        });
+        assert_eq!(10000, table.insert_batch(&mut bufmgr, records).unwrap());
+
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        for i in 0u32..10000 {
+            let record = iter.next(&mut bufmgr).unwrap().unwrap();
+            assert_eq!(Some(i.to_be_bytes().to_vec()), record[0]);
+        }
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[b"xxxxxx"])
+            .unwrap();
+        let mut count = 0;
+        while iter.next(&mut bufmgr).unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(10000 / 7, count);
+
+        // the third record collides with the first in the unique index
+        let records = vec![
+            vec![b"b".to_vec(), b"Bob".to_vec(), b"b".to_vec()],
+            vec![b"c".to_vec(), b"Charlie".to_vec(), b"c".to_vec()],
+            vec![b"d".to_vec(), b"Dave".to_vec(), b"b".to_vec()],
+            vec![b"e".to_vec(), b"Eve".to_vec(), b"e".to_vec()],
+        ];
+        let err = table
+            .insert_batch(&mut bufmgr, records.into_iter())
+            .unwrap_err();
+        match &err {
+            crate::Error::Table(Error::BatchInsert { inserted, source }) => {
+                assert_eq!(2, *inserted);
+                assert_eq!("duplicate key in unique index #0", source.to_string());
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(table.get(&mut bufmgr, &[b"c"]).unwrap().is_some());
+        assert!(table.get(&mut bufmgr, &[b"d"]).unwrap().is_none());
+
+        let records = vec![vec![b"a".to_vec(), b"Alice".to_vec(), b"f".to_vec()]];
+        let err = table
+            .insert_batch(&mut bufmgr, records.into_iter())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::BatchInsert { inserted: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_index_directions() {
+        use crate::query::{IndexOnlyScan, PlanNode, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("events", 1, 3);
+        table.create(&mut bufmgr).unwrap();
+        let events: [(&[u8], &[u8], &[u8]); 6] = [
+            (b"1", b"bob", b"0003"),
+            (b"2", b"alice", b"0001"),
+            (b"3", b"bob", b"0001"),
+            (b"4", b"alice", b"0010"),
+            (b"5", b"bob", b"0002"),
+            (b"6", b"alice", b"0005"),
+        ];
+        for (id, user_id, timestamp) in &events {
+            table
+                .insert(&mut bufmgr, &[id, user_id, timestamp])
+                .unwrap();
+        }
+        assert!(table
+            .create_index(
+                &mut bufmgr,
+                None,
+                vec![1],
+                vec![Direction::Asc, Direction::Desc],
+                vec![],
+                false
+            )
+            .is_err());
+        table
+            .create_index(
+                &mut bufmgr,
+                None,
+                vec![1, 2],
+                vec![Direction::Asc, Direction::Desc],
+                vec![],
+                false,
+            )
+            .unwrap();
+        table.insert(&mut bufmgr, &[b"7", b"bob", b"0004"]).unwrap();
+
+        let index = &table.secondary_indices[0];
+        let plan = IndexOnlyScan {
+            index_meta_page_id: index.meta_page_id,
+            directions: index.directions.clone(),
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            end: None,
+            while_cond: Box::new(|_| Ok(true)),
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut rows = vec![];
+        while let Some(row) = exec.next(&mut bufmgr).unwrap() {
+            let row: Vec<_> = row.into_iter().map(|elem| elem.unwrap()).collect();
+            rows.push(String::from_utf8(row[..3].concat()).unwrap());
+        }
+        assert_eq!(
+            vec![
+                "alice00104",
+                "alice00056",
+                "alice00012",
+                "bob00047",
+                "bob00031",
+                "bob00025",
+                "bob00013",
+            ],
+            rows
+        );
+
+        let plan = IndexOnlyScan {
+            index_meta_page_id: index.meta_page_id,
+            directions: index.directions.clone(),
+            search_mode: TupleSearchMode::Key(vec![Some(b"bob".to_vec())]),
+            direction: ScanDirection::Forward,
+            end: None,
+            while_cond: Box::new(|skey| Ok(skey[0].as_deref() == Some(b"bob"))),
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let row = exec.next(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(Some(b"0004".to_vec()), row[1]);
+    }
+
+    #[test]
+    fn test_index_scan_end() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("items", 1, 2);
+        table.create(&mut bufmgr).unwrap();
+        table
+            .create_index(
+                &mut bufmgr,
+                None,
+                vec![1],
+                vec![Direction::Desc],
+                vec![],
+                false,
+            )
+            .unwrap();
+        // long names put a dozen or so entries in each leaf, so some of the
+        // ends below fall on the first or last entry of a leaf
+        let name = |i: usize| format!("{:03}", i).repeat(60).into_bytes();
+        for i in 0..100 {
+            let id = format!("{:03}", i).into_bytes();
+            table.insert(&mut bufmgr, &[&id[..], &name(i)]).unwrap();
+        }
+
+        let index = &table.secondary_indices[0];
+        let scans = [(0..100).rev().collect(), (0..100).collect::<Vec<_>>()];
+        for (direction, order) in [ScanDirection::Forward, ScanDirection::Backward]
+            .iter()
+            .zip(&scans)
+        {
+            test_index_scan_end_in(&mut bufmgr, &table, index, *direction, order, &name);
+        }
+    }
+
+    fn test_index_scan_end_in(
+        bufmgr: &mut BufferPoolManager,
+        table: &Table,
+        index: &SecondaryIndex,
+        direction: ScanDirection,
+        order: &[usize],
+        name: &dyn Fn(usize) -> Vec<u8>,
+    ) {
+        use crate::query::{IndexScan, PlanNode, RangeEnd, TupleSearchMode};
+
+        for i in 0..100 {
+            for &inclusive in &[false, true] {
+                let plan = IndexScan {
+                    table_meta_page_id: table.meta_page_id,
+                    index_meta_page_id: index.meta_page_id,
+                    directions: index.directions.clone(),
+                    num_included: 0,
+                    search_mode: TupleSearchMode::Start,
+                    direction,
+                    end: Some(RangeEnd {
+                        key: vec![Some(name(i))],
+                        inclusive,
+                    }),
+                    while_cond: Box::new(|_| Ok(true)),
+                    expiry: None,
+                };
+                let mut exec = plan.start(bufmgr).unwrap();
+                let mut names = vec![];
+                while let Some(row) = exec.next(bufmgr).unwrap() {
+                    names.push(row[1].clone().unwrap());
+                }
+                // the index is descending, so a forward scan ends after the
+                // names above `i` and a backward one after those below it
+                let len = order.iter().position(|&j| j == i).unwrap() + inclusive as usize;
+                let expected: Vec<_> = order[..len].iter().map(|&j| name(j)).collect();
+                assert_eq!(expected, names, "{:?} {} {}", direction, i, inclusive);
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_scan_missing_row() {
+        use crate::query::{self, IndexScan, PlanNode, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("items", 1, 2)
+        };
+        table.create(&mut bufmgr).unwrap();
+        for (id, name) in &[("a", "x"), ("b", "y"), ("c", "z")] {
+            table
+                .insert(&mut bufmgr, &[id.as_bytes(), name.as_bytes()])
+                .unwrap();
+        }
+        // drop the row behind the middle index entry, which a lookup
+        // positioned on the next key would silently replace with "c"
+        let mut pkey = vec![];
+        table.format.encode([b"b"].iter(), &mut pkey);
+        BTree::new(table.meta_page_id)
+            .delete(&mut bufmgr, &pkey)
+            .unwrap();
+
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index_meta_page_id: table.unique_indices[0].meta_page_id,
+            directions: vec![],
+            num_included: 0,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            end: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let row = exec.next(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(b"a", &row[0].as_ref().unwrap()[..]);
+        let err = exec.next(&mut bufmgr).unwrap_err();
+        match &err {
+            crate::Error::Query(query::Error::MissingTableRow {
+                pkey: missing,
+                table: id,
+                ..
+            }) => {
+                assert_eq!(&pkey, missing);
+                assert_eq!(table.meta_page_id.to_u64(), *id);
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(err.to_string().contains(&tuple::to_hex(&pkey)));
+    }
+
+    #[test]
+    fn test_escaped_format() {
+        use crate::query::{IndexScan, PlanNode, SeqScan, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            format: Format::Escaped,
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![Direction::Desc],
+                include: vec![],
+            }],
+            ..Table::new("codes", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .create_index(&mut bufmgr, None, vec![2], vec![], vec![1], false)
+            .unwrap();
+        for (code, name, group) in &[
+            (&b"w"[..], &b"Dave"[..], &b"a\0b"[..]),
+            (b"x", b"Bob", b""),
+            (b"y", b"Charlie", b"a\0b"),
+            (b"w\0", b"Alice", b"a"),
+        ] {
+            table.insert(&mut bufmgr, &[code, name, group]).unwrap();
+        }
+        let table = catalog::open_table(&mut bufmgr, "codes").unwrap();
+        assert_eq!(Format::Escaped, table.format);
+        for meta_page_id in &[
+            table.meta_page_id,
+            table.unique_indices[0].meta_page_id,
+            table.secondary_indices[0].meta_page_id,
+        ] {
+            assert_eq!(
+                Format::Escaped,
+                btree_format(&mut bufmgr, *meta_page_id).unwrap()
+            );
+        }
+
+        let btree = BTree::new(table.meta_page_id);
+        let (key, _) = btree
+            .search(&mut bufmgr, SearchMode::Start)
+            .unwrap()
+            .next(&mut bufmgr)
+            .unwrap()
+            .unwrap();
+        assert_eq!(b"\x01w\x00\x01", &key[..]);
+        assert_eq!(9, skey(&[b"w"]).len());
+
+        assert_eq!(
+            Some(b"Bob".to_vec()),
+            table.get(&mut bufmgr, &[b"x"]).unwrap().unwrap()[1]
+        );
+        assert_eq!(
+            Some(b"w\0".to_vec()),
+            table
+                .get_by_index(&mut bufmgr, 0, &[b"Alice"])
+                .unwrap()
+                .unwrap()[0]
+        );
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        let mut codes = vec![];
+        while let Some(record) = iter.next(&mut bufmgr).unwrap() {
+            codes.push(record[0].clone().unwrap());
+        }
+        assert_eq!(
+            vec![b"w".to_vec(), b"w\0".to_vec(), b"x".to_vec(), b"y".to_vec()],
+            codes
+        );
+
+        let plan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Key(vec![Some(b"x".to_vec())]),
+            direction: ScanDirection::Forward,
+            while_bound: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+            filter: Some(Box::new(|record| {
+                Ok(record[2].as_deref() == Some(&b"a\0b"[..]))
+            })),
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(b"y".to_vec()),
+            exec.next(&mut bufmgr).unwrap().unwrap()[0]
+        );
+        assert!(exec.next(&mut bufmgr).unwrap().is_none());
+
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index_meta_page_id: table.unique_indices[0].meta_page_id,
+            directions: vec![Direction::Desc],
+            num_included: 0,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            end: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut names = vec![];
+        while let Some(record) = exec.next(&mut bufmgr).unwrap() {
+            names.push(record[1].clone().unwrap());
+        }
+        assert_eq!(
+            vec![
+                b"Dave".to_vec(),
+                b"Charlie".to_vec(),
+                b"Bob".to_vec(),
+                b"Alice".to_vec()
+            ],
+            names
+        );
+
+        let index = &table.secondary_indices[0];
+        let mut iter = index.lookup(&mut bufmgr, &[b"a\0b"]).unwrap();
+        let mut pkeys = vec![];
+        while let Some(pkey) = iter.next(&mut bufmgr).unwrap() {
+            pkeys.push(pkey);
+        }
+        assert_eq!(2, pkeys.len());
+        table.delete(&mut bufmgr, &[b"w"]).unwrap();
+        table.update(&mut bufmgr, &[b"y", b"Carol", b"a"]).unwrap();
+        assert!(table
+            .get_by_index(&mut bufmgr, 0, &[b"Dave"])
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            Some(b"y".to_vec()),
+            table
+                .get_by_index(&mut bufmgr, 0, &[b"Carol"])
+                .unwrap()
+                .unwrap()[0]
+        );
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        use crate::query::{PlanNode, SeqScan, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("future", 1, 2);
+        table.create(&mut bufmgr).unwrap();
+        table.insert(&mut bufmgr, &[b"a", b"b"]).unwrap();
+
+        // as if written by a binary with a format this one does not know
+        BTree::new(table.meta_page_id)
+            .set_key_format(&mut bufmgr, 99)
+            .unwrap();
+
+        let is_unknown = |err| matches!(err, crate::Error::Table(Error::UnknownFormat(99)));
+        assert!(is_unknown(
+            catalog::open_table(&mut bufmgr, "future").unwrap_err()
+        ));
+        let plan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            while_bound: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+            filter: None,
+        };
+        assert!(is_unknown(plan.start(&mut bufmgr).err().unwrap()));
+    }
+
+    #[test]
+    fn test_row_count() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        assert_eq!(0, table.approx_row_count(&mut bufmgr).unwrap());
+        for i in 0u32..200 {
+            let id = i.to_be_bytes();
+            table
+                .insert(&mut bufmgr, &[&id, &[b'a'; 100], &id])
+                .unwrap();
+        }
+        assert!(table
+            .insert(&mut bufmgr, &[b"x", b"Bob", &0u32.to_be_bytes()])
+            .is_err());
+        assert!(table
+            .insert(&mut bufmgr, &[&0u32.to_be_bytes(), b"Bob", b"x"])
+            .is_err());
+        table
+            .update(&mut bufmgr, &[&7u32.to_be_bytes(), &[b'b'; 1000], b"x"])
+            .unwrap();
+        for i in 0u32..50 {
+            table.delete(&mut bufmgr, &[&i.to_be_bytes()]).unwrap();
+        }
+        assert!(table.delete(&mut bufmgr, &[b"x"]).is_err());
+        assert_eq!(150, table.approx_row_count(&mut bufmgr).unwrap());
+
+        let btree = BTree::new(table.meta_page_id);
+        btree.set_num_entries(&mut bufmgr, 3).unwrap();
+        assert_eq!(150, table.recount(&mut bufmgr).unwrap());
+        assert_eq!(150, table.approx_row_count(&mut bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        for i in 0u32..100 {
+            let id = i.to_be_bytes();
+            table
+                .insert(&mut bufmgr, &[&id, &[b'a'; 200], &id])
+                .unwrap();
+        }
+        let num_pages = bufmgr.num_pages();
+        table.truncate(&mut bufmgr).unwrap();
+
+        let opened = catalog::open_table(&mut bufmgr, "people").unwrap();
+        assert_eq!(table.meta_page_id, opened.meta_page_id);
+        assert_eq!(0, table.approx_row_count(&mut bufmgr).unwrap());
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        drop(iter);
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[&[b'a'; 200]])
+            .unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        drop(iter);
+
+        for i in 0u32..100 {
+            let id = i.to_be_bytes();
+            table
+                .insert(&mut bufmgr, &[&id, &[b'b'; 200], &id])
+                .unwrap();
+        }
+        assert_eq!(100, table.recount(&mut bufmgr).unwrap());
+        assert_eq!(
+            Some(skey(&[&5u32.to_be_bytes()])),
+            table.unique_indices[0]
+                .lookup(&mut bufmgr, &[&5u32.to_be_bytes()])
+                .unwrap()
+        );
+        assert_eq!(num_pages, bufmgr.num_pages());
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        for i in 0u32..400 {
+            let id = (i * 7 % 400).to_be_bytes();
+            table
+                .insert(&mut bufmgr, &[&id, &[b'a'; 200], &id])
+                .unwrap();
+        }
+        for i in (0u32..400).filter(|i| i % 4 != 0) {
+            table.delete(&mut bufmgr, &[&i.to_be_bytes()]).unwrap();
+        }
+
+        bufmgr.begin().unwrap();
+        let err = table.vacuum(&mut bufmgr).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::BTree(btree::Error::InTransaction)
+        ));
+        bufmgr.rollback().unwrap();
+        let report = table.vacuum(&mut bufmgr).unwrap();
+        assert_eq!(100, report.rows_copied);
+        assert!(report.pages_after * 2 < report.pages_before);
+        let opened = catalog::open_table(&mut bufmgr, "people").unwrap();
+        assert_eq!(table.meta_page_id, opened.meta_page_id);
+        assert_eq!(100, table.approx_row_count(&mut bufmgr).unwrap());
+        assert_eq!(100, table.recount(&mut bufmgr).unwrap());
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[&[b'a'; 200]])
+            .unwrap();
+        let mut num_rows = 0;
+        while iter.next(&mut bufmgr).unwrap().is_some() {
+            num_rows += 1;
+        }
+        assert_eq!(100, num_rows);
+        drop(iter);
+        assert_eq!(
+            Some(skey(&[&8u32.to_be_bytes()])),
+            table.unique_indices[0]
+                .lookup(&mut bufmgr, &[&8u32.to_be_bytes()])
+                .unwrap()
+        );
+        assert!(table
+            .insert(&mut bufmgr, &[&[1; 4], &[b'b'; 200], &8u32.to_be_bytes()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_constraints() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            constraints: vec![Constraint {
+                name: "non_empty_name".to_string(),
+                expr: BoolExpr::Ne(
+                    BytesExpr::Column(1),
+                    BytesExpr::Literal(tuple::BytesValue::default()),
+                ),
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        // an unknown result passes
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"y"), None, Some(b"Jones")])
+            .unwrap();
+
+        let table = catalog::open_table(&mut bufmgr, "people").unwrap();
+        let err = table
+            .insert(&mut bufmgr, &[b"x", b"", b"Johnson"])
+            .unwrap_err();
+        assert_eq!(
+            r#"constraint "non_empty_name" is violated by Tuple("x" [78], "" [], "Johnson" [4a, 6f, 68, 6e, 73, 6f, 6e])"#,
+            err.to_string()
+        );
+        assert!(table.update(&mut bufmgr, &[b"z", b"", b"Smith"]).is_err());
+        assert!(table
+            .insert_batch(
+                &mut bufmgr,
+                vec![vec![b"w".to_vec(), vec![], vec![]]].into_iter()
+            )
+            .is_err());
+        assert_eq!(2, table.recount(&mut bufmgr).unwrap());
+
+        let mut other = Table {
+            constraints: table.constraints.clone(),
+            ..Table::new("other", 1, 1)
+        };
+        assert!(other.create(&mut bufmgr).is_err());
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = |name: &str, foreign_keys| Table {
+            foreign_keys,
+            ..Table::new(name, 1, 2)
+        };
+        let mut authors = table("authors", vec![]);
+        authors.create(&mut bufmgr).unwrap();
+        let mut books = table(
+            "books",
+            vec![ForeignKey {
+                columns: vec![1],
+                parent: "authors".to_string(),
+                on_delete: OnDelete::Cascade,
+            }],
+        );
+        books.create(&mut bufmgr).unwrap();
+        assert_eq!(vec![1], books.secondary_indices[0].skey);
+        let mut reviews = table(
+            "reviews",
+            vec![ForeignKey {
+                columns: vec![1],
+                parent: "books".to_string(),
+                on_delete: OnDelete::Restrict,
+            }],
+        );
+        reviews.create(&mut bufmgr).unwrap();
+        assert!(table(
+            "bad",
+            vec![ForeignKey {
+                columns: vec![0, 1],
+                parent: "authors".to_string(),
+                on_delete: OnDelete::Restrict,
+            }]
+        )
+        .create(&mut bufmgr)
+        .is_err());
+
+        authors.insert(&mut bufmgr, &[b"a1", b"Alice"]).unwrap();
+        authors.insert(&mut bufmgr, &[b"a2", b"Bob"]).unwrap();
+        books.insert(&mut bufmgr, &[b"b1", b"a1"]).unwrap();
+        books.insert(&mut bufmgr, &[b"b2", b"a1"]).unwrap();
+        books.insert(&mut bufmgr, &[b"b3", b"a2"]).unwrap();
+        books
+            .insert_nullable(&mut bufmgr, &[Some(b"b4"), None])
+            .unwrap();
+        let err = books.insert(&mut bufmgr, &[b"b5", b"a3"]).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ParentRowNotFound(parent)) if parent == "authors"
+        ));
+        assert!(books
+            .insert_batch(
+                &mut bufmgr,
+                vec![vec![b"b5".to_vec(), b"a3".to_vec()]].into_iter()
+            )
+            .is_err());
+        assert!(books.update(&mut bufmgr, &[b"b4", b"a3"]).is_err());
+        reviews.insert(&mut bufmgr, &[b"r1", b"b3"]).unwrap();
+
+        // b3 is reviewed, so deleting its author is blocked before anything is deleted
+        let err = authors.delete(&mut bufmgr, &[b"a2"]).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::RowReferenced(child)) if child == "reviews"
+        ));
+        assert_eq!(4, books.recount(&mut bufmgr).unwrap());
+        assert!(authors.get(&mut bufmgr, &[b"a2"]).unwrap().is_some());
+
+        authors.delete(&mut bufmgr, &[b"a1"]).unwrap();
+        let mut iter = books.scan(&mut bufmgr).unwrap();
+        let mut ids = vec![];
+        while let Some(record) = iter.next(&mut bufmgr).unwrap() {
+            ids.push(record[0].clone().unwrap());
+        }
+        assert_eq!(vec![b"b3".to_vec(), b"b4".to_vec()], ids);
+        drop(iter);
+        let mut iter = books.secondary_indices[0]
+            .lookup(&mut bufmgr, &[b"a1"])
+            .unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        drop(iter);
+
+        reviews.delete(&mut bufmgr, &[b"r1"]).unwrap();
+        authors.delete(&mut bufmgr, &[b"a2"]).unwrap();
+        assert_eq!(1, books.recount(&mut bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_self_referencing_batch() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut employees = Table {
+            foreign_keys: vec![ForeignKey {
+                columns: vec![1],
+                parent: "employees".to_string(),
+                on_delete: OnDelete::Restrict,
+            }],
+            ..Table::new("employees", 1, 2)
+        };
+        employees.create(&mut bufmgr).unwrap();
+        let row = |id: &'static [u8], manager: Option<&'static [u8]>| vec![Some(id), manager];
+
+        // a manager earlier in the batch counts, as it would for single inserts
+        let err = employees
+            .insert_batch(
+                &mut bufmgr,
+                vec![
+                    row(b"1", None),
+                    row(b"2", Some(b"1")),
+                    row(b"3", Some(b"2")),
+                    row(b"4", Some(b"5")),
+                    row(b"5", None),
+                ]
+                .into_iter(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::BatchInsert { inserted: 3, .. })
+        ));
+        assert_eq!(3, employees.recount(&mut bufmgr).unwrap());
+        // a row cannot be its own parent before it is inserted
+        assert!(employees
+            .insert_batch(&mut bufmgr, vec![row(b"6", Some(b"6"))].into_iter())
+            .is_err());
+        assert!(employees.insert(&mut bufmgr, &[b"6", b"6"]).is_err());
+    }
+
+    #[test]
+    fn test_foreign_keys_drop_and_truncate() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = |name: &str, foreign_keys| Table {
+            foreign_keys,
+            ..Table::new(name, 1, 2)
+        };
+        let foreign_key = |parent: &str, on_delete| {
+            vec![ForeignKey {
+                columns: vec![1],
+                parent: parent.to_string(),
+                on_delete,
+            }]
+        };
+        let mut authors = table("authors", vec![]);
+        authors.create(&mut bufmgr).unwrap();
+        // no table has a foreign key yet, so the catalog is not read
+        let catalog = Catalog::open(&mut bufmgr).unwrap();
+        assert!(!catalog.may_have_foreign_keys(&mut bufmgr).unwrap());
+        let mut books = table("books", foreign_key("authors", OnDelete::Cascade));
+        books.create(&mut bufmgr).unwrap();
+        let mut reviews = table("reviews", foreign_key("books", OnDelete::Restrict));
+        reviews.create(&mut bufmgr).unwrap();
+        assert!(catalog.may_have_foreign_keys(&mut bufmgr).unwrap());
+        let names = |tables: Vec<Table>| -> Vec<String> {
+            tables.into_iter().map(|table| table.name).collect()
+        };
+        assert_eq!(
+            vec!["books", "reviews"],
+            names(authors.referencing_tables(&mut bufmgr).unwrap())
+        );
+        assert!(reviews.referencing_tables(&mut bufmgr).unwrap().is_empty());
+
+        authors.insert(&mut bufmgr, &[b"a1", b"Alice"]).unwrap();
+        authors.insert(&mut bufmgr, &[b"a2", b"Bob"]).unwrap();
+        books.insert(&mut bufmgr, &[b"b1", b"a1"]).unwrap();
+        books.insert(&mut bufmgr, &[b"b2", b"a2"]).unwrap();
+        reviews.insert(&mut bufmgr, &[b"r1", b"b2"]).unwrap();
+
+        let err = catalog::open_table(&mut bufmgr, "authors")
+            .unwrap()
+            .drop(&mut bufmgr)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::TableReferenced(child)) if child == "books"
+        ));
+        // r1 reviews a book of a2, so nothing is truncated
+        let err = authors.truncate(&mut bufmgr).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::RowReferenced(child)) if child == "reviews"
+        ));
+        assert_eq!(2, authors.recount(&mut bufmgr).unwrap());
+        assert_eq!(2, books.recount(&mut bufmgr).unwrap());
+
+        reviews.delete(&mut bufmgr, &[b"r1"]).unwrap();
+        authors.truncate(&mut bufmgr).unwrap();
+        assert_eq!(0, authors.recount(&mut bufmgr).unwrap());
+        assert_eq!(0, books.recount(&mut bufmgr).unwrap());
+        let err = books.insert(&mut bufmgr, &[b"b3", b"a1"]).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ParentRowNotFound(parent)) if parent == "authors"
+        ));
+
+        for name in ["reviews", "books", "authors"] {
+            catalog::open_table(&mut bufmgr, name)
+                .unwrap()
+                .drop(&mut bufmgr)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_covering_index() {
+        use crate::query::{IndexOnlyScan, IndexScan, PlanNode, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![1],
+            }],
+            ..Table::new("digests", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .create_index(&mut bufmgr, None, vec![2], vec![], vec![], false)
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"1", b"md5-1", b"sha1-1"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"2", b"md5-2", b"sha1-2"])
+            .unwrap();
+
+        let unique_index = &table.unique_indices[0];
+        assert!(unique_index.covers(1, &[0, 1, 2]));
+        assert!(table.secondary_indices[0].covers(1, &[0, 2]));
+        assert!(!table.secondary_indices[0].covers(1, &[1, 2]));
+        assert_eq!(
+            Some(skey(&[b"2"])),
+            unique_index.lookup(&mut bufmgr, &[b"sha1-2"]).unwrap()
+        );
+
+        let scan_only = |bufmgr: &mut BufferPoolManager, meta_page_id| {
+            let plan = IndexOnlyScan {
+                index_meta_page_id: meta_page_id,
+                directions: vec![],
+                search_mode: TupleSearchMode::Start,
+                direction: ScanDirection::Forward,
+                end: None,
+                while_cond: Box::new(|_| Ok(true)),
+            };
+            let mut exec = plan.start(bufmgr).unwrap();
+            let mut rows = vec![];
+            while let Some(row) = exec.next(bufmgr).unwrap() {
+                rows.push(row);
+            }
+            rows
+        };
+        let row = |elems: &[&[u8]]| -> Tuple { elems.iter().map(|e| Some(e.to_vec())).collect() };
+        assert_eq!(
+            vec![
+                row(&[b"sha1-1", b"1", b"md5-1"]),
+                row(&[b"sha1-2", b"2", b"md5-2"])
+            ],
+            scan_only(&mut bufmgr, unique_index.meta_page_id)
+        );
+
+        table
+            .update(&mut bufmgr, &[b"1", b"md5-x", b"sha1-1"])
+            .unwrap();
+        table.delete(&mut bufmgr, &[b"2"]).unwrap();
+        assert_eq!(
+            vec![row(&[b"sha1-1", b"1", b"md5-x"])],
+            scan_only(&mut bufmgr, unique_index.meta_page_id)
+        );
+        assert_eq!(
+            vec![row(&[b"sha1-1", b"1", b"1"])],
+            scan_only(&mut bufmgr, table.secondary_indices[0].meta_page_id)
+        );
+
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index_meta_page_id: unique_index.meta_page_id,
+            directions: vec![],
+            num_included: 1,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            end: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(row(&[b"1", b"md5-x", b"sha1-1"])),
+            exec.next(&mut bufmgr).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_add_column() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            schema: Some(vec![ColumnType::I64, ColumnType::Utf8]),
+            ..Table::new("people", 1, 2)
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert_typed(&mut bufmgr, &[Value::I64(1), Value::Utf8("Alice".into())])
+            .unwrap();
+
+        let err = table
+            .add_column(&mut bufmgr, "age", ColumnType::U64, Value::Bool(true))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::TypeMismatch { column: 2, .. })
+        ));
+        table
+            .add_column(&mut bufmgr, "age", ColumnType::U64, Value::U64(20))
+            .unwrap();
+        assert!(table
+            .add_column(&mut bufmgr, "age", ColumnType::U64, Value::Null)
+            .is_err());
+        table
+            .add_column(&mut bufmgr, "nickname", ColumnType::Utf8, Value::Null)
+            .unwrap();
+        assert!(table
+            .insert_typed(&mut bufmgr, &[Value::I64(2), Value::Utf8("Bob".into())])
+            .is_err());
+        table
+            .insert_typed(
+                &mut bufmgr,
+                &[
+                    Value::I64(2),
+                    Value::Utf8("Bob".into()),
+                    Value::U64(30),
+                    Value::Utf8("Bobby".into()),
+                ],
+            )
+            .unwrap();
+
+        let table = catalog::open_table(&mut bufmgr, "people").unwrap();
+        let key = tuple::encode_value(&Value::I64(1)).unwrap();
+        let alice = vec![
+            Some(key.clone()),
+            Some(b"Alice".to_vec()),
+            tuple::encode_value(&Value::U64(20)),
+            None,
+        ];
+        assert_eq!(
+            Some(alice.clone()),
+            table.get(&mut bufmgr, &[&key]).unwrap()
+        );
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        assert_eq!(Some(alice), iter.next(&mut bufmgr).unwrap());
+        assert_eq!(4, iter.next(&mut bufmgr).unwrap().unwrap().len());
+        drop(iter);
+
+        let mut simple = Table {
+            name: "simple".to_string(),
+            schema: None,
+            ..table
+        };
+        let err = simple
+            .add_column(&mut bufmgr, "x", ColumnType::Bytes, Value::Null)
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Table(Error::NoSchema)));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1, 2],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(&mut bufmgr).unwrap();
+        let stats = table.analyze(&mut bufmgr).unwrap();
+        assert_eq!(0, stats.row_count);
+        assert_eq!(0.0, stats.avg_key_size);
+
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Alice", b"Johnson"])
+            .unwrap();
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"y"), None, None])
+            .unwrap();
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"w"), None, None])
+            .unwrap();
+        let stats = table.analyze(&mut bufmgr).unwrap();
+        assert_eq!(4, stats.row_count);
+        assert_eq!(9.0, stats.avg_key_size);
+        assert_eq!(18.0, stats.avg_value_size);
+        assert_eq!(
+            vec![(1, 2), (2, 3)],
+            stats.distinct_values.into_iter().collect::<Vec<_>>()
+        );
+
+        let table = catalog::open_table(&mut bufmgr, "people").unwrap();
+        assert_eq!(4, table.stats().unwrap().row_count);
+    }
+
+    #[test]
+    fn test_create_without_indices() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("simple", 1, 3);
+        let meta_page_id = table.create(&mut bufmgr).unwrap();
+        assert_eq!(table.meta_page_id, meta_page_id);
+        assert_eq!(
+            meta_page_id,
+            catalog::open_table(&mut bufmgr, "simple")
+                .unwrap()
+                .meta_page_id
+        );
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+
+        // rows are stored as the encoded pkey and the encoded other columns
+        let mut iter = BTree::new(meta_page_id)
+            .search(&mut bufmgr, SearchMode::Start)
+            .unwrap();
+        let mut value = vec![];
+        tuple::encode([b"Alice", b"Smith"].iter(), &mut value);
+        assert_eq!(
+            Some((skey(&[b"z"]), value)),
+            iter.next(&mut bufmgr).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        use crate::query::{PlanNode, SeqScan, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let cache = |expiry_column| Table {
+            expiry_column,
+            ..Table::new("cache", 1, 3)
+        };
+        let err = cache(Some(0)).create(&mut bufmgr).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::InvalidExpiryColumn(0))
+        ));
+        let mut table = cache(Some(2));
+        table.create(&mut bufmgr).unwrap();
+        assert_eq!(vec![2], table.secondary_indices[0].skey);
+        let err = table.insert(&mut bufmgr, &[b"a", b"A", b"10"]).unwrap_err();
+        assert!(matches!(err, crate::Error::Table(Error::InvalidExpiry(2))));
+        for (key, expires_at) in [(b"a", 10u64), (b"b", 30), (b"c", 20)] {
+            table
+                .insert(&mut bufmgr, &[key, b"value", &expires_at.to_be_bytes()])
+                .unwrap();
+        }
+        table
+            .insert_nullable(&mut bufmgr, &[Some(b"d"), Some(b"forever"), None])
+            .unwrap();
+
+        let keys = |iter: &mut TableIter, bufmgr: &mut BufferPoolManager| {
+            let mut keys = vec![];
+            while let Some(record) = iter.next(bufmgr).unwrap() {
+                keys.push(record[0].clone().unwrap());
+            }
+            keys
+        };
+        let mut iter = table.scan_at(&mut bufmgr, 20).unwrap();
+        assert_eq!(
+            vec![b"b".to_vec(), b"d".to_vec()],
+            keys(&mut iter, &mut bufmgr)
+        );
+        drop(iter);
+        assert!(table.get_at(&mut bufmgr, &[b"a"], 9).unwrap().is_some());
+        assert!(table.get_at(&mut bufmgr, &[b"a"], 10).unwrap().is_none());
+
+        let plan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            while_bound: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: table.expiry(25),
+            filter: None,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut rows = 0;
+        while exec.next(&mut bufmgr).unwrap().is_some() {
+            rows += 1;
+        }
+        assert_eq!(2, rows);
+        drop(exec);
+
+        assert_eq!(2, table.purge_expired(&mut bufmgr, 20).unwrap());
+        assert_eq!(0, table.purge_expired(&mut bufmgr, 20).unwrap());
+        let mut iter = table.scan_at(&mut bufmgr, 0).unwrap();
+        assert_eq!(
+            vec![b"b".to_vec(), b"d".to_vec()],
+            keys(&mut iter, &mut bufmgr)
+        );
+        drop(iter);
+        assert_eq!(1, table.purge_expired(&mut bufmgr, u64::MAX).unwrap());
+        assert_eq!(1, table.recount(&mut bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_row() {
+        use crate::query::{PlanNode, SeqScan, TupleSearchMode};
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut table = Table::new("people", 1, 2);
+        table.create(&mut bufmgr).unwrap();
+        BTree::new(table.meta_page_id)
+            .insert(&mut bufmgr, &skey(&[b"a"]), b"truncated")
+            .unwrap();
+
+        let err = table.get(&mut bufmgr, &[b"a"]).unwrap_err();
+        assert!(matches!(err, crate::Error::Decode(_)));
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        assert!(iter.next(&mut bufmgr).is_err());
+        drop(iter);
+        let plan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            search_mode: TupleSearchMode::Start,
+            direction: ScanDirection::Forward,
+            while_bound: None,
+            while_cond: Box::new(|_| Ok(true)),
+            expiry: None,
+            filter: None,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        assert!(exec.next(&mut bufmgr).is_err());
+    }
 }
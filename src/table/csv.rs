@@ -0,0 +1,283 @@
+//! CSV import and export following RFC 4180.
+//!
+//! Fields are written as UTF-8 text where possible. An empty unquoted field
+//! is NULL, while an empty string is written as `""`. A field starting with
+//! `\x` holds the hex-escaped bytes of a value, which is how values that are
+//! not valid UTF-8 (or that start with `\x` themselves) are written.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use super::{Error, Table};
+use crate::buffer::BufferPoolManager;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvEncoding {
+    /// UTF-8 text where valid, hex-escaped otherwise.
+    Utf8,
+    /// Every non-NULL value hex-escaped.
+    Hex,
+}
+
+const HEX_PREFIX: &[u8] = b"\\x";
+
+fn write_field(out: &mut impl Write, elem: Option<&[u8]>, encoding: CsvEncoding) -> io::Result<()> {
+    let bytes = match elem {
+        Some(bytes) => bytes,
+        None => return Ok(()),
+    };
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) if encoding == CsvEncoding::Utf8 && !bytes.starts_with(HEX_PREFIX) => text,
+        _ => {
+            out.write_all(HEX_PREFIX)?;
+            for b in bytes {
+                write!(out, "{:02x}", b)?;
+            }
+            return Ok(());
+        }
+    };
+    if text.is_empty() || text.contains(&[',', '"', '\r', '\n'][..]) {
+        write!(out, "\"{}\"", text.replace('"', "\"\""))
+    } else {
+        out.write_all(text.as_bytes())
+    }
+}
+
+/// Writes every row of `table` as a CSV record and returns the number of rows.
+pub fn export_csv(
+    bufmgr: &mut BufferPoolManager,
+    table: &Table,
+    mut out: impl Write,
+    encoding: CsvEncoding,
+) -> Result<usize> {
+    let mut iter = table.scan(bufmgr)?;
+    let mut num_rows = 0;
+    while let Some(record) = iter.next(bufmgr)? {
+        for (i, elem) in record.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            write_field(&mut out, elem.as_deref(), encoding)?;
+        }
+        out.write_all(b"\r\n")?;
+        num_rows += 1;
+    }
+    out.flush()?;
+    Ok(num_rows)
+}
+
+struct Field {
+    bytes: Vec<u8>,
+    quoted: bool,
+}
+
+impl Field {
+    fn decode(self) -> Result<Option<Vec<u8>>> {
+        if self.bytes.is_empty() && !self.quoted {
+            return Ok(None);
+        }
+        if !self.bytes.starts_with(HEX_PREFIX) {
+            return Ok(Some(self.bytes));
+        }
+        let hex = &self.bytes[HEX_PREFIX.len()..];
+        if !hex.len().is_multiple_of(2) {
+            return Err(Error::OddHexDigits.into());
+        }
+        hex.chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| {
+                        Error::InvalidHexDigits(String::from_utf8_lossy(pair).into_owned())
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+            .map_err(Into::into)
+    }
+}
+
+struct CsvReader<R> {
+    input: R,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = match self.input.fill_buf()?.first() {
+            Some(&byte) => byte,
+            None => return Ok(None),
+        };
+        self.input.consume(1);
+        if byte == b'\n' {
+            self.line += 1;
+        }
+        Ok(Some(byte))
+    }
+
+    fn peek_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    /// Reads the next record and the line it starts on.
+    fn read_record(&mut self) -> Result<Option<(usize, Vec<Field>)>> {
+        let line = self.line;
+        if self.peek_byte()?.is_none() {
+            return Ok(None);
+        }
+        let mut fields = vec![];
+        let mut field = Field {
+            bytes: vec![],
+            quoted: false,
+        };
+        let mut in_quotes = false;
+        loop {
+            let byte = self.read_byte()?;
+            if in_quotes {
+                match byte {
+                    Some(b'"') if self.peek_byte()? == Some(b'"') => {
+                        self.read_byte()?;
+                        field.bytes.push(b'"');
+                    }
+                    Some(b'"') => in_quotes = false,
+                    Some(byte) => field.bytes.push(byte),
+                    None => return Err(Error::UnterminatedField(line).into()),
+                }
+                continue;
+            }
+            match byte {
+                Some(b'"') if field.bytes.is_empty() && !field.quoted => {
+                    field.quoted = true;
+                    in_quotes = true;
+                }
+                Some(b',') => {
+                    let next = Field {
+                        bytes: vec![],
+                        quoted: false,
+                    };
+                    fields.push(std::mem::replace(&mut field, next));
+                }
+                Some(b'\r') if self.peek_byte()? == Some(b'\n') => {}
+                Some(b'\n') | None => {
+                    fields.push(field);
+                    return Ok(Some((line, fields)));
+                }
+                Some(byte) => field.bytes.push(byte),
+            }
+        }
+    }
+}
+
+/// Inserts every CSV record of `input` into `table` and returns the number
+/// of rows inserted. The first record that fails, or that goes past
+/// `max_rows`, stops the import with `Error::ImportFailed`, which carries
+/// the line the record starts on.
+pub fn import_csv(
+    bufmgr: &mut BufferPoolManager,
+    table: &Table,
+    input: impl Read,
+    has_header: bool,
+    max_rows: Option<usize>,
+) -> Result<usize> {
+    let mut reader = CsvReader {
+        input: BufReader::new(input),
+        line: 1,
+    };
+    if has_header {
+        reader.read_record()?;
+    }
+    let records = std::iter::from_fn(|| reader.read_record().transpose()).map(|record| {
+        let (line, fields) = record?;
+        let record = fields.into_iter().map(Field::decode).collect();
+        Ok((line, record))
+    });
+    super::import_records(bufmgr, table, records, max_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::Error;
+
+    fn people(bufmgr: &mut BufferPoolManager, name: &str) -> Table {
+        let mut table = Table::new(name, 1, 3);
+        table.create(bufmgr).unwrap();
+        table
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = people(&mut bufmgr, "people");
+        let records: [[Option<&[u8]>; 3]; 5] = [
+            [Some(b"a"), Some(b"Alice"), Some(b"Smith")],
+            [Some(b"b"), Some(b"Bob, Jr."), Some(b"say \"hi\"\r\nbye")],
+            [Some(b"c"), None, Some(b"")],
+            [Some(b"d"), Some(b"\xff\x00"), Some(b"\\x41")],
+            [Some(b"e"), Some("\u{3042}".as_bytes()), Some(b"\"")],
+        ];
+        for record in &records {
+            table.insert_nullable(&mut bufmgr, record).unwrap();
+        }
+
+        let mut csv = vec![];
+        assert_eq!(
+            5,
+            export_csv(&mut bufmgr, &table, &mut csv, CsvEncoding::Utf8).unwrap()
+        );
+        assert_eq!(
+            "a,Alice,Smith\r\n\
+             b,\"Bob, Jr.\",\"say \"\"hi\"\"\r\nbye\"\r\n\
+             c,,\"\"\r\n\
+             d,\\xff00,\\x5c783431\r\n\
+             e,\u{3042},\"\"\"\"\r\n",
+            String::from_utf8(csv.clone()).unwrap()
+        );
+
+        let copy = people(&mut bufmgr, "copy");
+        let mut input = b"id,first_name,last_name\n".to_vec();
+        input.extend_from_slice(&csv);
+        assert_eq!(
+            5,
+            import_csv(&mut bufmgr, &copy, &input[..], true, None).unwrap()
+        );
+        let mut hex = vec![];
+        export_csv(&mut bufmgr, &copy, &mut hex, CsvEncoding::Hex).unwrap();
+        assert!(String::from_utf8(hex.clone())
+            .unwrap()
+            .starts_with("\\x61,\\x416c696365,\\x536d697468\r\n"));
+
+        let other = people(&mut bufmgr, "other");
+        import_csv(&mut bufmgr, &other, &hex[..], false, None).unwrap();
+        let mut again = vec![];
+        export_csv(&mut bufmgr, &other, &mut again, CsvEncoding::Utf8).unwrap();
+        assert_eq!(csv, again);
+    }
+
+    #[test]
+    fn test_import_error() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = people(&mut bufmgr, "people");
+        let input = "a,\"multi\nline\",x\nb,Bob,y\na,Alice,z\n";
+        let err = import_csv(&mut bufmgr, &table, input.as_bytes(), false, None).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ImportFailed { line: 4, .. })
+        ));
+        assert_eq!(2, table.recount(&mut bufmgr).unwrap());
+
+        let err = import_csv(&mut bufmgr, &table, &b"c,\\x4,z\n"[..], false, None).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ImportFailed { line: 1, .. })
+        ));
+        assert!(import_csv(&mut bufmgr, &table, &b"c,\"x"[..], false, None).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Import of newline-delimited JSON: each line is a record in the wire
+//! representation of `tuple::Record`, so an element is a string,
+//! `{"hex": "..."}` or `{"b64": "..."}`, or `null`. Blank lines are skipped.
+
+use std::io::{BufRead, BufReader, Read};
+
+use super::Table;
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Record;
+use crate::Result;
+
+/// Inserts the record on each line of `input` into `table` and returns the
+/// number of rows inserted. Like `import_csv`, the first line that fails,
+/// or that goes past `max_rows`, stops the import with
+/// `Error::ImportFailed`.
+pub fn import_jsonl(
+    bufmgr: &mut BufferPoolManager,
+    table: &Table,
+    input: impl Read,
+    max_rows: Option<usize>,
+) -> Result<usize> {
+    let mut input = BufReader::new(input);
+    let mut line = 0;
+    let records = std::iter::from_fn(|| loop {
+        let mut bytes = vec![];
+        line += 1;
+        match input.read_until(b'\n', &mut bytes) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(err.into())),
+        }
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let record = serde_json::from_slice::<Record>(&bytes)
+            .map(|record| record.0)
+            .map_err(Into::into);
+        return Some(Ok((line, record)));
+    });
+    super::import_records(bufmgr, table, records, max_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::Error;
+
+    fn people(bufmgr: &mut BufferPoolManager) -> Table {
+        let mut table = Table::new("people", 1, 3);
+        table.create(bufmgr).unwrap();
+        table
+    }
+
+    #[test]
+    fn test_import() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = people(&mut bufmgr);
+        let input = r#"["a", "Alice", null]
+
+["b", {"hex": "ff00"}, {"b64": "eQ=="}]
+"#;
+        assert_eq!(
+            2,
+            import_jsonl(&mut bufmgr, &table, input.as_bytes(), None).unwrap()
+        );
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        let mut records = vec![];
+        while let Some(record) = iter.next(&mut bufmgr).unwrap() {
+            records.push(record);
+        }
+        assert_eq!(
+            vec![
+                vec![Some(b"a".to_vec()), Some(b"Alice".to_vec()), None],
+                vec![
+                    Some(b"b".to_vec()),
+                    Some(vec![0xff, 0x00]),
+                    Some(b"y".to_vec())
+                ],
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn test_import_error() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let table = people(&mut bufmgr);
+        let input =
+            "[\"a\", \"x\", \"y\"]\n[\"b\", \"x\", \"y\"]\n[\"c\", \"x\"\n[\"d\", \"x\", \"y\"]\n";
+        let err = import_jsonl(&mut bufmgr, &table, input.as_bytes(), None).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ImportFailed {
+                line: 3,
+                inserted: 2,
+                ..
+            })
+        ));
+        assert_eq!(2, table.recount(&mut bufmgr).unwrap());
+
+        // a duplicate key is found by the batch insert, after decoding
+        let input = "[\"c\", \"x\", \"y\"]\n[\"a\", \"x\", \"y\"]\n";
+        let err = import_jsonl(&mut bufmgr, &table, input.as_bytes(), None).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(Error::ImportFailed {
+                line: 2,
+                inserted: 1,
+                ..
+            })
+        ));
+
+        let input = "[\"d\", \"x\", \"y\"]\n[\"e\", \"x\", \"y\"]\n";
+        let err = import_jsonl(&mut bufmgr, &table, input.as_bytes(), Some(1)).unwrap_err();
+        assert_eq!("import failed at line 2 after 1 rows", err.to_string());
+        assert_eq!(4, table.recount(&mut bufmgr).unwrap());
+    }
+}
@@ -0,0 +1,338 @@
+//! A `PageStore` for crash tests: it keeps pages in memory, records every
+//! write and sync, and can say what a heap file would hold after a crash
+//! at any point of what it recorded. Outside this crate's tests it needs
+//! the `testutil` feature.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use crate::disk::{DiskManager, PageId, PageStore, PAGE_SIZE};
+
+/// What a crash does to the writes since the last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crash {
+    /// They are all lost.
+    DropUnsynced,
+    /// They are all lost but the write the crash interrupted, which
+    /// reaches the first half of its page.
+    TearWrite,
+}
+
+#[derive(Debug, Clone)]
+enum Event {
+    Write { page_id: u64, data: Vec<u8> },
+    Truncate(u64),
+    Sync,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The pages the store started with.
+    synced: Vec<Vec<u8>>,
+    /// The pages as reads see them, with every write applied.
+    pages: Vec<Vec<u8>>,
+    events: Vec<Event>,
+}
+
+/// Clones share their pages and record, so a test can hand one clone to a
+/// `DiskManager` and look at what it did through another.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjectingStore {
+    state: Rc<RefCell<State>>,
+}
+
+impl FaultInjectingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store holding `image`, as the heap file it would be, with nothing
+    /// recorded yet.
+    pub fn from_image(image: &[u8]) -> Self {
+        let pages: Vec<_> = image.chunks(PAGE_SIZE).map(|page| page.to_vec()).collect();
+        Self {
+            state: Rc::new(RefCell::new(State {
+                synced: pages.clone(),
+                pages,
+                events: vec![],
+            })),
+        }
+    }
+
+    /// The number of page writes recorded. A crash can happen before any
+    /// of them, or after the last, which is `crash_image(num_writes(), _)`.
+    pub fn num_writes(&self) -> usize {
+        self.state
+            .borrow()
+            .events
+            .iter()
+            .filter(|event| matches!(event, Event::Write { .. }))
+            .count()
+    }
+
+    /// The number of syncs recorded.
+    pub fn num_syncs(&self) -> usize {
+        self.syncs_before(usize::MAX)
+    }
+
+    /// The number of syncs recorded before write `write_no`.
+    pub fn syncs_before(&self, write_no: usize) -> usize {
+        let state = self.state.borrow();
+        let mut num_writes = 0;
+        let mut num_syncs = 0;
+        for event in &state.events {
+            match event {
+                Event::Write { .. } if num_writes == write_no => break,
+                Event::Write { .. } => num_writes += 1,
+                Event::Truncate(_) => {}
+                Event::Sync => num_syncs += 1,
+            }
+        }
+        num_syncs
+    }
+
+    /// The heap file as it would be after a crash in the middle of write
+    /// `write_no`, counting from 0.
+    pub fn crash_image(&self, write_no: usize, crash: Crash) -> Vec<u8> {
+        let state = self.state.borrow();
+        let mut durable = state.synced.clone();
+        let mut pending: Vec<&Event> = vec![];
+        let mut num_writes = 0;
+        let mut torn = None;
+        for event in &state.events {
+            match event {
+                Event::Write { page_id, data } => {
+                    if num_writes == write_no {
+                        torn = Some((*page_id, data));
+                        break;
+                    }
+                    num_writes += 1;
+                    pending.push(event);
+                }
+                Event::Truncate(_) => pending.push(event),
+                Event::Sync => {
+                    for event in pending.drain(..) {
+                        apply(&mut durable, event);
+                    }
+                }
+            }
+        }
+        if let (Crash::TearWrite, Some((page_id, data))) = (crash, torn) {
+            let page = page_mut(&mut durable, page_id);
+            page[..PAGE_SIZE / 2].copy_from_slice(&data[..PAGE_SIZE / 2]);
+        }
+        durable.concat()
+    }
+
+    /// A `DiskManager` over what `crash_image` gives, as recovery would
+    /// open the heap file.
+    pub fn recover(&self, write_no: usize, crash: Crash) -> io::Result<DiskManager> {
+        DiskManager::with_store(Self::from_image(&self.crash_image(write_no, crash)))
+    }
+}
+
+fn page_mut(pages: &mut Vec<Vec<u8>>, page_id: u64) -> &mut Vec<u8> {
+    let index = page_id as usize;
+    if pages.len() <= index {
+        pages.resize(index + 1, vec![0; PAGE_SIZE]);
+    }
+    &mut pages[index]
+}
+
+fn apply(pages: &mut Vec<Vec<u8>>, event: &Event) {
+    match event {
+        Event::Write { page_id, data } => page_mut(pages, *page_id).copy_from_slice(data),
+        Event::Truncate(num_pages) => pages.truncate(*num_pages as usize),
+        Event::Sync => {}
+    }
+}
+
+impl PageStore for FaultInjectingStore {
+    fn num_pages(&self) -> io::Result<u64> {
+        Ok(self.state.borrow().pages.len() as u64)
+    }
+
+    fn read_page(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        let state = self.state.borrow();
+        let page = state.pages.get(page_id.to_u64() as usize).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("page {} is past the end of the store", page_id.to_u64()),
+            )
+        })?;
+        data.copy_from_slice(page);
+        Ok(())
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        let event = Event::Write {
+            page_id: page_id.to_u64(),
+            data: data.to_vec(),
+        };
+        let mut state = self.state.borrow_mut();
+        apply(&mut state.pages, &event);
+        state.events.push(event);
+        Ok(())
+    }
+
+    fn free_page(&mut self, _page_id: PageId) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn holes(&mut self) -> io::Result<Vec<u64>> {
+        Ok(vec![])
+    }
+
+    fn truncate(&mut self, num_pages: u64) -> io::Result<u64> {
+        let event = Event::Truncate(num_pages);
+        let mut state = self.state.borrow_mut();
+        let num_bytes = state.pages.len().saturating_sub(num_pages as usize) * PAGE_SIZE;
+        apply(&mut state.pages, &event);
+        state.events.push(event);
+        Ok(num_bytes as u64)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.state.borrow_mut().events.push(Event::Sync);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::btree::{BTree, MIN_POOL_SIZE};
+    use crate::buffer::{BufferPool, BufferPoolManager};
+    use crate::lang::{self, Request, Response};
+
+    fn execute(bufmgr: &mut BufferPoolManager, json: &str) -> crate::Result<Response> {
+        let req: Request = serde_json::from_str(json)?;
+        req.execute(bufmgr)
+    }
+
+    fn keys(bufmgr: &mut BufferPoolManager) -> crate::Result<BTreeSet<String>> {
+        let resp = execute(
+            bufmgr,
+            r#"{"Query": {"plan": {"SeqScan": {"table": "t"}}}}"#,
+        )?;
+        match resp {
+            Response::Query(resp) => Ok(resp
+                .rows
+                .into_iter()
+                .map(|row| String::from_utf8(row.0[0].clone().unwrap()).unwrap())
+                .collect()),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    }
+
+    #[test]
+    fn test_crash_at_every_write() {
+        let store = FaultInjectingStore::new();
+        let disk = DiskManager::with_store(store.clone()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+        execute(
+            &mut bufmgr,
+            r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2,
+                "indices": [{"skey": [1]}]}}"#,
+        )
+        .unwrap();
+        bufmgr.flush().unwrap();
+        let first_write = store.num_writes();
+        // what a recovery must find once each number of syncs is durable
+        let mut checkpoints = vec![(store.num_syncs(), BTreeSet::new())];
+        let mut committed = BTreeSet::new();
+        for round in 0u32..12 {
+            execute(&mut bufmgr, r#""Begin""#).unwrap();
+            let mut inserted = vec![];
+            for i in 0..40 {
+                let key = format!("{:02}-{:03}", round, i * 7 % 40);
+                let json = format!(
+                    r#"{{"Insert": {{"table": "t", "record": ["{}", "{}"]}}}}"#,
+                    key,
+                    "v".repeat(i * 13 % 200)
+                );
+                execute(&mut bufmgr, &json).unwrap();
+                inserted.push(key);
+            }
+            if round % 3 == 2 {
+                execute(&mut bufmgr, r#""Rollback""#).unwrap();
+                continue;
+            }
+            let deleted = format!("{:02}-", round.saturating_sub(1));
+            let json = format!(
+                r#"{{"Delete": {{"table": "t", "plan": {{"SeqScan": {{"table": "t",
+                    "key": ["{0}"], "while": {{"Lt": ["{0}010"]}}}}}}}}}}"#,
+                deleted
+            );
+            execute(&mut bufmgr, &json).unwrap();
+            execute(&mut bufmgr, r#""Commit""#).unwrap();
+            committed.extend(inserted);
+            let end = format!("{}010", deleted);
+            committed.retain(|key: &String| !(key.starts_with(&deleted) && *key < end));
+            checkpoints.push((store.num_syncs(), committed.clone()));
+        }
+        bufmgr.leak_on_drop();
+        drop(bufmgr);
+
+        // with no write-ahead log, a torn page of a btree is not recovered
+        // from, so only whole unsynced writes are lost here
+        for write_no in first_write..=store.num_writes() {
+            let num_syncs = store.syncs_before(write_no);
+            let (_, expected) = checkpoints
+                .iter()
+                .rev()
+                .find(|(synced, _)| *synced <= num_syncs)
+                .unwrap();
+            let disk = store.recover(write_no, Crash::DropUnsynced).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+            assert_eq!(
+                Vec::<String>::new(),
+                lang::verify_tables(&mut bufmgr).unwrap(),
+                "crash at write {}",
+                write_no
+            );
+            assert_eq!(
+                expected,
+                &keys(&mut bufmgr).unwrap(),
+                "crash at write {}",
+                write_no
+            );
+            bufmgr.leak_on_drop();
+        }
+    }
+
+    #[test]
+    fn test_torn_meta_write() {
+        let store = FaultInjectingStore::new();
+        let disk = DiskManager::with_store(store.clone()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+        let btree = BTree::create_first(&mut bufmgr, false).unwrap();
+        for i in 0u64..100 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), b"value")
+                .unwrap();
+        }
+        bufmgr.flush().unwrap();
+        // both copies of the header are written, then one of them again
+        let first_write = store.num_writes();
+        btree.set_num_entries(&mut bufmgr, 100).unwrap();
+        btree.set_num_entries(&mut bufmgr, 100).unwrap();
+        bufmgr.flush().unwrap();
+        btree.set_num_entries(&mut bufmgr, 100).unwrap();
+        bufmgr.flush().unwrap();
+        bufmgr.leak_on_drop();
+        drop(bufmgr);
+
+        for write_no in first_write..store.num_writes() {
+            let disk = store.recover(write_no, Crash::TearWrite).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(MIN_POOL_SIZE));
+            let verification = btree.verify(&mut bufmgr).unwrap();
+            assert!(verification.problems.is_empty());
+            assert_eq!(100, verification.num_entries);
+            bufmgr.leak_on_drop();
+        }
+    }
+}
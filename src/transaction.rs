@@ -0,0 +1,309 @@
+//! A handle on the transaction of a `BufferPoolManager`.
+//!
+//! There is one writer and at most one transaction at a time. While it is
+//! open, the first modification of each page saves what the page held
+//! before, and the pages it dirties stay in the pool; `rollback` copies the
+//! saved pages back. The handle derefs to the `BufferPoolManager`, so
+//! `Table::insert`, `delete`, `update` and the rest take `&mut tx` and run
+//! within the transaction. With a `LockManager` set on the manager, the
+//! rows they write are locked until the transaction ends.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::buffer::{BufferPoolManager, Commit};
+use crate::Result;
+
+pub struct Transaction<'a> {
+    bufmgr: &'a mut BufferPoolManager,
+    /// Cleared by `commit` and `rollback`, so that `Drop` leaves it alone.
+    open: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn begin(bufmgr: &'a mut BufferPoolManager) -> Result<Self> {
+        bufmgr.begin()?;
+        Ok(Self { bufmgr, open: true })
+    }
+
+    /// Runs `f` in a transaction, committing it if `f` succeeds and
+    /// rolling it back if `f` fails.
+    pub fn run<T>(
+        bufmgr: &mut BufferPoolManager,
+        f: impl FnOnce(&mut Transaction) -> Result<T>,
+    ) -> Result<T> {
+        let mut tx = Transaction::begin(bufmgr)?;
+        match f(&mut tx) {
+            Ok(value) => {
+                tx.commit()?;
+                Ok(value)
+            }
+            Err(err) => {
+                tx.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Writes the pages the transaction dirtied to the heap file and syncs
+    /// it, unless group commit leaves that to a later commit; see
+    /// `Commit::durable`.
+    pub fn commit(mut self) -> Result<Commit> {
+        self.open = false;
+        Ok(self.bufmgr.commit()?)
+    }
+
+    /// Puts back every page the transaction modified. Returns the number
+    /// of pages undone.
+    pub fn rollback(mut self) -> Result<usize> {
+        self.open = false;
+        Ok(self.bufmgr.rollback()?)
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = BufferPoolManager;
+
+    fn deref(&self) -> &Self::Target {
+        self.bufmgr
+    }
+}
+
+impl DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.bufmgr
+    }
+}
+
+impl Drop for Transaction<'_> {
+    /// A transaction neither committed nor rolled back is rolled back.
+    fn drop(&mut self) {
+        if !self.open {
+            return;
+        }
+        if let Err(err) = self.bufmgr.rollback() {
+            eprintln!("relly: failed to roll back a dropped transaction: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+    use crate::buffer::{self, BufferPool};
+    use crate::disk::{DiskManager, PageId};
+    use crate::lock::{LockError, LockManager, LockMode};
+    use crate::table::{self, SecondaryIndex, Table, UniqueIndex};
+
+    fn create_table(bufmgr: &mut BufferPoolManager) -> Table {
+        let mut table = Table {
+            unique_indices: vec![UniqueIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+                directions: vec![],
+                include: vec![],
+            }],
+            secondary_indices: vec![SecondaryIndex {
+                name: None,
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+                directions: vec![],
+                include: vec![],
+            }],
+            ..Table::new("people", 1, 3)
+        };
+        table.create(bufmgr).unwrap();
+        table
+    }
+
+    fn keys(bufmgr: &mut BufferPoolManager, table: &Table) -> Vec<Vec<u8>> {
+        let mut iter = table.scan(bufmgr).unwrap();
+        let mut keys = vec![];
+        while let Some(record) = iter.next(bufmgr).unwrap() {
+            keys.push(record[0].clone().unwrap());
+        }
+        keys
+    }
+
+    #[test]
+    fn test_rollback_batch() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+        let table = create_table(&mut bufmgr);
+        table.insert(&mut bufmgr, &[b"a", b"Alice", b"a@"]).unwrap();
+
+        let records: Vec<_> = (0..500)
+            .map(|i| format!("{:03}", i).into_bytes())
+            .chain(std::iter::once(b"x".to_vec()))
+            .map(|key| {
+                // the last one collides with "a" in the unique index
+                let email = if key == b"x" {
+                    b"a@".to_vec()
+                } else {
+                    key.clone()
+                };
+                vec![key, b"Bob".to_vec(), email]
+            })
+            .collect();
+        let err = Transaction::run(&mut bufmgr, |tx| {
+            table.insert_batch(tx, records.into_iter())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Table(table::Error::BatchInsert { inserted: 500, .. })
+        ));
+        assert_eq!(vec![b"a".to_vec()], keys(&mut bufmgr, &table));
+        assert!(!bufmgr.in_transaction());
+        assert!(bufmgr.resident_pages().iter().all(|page| !page.is_dirty));
+        for meta_page_id in [
+            table.meta_page_id,
+            table.unique_indices[0].meta_page_id,
+            table.secondary_indices[0].meta_page_id,
+        ] {
+            let verification = crate::btree::BTree::new(meta_page_id)
+                .verify(&mut bufmgr)
+                .unwrap();
+            assert!(verification.problems.is_empty());
+        }
+        let mut iter = table.secondary_indices[0]
+            .lookup(&mut bufmgr, &[b"Bob"])
+            .unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+
+        // the pages the transaction allocated are handed out again
+        let num_pages = bufmgr.num_pages();
+        table.insert(&mut bufmgr, &[b"b", b"Bob", b"b@"]).unwrap();
+        assert_eq!(num_pages, bufmgr.num_pages());
+    }
+
+    #[test]
+    fn test_commit_and_drop() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let table = create_table(&mut bufmgr);
+
+        let mut tx = Transaction::begin(&mut bufmgr).unwrap();
+        table.insert(&mut tx, &[b"a", b"Alice", b"a@"]).unwrap();
+        table.insert(&mut tx, &[b"b", b"Bob", b"b@"]).unwrap();
+        let commit = tx.commit().unwrap();
+        assert!(commit.pages > 0);
+        assert!(commit.durable);
+
+        let mut tx = Transaction::begin(&mut bufmgr).unwrap();
+        table.delete(&mut tx, &[b"a"]).unwrap();
+        table.update(&mut tx, &[b"b", b"Robert", b"b@"]).unwrap();
+        assert!(matches!(
+            Transaction::begin(&mut tx),
+            Err(crate::Error::Buffer(buffer::Error::TransactionOpen))
+        ));
+        drop(tx);
+        assert!(!bufmgr.in_transaction());
+        assert_eq!(
+            vec![b"a".to_vec(), b"b".to_vec()],
+            keys(&mut bufmgr, &table)
+        );
+        let record = table.get(&mut bufmgr, &[b"b"]).unwrap().unwrap();
+        assert_eq!(Some(b"Bob".to_vec()), record[1]);
+    }
+
+    #[test]
+    fn test_row_locks() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let table = create_table(&mut bufmgr);
+        table.insert(&mut bufmgr, &[b"b", b"Bob", b"b@"]).unwrap();
+        let locks = LockManager::new(std::time::Duration::from_secs(3600));
+        bufmgr.set_lock_manager(Some(locks.clone())).unwrap();
+        let key = |elem: &[u8]| {
+            let mut key = vec![];
+            table.format.encode([elem].iter(), &mut key);
+            key
+        };
+        let is_wait = |err| matches!(err, crate::Error::Lock(LockError::Wait { .. }));
+
+        // another transaction, as another connection would run it
+        let other = locks.new_txn_id();
+        let other_lock = locks
+            .acquire(other, table.meta_page_id, &key(b"b"), LockMode::Exclusive)
+            .unwrap();
+
+        let mut tx = Transaction::begin(&mut bufmgr).unwrap();
+        let txn_id = tx.txn_id().unwrap();
+        table.insert(&mut tx, &[b"a", b"Alice", b"a@"]).unwrap();
+        assert_eq!(
+            vec![(txn_id, LockMode::Exclusive)],
+            locks.holders(table.meta_page_id, &key(b"a"))
+        );
+        let err = table
+            .update(&mut tx, &[b"b", b"Robert", b"b@"])
+            .unwrap_err();
+        assert!(is_wait(err));
+        let err = table.delete(&mut tx, &[b"b"]).unwrap_err();
+        assert!(is_wait(err));
+        // reads take no locks unless asked to
+        assert!(table.get(&mut tx, &[b"b"]).unwrap().is_some());
+        locks.set_lock_point_reads(true);
+        let err = table.get(&mut tx, &[b"b"]).unwrap_err();
+        assert!(is_wait(err));
+        let err = table.get_by_index(&mut tx, 0, &[b"b@"]).unwrap_err();
+        assert!(is_wait(err));
+        // scans lock nothing, so they read past the row the other one holds
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], keys(&mut tx, &table));
+        assert_eq!(1, tx.num_row_locks());
+        assert!(matches!(
+            locks.acquire(other, table.meta_page_id, &key(b"a"), LockMode::Shared),
+            Err(LockError::Wait { holder }) if holder == txn_id
+        ));
+
+        // once the other one commits, the update it held back goes through
+        drop(other_lock);
+        assert!(table.get(&mut tx, &[b"b"]).is_ok());
+        assert_eq!(
+            vec![(txn_id, LockMode::Shared)],
+            locks.holders(table.meta_page_id, &key(b"b"))
+        );
+        for name in [&b"Robert"[..], b"Rob", b"Bobby"] {
+            table.update(&mut tx, &[b"b", name, b"b@"]).unwrap();
+        }
+        assert!(table.get(&mut tx, &[b"b"]).is_ok());
+        // the shared lock was upgraded, and each row is locked once
+        assert_eq!(
+            vec![(txn_id, LockMode::Exclusive)],
+            locks.holders(table.meta_page_id, &key(b"b"))
+        );
+        assert_eq!(2, tx.num_row_locks());
+        tx.commit().unwrap();
+        assert!(locks.holders(table.meta_page_id, &key(b"b")).is_empty());
+        // and the request the other one queued for "a" is granted
+        let _ = locks
+            .acquire(other, table.meta_page_id, &key(b"a"), LockMode::Shared)
+            .unwrap();
+
+        // a rollback releases the locks and the queued requests too
+        let mut tx = Transaction::begin(&mut bufmgr).unwrap();
+        table.delete(&mut tx, &[b"a"]).unwrap();
+        let other = locks.new_txn_id();
+        assert!(locks
+            .acquire(other, table.meta_page_id, &key(b"a"), LockMode::Exclusive)
+            .is_err());
+        let other_lock = locks
+            .acquire(other, table.meta_page_id, &key(b"c"), LockMode::Exclusive)
+            .unwrap();
+        let err = table.insert(&mut tx, &[b"c", b"Carol", b"c@"]).unwrap_err();
+        assert!(is_wait(err));
+        tx.rollback().unwrap();
+        drop(other_lock);
+        assert_eq!(1, locks.num_locks());
+        let _ = locks
+            .acquire(other, table.meta_page_id, &key(b"a"), LockMode::Exclusive)
+            .unwrap();
+        assert_eq!(0, locks.num_locks());
+        assert_eq!(
+            vec![b"a".to_vec(), b"b".to_vec()],
+            keys(&mut bufmgr, &table)
+        );
+    }
+}
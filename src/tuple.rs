@@ -1,41 +1,1359 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::convert::TryInto;
 use std::fmt::{self, Debug};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::memcmpable;
+pub use crate::memcmpable::DecodeError;
+
+#[cfg(any(test, feature = "testutil"))]
+pub mod testing;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid {0:?} value")]
+    InvalidValue(ColumnType),
+    #[error("invalid JSON tuple element: {0}")]
+    InvalidJson(serde_json::Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ColumnType {
+    Bytes,
+    Utf8,
+    I64,
+    U64,
+    F64,
+    Bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
 
-pub fn encode(elems: impl Iterator<Item = impl AsRef<[u8]>>, bytes: &mut Vec<u8>) {
-    elems.for_each(|elem| {
-        let elem_bytes = elem.as_ref();
-        let len = memcmpable::encoded_size(elem_bytes.len());
-        bytes.reserve(len);
-        memcmpable::encode(elem_bytes, bytes);
-    });
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    Utf8(String),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+    Null,
 }
 
-pub fn decode(bytes: &[u8], elems: &mut Vec<Vec<u8>>) {
-    let mut rest = bytes;
-    while !rest.is_empty() {
-        let mut elem = vec![];
-        memcmpable::decode(&mut rest, &mut elem);
-        elems.push(elem);
+impl Value {
+    /// Returns `None` for `Value::Null`, which fits any column type.
+    pub fn column_type(&self) -> Option<ColumnType> {
+        let ty = match self {
+            Value::Bytes(_) => ColumnType::Bytes,
+            Value::Utf8(_) => ColumnType::Utf8,
+            Value::I64(_) => ColumnType::I64,
+            Value::U64(_) => ColumnType::U64,
+            Value::F64(_) => ColumnType::F64,
+            Value::Bool(_) => ColumnType::Bool,
+            Value::Null => return None,
+        };
+        Some(ty)
+    }
+}
+
+/// Stores `n` big-endian with the sign bit flipped, so that byte order
+/// matches numeric order.
+pub fn encode_i64(n: i64) -> [u8; 8] {
+    ((n as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+pub fn decode_i64(bytes: [u8; 8]) -> i64 {
+    (u64::from_be_bytes(bytes) ^ (1 << 63)) as i64
+}
+
+pub fn encode_u64(n: u64) -> [u8; 8] {
+    n.to_be_bytes()
+}
+
+pub fn decode_u64(bytes: [u8; 8]) -> u64 {
+    u64::from_be_bytes(bytes)
+}
+
+/// Stores `x` so that byte order matches numeric order: positive numbers
+/// get their sign bit set and negative numbers are complemented. -0.0 is
+/// stored as 0.0, and every NaN as the same NaN, which sorts after infinity.
+pub fn encode_f64(x: f64) -> [u8; 8] {
+    let x = if x == 0.0 {
+        0.0
+    } else if x.is_nan() {
+        f64::NAN
+    } else {
+        x
+    };
+    let bits = x.to_bits();
+    let bits = if bits >> 63 == 0 {
+        bits | (1 << 63)
+    } else {
+        !bits
+    };
+    bits.to_be_bytes()
+}
+
+pub fn decode_f64(bytes: [u8; 8]) -> f64 {
+    let bits = u64::from_be_bytes(bytes);
+    let bits = if bits >> 63 == 1 {
+        bits & !(1 << 63)
+    } else {
+        !bits
+    };
+    f64::from_bits(bits)
+}
+
+/// Encodes a value as a tuple element whose byte order matches the value
+/// order; numbers use `encode_i64`, `encode_u64` and `encode_f64`.
+pub fn encode_value(value: &Value) -> Option<Vec<u8>> {
+    let elem = match value {
+        Value::Bytes(bytes) => bytes.clone(),
+        Value::Utf8(s) => s.as_bytes().to_vec(),
+        Value::I64(n) => encode_i64(*n).to_vec(),
+        Value::U64(n) => encode_u64(*n).to_vec(),
+        Value::F64(x) => encode_f64(*x).to_vec(),
+        Value::Bool(b) => vec![*b as u8],
+        Value::Null => return None,
+    };
+    Some(elem)
+}
+
+pub fn decode_value(ty: ColumnType, elem: Option<&[u8]>) -> Result<Value, Error> {
+    let invalid = || Error::InvalidValue(ty);
+    let elem = match elem {
+        Some(elem) => elem,
+        None => return Ok(Value::Null),
+    };
+    let value = match ty {
+        ColumnType::Bytes => Value::Bytes(elem.to_vec()),
+        ColumnType::Utf8 => Value::Utf8(String::from_utf8(elem.to_vec()).map_err(|_| invalid())?),
+        ColumnType::I64 => Value::I64(decode_i64(elem.try_into().map_err(|_| invalid())?)),
+        ColumnType::U64 => Value::U64(decode_u64(elem.try_into().map_err(|_| invalid())?)),
+        ColumnType::F64 => Value::F64(decode_f64(elem.try_into().map_err(|_| invalid())?)),
+        ColumnType::Bool => match elem {
+            [0] => Value::Bool(false),
+            [1] => Value::Bool(true),
+            _ => return Err(invalid()),
+        },
+    };
+    Ok(value)
+}
+
+/// A tuple element: a byte string, or NULL if `as_elem` returns `None`.
+pub trait Element {
+    fn as_elem(&self) -> Option<&[u8]>;
+}
+
+impl Element for [u8] {
+    fn as_elem(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl<const N: usize> Element for [u8; N] {
+    fn as_elem(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl Element for Vec<u8> {
+    fn as_elem(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl Element for str {
+    fn as_elem(&self) -> Option<&[u8]> {
+        Some(self.as_bytes())
+    }
+}
+
+impl Element for String {
+    fn as_elem(&self) -> Option<&[u8]> {
+        Some(self.as_bytes())
+    }
+}
+
+impl<T: AsRef<[u8]>> Element for Option<T> {
+    fn as_elem(&self) -> Option<&[u8]> {
+        self.as_ref().map(AsRef::as_ref)
+    }
+}
+
+impl<T: Element + ?Sized> Element for &T {
+    fn as_elem(&self) -> Option<&[u8]> {
+        (**self).as_elem()
+    }
+}
+
+/// Compares elements in the order of their encodings: NULL sorts last.
+pub fn cmp_elem(a: Option<&[u8]>, b: Option<&[u8]>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+    }
+}
+
+/// How the elements of a tuple are encoded. All the btrees of a table use
+/// the format it was created with, which is recorded in their meta pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Format {
+    /// 9-byte blocks; see `memcmpable::encode`.
+    #[default]
+    Blocks,
+    /// A marker byte, then the value with 0x00 escaped and a 2-byte
+    /// terminator, so short elements take far less than 9 bytes; see
+    /// `memcmpable::encode_escaped`.
+    Escaped,
+}
+
+impl Format {
+    /// The tag stored in a btree meta page. Trees written before formats
+    /// existed read as 0, which is `Blocks`.
+    pub fn tag(self) -> u64 {
+        match self {
+            Format::Blocks => 0,
+            Format::Escaped => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u64) -> Option<Self> {
+        match tag {
+            0 => Some(Format::Blocks),
+            1 => Some(Format::Escaped),
+            _ => None,
+        }
+    }
+
+    /// Appends one element; if `desc`, its encoding is complemented so it
+    /// sorts in reverse.
+    fn encode_elem(self, elem: Option<&[u8]>, desc: bool, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        match (self, elem) {
+            (Format::Blocks, Some(elem_bytes)) if desc => {
+                return memcmpable::encode_desc(elem_bytes, bytes);
+            }
+            (Format::Blocks, Some(elem_bytes)) => memcmpable::encode(elem_bytes, bytes),
+            (Format::Blocks, None) => memcmpable::encode_null(bytes),
+            (Format::Escaped, Some(elem_bytes)) => memcmpable::encode_escaped(elem_bytes, bytes),
+            (Format::Escaped, None) => memcmpable::encode_escaped_null(bytes),
+        }
+        if desc {
+            bytes[start..].iter_mut().for_each(|b| *b = !*b);
+        }
+    }
+
+    /// Decodes one element into `dst`, returning `false` if it is NULL.
+    fn decode_elem(
+        self,
+        rest: &mut &[u8],
+        dst: &mut Vec<u8>,
+        desc: bool,
+    ) -> Result<bool, DecodeError> {
+        match self {
+            Format::Blocks if desc => memcmpable::decode_nullable_inverted(rest, dst),
+            Format::Blocks => memcmpable::decode_nullable(rest, dst),
+            Format::Escaped => memcmpable::decode_escaped(rest, dst, desc),
+        }
+    }
+
+    /// The exact size `encode` produces for `elems`.
+    pub fn encoded_len(self, elems: impl Iterator<Item = impl Element>) -> usize {
+        match self {
+            Format::Blocks => encoded_len(elems.map(|elem| elem.as_elem().map_or(0, <[u8]>::len))),
+            Format::Escaped => elems
+                .map(|elem| elem.as_elem().map_or(1, memcmpable::escaped_size))
+                .sum(),
+        }
+    }
+
+    /// The length of the first element of `bytes`.
+    fn element_len(self, bytes: &[u8], desc: bool) -> Result<usize, DecodeError> {
+        match self {
+            Format::Blocks => memcmpable::element_len(bytes, desc),
+            Format::Escaped => memcmpable::escaped_element_len(bytes, desc),
+        }
+    }
+
+    /// Appends the encoded elements to `bytes`, growing it only once.
+    pub fn encode(self, elems: impl Iterator<Item = impl Element> + Clone, bytes: &mut Vec<u8>) {
+        bytes.reserve(self.encoded_len(elems.clone()));
+        elems.for_each(|elem| self.encode_elem(elem.as_elem(), false, bytes));
+    }
+
+    /// Like `encode`, but elements whose direction is `Desc` have their
+    /// encoding complemented so they sort in reverse. Elements past the end
+    /// of `directions` are ascending.
+    pub fn encode_directed(
+        self,
+        elems: impl Iterator<Item = impl Element> + Clone,
+        directions: &[Direction],
+        bytes: &mut Vec<u8>,
+    ) {
+        bytes.reserve(self.encoded_len(elems.clone()));
+        for (i, elem) in elems.enumerate() {
+            let desc = directions.get(i) == Some(&Direction::Desc);
+            self.encode_elem(elem.as_elem(), desc, bytes);
+        }
+    }
+
+    /// Decodes a tuple that is known to contain no NULLs, such as a primary
+    /// key.
+    pub fn decode(self, bytes: &[u8], elems: &mut Vec<Vec<u8>>) -> Result<(), DecodeError> {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let mut elem = vec![];
+            match self {
+                Format::Blocks => memcmpable::decode(&mut rest, &mut elem)?,
+                Format::Escaped => {
+                    if !memcmpable::decode_escaped(&mut rest, &mut elem, false)? {
+                        return Err(DecodeError::UnexpectedNull);
+                    }
+                }
+            }
+            elems.push(elem);
+        }
+        Ok(())
+    }
+
+    pub fn decode_nullable(
+        self,
+        bytes: &[u8],
+        elems: &mut Vec<Option<Vec<u8>>>,
+    ) -> Result<(), DecodeError> {
+        self.decode_directed(bytes, &[], elems)
+    }
+
+    pub fn decode_directed(
+        self,
+        bytes: &[u8],
+        directions: &[Direction],
+        elems: &mut Vec<Option<Vec<u8>>>,
+    ) -> Result<(), DecodeError> {
+        self.decode_with_limit(bytes, directions, elems, usize::MAX)
+    }
+
+    /// Like `decode_directed`, but fails with `DecodeError::TooLarge` once
+    /// the decoded elements total more than `max_total_bytes`. An element
+    /// never decodes to more bytes than it takes in `bytes`, so checking
+    /// after each one is enough to bound the allocation.
+    pub fn decode_with_limit(
+        self,
+        bytes: &[u8],
+        directions: &[Direction],
+        elems: &mut Vec<Option<Vec<u8>>>,
+        max_total_bytes: usize,
+    ) -> Result<(), DecodeError> {
+        let mut rest = bytes;
+        let mut total = 0;
+        let mut i = 0;
+        while !rest.is_empty() {
+            let mut elem = vec![];
+            let desc = directions.get(i) == Some(&Direction::Desc);
+            let not_null = self.decode_elem(&mut rest, &mut elem, desc)?;
+            total += elem.len();
+            if total > max_total_bytes {
+                return Err(DecodeError::TooLarge(max_total_bytes));
+            }
+            elems.push(if not_null { Some(elem) } else { None });
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Iterates over the elements of a tuple encoded by `encode`, borrowing
+    /// each one that can be used as stored instead of copying it.
+    pub fn elements(self, bytes: &[u8]) -> Elements<'_> {
+        Elements {
+            format: self,
+            rest: bytes,
+        }
+    }
+
+    /// Decodes the first `n` elements of `bytes` into `elems`, leaving the
+    /// rest untouched, and returns the number of bytes consumed. Fails with
+    /// `DecodeError::Truncated` if `bytes` holds fewer than `n` elements.
+    pub fn decode_n(
+        self,
+        bytes: &[u8],
+        n: usize,
+        elems: &mut Vec<Option<Vec<u8>>>,
+    ) -> Result<usize, DecodeError> {
+        let mut rest = bytes;
+        for _ in 0..n {
+            if rest.is_empty() {
+                return Err(DecodeError::Truncated);
+            }
+            let mut elem = vec![];
+            let not_null = self.decode_elem(&mut rest, &mut elem, false)?;
+            elems.push(if not_null { Some(elem) } else { None });
+        }
+        Ok(bytes.len() - rest.len())
+    }
+
+    /// Advances `bytes` past its first `n` elements without keeping them.
+    pub fn skip_n(self, bytes: &mut &[u8], n: usize) -> Result<(), DecodeError> {
+        for _ in 0..n {
+            if bytes.is_empty() {
+                return Err(DecodeError::Truncated);
+            }
+            *bytes = &bytes[self.element_len(bytes, false)?..];
+        }
+        Ok(())
+    }
+
+    /// Iterates over the byte ranges of the elements of a tuple encoded by
+    /// `encode`, checking them like `decode_nullable` but without copying.
+    /// Elements of two tuples in the same format compare like their ranges.
+    pub fn element_ranges(self, bytes: &[u8]) -> ElementRanges<'_> {
+        ElementRanges {
+            format: self,
+            bytes,
+            offset: 0,
+        }
+    }
+
+    pub fn count_elements(self, bytes: &[u8]) -> Result<usize, DecodeError> {
+        self.element_ranges(bytes)
+            .try_fold(0, |count, range| range.map(|_| count + 1))
+    }
+
+    /// Splits a tuple encoded by `encode` before its last `n` elements.
+    pub fn split_last(self, bytes: &[u8], n: usize) -> Result<(&[u8], &[u8]), DecodeError> {
+        let count = self.count_elements(bytes)?;
+        if n > count {
+            return Err(DecodeError::Truncated);
+        }
+        let mut rest = bytes;
+        self.skip_n(&mut rest, count - n)?;
+        Ok(bytes.split_at(bytes.len() - rest.len()))
+    }
+
+    /// Compares two encoded tuples like their decoded elements, with NULL
+    /// sorting as in `cmp_elem` and a tuple sorting before any longer one it
+    /// is a prefix of. Every element encoding sorts like its value and none
+    /// is a prefix of another, so this is a byte comparison once both are
+    /// checked. Tuples from `encode_directed` with the same directions
+    /// compare the same way as bytes, but the check only accepts ascending
+    /// ones.
+    pub fn cmp_encoded(self, a: &[u8], b: &[u8]) -> Result<Ordering, DecodeError> {
+        self.count_elements(a)?;
+        self.count_elements(b)?;
+        Ok(a.cmp(b))
+    }
+
+    /// Compares the leading elements of `a` with all of `b_prefix`: `Equal`
+    /// if `a` starts with them, `Less` if `a` is shorter and a prefix of
+    /// `b_prefix`.
+    pub fn cmp_encoded_prefix(self, a: &[u8], b_prefix: &[u8]) -> Result<Ordering, DecodeError> {
+        self.cmp_encoded_prefix_directed(a, b_prefix, &[])
+    }
+
+    /// Like `cmp_encoded_prefix`, for tuples from `encode_directed`.
+    pub fn cmp_encoded_prefix_directed(
+        self,
+        a: &[u8],
+        b_prefix: &[u8],
+        directions: &[Direction],
+    ) -> Result<Ordering, DecodeError> {
+        let (_, n) = self.prefix_len(b_prefix, usize::MAX, directions)?;
+        let (end, _) = self.prefix_len(a, n, directions)?;
+        Ok(a[..end].cmp(b_prefix))
+    }
+
+    /// The length in bytes and in elements of the first `n` elements of
+    /// `bytes`, or of all of them if there are fewer.
+    fn prefix_len(
+        self,
+        bytes: &[u8],
+        n: usize,
+        directions: &[Direction],
+    ) -> Result<(usize, usize), DecodeError> {
+        let mut end = 0;
+        let mut i = 0;
+        while i < n && end < bytes.len() {
+            let desc = directions.get(i) == Some(&Direction::Desc);
+            end += self.element_len(&bytes[end..], desc)?;
+            i += 1;
+        }
+        Ok((end, i))
+    }
+}
+
+// The free functions below use `Format::Blocks`, the format of the catalog
+// and of tables that do not choose one.
+
+/// The exact size `encode` produces for elements of these lengths. A NULL
+/// takes as much space as an empty element, so pass 0 for it.
+pub fn encoded_len(lens: impl Iterator<Item = usize>) -> usize {
+    lens.map(memcmpable::encoded_size).sum()
+}
+
+pub fn encode(elems: impl Iterator<Item = impl Element> + Clone, bytes: &mut Vec<u8>) {
+    Format::Blocks.encode(elems, bytes)
+}
+
+pub fn encode_directed(
+    elems: impl Iterator<Item = impl Element> + Clone,
+    directions: &[Direction],
+    bytes: &mut Vec<u8>,
+) {
+    Format::Blocks.encode_directed(elems, directions, bytes)
+}
+
+pub fn decode(bytes: &[u8], elems: &mut Vec<Vec<u8>>) -> Result<(), DecodeError> {
+    Format::Blocks.decode(bytes, elems)
+}
+
+pub fn decode_nullable(bytes: &[u8], elems: &mut Vec<Option<Vec<u8>>>) -> Result<(), DecodeError> {
+    Format::Blocks.decode_nullable(bytes, elems)
+}
+
+pub fn decode_directed(
+    bytes: &[u8],
+    directions: &[Direction],
+    elems: &mut Vec<Option<Vec<u8>>>,
+) -> Result<(), DecodeError> {
+    Format::Blocks.decode_directed(bytes, directions, elems)
+}
+
+pub fn elements(bytes: &[u8]) -> Elements<'_> {
+    Format::Blocks.elements(bytes)
+}
+
+pub fn decode_n(
+    bytes: &[u8],
+    n: usize,
+    elems: &mut Vec<Option<Vec<u8>>>,
+) -> Result<usize, DecodeError> {
+    Format::Blocks.decode_n(bytes, n, elems)
+}
+
+pub fn skip_n(bytes: &mut &[u8], n: usize) -> Result<(), DecodeError> {
+    Format::Blocks.skip_n(bytes, n)
+}
+
+pub fn element_ranges(bytes: &[u8]) -> ElementRanges<'_> {
+    Format::Blocks.element_ranges(bytes)
+}
+
+pub fn count_elements(bytes: &[u8]) -> Result<usize, DecodeError> {
+    Format::Blocks.count_elements(bytes)
+}
+
+pub fn split_last(bytes: &[u8], n: usize) -> Result<(&[u8], &[u8]), DecodeError> {
+    Format::Blocks.split_last(bytes, n)
+}
+
+pub fn cmp_encoded(a: &[u8], b: &[u8]) -> Result<Ordering, DecodeError> {
+    Format::Blocks.cmp_encoded(a, b)
+}
+
+pub fn cmp_encoded_prefix(a: &[u8], b_prefix: &[u8]) -> Result<Ordering, DecodeError> {
+    Format::Blocks.cmp_encoded_prefix(a, b_prefix)
+}
+
+pub struct ElementRanges<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for ElementRanges<'a> {
+    type Item = Result<Range<usize>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset == self.bytes.len() {
+            return None;
+        }
+        let start = self.offset;
+        match self.format.element_len(&self.bytes[start..], false) {
+            Ok(len) => {
+                self.offset += len;
+                Some(Ok(start..self.offset))
+            }
+            Err(err) => {
+                self.offset = self.bytes.len();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+pub struct Elements<'a> {
+    format: Format,
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<Option<Cow<'a, [u8]>>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let result = match self.format {
+            Format::Blocks => memcmpable::decode_borrowed(&mut self.rest),
+            Format::Escaped => memcmpable::decode_escaped_borrowed(&mut self.rest),
+        };
+        if result.is_err() {
+            self.rest = &[];
+        }
+        Some(result)
+    }
+}
+
+/// Builds an encoded key element by element, in `Format::Blocks` and
+/// ascending order unless told otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct KeyBuilder {
+    format: Format,
+    directions: Vec<Direction>,
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl KeyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_format(format: Format) -> Self {
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the sort direction of each element, as in `Format::encode_directed`.
+    pub fn directions(self, directions: &[Direction]) -> Self {
+        Self {
+            directions: directions.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn push_bytes(mut self, bytes: &[u8]) -> Self {
+        let desc = self.directions.get(self.len) == Some(&Direction::Desc);
+        self.format.encode_elem(Some(bytes), desc, &mut self.bytes);
+        self.len += 1;
+        self
+    }
+
+    pub fn push_str(self, s: &str) -> Self {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Pushes `n` encoded by `encode_u64`, so keys sort numerically.
+    pub fn push_u64(self, n: u64) -> Self {
+        self.push_bytes(&encode_u64(n))
+    }
+
+    /// Returns the key of a tuple with exactly the pushed elements.
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Returns the pushed elements as a search key for a prefix scan. Every
+    /// encoded element is self-delimiting, so the keys whose leading
+    /// elements equal the pushed ones are exactly those starting with the
+    /// returned bytes, and they sort contiguously from it. A byte prefix
+    /// that ends inside an element has no such guarantee, which is why the
+    /// prefix has to be built from whole elements.
+    pub fn finish_prefix(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// How a non-NULL element is shown: as text if it is valid UTF-8, as hex
+/// otherwise.
+enum Repr<'a> {
+    Utf8(&'a str),
+    Binary(&'a [u8]),
+}
+
+fn classify(bytes: &[u8]) -> Repr<'_> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Repr::Utf8(s),
+        Err(_) => Repr::Binary(bytes),
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Converts a tuple to a JSON array whose elements are strings if valid
+/// UTF-8, `{"hex": "..."}` otherwise, and `null` if NULL.
+pub fn to_json(elems: &[impl Element]) -> serde_json::Value {
+    let elems = elems
+        .iter()
+        .map(|elem| match elem.as_elem().map(classify) {
+            Some(Repr::Utf8(s)) => serde_json::Value::from(s),
+            Some(Repr::Binary(bytes)) => serde_json::json!({ "hex": to_hex(bytes) }),
+            None => serde_json::Value::Null,
+        })
+        .collect();
+    serde_json::Value::Array(elems)
+}
+
+/// Decodes standard base64, with or without padding.
+pub(crate) fn from_base64(b64: &str) -> Option<Vec<u8>> {
+    let sextet = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let b64 = b64.as_bytes();
+    let unpadded = b64.len() - b64.iter().rev().take(2).take_while(|&&c| c == b'=').count();
+    if b64.len() != unpadded && !b64.len().is_multiple_of(4) {
+        return None;
+    }
+    let sextets = b64[..unpadded]
+        .iter()
+        .map(|&c| sextet(c))
+        .collect::<Option<Vec<_>>>()?;
+    let mut bytes = Vec::with_capacity(sextets.len() * 3 / 4);
+    for chunk in sextets.chunks(4) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &sextet)| {
+            bits | (sextet as u32) << (18 - 6 * i)
+        });
+        match chunk.len() {
+            1 => return None,
+            len => bytes.extend_from_slice(&bits.to_be_bytes()[1..len]),
+        }
+    }
+    Some(bytes)
+}
+
+/// A non-NULL element written as a string if it is UTF-8, or as
+/// `{"hex": "..."}` or `{"b64": "..."}`.
+fn elem_from_json(json: &serde_json::Value) -> Option<Vec<u8>> {
+    match json {
+        serde_json::Value::String(s) => Some(s.as_bytes().to_vec()),
+        serde_json::Value::Object(obj) if obj.len() == 1 => {
+            let (encoding, value) = obj.iter().next()?;
+            let decode = match encoding.as_str() {
+                "hex" => from_hex,
+                "b64" => from_base64,
+                _ => return None,
+            };
+            decode(value.as_str()?)
+        }
+        _ => None,
+    }
+}
+
+/// The inverse of `to_json`, which also takes `{"b64": "..."}` elements.
+pub fn from_json(json: &serde_json::Value) -> Result<Vec<Option<Vec<u8>>>, Error> {
+    let invalid = |json: &serde_json::Value| Error::InvalidJson(json.clone());
+    let elems = json.as_array().ok_or_else(|| invalid(json))?;
+    elems
+        .iter()
+        .map(|elem| match elem {
+            serde_json::Value::Null => Ok(None),
+            _ => elem_from_json(elem).map(Some).ok_or_else(|| invalid(elem)),
+        })
+        .collect()
+}
+
+/// A non-NULL value with the wire representation of an element of
+/// `Record`, for the literals of requests. Formats that are not human
+/// readable, like the catalog's, store the bytes as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BytesValue(pub Vec<u8>);
+
+impl BytesValue {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&str> for BytesValue {
+    fn from(s: &str) -> Self {
+        BytesValue(s.as_bytes().to_vec())
+    }
+}
+
+impl Serialize for BytesValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(&self.0);
+        }
+        match classify(&self.0) {
+            Repr::Utf8(s) => s.serialize(serializer),
+            Repr::Binary(bytes) => {
+                serde_json::json!({ "hex": to_hex(bytes) }).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BytesValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Vec::deserialize(deserializer).map(BytesValue);
+        }
+        let json = serde_json::Value::deserialize(deserializer)?;
+        elem_from_json(&json)
+            .map(BytesValue)
+            .ok_or_else(|| serde::de::Error::custom(Error::InvalidJson(json)))
+    }
+}
+
+/// A decoded tuple with the wire representation of `to_json`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Record(pub Vec<Option<Vec<u8>>>);
+
+impl Serialize for Record {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        to_json(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = serde_json::Value::deserialize(deserializer)?;
+        from_json(&json)
+            .map(Record)
+            .map_err(serde::de::Error::custom)
     }
 }
 
 pub struct Pretty<'a, T>(pub &'a [T]);
 
-impl<'a, T: AsRef<[u8]>> Debug for Pretty<'a, T> {
+impl<'a, T: Element> Debug for Pretty<'a, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut d = f.debug_tuple("Tuple");
         for elem in self.0 {
-            let bytes = elem.as_ref();
-            match std::str::from_utf8(bytes) {
-                Ok(s) => {
-                    d.field(&format_args!("{:?} {:02x?}", s, bytes));
+            match elem.as_elem().map(classify) {
+                Some(Repr::Utf8(s)) => {
+                    d.field(&format_args!("{:?} {:02x?}", s, s.as_bytes()));
                 }
-                Err(_) => {
+                Some(Repr::Binary(bytes)) => {
                     d.field(&format_args!("{:02x?}", bytes));
                 }
+                None => {
+                    d.field(&format_args!("NULL"));
+                }
             }
         }
         d.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_order() {
+        let values = [
+            Value::I64(i64::MIN),
+            Value::I64(-10),
+            Value::I64(-1),
+            Value::I64(0),
+            Value::I64(9),
+            Value::I64(10),
+            Value::I64(i64::MAX),
+        ];
+        let mut prev: Option<Vec<u8>> = None;
+        for value in &values {
+            let mut bytes = vec![];
+            encode([encode_value(value)].iter(), &mut bytes);
+            if let Some(prev) = prev {
+                assert!(prev < bytes);
+            }
+            let mut elems = vec![];
+            decode(&bytes, &mut elems).unwrap();
+            assert_eq!(
+                value,
+                &decode_value(ColumnType::I64, Some(&elems[0])).unwrap()
+            );
+            prev = Some(bytes);
+        }
+        assert!(encode_value(&Value::U64(9)) < encode_value(&Value::U64(10)));
+        let encoded = encode_value(&Value::Bool(true));
+        assert_eq!(
+            Value::Bool(true),
+            decode_value(ColumnType::Bool, encoded.as_deref()).unwrap()
+        );
+        assert!(decode_value(ColumnType::U64, Some(b"10")).is_err());
+        assert!(decode_value(ColumnType::Utf8, Some(&[0xff])).is_err());
+        assert_eq!(Value::Null, decode_value(ColumnType::I64, None).unwrap());
+    }
+
+    #[test]
+    fn test_f64_order() {
+        let values = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            1.5,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let encoded: Vec<_> = values.iter().map(|&x| encode_f64(x)).collect();
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        for (&x, &bytes) in values.iter().zip(&encoded) {
+            let decoded = decode_f64(bytes);
+            assert!(decoded == x || (decoded.is_nan() && x.is_nan()));
+        }
+        assert_eq!(encode_f64(0.0), encode_f64(-0.0));
+        assert!(decode_f64(encode_f64(-0.0)).is_sign_positive());
+        assert_eq!(encode_f64(f64::NAN), encode_f64(-f64::NAN));
+
+        assert!(encode_i64(-1) < encode_i64(0));
+        assert_eq!(i64::MIN, decode_i64(encode_i64(i64::MIN)));
+        assert_eq!(u64::MAX, decode_u64(encode_u64(u64::MAX)));
+        let encoded = encode_value(&Value::F64(-2.5));
+        assert_eq!(
+            Value::F64(-2.5),
+            decode_value(ColumnType::F64, encoded.as_deref()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_null() {
+        let mut bytes = vec![];
+        encode([Some(&b"a"[..]), None, Some(b"")].iter(), &mut bytes);
+        let mut elems = vec![];
+        decode_nullable(&bytes, &mut elems).unwrap();
+        assert_eq!(vec![Some(b"a".to_vec()), None, Some(vec![])], elems);
+        assert_eq!(
+            r#"Tuple("a" [61], NULL, "" [])"#,
+            format!("{:?}", Pretty(&elems))
+        );
+
+        let mut null = vec![];
+        encode([None::<&[u8]>].iter(), &mut null);
+        let mut value = vec![];
+        encode([b"\xff\xff"].iter(), &mut value);
+        assert!(value < null);
+        assert_eq!(Ordering::Greater, cmp_elem(None, Some(b"\xff\xff")));
+
+        let mut bytes = vec![];
+        encode(
+            [Some(&b"a"[..]), None, Some(&[9; 9][..])].iter(),
+            &mut bytes,
+        );
+        let (head, tail) = split_last(&bytes, 2).unwrap();
+        let mut elems = vec![];
+        decode_nullable(tail, &mut elems).unwrap();
+        assert_eq!(vec![None, Some(vec![9; 9])], elems);
+        assert_eq!(9, head.len());
+        assert_eq!(bytes.len(), split_last(&bytes, 0).unwrap().0.len());
+    }
+
+    #[test]
+    fn test_json() {
+        let elems = vec![Some(b"a\"b".to_vec()), None, Some(vec![0xff, 0x00])];
+        let json = to_json(&elems);
+        assert_eq!(r#"["a\"b",null,{"hex":"ff00"}]"#, json.to_string());
+        assert_eq!(elems, from_json(&json).unwrap());
+        assert_eq!(
+            r#"Tuple("a\"b" [61, 22, 62], NULL, [ff, 00])"#,
+            format!("{:?}", Pretty(&elems))
+        );
+
+        for bad in &[
+            r#""a""#,
+            r#"[1]"#,
+            r#"[{"hex": "f"}]"#,
+            r#"[{"hex": "zz"}]"#,
+        ] {
+            assert!(from_json(&serde_json::from_str(bad).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_record_serde() {
+        let record = Record(vec![Some(b"a".to_vec()), None, Some(vec![0xff])]);
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(r#"["a",null,{"hex":"ff"}]"#, json);
+        assert_eq!(record, serde_json::from_str(&json).unwrap());
+
+        let err = serde_json::from_str::<Record>(r#"[{"hex": "ff", "str": "a"}]"#).unwrap_err();
+        assert!(err.to_string().contains("invalid JSON tuple element"));
+    }
+
+    #[test]
+    fn test_bytes_value_serde() {
+        for (json, bytes) in &[
+            (r#""ab""#, &b"ab"[..]),
+            (r#"{"hex": "ec2cdd0e"}"#, &[0xec, 0x2c, 0xdd, 0x0e][..]),
+            (r#"{"b64": "7CzdDg=="}"#, &[0xec, 0x2c, 0xdd, 0x0e][..]),
+            (r#"{"b64": "7CzdDg"}"#, &[0xec, 0x2c, 0xdd, 0x0e][..]),
+            (r#"{"b64": "YWJj"}"#, &b"abc"[..]),
+            (r#"{"b64": ""}"#, &b""[..]),
+        ] {
+            let value: BytesValue = serde_json::from_str(json).unwrap();
+            assert_eq!(*bytes, value.as_bytes(), "{}", json);
+        }
+        let value = BytesValue(vec![0xff, 0x00]);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(r#"{"hex":"ff00"}"#, json);
+        assert_eq!(value, serde_json::from_str(&json).unwrap());
+
+        for bad in &[
+            r#"{"hex": "zz"}"#,
+            r#"{"b64": "7CzdD"}"#,
+            r#"{"b64": "7C==zdDg"}"#,
+            r#"{"utf16": "ab"}"#,
+            r#"null"#,
+            r#"1"#,
+        ] {
+            let err = serde_json::from_str::<BytesValue>(bad).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid JSON tuple element"),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let values: [Option<&[u8]>; 7] = [
+            Some(b""),
+            Some(b"\0"),
+            Some(b"\0\0"),
+            Some(b"a"),
+            Some(b"a\0"),
+            Some(&[0xff; 9]),
+            None,
+        ];
+        let directions = [Direction::Desc, Direction::Asc];
+        for &format in &[Format::Blocks, Format::Escaped] {
+            let mut tuples = vec![];
+            for &a in &values {
+                for &b in &values {
+                    let mut bytes = vec![];
+                    format.encode_directed([a, b].iter(), &directions, &mut bytes);
+                    let mut elems = vec![];
+                    format
+                        .decode_directed(&bytes, &directions, &mut elems)
+                        .unwrap();
+                    assert_eq!(vec![a.map(<[u8]>::to_vec), b.map(<[u8]>::to_vec)], elems);
+                    tuples.push(((a, b), bytes));
+                }
+            }
+            for ((a1, b1), bytes1) in &tuples {
+                for ((a2, b2), bytes2) in &tuples {
+                    let expected = cmp_elem(*a1, *a2).reverse().then(cmp_elem(*b1, *b2));
+                    assert_eq!(expected, bytes1.cmp(bytes2), "{:?}", (a1, b1, a2, b2));
+                }
+            }
+
+            let mut bytes = vec![];
+            format.encode(values.iter(), &mut bytes);
+            assert_eq!(bytes.len(), format.encoded_len(values.iter()));
+            let elems: Vec<_> = format.elements(&bytes).collect::<Result<_, _>>().unwrap();
+            assert_eq!(
+                &values[..],
+                &elems.iter().map(Option::as_deref).collect::<Vec<_>>()[..]
+            );
+            let (_, tail) = format.split_last(&bytes, 2).unwrap();
+            let mut elems = vec![];
+            format.decode_nullable(tail, &mut elems).unwrap();
+            assert_eq!(vec![Some(vec![0xff; 9]), None], elems);
+        }
+
+        let size = |format: Format| {
+            let mut bytes = vec![];
+            format.encode([b"w", b"x", b"y"].iter(), &mut bytes);
+            bytes.len()
+        };
+        assert_eq!(27, size(Format::Blocks));
+        assert_eq!(27, encoded_len([1, 1, 0].iter().copied()));
+        assert_eq!(12, size(Format::Escaped));
+        let mut bytes = vec![];
+        Format::Escaped.encode([None::<&[u8]>].iter(), &mut bytes);
+        assert_eq!(
+            Err(DecodeError::UnexpectedNull),
+            Format::Escaped.decode(&bytes, &mut vec![])
+        );
+        assert_eq!(
+            Some(Format::Escaped),
+            Format::from_tag(Format::Escaped.tag())
+        );
+        assert_eq!(None, Format::from_tag(2));
+    }
+
+    #[test]
+    fn test_properties() {
+        for &format in &[Format::Blocks, Format::Escaped] {
+            testing::check_properties(format, 0x2545_f491_4f6c_dd1d, 2_000);
+        }
+    }
+
+    #[test]
+    fn test_cmp_encoded() {
+        let values: [Option<&[u8]>; 5] = [Some(b""), Some(b"\0"), Some(b"a"), Some(&[7; 9]), None];
+        let mut tuples: Vec<Vec<Option<&[u8]>>> = vec![vec![]];
+        for &a in &values {
+            tuples.push(vec![a]);
+            for &b in &values {
+                tuples.push(vec![a, b]);
+            }
+        }
+        // the order of the decoded tuples, element by element
+        let cmp_tuples = |a: &[Option<&[u8]>], b: &[Option<&[u8]>]| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| cmp_elem(*a, *b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        };
+        for &format in &[Format::Blocks, Format::Escaped] {
+            let encoded: Vec<_> = tuples
+                .iter()
+                .map(|tuple| {
+                    let mut bytes = vec![];
+                    format.encode(tuple.iter(), &mut bytes);
+                    bytes
+                })
+                .collect();
+            for (a, a_bytes) in tuples.iter().zip(&encoded) {
+                for (b, b_bytes) in tuples.iter().zip(&encoded) {
+                    assert_eq!(
+                        Ok(cmp_tuples(a, b)),
+                        format.cmp_encoded(a_bytes, b_bytes),
+                        "{:?}",
+                        (a, b)
+                    );
+                    let n = a.len().min(b.len());
+                    let expected = cmp_tuples(&a[..n], b);
+                    assert_eq!(
+                        Ok(expected),
+                        format.cmp_encoded_prefix(a_bytes, b_bytes),
+                        "{:?}",
+                        (a, b)
+                    );
+                }
+            }
+            assert!(format.cmp_encoded(&encoded[1][1..], &encoded[1]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_element_ranges() {
+        for &format in &[Format::Blocks, Format::Escaped] {
+            let (mut a, mut b) = (vec![], vec![]);
+            format.encode([Some(&b"x"[..]), Some(&[7; 20][..]), None].iter(), &mut a);
+            format.encode(
+                [Some(&b"y"[..]), Some(&[7; 9][..]), Some(b"")].iter(),
+                &mut b,
+            );
+            let ranges_a: Vec<_> = format.element_ranges(&a).collect::<Result<_, _>>().unwrap();
+            let ranges_b: Vec<_> = format.element_ranges(&b).collect::<Result<_, _>>().unwrap();
+            assert_eq!(3, ranges_a.len());
+            assert_eq!(Ok(3), format.count_elements(&b));
+            assert_eq!(a.len(), ranges_a[2].end);
+            assert_eq!(ranges_a[1].start, ranges_a[0].end);
+            let mut elems = vec![];
+            format
+                .decode_nullable(&a[ranges_a[1].clone()], &mut elems)
+                .unwrap();
+            assert_eq!(vec![Some(vec![7; 20])], elems);
+            // column 1 compares without decoding either tuple
+            for i in 0..3 {
+                let expected = cmp_elem(
+                    [Some(&b"x"[..]), Some(&[7; 20][..]), None][i],
+                    [Some(&b"y"[..]), Some(&[7; 9][..]), Some(b"")][i],
+                );
+                assert_eq!(
+                    expected,
+                    a[ranges_a[i].clone()].cmp(&b[ranges_b[i].clone()])
+                );
+            }
+
+            let truncated = &a[..a.len() - 1];
+            let mut ranges = format.element_ranges(truncated);
+            assert_eq!(2, ranges.by_ref().take_while(Result::is_ok).count());
+            assert!(ranges.next().is_none());
+            assert_eq!(
+                format.decode_nullable(truncated, &mut vec![]),
+                format.count_elements(truncated).map(|_| ())
+            );
+        }
+        assert_eq!(Ok(0), count_elements(&[]));
+        let mut bytes = vec![];
+        encode([b"a", b"b"].iter(), &mut bytes);
+        bytes[17] = 10;
+        assert_eq!(Err(DecodeError::InvalidLength(10)), count_elements(&bytes));
+    }
+
+    #[test]
+    fn test_key_builder() {
+        let mut bytes = vec![];
+        encode([&b"Smith"[..], b"", &[0xff; 9]].iter(), &mut bytes);
+        let key = KeyBuilder::new()
+            .push_bytes(b"Smith")
+            .push_str("")
+            .push_bytes(&[0xff; 9])
+            .finish();
+        assert_eq!(bytes, key);
+
+        let directions = [Direction::Asc, Direction::Desc];
+        let mut bytes = vec![];
+        Format::Escaped.encode_directed([&b"a"[..], b"b"].iter(), &directions, &mut bytes);
+        let key = KeyBuilder::with_format(Format::Escaped)
+            .directions(&directions)
+            .push_str("a")
+            .push_str("b")
+            .finish();
+        assert_eq!(bytes, key);
+
+        let keys: Vec<_> = [9, 10, 255, 256]
+            .iter()
+            .map(|&n| KeyBuilder::new().push_u64(n).finish())
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        let prefix = KeyBuilder::new().push_str("ab").finish_prefix();
+        let full = |a: &str, b: &str| KeyBuilder::new().push_str(a).push_str(b).finish();
+        assert!(full("ab", "").starts_with(&prefix));
+        assert!(full("ab", "z") > prefix);
+        assert!(!full("abc", "").starts_with(&prefix));
+        assert!(!full("a", "b").starts_with(&prefix));
+    }
+
+    #[test]
+    fn test_elements() {
+        let long = [7; 20];
+        let mut bytes = vec![];
+        encode(
+            [Some(&b"short"[..]), None, Some(&long[..]), Some(b"")].iter(),
+            &mut bytes,
+        );
+        let elems: Vec<_> = elements(&bytes).collect::<Result<_, _>>().unwrap();
+        assert!(matches!(elems[0], Some(Cow::Borrowed(b"short"))));
+        assert!(elems[1].is_none());
+        assert!(matches!(&elems[2], Some(Cow::Owned(elem)) if elem[..] == long[..]));
+        assert!(matches!(elems[3], Some(Cow::Borrowed(b""))));
+
+        let mut iter = elements(&bytes[..bytes.len() - 1]);
+        assert_eq!(3, iter.by_ref().take_while(Result::is_ok).count());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_decode_with_limit() {
+        for format in [Format::Blocks, Format::Escaped] {
+            let mut bytes = vec![];
+            format.encode(std::iter::repeat_n(&[7u8; 20][..], 100), &mut bytes);
+            let mut elems = vec![];
+            assert_eq!(
+                Err(DecodeError::TooLarge(100)),
+                format.decode_with_limit(&bytes, &[], &mut elems, 100)
+            );
+            assert_eq!(5, elems.len());
+
+            let mut elems = vec![];
+            format
+                .decode_with_limit(&bytes, &[], &mut elems, 2000)
+                .unwrap();
+            assert_eq!(100, elems.len());
+        }
+    }
+
+    #[test]
+    fn test_decode_n() {
+        let mut bytes = vec![];
+        encode(
+            [Some(&b"a"[..]), None, Some(&[9; 9][..]), Some(b"z")].iter(),
+            &mut bytes,
+        );
+        let mut elems = vec![];
+        let consumed = decode_n(&bytes, 2, &mut elems).unwrap();
+        assert_eq!(vec![Some(b"a".to_vec()), None], elems);
+        assert_eq!(18, consumed);
+
+        let mut rest = &bytes[..];
+        skip_n(&mut rest, 3).unwrap();
+        let mut elems = vec![];
+        decode_nullable(rest, &mut elems).unwrap();
+        assert_eq!(vec![Some(b"z".to_vec())], elems);
+
+        assert_eq!(
+            Err(DecodeError::Truncated),
+            decode_n(&bytes, 5, &mut vec![])
+        );
+        assert_eq!(Err(DecodeError::Truncated), skip_n(&mut &bytes[..], 5));
+        assert_eq!(Err(DecodeError::Truncated), split_last(&bytes, 5));
+        assert_eq!(Ok(0), decode_n(&[], 0, &mut vec![]));
+    }
+
+    #[test]
+    fn test_directed() {
+        use Direction::*;
+
+        let rows: [[Option<&[u8]>; 3]; 5] = [
+            [Some(b"a"), Some(b"2"), Some(b"x")],
+            [Some(b"a"), Some(b"10"), Some(b"x")],
+            [Some(b"a"), Some(b"1"), Some(b"x")],
+            [Some(b"a"), None, Some(b"x")],
+            [Some(b"b"), Some(b"3"), None],
+        ];
+        let directions = [Asc, Desc];
+        let mut encoded = vec![];
+        for row in &rows {
+            let mut bytes = vec![];
+            encode_directed(row.iter(), &directions, &mut bytes);
+            let mut elems = vec![];
+            decode_directed(&bytes, &directions, &mut elems).unwrap();
+            let expected: Vec<_> = row.iter().map(|elem| elem.map(<[u8]>::to_vec)).collect();
+            assert_eq!(expected, elems);
+            encoded.push(bytes);
+        }
+        // NULL sorts last ascending, so first descending
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(
+            vec![
+                &encoded[3],
+                &encoded[0],
+                &encoded[1],
+                &encoded[2],
+                &encoded[4]
+            ],
+            sorted.iter().collect::<Vec<_>>()
+        );
+    }
+}
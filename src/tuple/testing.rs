@@ -0,0 +1,166 @@
+//! Random tuples and the properties every `Format` must have, for the
+//! tests of the encodings. Outside this crate's tests it needs the
+//! `testutil` feature.
+//!
+//! The properties are those the btrees rely on: decoding gives back the
+//! encoded elements, encodings compare like their elements, and the
+//! encoding of the first elements of a tuple is a prefix of its encoding.
+
+use std::cmp::Ordering;
+
+use super::{cmp_elem, Direction, Format};
+
+pub type TestTuple = Vec<Option<Vec<u8>>>;
+
+/// A xorshift generator, so a failure is reproducible from its seed.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Short strings over a small alphabet, so that shared prefixes,
+    /// block boundaries and escape-like bytes all come up often.
+    pub fn bytes(&mut self) -> Vec<u8> {
+        const ALPHABET: [u8; 6] = [0, 1, 8, 9, 0xfe, 0xff];
+        let len = (self.next_u64() % 20) as usize;
+        (0..len)
+            .map(|_| ALPHABET[(self.next_u64() % ALPHABET.len() as u64) as usize])
+            .collect()
+    }
+
+    /// NULL one time in eight.
+    pub fn elem(&mut self) -> Option<Vec<u8>> {
+        if self.next_u64().is_multiple_of(8) {
+            None
+        } else {
+            Some(self.bytes())
+        }
+    }
+
+    /// Up to `max_len` elements.
+    pub fn tuple(&mut self, max_len: usize) -> TestTuple {
+        let len = (self.next_u64() % (max_len as u64 + 1)) as usize;
+        (0..len).map(|_| self.elem()).collect()
+    }
+
+    pub fn directions(&mut self, len: usize) -> Vec<Direction> {
+        (0..len)
+            .map(|_| {
+                if self.next_u64().is_multiple_of(2) {
+                    Direction::Asc
+                } else {
+                    Direction::Desc
+                }
+            })
+            .collect()
+    }
+}
+
+/// The order the encodings of `a` and `b` must have: element by element as
+/// in `cmp_elem`, reversed for `Direction::Desc`, with a tuple sorting
+/// before any longer one it is a prefix of.
+pub fn cmp_tuples(
+    a: &[Option<Vec<u8>>],
+    b: &[Option<Vec<u8>>],
+    directions: &[Direction],
+) -> Ordering {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .map(|(i, (a, b))| {
+            let ordering = cmp_elem(a.as_deref(), b.as_deref());
+            match directions.get(i) {
+                Some(Direction::Desc) => ordering.reverse(),
+                _ => ordering,
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// `Format::cmp_encoded` only accepts tuples of ascending elements.
+fn is_ascending(directions: &[Direction]) -> bool {
+    directions
+        .iter()
+        .all(|&direction| direction == Direction::Asc)
+}
+
+pub fn encode(format: Format, tuple: &[Option<Vec<u8>>], directions: &[Direction]) -> Vec<u8> {
+    let mut bytes = vec![];
+    format.encode_directed(tuple.iter(), directions, &mut bytes);
+    bytes
+}
+
+pub fn assert_round_trip(format: Format, tuple: &[Option<Vec<u8>>], directions: &[Direction]) {
+    let bytes = encode(format, tuple, directions);
+    assert_eq!(bytes.len(), format.encoded_len(tuple.iter()), "{:?}", tuple);
+    let mut decoded = vec![];
+    format
+        .decode_directed(&bytes, directions, &mut decoded)
+        .unwrap();
+    assert_eq!(tuple, &decoded[..], "{:?} {:?}", format, directions);
+}
+
+pub fn assert_order(
+    format: Format,
+    a: &[Option<Vec<u8>>],
+    b: &[Option<Vec<u8>>],
+    directions: &[Direction],
+) {
+    let (enc_a, enc_b) = (encode(format, a, directions), encode(format, b, directions));
+    assert_eq!(
+        cmp_tuples(a, b, directions),
+        enc_a.cmp(&enc_b),
+        "{:?} {:?} {:?} {:?}",
+        format,
+        directions,
+        a,
+        b
+    );
+    if is_ascending(directions) {
+        assert_eq!(Ok(enc_a.cmp(&enc_b)), format.cmp_encoded(&enc_a, &enc_b));
+    }
+}
+
+/// The encoding of the first `n` elements of `tuple` is a prefix of the
+/// encoding of `tuple`, and the tuple matches it as a prefix bound.
+pub fn assert_prefix(
+    format: Format,
+    tuple: &[Option<Vec<u8>>],
+    n: usize,
+    directions: &[Direction],
+) {
+    let n = n.min(tuple.len());
+    let bytes = encode(format, tuple, directions);
+    let prefix = encode(format, &tuple[..n], directions);
+    assert!(bytes.starts_with(&prefix), "{:?} {:?}", format, tuple);
+    assert!(prefix <= bytes);
+    assert_eq!(
+        Ok(Ordering::Equal),
+        format.cmp_encoded_prefix_directed(&bytes, &prefix, directions)
+    );
+}
+
+/// Checks every property on `iterations` random tuples from `seed`.
+pub fn check_properties(format: Format, seed: u64, iterations: usize) {
+    let mut rng = Rng(seed);
+    for _ in 0..iterations {
+        let a = rng.tuple(4);
+        let b = rng.tuple(4);
+        // shares a prefix with `a`, so later elements decide the order
+        let shared = (rng.next_u64() % (a.len() as u64 + 1)) as usize;
+        let c: TestTuple = a[..shared].iter().cloned().chain(rng.tuple(2)).collect();
+        let directions = rng.directions(6);
+        for directions in &[&[][..], &directions[..]] {
+            assert_round_trip(format, &a, directions);
+            assert_order(format, &a, &b, directions);
+            assert_order(format, &a, &c, directions);
+            assert_prefix(format, &a, (rng.next_u64() % 5) as usize, directions);
+        }
+    }
+}
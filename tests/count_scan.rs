@@ -0,0 +1,72 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cmp::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use tempfile::tempfile;
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::disk::DiskManager;
+use relly::query::{CountScan, KeyBound, PlanNode, TupleSearchMode};
+use relly::table::Table;
+use relly::tuple;
+
+/// Counts allocations, so the test can tell whether counting rows copies
+/// them. It is the only test in this binary, so nothing else allocates
+/// while it measures.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, AtomicOrdering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const NUM_ROWS: u32 = 20_000;
+
+#[test]
+fn test_count_scan_does_not_allocate_per_row() {
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1000));
+    let mut table = Table::new("items", 1, 2);
+    table.create(&mut bufmgr).unwrap();
+    for i in 0..NUM_ROWS {
+        table
+            .insert(&mut bufmgr, &[&i.to_be_bytes()[..], b"value"])
+            .unwrap();
+    }
+
+    let plan = CountScan {
+        table_meta_page_id: table.meta_page_id,
+        search_mode: TupleSearchMode::Key(vec![Some(100u32.to_be_bytes().to_vec())]),
+        while_bound: Some(KeyBound {
+            key: vec![Some((NUM_ROWS - 100).to_be_bytes().to_vec())],
+            accept: Ordering::is_lt,
+        }),
+        while_cond: None,
+        expiry: None,
+    };
+    let mut exec = plan.start(&mut bufmgr).unwrap();
+    let before = ALLOCATIONS.load(AtomicOrdering::Relaxed);
+    let row = exec.next(&mut bufmgr).unwrap().unwrap();
+    let allocations = ALLOCATIONS.load(AtomicOrdering::Relaxed) - before;
+
+    let count = NUM_ROWS as u64 - 200;
+    assert_eq!(vec![Some(tuple::encode_u64(count).to_vec())], row);
+    assert!(exec.next(&mut bufmgr).unwrap().is_none());
+    assert!(
+        (allocations as u64) < count / 100,
+        "{} allocations for {} rows",
+        allocations,
+        count
+    );
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use tempfile::NamedTempFile;
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog::Catalog;
+use relly::disk::DiskManager;
+use relly::repl::{self, Output};
+
+#[test]
+fn test_read_only_session() {
+    let (_, path) = NamedTempFile::new().unwrap().into_parts();
+    {
+        let disk = DiskManager::open(&path).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        Catalog::open_or_create(&mut bufmgr).unwrap();
+        let script = r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}
+{"Insert": {"table": "t", "record": ["a", "x"]}}"#;
+        repl::run_script(&mut bufmgr, Output::Json, script, &mut vec![]).unwrap();
+        bufmgr.close().unwrap();
+    }
+    let before = fs::read(&path).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_relly"))
+        .arg("--json")
+        .arg("--read-only")
+        .arg(&*path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            format!(
+                r#"{{"Insert": {{"table": "t", "record": ["b", "y"]}}}}
+{{"CreateTable": {{"name": "u", "num_key_elems": 1, "num_columns": 1}}}}
+.flush
+{{"Export": {{"table": "t", "path": {}}}}}
+{{"Query": {{"plan": {{"SeqScan": {{"table": "t"}}}}}}}}
+"#,
+                serde_json::to_string(path.to_str().unwrap()).unwrap()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let lines: Vec<_> = std::str::from_utf8(&output.stdout)
+        .unwrap()
+        .lines()
+        .collect();
+    assert_eq!(5, lines.len(), "{:?}", lines);
+    for line in &lines[..4] {
+        assert!(line.contains(r#""kind":"read_only""#), "{}", line);
+    }
+    assert!(lines[4].contains(r#""x""#) && !lines[4].contains(r#""y""#));
+
+    assert_eq!(before, fs::read(&path).unwrap());
+}
+
+#[test]
+fn test_unknown_arguments() {
+    let (_, path) = NamedTempFile::new().unwrap().into_parts();
+    let run = |args: &[&std::ffi::OsStr]| {
+        Command::new(env!("CARGO_BIN_EXE_relly"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["--readonly".as_ref(), path.as_os_str()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--readonly"));
+    let output = run(&[path.as_os_str(), "other.rly".as_ref()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("other.rly"));
+    assert_eq!(0, fs::metadata(&path).unwrap().len());
+}
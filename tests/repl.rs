@@ -0,0 +1,262 @@
+use tempfile::{tempfile, NamedTempFile};
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog::Catalog;
+use relly::disk::DiskManager;
+use relly::repl::{self, Output, Repl};
+
+fn run(output: Output, input: &str) -> Vec<String> {
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+    Catalog::open_or_create(&mut bufmgr).unwrap();
+    let mut out = vec![];
+    repl::run_script(&mut bufmgr, output, input, &mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_multi_line_requests_and_recovery() {
+    let lines = run(
+        Output::Json,
+        r#"{"CreateTable": {
+    "name": "people", "num_key_elems": 1, "num_columns": 2
+}}
+{"Insert": {"table": "people", "record": ["a", "Alice"]}} {"Insert": {"table": "people",
+    "record": ["b", "Bob"]}}
+{"Insert": {"table": ]}
+{"NoSuchRequest": {}}
+{"Insert": {"table": "nobody", "record": ["c", "Carol"]}}
+{"Import": {"table": "people", "path": "."}}
+.flush
+.nonsense
+{"Query": {"plan": {"SeqScan": {"table": "people"}}}}
+.exit
+{"Insert": {"table": "people", "record": ["d", "Dave"]}}
+"#,
+    );
+    let kinds: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            match (&value["error"]["kind"], &value) {
+                (serde_json::Value::String(kind), _) => kind.clone(),
+                (_, serde_json::Value::String(unit)) => unit.clone(),
+                (_, value) => value.as_object().unwrap().keys().next().unwrap().clone(),
+            }
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            "CreateTable",
+            "Insert",
+            "Insert",
+            "parse",
+            "parse",
+            "execute",
+            "execute",
+            "command",
+            "Query"
+        ],
+        kinds
+    );
+    assert!(lines[6].contains("not a regular file"), "{}", lines[6]);
+    assert!(lines[8].contains("Alice") && lines[8].contains("Bob"));
+    assert!(!lines[8].contains("Dave"));
+}
+
+#[test]
+fn test_incomplete_request_at_end() {
+    let lines = run(Output::Text, "{\"ListTables\":\n");
+    assert_eq!(vec!["ERROR: EOF while parsing a request"], lines);
+}
+
+#[cfg(feature = "sql")]
+#[test]
+fn test_sql() {
+    let lines = run(
+        Output::Text,
+        "CREATE TABLE t (k, v, KEY(k))
+INSERT INTO t VALUES ('a', 'x')
+{\"Insert\": {\"table\": \"t\", \"record\": [\"b\", \"y\"]}}
+SELECT v FROM t WHERE k > 'a'
+SELECT v FROM",
+    );
+    assert_eq!(
+        vec![
+            "CREATE TABLE (meta_page_id = 3)",
+            "INSERT",
+            "INSERT",
+            "v",
+            "-",
+            "y",
+            "(1 rows)",
+            "ERROR: expected a name at offset 13 but found end of input",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_flush_every() {
+    let file = NamedTempFile::new().unwrap();
+    let open = || {
+        let disk = DiskManager::open(file.path()).unwrap();
+        BufferPoolManager::new(disk, BufferPool::new(10))
+    };
+    let mut bufmgr = open();
+    Catalog::open_or_create(&mut bufmgr).unwrap();
+    bufmgr.flush().unwrap();
+    let mut repl = Repl::new(Output::Json);
+    repl.flush_every = Some(3);
+    let mut out = vec![];
+    repl.run_script(
+        &mut bufmgr,
+        r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}
+{"Insert": {"table": "t", "record": ["a", "x"]}}
+{"Query": {"plan": {"SeqScan": {"table": "t"}}}}
+{"Insert": {"table": "t", "record": ["b", "y"]}}
+{"Insert": {"table": "t", "record": ["c", "z"]}}
+"#,
+        &mut out,
+    )
+    .unwrap();
+    // the last insert was not flushed, and is lost in a crash
+    bufmgr.leak_on_drop();
+    drop(bufmgr);
+
+    let mut bufmgr = open();
+    let mut out = vec![];
+    repl::run_script(
+        &mut bufmgr,
+        Output::Json,
+        r#"{"Query": {"plan": {"SeqScan": {"table": "t"}}}}"#,
+        &mut out,
+    )
+    .unwrap();
+    assert_eq!(
+        r#"{"Query":{"rows":[["a","x"],["b","y"]]}}"#,
+        String::from_utf8(out).unwrap().trim()
+    );
+}
+
+#[test]
+fn test_transaction_left_open() {
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+    Catalog::open_or_create(&mut bufmgr).unwrap();
+    let mut repl = Repl::new(Output::Text);
+    let mut out = vec![];
+    repl.feed_line(&mut bufmgr, r#""Begin""#, &mut out).unwrap();
+    assert_eq!(repl::TRANSACTION_PROMPT, repl.prompt());
+    repl.run_script(
+        &mut bufmgr,
+        r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 1}}
+"Begin""#,
+        &mut out,
+    )
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!("ERROR: a transaction is already open", lines[2]);
+    assert!(lines[3].starts_with("ROLLBACK ("), "{}", lines[3]);
+    assert!(!bufmgr.in_transaction());
+    assert_eq!(repl::PROMPT, repl.prompt());
+    // the table was created inside the transaction
+    let mut out = vec![];
+    repl::run_script(&mut bufmgr, Output::Text, r#""ListTables""#, &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().ends_with("(0 tables)\n"));
+}
+
+#[test]
+fn test_meta_commands() {
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+    Catalog::open_or_create(&mut bufmgr).unwrap();
+    let mut repl = Repl::new(Output::Text);
+    let mut command = |bufmgr: &mut BufferPoolManager, line: &str| {
+        let mut out = vec![];
+        repl.command(bufmgr, line, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let create = r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2,
+        "indices": [{"name": "by_v", "skey": [1]}]}}"#;
+    repl::run_script(&mut bufmgr, Output::Text, create, &mut vec![]).unwrap();
+
+    let stats = command(&mut bufmgr, ".stats");
+    assert!(stats.starts_with("counter        | value\n"), "{}", stats);
+    assert!(stats.contains("pool_size      | 10\n"), "{}", stats);
+    let buffers = command(&mut bufmgr, ".buffers");
+    assert!(buffers.starts_with("page_id | dirty | pins | usage_count\n"));
+    assert!(buffers.lines().count() > 2);
+    assert!(command(&mut bufmgr, ".tables").starts_with("name | meta_page_id"));
+
+    let verify = command(&mut bufmgr, ".verify t");
+    let lines: Vec<_> = verify.lines().collect();
+    assert_eq!(4, lines.len(), "{}", verify);
+    assert!(lines[2].starts_with("primary") && lines[2].ends_with("| ok"));
+    assert!(lines[3].starts_with("by_v") && lines[3].ends_with("| ok"));
+    assert!(command(&mut bufmgr, ".verify nope").starts_with("ERROR: "));
+    assert!(command(&mut bufmgr, ".verify").starts_with("ERROR: unknown command"));
+
+    assert_eq!("", command(&mut bufmgr, ".flush"));
+    assert_eq!(
+        0,
+        bufmgr
+            .resident_pages()
+            .iter()
+            .filter(|page| page.is_dirty)
+            .count()
+    );
+}
+
+#[test]
+fn test_settings() {
+    let lines = run(
+        Output::Text,
+        r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}
+{"Insert": {"table": "t", "record": ["a", "x"]}}
+{"Set": {"name": "analyze", "value": "on"}}
+{"Query": {"plan": {"SeqScan": {"table": "t"}}}}
+{"Set": {"name": "colour", "value": "on"}}
+{"Set": {"name": "flush_every", "value": 0}}
+{"Show": {"name": "flush_every"}}
+{"Set": {"name": "output", "value": "json"}}
+{"Query": {"plan": {"SeqScan": {"table": "t"}}}}
+{"Set": {"name": "read_only", "value": true}}
+{"Insert": {"table": "t", "record": ["b", "y"]}}
+{"Set": {"name": "read_only", "value": "off"}}"#,
+    );
+    assert_eq!("SET analyze = on", lines[2]);
+    // analyze adds the metrics after the row count
+    let metrics = lines.iter().position(|line| line == "(1 rows)").unwrap();
+    assert!(lines[metrics + 1].contains("SeqScan"), "{:?}", lines);
+    let lines = &lines[metrics + 1..];
+    let error = |prefix: &str| lines.iter().find(|line| line.starts_with(prefix)).unwrap();
+    assert_eq!(
+        "ERROR: unknown setting \"colour\", expected one of \
+         analyze, flush_every, max_cell_width, output, read_only",
+        error("ERROR: unknown")
+    );
+    assert_eq!(
+        "ERROR: invalid value \"0\" for flush_every, expected a positive number or off",
+        error("ERROR: invalid")
+    );
+    assert!(
+        lines.contains(&"flush_every | off".to_string()),
+        "{:?}",
+        lines
+    );
+
+    // from here on, every response is a line of JSON
+    let json = &lines[lines
+        .iter()
+        .position(|line| line.starts_with("{\"Set\""))
+        .unwrap()..];
+    assert!(json[1].starts_with("{\"Query\":{\"rows\":[[\"a\",\"x\"]],\"metrics\":"));
+    assert!(json[3].contains("\"kind\":\"read_only\""), "{:?}", json);
+    assert!(json[4].contains("\"kind\":\"setting\""), "{:?}", json);
+}
@@ -0,0 +1,145 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use tempfile::tempfile;
+
+use relly::buffer::{BufferPool, BufferPoolManager};
+use relly::catalog::Catalog;
+use relly::disk::DiskManager;
+use relly::lang::FileAccess;
+use relly::server::Server;
+
+/// Sends `requests` on a new connection and returns the response lines.
+fn exchange(addr: &str, requests: &[&str]) -> Vec<String> {
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut writer = &stream;
+    for request in requests {
+        writeln!(writer, "{}", request).unwrap();
+    }
+    stream.shutdown(Shutdown::Write).unwrap();
+    BufReader::new(&stream)
+        .lines()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Serves a new database on a thread until the returned flag is set.
+fn spawn_server(file_access: FileAccess) -> (String, Arc<AtomicBool>, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    let server = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+            Catalog::open_or_create(&mut bufmgr).unwrap();
+            let server = Server {
+                flush_every: None,
+                read_only: false,
+                file_access,
+            };
+            server.serve(&mut bufmgr, &listener, &stop).unwrap();
+        })
+    };
+    (addr, stop, server)
+}
+
+#[test]
+fn test_serve() {
+    let (addr, stop, server) = spawn_server(FileAccess::Refused);
+
+    assert_eq!(
+        vec![
+            r#"{"CreateTable":{"meta_page_id":3,"index_meta_page_ids":[]}}"#,
+            r#""Insert""#,
+            r#""Insert""#,
+            r#"{"error":{"kind":"execute","message":"duplicate key"}}"#,
+        ],
+        exchange(
+            &addr,
+            &[
+                r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}"#,
+                r#"{"Insert": {"table": "t", "record": ["a", "x"]}}"#,
+                r#"{"Insert": {"table": "t", "record": ["b", "y"]}}"#,
+                r#"{"Insert": {"table": "t", "record": ["a", "z"]}}"#,
+            ],
+        )
+    );
+    // a second connection sees what the first one did
+    assert_eq!(
+        vec![
+            r#"{"error":{"kind":"parse","message":"expected value at line 1 column 1"}}"#,
+            r#"{"Query":{"rows":[["b","y"]]}}"#,
+        ],
+        exchange(
+            &addr,
+            &[
+                "]",
+                r#"{"Query": {"plan": {"Filter": {"cond": {"Ne": [{"Column": 0}, {"Literal": "a"}]},"#,
+                r#"    "from": {"SeqScan": {"table": "t"}}}}}}"#,
+                ".exit",
+            ],
+        )
+    );
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_file_access() {
+    let dir = tempfile::tempdir().unwrap();
+    let inside = dir.path().join("t.csv");
+    let mut name = dir.path().file_name().unwrap().to_os_string();
+    name.push(".csv");
+    let outside = dir.path().join("..").join(name);
+    let requests = |path: &std::path::Path| {
+        let path = serde_json::to_string(path.to_str().unwrap()).unwrap();
+        vec![
+            format!(r#"{{"Export": {{"table": "t", "path": {}}}}}"#, path),
+            format!(r#"{{"Import": {{"table": "t", "path": {}}}}}"#, path),
+        ]
+    };
+    let create = r#"{"CreateTable": {"name": "t", "num_key_elems": 1, "num_columns": 2}}"#;
+
+    let (addr, stop, server) = spawn_server(FileAccess::Refused);
+    let mut lines = vec![create.to_string()];
+    lines.extend(requests(&inside));
+    let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+    let responses = exchange(&addr, &lines);
+    assert_eq!(3, responses.len());
+    assert!(
+        responses[1].contains("Export is not allowed"),
+        "{}",
+        responses[1]
+    );
+    assert!(
+        responses[2].contains("Import is not allowed"),
+        "{}",
+        responses[2]
+    );
+    assert!(!inside.exists());
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+
+    let (addr, stop, server) = spawn_server(FileAccess::Within(dir.path().canonicalize().unwrap()));
+    let mut lines = vec![create.to_string()];
+    lines.extend(requests(&inside));
+    lines.extend(requests(&outside));
+    let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+    let responses = exchange(&addr, &lines);
+    assert_eq!(
+        vec![r#"{"Export":{"rows":0}}"#, r#"{"Import":{"rows":0}}"#],
+        responses[1..3]
+    );
+    for response in &responses[3..] {
+        assert!(response.contains("is not inside"), "{}", response);
+    }
+    assert!(!outside.exists());
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}